@@ -0,0 +1,234 @@
+mod common;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::{distributions, Rng};
+
+use common::{bench_impl, loaded_filters, random_filters, FilterType};
+use rust_arrow_benches::{bit_packed, dataset, filter, filter_max, filter_sum};
+
+const ROWS: usize = 1_000_003; // ~1 million values in the column for now. (3 encourages non-chunking edge cases)
+
+fn bench_bit_packed(c: &mut Criterion) {
+    // pack a column and use the filters from a dataset file if one is given.
+    if let Some(data) = dataset::from_env() {
+        let packed = bit_packed::BitPackedColumn::from_values(&data.values);
+        for filter_type in &loaded_filters(data.filters) {
+            filter_materialise_bit_packed(c, &packed, filter_type);
+            filter_sum_bit_packed(c, &packed, filter_type);
+            filter_max_bit_packed(c, &packed, filter_type);
+        }
+        return;
+    }
+
+    let mut rng = rand::thread_rng();
+
+    // initialise different filters on the columns (create a set of row_ids to apply to col)
+    let filter_types = random_filters(&mut rng, ROWS);
+
+    for &width in &[7_u32, 20, 41] {
+        // initialise column with random values that need `width` bits.
+        let col = rng
+            .sample_iter(distributions::Uniform::from(0..1_u64 << width))
+            .take(ROWS)
+            .collect::<Vec<_>>();
+        let packed = bit_packed::BitPackedColumn::new(&col, width);
+
+        for filter_type in &filter_types {
+            filter_materialise_bit_packed(c, &packed, filter_type);
+            filter_sum_bit_packed(c, &packed, filter_type);
+            filter_max_bit_packed(c, &packed, filter_type);
+        }
+    }
+}
+
+fn filter_materialise_bit_packed(
+    c: &mut Criterion,
+    col: &bit_packed::BitPackedColumn,
+    row_ids: &FilterType,
+) {
+    let w = col.width();
+    let check = |dst: Vec<u64>| assert_eq!(dst.len(), row_ids.len());
+
+    // TODO(edd): like the other materialise benchmarks these don't re-use the
+    // `dst` buffer.
+    bench_impl(
+        c,
+        &format!("bit_packed_materialise_decode_simd_w{}", w),
+        row_ids,
+        || {
+            let decoded = col.decode();
+            check(
+                filter::filter_materialise_values_simd(&decoded, row_ids.as_slice(), vec![])
+                    .unwrap(),
+            )
+        },
+    );
+    bench_impl(
+        c,
+        &format!("bit_packed_materialise_rust_idiomatic_w{}", w),
+        row_ids,
+        || {
+            check(bit_packed::filter_materialise_values(
+                col,
+                row_ids.as_slice(),
+                vec![],
+            ))
+        },
+    );
+    bench_impl(
+        c,
+        &format!("bit_packed_materialise_gather_simd_w{}", w),
+        row_ids,
+        || {
+            check(
+                bit_packed::filter_materialise_values_gather_simd(col, row_ids.as_slice(), vec![])
+                    .unwrap(),
+            )
+        },
+    );
+    bench_impl(
+        c,
+        &format!("bit_packed_materialise_block_simd_w{}", w),
+        row_ids,
+        || {
+            check(
+                bit_packed::filter_materialise_values_block_simd(col, row_ids.as_slice(), vec![])
+                    .unwrap(),
+            )
+        },
+    );
+    bench_impl(
+        c,
+        &format!("bit_packed_materialise_simd_w{}", w),
+        row_ids,
+        || {
+            check(
+                bit_packed::filter_materialise_values_simd(col, row_ids.as_slice(), vec![])
+                    .unwrap(),
+            )
+        },
+    );
+}
+
+fn filter_sum_bit_packed(
+    c: &mut Criterion,
+    col: &bit_packed::BitPackedColumn,
+    row_ids: &FilterType,
+) {
+    let w = col.width();
+
+    // for assertion
+    let sum = bit_packed::filter_sum(col, row_ids.as_slice());
+
+    bench_impl(
+        c,
+        &format!("bit_packed_sum_decode_simd_w{}", w),
+        row_ids,
+        || {
+            let decoded = col.decode();
+            assert_eq!(
+                filter_sum::filter_sum_simd(&decoded, row_ids.as_slice()).unwrap(),
+                sum
+            );
+        },
+    );
+    bench_impl(
+        c,
+        &format!("bit_packed_sum_rust_idiomatic_w{}", w),
+        row_ids,
+        || {
+            assert_eq!(bit_packed::filter_sum(col, row_ids.as_slice()), sum);
+        },
+    );
+    bench_impl(
+        c,
+        &format!("bit_packed_sum_gather_simd_w{}", w),
+        row_ids,
+        || {
+            assert_eq!(
+                bit_packed::filter_sum_gather_simd(col, row_ids.as_slice()).unwrap(),
+                sum
+            );
+        },
+    );
+    bench_impl(
+        c,
+        &format!("bit_packed_sum_block_simd_w{}", w),
+        row_ids,
+        || {
+            assert_eq!(
+                bit_packed::filter_sum_block_simd(col, row_ids.as_slice()).unwrap(),
+                sum
+            );
+        },
+    );
+    bench_impl(c, &format!("bit_packed_sum_simd_w{}", w), row_ids, || {
+        assert_eq!(
+            bit_packed::filter_sum_simd(col, row_ids.as_slice()).unwrap(),
+            sum
+        );
+    });
+}
+
+fn filter_max_bit_packed(
+    c: &mut Criterion,
+    col: &bit_packed::BitPackedColumn,
+    row_ids: &FilterType,
+) {
+    let w = col.width();
+
+    // for assertion
+    let max = bit_packed::filter_max(col, row_ids.as_slice());
+
+    bench_impl(
+        c,
+        &format!("bit_packed_max_decode_simd_w{}", w),
+        row_ids,
+        || {
+            let decoded = col.decode();
+            assert_eq!(
+                filter_max::filter_max_simd(&decoded, row_ids.as_slice()).unwrap(),
+                max
+            );
+        },
+    );
+    bench_impl(
+        c,
+        &format!("bit_packed_max_rust_idiomatic_w{}", w),
+        row_ids,
+        || {
+            assert_eq!(bit_packed::filter_max(col, row_ids.as_slice()), max);
+        },
+    );
+    bench_impl(
+        c,
+        &format!("bit_packed_max_gather_simd_w{}", w),
+        row_ids,
+        || {
+            assert_eq!(
+                bit_packed::filter_max_gather_simd(col, row_ids.as_slice()).unwrap(),
+                max
+            );
+        },
+    );
+    bench_impl(
+        c,
+        &format!("bit_packed_max_block_simd_w{}", w),
+        row_ids,
+        || {
+            assert_eq!(
+                bit_packed::filter_max_block_simd(col, row_ids.as_slice()).unwrap(),
+                max
+            );
+        },
+    );
+    bench_impl(c, &format!("bit_packed_max_simd_w{}", w), row_ids, || {
+        assert_eq!(
+            bit_packed::filter_max_simd(col, row_ids.as_slice()).unwrap(),
+            max
+        );
+    });
+}
+
+criterion_group!(benches, bench_bit_packed);
+criterion_main!(benches);
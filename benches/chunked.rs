@@ -0,0 +1,203 @@
+mod common;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::{distributions, Rng};
+
+use common::{bench_impl, loaded_filters, random_filters, FilterType};
+use rust_arrow_benches::{chunked, dataset, filter, filter_max, filter_sum};
+
+const ROWS: usize = 1_000_003; // ~1 million values in the column for now. (3 encourages non-chunking edge cases)
+
+fn bench_chunked(c: &mut Criterion) {
+    let mut rng = rand::thread_rng();
+
+    // load a column and filters from a dataset file if one is given, otherwise
+    // generate them.
+    let (col, filter_types) = match dataset::from_env() {
+        Some(data) => (data.values, loaded_filters(data.filters)),
+        None => {
+            // initialise different filters on the columns (create a set of row_ids to apply to col)
+            let filter_types = random_filters(&mut rng, ROWS);
+
+            // initialise column with random values. They're small enough that
+            // the sums don't overflow.
+            let col = rng
+                .sample_iter(distributions::Uniform::from(0..u32::MAX as u64))
+                .take(ROWS)
+                .collect::<Vec<_>>();
+            (col, filter_types)
+        }
+    };
+
+    for &chunk_size in &[1024_usize, 65536, col.len()] {
+        let chunked = chunked::ChunkedColumn::from_values(&col, chunk_size);
+
+        for filter_type in &filter_types {
+            filter_materialise_chunked(c, &col, &chunked, chunk_size, filter_type);
+            filter_sum_chunked(c, &col, &chunked, chunk_size, filter_type);
+            filter_max_chunked(c, &col, &chunked, chunk_size, filter_type);
+        }
+    }
+}
+
+fn filter_materialise_chunked(
+    c: &mut Criterion,
+    col: &[u64],
+    chunked: &chunked::ChunkedColumn,
+    chunk_size: usize,
+    row_ids: &FilterType,
+) {
+    let check = |dst: Vec<u64>| assert_eq!(dst.len(), row_ids.len());
+
+    // TODO(edd): like the other materialise benchmarks these don't re-use the
+    // `dst` buffer.
+    bench_impl(
+        c,
+        &format!("chunked_materialise_contiguous_simd_chunk{}", chunk_size),
+        row_ids,
+        || check(filter::filter_materialise_values_simd(col, row_ids.as_slice(), vec![]).unwrap()),
+    );
+    bench_impl(
+        c,
+        &format!("chunked_materialise_rust_idiomatic_chunk{}", chunk_size),
+        row_ids,
+        || {
+            check(chunked::filter_materialise_values(
+                chunked,
+                row_ids.as_slice(),
+                vec![],
+            ))
+        },
+    );
+    bench_impl(
+        c,
+        &format!("chunked_materialise_split_chunk{}", chunk_size),
+        row_ids,
+        || {
+            check(
+                chunked::filter_materialise_values_split(chunked, row_ids.as_slice(), vec![])
+                    .unwrap(),
+            )
+        },
+    );
+    bench_impl(
+        c,
+        &format!("chunked_materialise_simd_chunk{}", chunk_size),
+        row_ids,
+        || {
+            check(
+                chunked::filter_materialise_values_simd(chunked, row_ids.as_slice(), vec![])
+                    .unwrap(),
+            )
+        },
+    );
+}
+
+fn filter_sum_chunked(
+    c: &mut Criterion,
+    col: &[u64],
+    chunked: &chunked::ChunkedColumn,
+    chunk_size: usize,
+    row_ids: &FilterType,
+) {
+    // for assertion
+    let sum = filter_sum::filter_sum(col, row_ids.as_slice());
+
+    bench_impl(
+        c,
+        &format!("chunked_sum_contiguous_simd_chunk{}", chunk_size),
+        row_ids,
+        || {
+            assert_eq!(
+                filter_sum::filter_sum_simd(col, row_ids.as_slice()).unwrap(),
+                sum
+            );
+        },
+    );
+    bench_impl(
+        c,
+        &format!("chunked_sum_rust_idiomatic_chunk{}", chunk_size),
+        row_ids,
+        || {
+            assert_eq!(chunked::filter_sum(chunked, row_ids.as_slice()), sum);
+        },
+    );
+    bench_impl(
+        c,
+        &format!("chunked_sum_split_chunk{}", chunk_size),
+        row_ids,
+        || {
+            assert_eq!(
+                chunked::filter_sum_split(chunked, row_ids.as_slice()).unwrap(),
+                sum
+            );
+        },
+    );
+    bench_impl(
+        c,
+        &format!("chunked_sum_simd_chunk{}", chunk_size),
+        row_ids,
+        || {
+            assert_eq!(
+                chunked::filter_sum_simd(chunked, row_ids.as_slice()).unwrap(),
+                sum
+            );
+        },
+    );
+}
+
+fn filter_max_chunked(
+    c: &mut Criterion,
+    col: &[u64],
+    chunked: &chunked::ChunkedColumn,
+    chunk_size: usize,
+    row_ids: &FilterType,
+) {
+    // for assertion
+    let max = filter_max::filter_max(col, row_ids.as_slice());
+
+    bench_impl(
+        c,
+        &format!("chunked_max_contiguous_simd_chunk{}", chunk_size),
+        row_ids,
+        || {
+            assert_eq!(
+                filter_max::filter_max_simd(col, row_ids.as_slice()).unwrap(),
+                max
+            );
+        },
+    );
+    bench_impl(
+        c,
+        &format!("chunked_max_rust_idiomatic_chunk{}", chunk_size),
+        row_ids,
+        || {
+            assert_eq!(chunked::filter_max(chunked, row_ids.as_slice()), max);
+        },
+    );
+    bench_impl(
+        c,
+        &format!("chunked_max_split_chunk{}", chunk_size),
+        row_ids,
+        || {
+            assert_eq!(
+                chunked::filter_max_split(chunked, row_ids.as_slice()).unwrap(),
+                max
+            );
+        },
+    );
+    bench_impl(
+        c,
+        &format!("chunked_max_simd_chunk{}", chunk_size),
+        row_ids,
+        || {
+            assert_eq!(
+                chunked::filter_max_simd(chunked, row_ids.as_slice()).unwrap(),
+                max
+            );
+        },
+    );
+}
+
+criterion_group!(benches, bench_chunked);
+criterion_main!(benches);
@@ -0,0 +1,135 @@
+//! The filters the benchmarks run on and the helper that registers each
+//! benchmark, shared by every bench target with `mod common;`.
+//!
+//! Each bench target compiles this module separately and not all of them use
+//! every helper, hence the `dead_code` allowance.
+#![allow(dead_code)]
+
+use std::fmt;
+
+use criterion::{BenchmarkId, Criterion, Throughput};
+use rand::{distributions, rngs::ThreadRng, Rng};
+
+pub enum FilterType {
+    // a filter with uniformly distributed rows of a certain density
+    // (10 would be 10% of rows)
+    Uniform(Vec<u32>, usize),
+
+    // a filter with a run of rows distributed through a column. This more closely
+    // mimics a column that has been sorted by some other columns.
+    Run(Vec<u32>, usize, usize),
+
+    // a named filter loaded from a dataset file.
+    Loaded(Vec<u32>, String),
+}
+
+impl FilterType {
+    pub fn len(&self) -> usize {
+        match self {
+            FilterType::Uniform(v, _) => v.len(),
+            FilterType::Run(v, _, _) => v.len(),
+            FilterType::Loaded(v, _) => v.len(),
+        }
+    }
+
+    pub fn as_slice(&self) -> &[u32] {
+        match self {
+            FilterType::Uniform(v, _) => v.as_slice(),
+            FilterType::Run(v, _, _) => v.as_slice(),
+            FilterType::Loaded(v, _) => v.as_slice(),
+        }
+    }
+}
+
+impl fmt::Display for FilterType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterType::Uniform(_, density) => write!(f, "uniform_density_{:?}%", density),
+            FilterType::Run(_, density, block_size) => write!(
+                f,
+                "uniform_density_{:?}%_block_size_{:?}",
+                density, block_size
+            ),
+            FilterType::Loaded(_, name) => write!(f, "loaded_{}", name),
+        }
+    }
+}
+
+// Create a set of row_ids to apply to a column. Provide a prng, the domain that
+// the row_ids can be picked from (`n`) and the probability of a row being
+// selected, represented as `1/prop`.
+pub fn random_filter(rng: &mut ThreadRng, n: usize, prop: usize) -> Vec<u32> {
+    let dist = distributions::Uniform::from(0..100);
+    rng.sample_iter(dist)
+        .enumerate()
+        .take(n)
+        .filter_map(|(row_id, x)| {
+            if x < prop {
+                return Some(row_id as u32);
+            }
+            None
+        })
+        .collect::<Vec<_>>()
+}
+
+// Create a set of row_ids to apply to a column using a strategy where "runs"
+// of matching rows are created according to 1/prop probability.
+pub fn random_filter_run(rng: &mut ThreadRng, n: usize, prop: usize, run_size: usize) -> Vec<u32> {
+    let dist = distributions::Uniform::from(0..100);
+
+    // this is not at all perfect. When the prng decides to emit a run
+    // of row ids it doesn't skip the `for` to the end of the run, which means
+    // you can lead to larger blocks than `run_size`. The general data layout
+    // is okay though for the use-case.
+    let mut result = vec![];
+    for row_id in 0..n {
+        if rng.sample(dist) < prop {
+            result.extend(row_id..row_id + run_size);
+        }
+    }
+
+    // This generator is a bit ghetto - it could generate row_ids that are
+    // upto block_size-1 over the max. It can also generate duplicates so remove
+    // those.
+    result
+        .into_iter()
+        .filter_map(|row_id| {
+            if row_id < n - 1 {
+                Some(row_id as u32)
+            } else {
+                None
+            }
+        })
+        .collect::<std::collections::BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+// Wrap the filters loaded from a dataset file.
+pub fn loaded_filters(filters: Vec<(String, Vec<u32>)>) -> Vec<FilterType> {
+    filters
+        .into_iter()
+        .map(|(name, row_ids)| FilterType::Loaded(row_ids, name))
+        .collect()
+}
+
+// Create the filters most benchmarks run on: uniformly distributed rows at a
+// low, medium and high density, and two sizes of runs.
+pub fn random_filters(rng: &mut ThreadRng, n: usize) -> Vec<FilterType> {
+    vec![
+        FilterType::Uniform(random_filter(rng, n, 10), 10),
+        FilterType::Uniform(random_filter(rng, n, 50), 50),
+        FilterType::Uniform(random_filter(rng, n, 75), 75),
+        FilterType::Run(random_filter_run(rng, n, 5, 5), 5, 5),
+        FilterType::Run(random_filter_run(rng, n, 10, 10), 10, 10),
+    ]
+}
+
+// Benchmark `f` in the group `name`, with the filter shape as the parameter.
+pub fn bench_impl(c: &mut Criterion, name: &str, row_ids: &FilterType, mut f: impl FnMut()) {
+    let mut group = c.benchmark_group(name);
+    group.throughput(Throughput::Elements(row_ids.len() as u64));
+    group.bench_function(BenchmarkId::from_parameter(format!("{}", row_ids)), |b| {
+        b.iter(&mut f);
+    });
+}
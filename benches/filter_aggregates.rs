@@ -0,0 +1,111 @@
+mod common;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::{distributions, Rng};
+
+use common::{bench_impl, loaded_filters, random_filters, FilterType};
+use rust_arrow_benches::{
+    dataset,
+    filter_aggregates::{self, Aggregate},
+    filter_max, filter_signed, filter_sum,
+};
+
+const ROWS: usize = 1_000_003; // ~1 million values in the column for now. (3 encourages non-chunking edge cases)
+
+const ALL: [Aggregate; 4] = [
+    Aggregate::Sum,
+    Aggregate::Min,
+    Aggregate::Max,
+    Aggregate::Count,
+];
+
+fn bench_filter_aggregates(c: &mut Criterion) {
+    let mut rng = rand::thread_rng();
+
+    // load a column and filters from a dataset file if one is given, otherwise
+    // generate them.
+    let (col, filter_types) = match dataset::from_env() {
+        Some(data) => (data.values, loaded_filters(data.filters)),
+        None => {
+            // initialise a column with random values.
+            let col = rng
+                .sample_iter(distributions::Uniform::from(0..1_000_000))
+                .take(ROWS)
+                .collect::<Vec<u64>>();
+
+            // initialise different filters on the above column (create a set of row_ids to apply to col)
+            let filter_types = random_filters(&mut rng, ROWS);
+            (col, filter_types)
+        }
+    };
+
+    // the same values as i64s, for the SIMD min kernel. A loaded column's
+    // values must be below `2^63` for its min to match.
+    let col_i64 = col.iter().map(|&v| v as i64).collect::<Vec<_>>();
+
+    for filter_type in &filter_types {
+        // the single-aggregate kernels have no min or max of no rows, and a
+        // loaded filter can select nothing.
+        if filter_type.len() == 0 {
+            continue;
+        }
+        filter_aggregates_all(c, &col, &col_i64, filter_type);
+        filter_aggregates_sum_max(c, &col, filter_type);
+    }
+}
+
+// SUM, MIN, MAX and COUNT.
+fn filter_aggregates_all(c: &mut Criterion, col: &[u64], col_i64: &[i64], row_ids: &FilterType) {
+    // for assertion
+    let exp = filter_aggregates::filter_aggregates(col, row_ids.as_slice(), &ALL);
+
+    bench_impl(c, "filter_aggregates_all_rust_idiomatic", row_ids, || {
+        let result = filter_aggregates::filter_aggregates(col, row_ids.as_slice(), &ALL);
+        assert_eq!(result, exp);
+    });
+
+    bench_impl(c, "filter_aggregates_all_simd", row_ids, || {
+        let result =
+            filter_aggregates::filter_aggregates_simd(col, row_ids.as_slice(), &ALL).unwrap();
+        assert_eq!(result, exp);
+    });
+
+    // the single-aggregate kernels one after another.
+    bench_impl(c, "filter_aggregates_all_separate_simd", row_ids, || {
+        let sum = filter_sum::filter_sum_simd(col, row_ids.as_slice()).unwrap();
+        let min = filter_signed::filter_min_i64_simd(col_i64, row_ids.as_slice()).unwrap();
+        let max = filter_max::filter_max_simd(col, row_ids.as_slice()).unwrap();
+        let count = row_ids.len() as u64;
+        let result = vec![Some(sum), Some(min as u64), Some(max), Some(count)];
+        assert_eq!(result, exp);
+    });
+}
+
+// SUM and MAX.
+fn filter_aggregates_sum_max(c: &mut Criterion, col: &[u64], row_ids: &FilterType) {
+    let aggregates = [Aggregate::Sum, Aggregate::Max];
+
+    // for assertion
+    let exp = filter_aggregates::filter_aggregates(col, row_ids.as_slice(), &aggregates);
+
+    bench_impl(c, "filter_aggregates_sum_max_simd", row_ids, || {
+        let result =
+            filter_aggregates::filter_aggregates_simd(col, row_ids.as_slice(), &aggregates)
+                .unwrap();
+        assert_eq!(result, exp);
+    });
+
+    bench_impl(
+        c,
+        "filter_aggregates_sum_max_separate_simd",
+        row_ids,
+        || {
+            let sum = filter_sum::filter_sum_simd(col, row_ids.as_slice()).unwrap();
+            let max = filter_max::filter_max_simd(col, row_ids.as_slice()).unwrap();
+            assert_eq!(vec![Some(sum), Some(max)], exp);
+        },
+    );
+}
+
+criterion_group!(benches, bench_filter_aggregates);
+criterion_main!(benches);
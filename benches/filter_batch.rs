@@ -0,0 +1,142 @@
+mod common;
+
+use std::sync::Arc;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rand::{distributions, rngs::ThreadRng, Rng};
+
+use arrow::{
+    array,
+    datatypes::{DataType, Field, Schema},
+    record_batch::RecordBatch,
+};
+
+use common::{random_filters, FilterType};
+use rust_arrow_benches::filter_batch;
+
+const ROWS: usize = 1_000_003; // ~1 million values in the column for now. (3 encourages non-chunking edge cases)
+
+// Create a batch with `n` columns, cycling through some common column types.
+fn random_batch(rng: &mut ThreadRng, n: usize) -> RecordBatch {
+    let strs = [
+        "cpu",
+        "memory",
+        "disk",
+        "network",
+        "a_longer_measurement_name",
+    ];
+
+    let mut fields = vec![];
+    let mut columns: Vec<array::ArrayRef> = vec![];
+    for i in 0..n {
+        match i % 5 {
+            0 => {
+                fields.push(Field::new(&format!("u64_{}", i), DataType::UInt64, false));
+                columns.push(Arc::new(array::UInt64Array::from(
+                    rng.sample_iter(distributions::Uniform::from(0..u64::MAX))
+                        .take(ROWS)
+                        .collect::<Vec<_>>(),
+                )));
+            }
+            1 => {
+                fields.push(Field::new(&format!("i64_{}", i), DataType::Int64, false));
+                columns.push(Arc::new(array::Int64Array::from(
+                    rng.sample_iter(distributions::Uniform::from(i64::MIN..i64::MAX))
+                        .take(ROWS)
+                        .collect::<Vec<_>>(),
+                )));
+            }
+            2 => {
+                fields.push(Field::new(&format!("f64_{}", i), DataType::Float64, false));
+                columns.push(Arc::new(array::Float64Array::from(
+                    rng.sample_iter(distributions::Uniform::from(0.0..1000.0))
+                        .take(ROWS)
+                        .collect::<Vec<f64>>(),
+                )));
+            }
+            3 => {
+                fields.push(Field::new(&format!("i32_{}", i), DataType::Int32, false));
+                columns.push(Arc::new(array::Int32Array::from(
+                    rng.sample_iter(distributions::Uniform::from(i32::MIN..i32::MAX))
+                        .take(ROWS)
+                        .collect::<Vec<_>>(),
+                )));
+            }
+            _ => {
+                fields.push(Field::new(&format!("str_{}", i), DataType::Utf8, false));
+                columns.push(Arc::new(array::StringArray::from(
+                    rng.sample_iter(distributions::Uniform::from(0..strs.len()))
+                        .take(ROWS)
+                        .map(|k| strs[k])
+                        .collect::<Vec<_>>(),
+                )));
+            }
+        }
+    }
+
+    RecordBatch::try_new(Arc::new(Schema::new(fields)), columns).unwrap()
+}
+
+fn bench_filter_batch(c: &mut Criterion) {
+    let mut rng = rand::thread_rng();
+
+    // initialise different filters on the columns (create a set of row_ids to apply to col)
+    let filter_types = random_filters(&mut rng, ROWS);
+
+    for &columns in &[2_usize, 8] {
+        let batch = random_batch(&mut rng, columns);
+        for filter_type in &filter_types {
+            filter_record_batch(c, &batch, filter_type);
+        }
+    }
+}
+
+fn filter_record_batch(c: &mut Criterion, batch: &RecordBatch, row_ids: &FilterType) {
+    let projection = (0..batch.num_columns()).collect::<Vec<_>>();
+    let selection = filter_batch::Selection::from_row_ids(row_ids.as_slice().to_vec());
+
+    let mut filter = vec![false; ROWS];
+    for &id in row_ids.as_slice() {
+        filter[id as usize] = true;
+    }
+    let filter = array::BooleanArray::from(filter);
+
+    let check = |batch: RecordBatch| assert_eq!(batch.num_rows(), row_ids.len());
+
+    let mut group = c.benchmark_group(format!("filter_batch_columns_{}", batch.num_columns()));
+    // every value in every projected column is materialised.
+    group.throughput(Throughput::Elements(
+        (row_ids.len() * batch.num_columns()) as u64,
+    ));
+
+    group.bench_function(BenchmarkId::new("arrow", format!("{}", row_ids)), |b| {
+        b.iter(|| {
+            check(filter_batch::filter_record_batch_arrow(
+                batch,
+                &filter,
+                &projection,
+            ))
+        });
+    });
+    group.bench_function(
+        BenchmarkId::new("selection_from_boolean_array", format!("{}", row_ids)),
+        |b| {
+            b.iter(|| {
+                let selection = filter_batch::Selection::from_boolean_array(&filter);
+                check(filter_batch::filter_record_batch(batch, &selection, &projection).unwrap())
+            });
+        },
+    );
+    group.bench_function(
+        BenchmarkId::new("selection_from_row_ids", format!("{}", row_ids)),
+        |b| {
+            b.iter(|| {
+                check(filter_batch::filter_record_batch(batch, &selection, &projection).unwrap())
+            });
+        },
+    );
+    group.finish();
+}
+
+criterion_group!(benches, bench_filter_batch);
+criterion_main!(benches);
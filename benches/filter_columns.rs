@@ -0,0 +1,130 @@
+mod common;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::{distributions, Rng};
+
+use common::{bench_impl, loaded_filters, random_filters, FilterType};
+use rust_arrow_benches::{
+    dataset, filter,
+    filter_columns::{self, Column, ColumnBuffer},
+    filter_narrow,
+};
+
+const ROWS: usize = 1_000_003; // ~1 million values in the column for now. (3 encourages non-chunking edge cases)
+
+// The number of columns projected.
+const COLUMNS: [usize; 4] = [2, 4, 8, 16];
+
+fn bench_filter_materialise_columns(c: &mut Criterion) {
+    let mut rng = rand::thread_rng();
+
+    // load a column and filters from a dataset file if one is given, otherwise
+    // generate them.
+    let (col, filter_types) = match dataset::from_env() {
+        Some(data) => (data.values, loaded_filters(data.filters)),
+        None => {
+            // initialise a column with random values.
+            let col = rng
+                .sample_iter(distributions::Uniform::from(0..u64::MAX))
+                .take(ROWS)
+                .collect::<Vec<u64>>();
+
+            // initialise different filters on the above column (create a set of row_ids to apply to col)
+            let filter_types = random_filters(&mut rng, ROWS);
+            (col, filter_types)
+        }
+    };
+
+    // the narrower columns hold the low bits of the same values.
+    let col_u32 = col.iter().map(|&v| v as u32).collect::<Vec<_>>();
+    let col_u16 = col.iter().map(|&v| v as u16).collect::<Vec<_>>();
+    let col_u8 = col.iter().map(|&v| v as u8).collect::<Vec<_>>();
+
+    for &n in &COLUMNS {
+        // a projection of mixed widths.
+        let columns = (0..n)
+            .map(|i| match i % 4 {
+                0 => Column::U64(&col),
+                1 => Column::U32(&col_u32),
+                2 => Column::U16(&col_u16),
+                _ => Column::U8(&col_u8),
+            })
+            .collect::<Vec<_>>();
+
+        for filter_type in &filter_types {
+            filter_materialise_columns(c, &columns, filter_type);
+        }
+    }
+}
+
+fn filter_materialise_columns(c: &mut Criterion, columns: &[Column<'_>], row_ids: &FilterType) {
+    // for assertion
+    let exp = filter_columns::filter_materialise_columns(columns, row_ids.as_slice(), vec![]);
+    let n = columns.len();
+
+    let mut dst = vec![];
+    bench_impl(
+        c,
+        &format!("filter_materialise_columns_{}_rust_idiomatic", n),
+        row_ids,
+        || {
+            dst = filter_columns::filter_materialise_columns(
+                columns,
+                row_ids.as_slice(),
+                std::mem::take(&mut dst),
+            );
+            assert_eq!(dst.len(), n);
+        },
+    );
+    assert_eq!(dst, exp);
+
+    let mut dst = vec![];
+    bench_impl(
+        c,
+        &format!("filter_materialise_columns_{}_simd", n),
+        row_ids,
+        || {
+            dst = filter_columns::filter_materialise_columns_simd(
+                columns,
+                row_ids.as_slice(),
+                std::mem::take(&mut dst),
+            )
+            .unwrap();
+            assert_eq!(dst.len(), n);
+        },
+    );
+    assert_eq!(dst, exp);
+
+    // the single-column kernels, one call (and pass over the row ids) per
+    // column.
+    let mut dst = exp.clone();
+    bench_impl(
+        c,
+        &format!("filter_materialise_columns_{}_per_column_simd", n),
+        row_ids,
+        || {
+            for (column, buffer) in columns.iter().zip(dst.iter_mut()) {
+                let row_ids = row_ids.as_slice();
+                *buffer = match (column, std::mem::replace(buffer, ColumnBuffer::U8(vec![]))) {
+                    (Column::U8(v), ColumnBuffer::U8(b)) => ColumnBuffer::U8(
+                        filter_narrow::filter_materialise_values_simd(v, row_ids, b).unwrap(),
+                    ),
+                    (Column::U16(v), ColumnBuffer::U16(b)) => ColumnBuffer::U16(
+                        filter_narrow::filter_materialise_values_simd(v, row_ids, b).unwrap(),
+                    ),
+                    (Column::U32(v), ColumnBuffer::U32(b)) => ColumnBuffer::U32(
+                        filter_narrow::filter_materialise_values_simd(v, row_ids, b).unwrap(),
+                    ),
+                    (Column::U64(v), ColumnBuffer::U64(b)) => ColumnBuffer::U64(
+                        filter::filter_materialise_values_simd(v, row_ids, b).unwrap(),
+                    ),
+                    _ => unreachable!(),
+                };
+            }
+        },
+    );
+    assert_eq!(dst, exp);
+}
+
+criterion_group!(benches, bench_filter_materialise_columns);
+criterion_main!(benches);
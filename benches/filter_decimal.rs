@@ -0,0 +1,119 @@
+mod common;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::{distributions, Rng};
+
+use common::{bench_impl, loaded_filters, random_filters, FilterType};
+use rust_arrow_benches::{
+    dataset,
+    filter_decimal::{self, Decimal128Column},
+    filter_signed,
+};
+
+const ROWS: usize = 1_000_003; // ~1 million values in the column for now. (3 encourages non-chunking edge cases)
+
+fn bench_filter_decimal(c: &mut Criterion) {
+    let mut rng = rand::thread_rng();
+
+    // load a column and filters from a dataset file if one is given, otherwise
+    // generate them. A loaded column's values are used as the unscaled
+    // decimals, which any `u64` fits.
+    let (values, filter_types) = match dataset::from_env() {
+        Some(data) => {
+            let values = data.values.iter().map(|&v| v as i128).collect::<Vec<_>>();
+            (values, loaded_filters(data.filters))
+        }
+        None => {
+            // initialise a column of decimal(38, 2) values either side of zero,
+            // large enough that the high limbs are used.
+            let values = rng
+                .sample_iter(distributions::Uniform::from(
+                    -1_000_000_000_000_000_000_000_000_i128..1_000_000_000_000_000_000_000_000,
+                ))
+                .take(ROWS)
+                .collect::<Vec<i128>>();
+
+            // initialise different filters on the above column (create a set of row_ids to apply to col)
+            let filter_types = random_filters(&mut rng, ROWS);
+            (values, filter_types)
+        }
+    };
+    let col = Decimal128Column::try_new(&values, 38, 2).unwrap();
+
+    // the same number of rows of i64 values, for the i64 kernels.
+    let col_i64 = values
+        .iter()
+        .map(|&v| (v % 100_000_000_000) as i64)
+        .collect::<Vec<_>>();
+
+    for filter_type in &filter_types {
+        // there's no max or min of no rows, and a loaded filter can select
+        // nothing.
+        if filter_type.len() == 0 {
+            continue;
+        }
+        filter_sum_decimal(c, &col, &col_i64, filter_type);
+        filter_max_decimal(c, &col, filter_type);
+        filter_min_decimal(c, &col, filter_type);
+    }
+}
+
+fn filter_sum_decimal(
+    c: &mut Criterion,
+    col: &Decimal128Column,
+    col_i64: &[i64],
+    row_ids: &FilterType,
+) {
+    // for assertion
+    let sum = filter_decimal::filter_sum(col, row_ids.as_slice()).unwrap();
+    let sum_i64 = filter_signed::filter_sum_i64(col_i64, row_ids.as_slice()).unwrap();
+
+    // the i64 kernel for comparison.
+    bench_impl(c, "filter_sum_i64_simd", row_ids, || {
+        let result = filter_signed::filter_sum_i64_simd(col_i64, row_ids.as_slice()).unwrap();
+        assert_eq!(result, Some(sum_i64));
+    });
+
+    bench_impl(c, "filter_sum_decimal_rust_idiomatic", row_ids, || {
+        let result = filter_decimal::filter_sum(col, row_ids.as_slice());
+        assert_eq!(result, Ok(sum));
+    });
+
+    bench_impl(c, "filter_sum_decimal_simd", row_ids, || {
+        let result = filter_decimal::filter_sum_simd(col, row_ids.as_slice());
+        assert_eq!(result, Ok(sum));
+    });
+}
+
+fn filter_max_decimal(c: &mut Criterion, col: &Decimal128Column, row_ids: &FilterType) {
+    // for assertion
+    let max = filter_decimal::filter_max(col, row_ids.as_slice());
+
+    bench_impl(c, "filter_max_decimal_rust_idiomatic", row_ids, || {
+        let result = filter_decimal::filter_max(col, row_ids.as_slice());
+        assert_eq!(result, max);
+    });
+
+    bench_impl(c, "filter_max_decimal_simd", row_ids, || {
+        let result = filter_decimal::filter_max_simd(col, row_ids.as_slice()).unwrap();
+        assert_eq!(result, max);
+    });
+}
+
+fn filter_min_decimal(c: &mut Criterion, col: &Decimal128Column, row_ids: &FilterType) {
+    // for assertion
+    let min = filter_decimal::filter_min(col, row_ids.as_slice());
+
+    bench_impl(c, "filter_min_decimal_rust_idiomatic", row_ids, || {
+        let result = filter_decimal::filter_min(col, row_ids.as_slice());
+        assert_eq!(result, min);
+    });
+
+    bench_impl(c, "filter_min_decimal_simd", row_ids, || {
+        let result = filter_decimal::filter_min_simd(col, row_ids.as_slice()).unwrap();
+        assert_eq!(result, min);
+    });
+}
+
+criterion_group!(benches, bench_filter_decimal);
+criterion_main!(benches);
@@ -0,0 +1,162 @@
+mod common;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::{distributions, Rng};
+
+use common::{bench_impl, random_filters, FilterType};
+use rust_arrow_benches::filter_dictionary;
+
+const ROWS: usize = 1_000_003; // ~1 million values in the column for now. (3 encourages non-chunking edge cases)
+
+const CARDINALITY: usize = 200; // number of distinct values in the dictionary
+
+fn bench_filter_dictionary(c: &mut Criterion) {
+    let mut rng = rand::thread_rng();
+
+    // initialise a dictionary and a column of random keys into it.
+    let dictionary = (0..CARDINALITY)
+        .map(|i| format!("tag_value_{}", i))
+        .collect::<Vec<_>>();
+    let dictionary_str = dictionary.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+    let keys = rng
+        .sample_iter(distributions::Uniform::from(0..CARDINALITY as u32))
+        .take(ROWS)
+        .collect::<Vec<u32>>();
+
+    // initialise different filters on the above column (create a set of row_ids to apply to col)
+    let filter_types = random_filters(&mut rng, ROWS);
+
+    for filter_type in &filter_types {
+        filter_materialise_keys(c, &keys, filter_type);
+        filter_materialise_keys_arrow(c, &keys, &dictionary_str, filter_type);
+        filter_materialise_decoded(c, &keys, &dictionary_str, filter_type);
+        filter_materialise_decoded_arrow(c, &keys, &dictionary_str, filter_type);
+    }
+}
+
+fn arrow_inputs(
+    keys: &[u32],
+    dictionary: &[&str],
+    row_ids: &FilterType,
+) -> (
+    arrow::array::DictionaryArray<arrow::datatypes::UInt32Type>,
+    arrow::array::BooleanArray,
+) {
+    let col_arr = keys
+        .iter()
+        .map(|&k| dictionary[k as usize])
+        .collect::<arrow::array::DictionaryArray<arrow::datatypes::UInt32Type>>();
+
+    let mut filter = Vec::with_capacity(keys.len());
+    filter.resize(keys.len(), false);
+    for &row_id in row_ids.as_slice().iter() {
+        filter[row_id as usize] = true;
+    }
+    (col_arr, arrow::array::BooleanArray::from(filter))
+}
+
+fn filter_materialise_keys(c: &mut Criterion, keys: &[u32], row_ids: &FilterType) {
+    let keys_u8 = keys.iter().map(|&k| k as u8).collect::<Vec<_>>();
+    let keys_u16 = keys.iter().map(|&k| k as u16).collect::<Vec<_>>();
+
+    // TODO(edd): like the other materialise benchmarks these don't re-use the
+    // `dst` buffer.
+    bench_impl(c, "filter_dict_keys_u32_rust_idiomatic", row_ids, || {
+        let dst = filter_dictionary::filter_materialise_keys(keys, row_ids.as_slice(), vec![]);
+        assert_eq!(dst.len(), row_ids.len());
+    });
+    bench_impl(c, "filter_dict_keys_u32_simd", row_ids, || {
+        let dst = filter_dictionary::filter_materialise_keys_simd(keys, row_ids.as_slice(), vec![])
+            .unwrap();
+        assert_eq!(dst.len(), row_ids.len());
+    });
+    bench_impl(c, "filter_dict_keys_u16_rust_idiomatic", row_ids, || {
+        let dst = filter_dictionary::filter_materialise_keys(&keys_u16, row_ids.as_slice(), vec![]);
+        assert_eq!(dst.len(), row_ids.len());
+    });
+    bench_impl(c, "filter_dict_keys_u16_simd", row_ids, || {
+        let dst =
+            filter_dictionary::filter_materialise_keys_simd(&keys_u16, row_ids.as_slice(), vec![])
+                .unwrap();
+        assert_eq!(dst.len(), row_ids.len());
+    });
+    bench_impl(c, "filter_dict_keys_u8_rust_idiomatic", row_ids, || {
+        let dst = filter_dictionary::filter_materialise_keys(&keys_u8, row_ids.as_slice(), vec![]);
+        assert_eq!(dst.len(), row_ids.len());
+    });
+    bench_impl(c, "filter_dict_keys_u8_simd", row_ids, || {
+        let dst =
+            filter_dictionary::filter_materialise_keys_simd(&keys_u8, row_ids.as_slice(), vec![])
+                .unwrap();
+        assert_eq!(dst.len(), row_ids.len());
+    });
+}
+
+fn filter_materialise_keys_arrow(
+    c: &mut Criterion,
+    keys: &[u32],
+    dictionary: &[&str],
+    row_ids: &FilterType,
+) {
+    let (col_arr, row_ids_arr) = arrow_inputs(keys, dictionary, row_ids);
+    bench_impl(c, "filter_dict_keys_u32_arrow", row_ids, || {
+        let dst = filter_dictionary::filter_materialise_keys_arrow(&col_arr, &row_ids_arr);
+        assert_eq!(dst.len(), row_ids.len());
+    });
+}
+
+fn filter_materialise_decoded(
+    c: &mut Criterion,
+    keys: &[u32],
+    dictionary: &[&str],
+    row_ids: &FilterType,
+) {
+    bench_impl(c, "filter_dict_decoded_rust_idiomatic", row_ids, || {
+        let dst = filter_dictionary::filter_materialise_decoded(
+            keys,
+            dictionary,
+            row_ids.as_slice(),
+            vec![],
+        );
+        assert_eq!(dst.len(), row_ids.len());
+    });
+    bench_impl(c, "filter_dict_decoded_simd", row_ids, || {
+        let dst = filter_dictionary::filter_materialise_decoded_simd(
+            keys,
+            dictionary,
+            row_ids.as_slice(),
+            vec![],
+        )
+        .unwrap();
+        assert_eq!(dst.len(), row_ids.len());
+    });
+
+    // a dictionary of fixed-width values can be decoded with a second gather.
+    let dictionary_u64 = (0..dictionary.len() as u64).collect::<Vec<_>>();
+    bench_impl(c, "filter_dict_decoded_u64_simd", row_ids, || {
+        let dst = filter_dictionary::filter_materialise_decoded_u64_simd(
+            keys,
+            &dictionary_u64,
+            row_ids.as_slice(),
+            vec![],
+        )
+        .unwrap();
+        assert_eq!(dst.len(), row_ids.len());
+    });
+}
+
+fn filter_materialise_decoded_arrow(
+    c: &mut Criterion,
+    keys: &[u32],
+    dictionary: &[&str],
+    row_ids: &FilterType,
+) {
+    let (col_arr, row_ids_arr) = arrow_inputs(keys, dictionary, row_ids);
+    bench_impl(c, "filter_dict_decoded_arrow", row_ids, || {
+        let dst = filter_dictionary::filter_materialise_decoded_arrow(&col_arr, &row_ids_arr);
+        assert_eq!(dst.len(), row_ids.len());
+    });
+}
+
+criterion_group!(benches, bench_filter_dictionary);
+criterion_main!(benches);
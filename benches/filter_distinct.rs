@@ -0,0 +1,91 @@
+mod common;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::{distributions, Rng};
+
+use common::{bench_impl, loaded_filters, random_filters, FilterType};
+use rust_arrow_benches::{
+    dataset,
+    filter_distinct::{self, HyperLogLog, DEFAULT_PRECISION},
+};
+
+const ROWS: usize = 1_000_003; // ~1 million values in the column for now. (3 encourages non-chunking edge cases)
+
+fn bench_filter_distinct(c: &mut Criterion) {
+    let mut rng = rand::thread_rng();
+
+    // load a column and filters from a dataset file if one is given, otherwise
+    // generate columns with a low, medium and high number of distinct values.
+    let (cols, filter_types) = match dataset::from_env() {
+        Some(data) => (
+            vec![("loaded".to_owned(), data.values)],
+            loaded_filters(data.filters),
+        ),
+        None => {
+            let cols = [100, 10_000, 1_000_000]
+                .iter()
+                .map(|&cardinality| {
+                    let col = (&mut rng)
+                        .sample_iter(distributions::Uniform::from(0..cardinality))
+                        .take(ROWS)
+                        .collect::<Vec<u64>>();
+                    (cardinality.to_string(), col)
+                })
+                .collect::<Vec<_>>();
+
+            // initialise different filters on the columns (create a set of row_ids to apply to col)
+            (cols, random_filters(&mut rng, ROWS))
+        }
+    };
+
+    for (cardinality, col) in &cols {
+        for filter_type in &filter_types {
+            filter_count_distinct(c, cardinality, col, filter_type);
+            filter_hyperloglog(c, cardinality, col, filter_type);
+        }
+    }
+}
+
+fn filter_count_distinct(c: &mut Criterion, cardinality: &str, col: &[u64], row_ids: &FilterType) {
+    // for assertion
+    let count = filter_distinct::filter_count_distinct(col, row_ids.as_slice());
+
+    let name = format!("filter_count_distinct_{}_rust_idiomatic", cardinality);
+    bench_impl(c, &name, row_ids, || {
+        let result = filter_distinct::filter_count_distinct(col, row_ids.as_slice());
+        assert_eq!(result, count);
+    });
+
+    let name = format!("filter_count_distinct_{}_simd", cardinality);
+    bench_impl(c, &name, row_ids, || {
+        let result = filter_distinct::filter_count_distinct_simd(col, row_ids.as_slice()).unwrap();
+        assert_eq!(result, count);
+    });
+}
+
+fn filter_hyperloglog(c: &mut Criterion, cardinality: &str, col: &[u64], row_ids: &FilterType) {
+    // for assertion: the estimate is within four standard errors (a loaded
+    // filter can select nothing, which estimates exactly).
+    let count = filter_distinct::filter_count_distinct(col, row_ids.as_slice()) as f64;
+    let mut exp = HyperLogLog::new(DEFAULT_PRECISION);
+    filter_distinct::filter_hyperloglog(col, row_ids.as_slice(), &mut exp);
+    let error = (exp.estimate() - count).abs() / count.max(1.0);
+    assert!(error < 4.0 * 1.04 / 128.0, "{} {}", exp.estimate(), count);
+
+    let name = format!("filter_hyperloglog_{}_rust_idiomatic", cardinality);
+    bench_impl(c, &name, row_ids, || {
+        let mut hll = HyperLogLog::new(DEFAULT_PRECISION);
+        filter_distinct::filter_hyperloglog(col, row_ids.as_slice(), &mut hll);
+        assert_eq!(hll.precision(), DEFAULT_PRECISION);
+    });
+
+    let name = format!("filter_hyperloglog_{}_simd", cardinality);
+    bench_impl(c, &name, row_ids, || {
+        let mut hll = HyperLogLog::new(DEFAULT_PRECISION);
+        filter_distinct::filter_hyperloglog_simd(col, row_ids.as_slice(), &mut hll).unwrap();
+        assert_eq!(hll.precision(), DEFAULT_PRECISION);
+    });
+}
+
+criterion_group!(benches, bench_filter_distinct);
+criterion_main!(benches);
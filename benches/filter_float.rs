@@ -0,0 +1,144 @@
+mod common;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::{distributions, Rng};
+
+use common::{bench_impl, random_filters, FilterType};
+use rust_arrow_benches::filter_float::{self, NanMode};
+
+const ROWS: usize = 1_000_003; // ~1 million values in the column for now. (3 encourages non-chunking edge cases)
+
+fn bench_filter_float(c: &mut Criterion) {
+    let mut rng = rand::thread_rng();
+
+    // initialise columns with random values.
+    let col = rng
+        .sample_iter(distributions::Uniform::from(0.0..100000.0))
+        .take(ROWS)
+        .collect::<Vec<f64>>();
+    let col_f32 = col.iter().map(|&v| v as f32).collect::<Vec<_>>();
+
+    // initialise different filters on the above column (create a set of row_ids to apply to col)
+    let filter_types = random_filters(&mut rng, ROWS);
+
+    for filter_type in &filter_types {
+        filter_sum_f64(c, &col, filter_type);
+        filter_max_f64(c, &col, filter_type);
+        filter_sum_f32(c, &col_f32, filter_type);
+        filter_max_f32(c, &col_f32, filter_type);
+    }
+}
+
+fn bool_filter(len: usize, row_ids: &FilterType) -> arrow::array::BooleanArray {
+    let mut filter = Vec::with_capacity(len);
+    filter.resize(len, false);
+    for &row_id in row_ids.as_slice().iter() {
+        filter[row_id as usize] = true;
+    }
+    arrow::array::BooleanArray::from(filter)
+}
+
+fn filter_sum_f64(c: &mut Criterion, col: &[f64], row_ids: &FilterType) {
+    // floating point sums depend on the order of addition, so only check that
+    // the implementations roughly agree.
+    let exp = filter_float::filter_sum_f64_kahan(col, row_ids.as_slice());
+    let check = |result: f64| assert!((result - exp).abs() / exp < 1e-9);
+
+    bench_impl(c, "filter_sum_f64_rust_idiomatic", row_ids, || {
+        check(filter_float::filter_sum_f64(col, row_ids.as_slice()))
+    });
+    bench_impl(c, "filter_sum_f64_rust_kahan", row_ids, || {
+        check(filter_float::filter_sum_f64_kahan(col, row_ids.as_slice()))
+    });
+
+    let col_arr = arrow::array::Float64Array::from(col.to_owned());
+    let row_ids_arr = bool_filter(col.len(), row_ids);
+    bench_impl(c, "filter_sum_f64_arrow", row_ids, || {
+        check(filter_float::filter_sum_f64_arrow(&col_arr, &row_ids_arr))
+    });
+
+    bench_impl(c, "filter_sum_f64_simd", row_ids, || {
+        check(filter_float::filter_sum_f64_simd(col, row_ids.as_slice()).unwrap())
+    });
+    bench_impl(c, "filter_sum_f64_simd_kahan", row_ids, || {
+        check(filter_float::filter_sum_f64_kahan_simd(col, row_ids.as_slice()).unwrap())
+    });
+}
+
+fn filter_max_f64(c: &mut Criterion, col: &[f64], row_ids: &FilterType) {
+    // for assertion
+    let max = filter_float::filter_max_f64(col, row_ids.as_slice(), NanMode::Ignore);
+
+    bench_impl(c, "filter_max_f64_rust_idiomatic", row_ids, || {
+        let result = filter_float::filter_max_f64(col, row_ids.as_slice(), NanMode::Ignore);
+        assert_eq!(result, max);
+    });
+
+    let col_arr = arrow::array::Float64Array::from(col.to_owned());
+    let row_ids_arr = bool_filter(col.len(), row_ids);
+    bench_impl(c, "filter_max_f64_arrow", row_ids, || {
+        let result = filter_float::filter_max_f64_arrow(&col_arr, &row_ids_arr);
+        assert_eq!(result, max);
+    });
+
+    bench_impl(c, "filter_max_f64_simd", row_ids, || {
+        let result =
+            filter_float::filter_max_f64_simd(col, row_ids.as_slice(), NanMode::Ignore).unwrap();
+        assert_eq!(result, max);
+    });
+    bench_impl(c, "filter_max_f64_simd_propagate", row_ids, || {
+        let result =
+            filter_float::filter_max_f64_simd(col, row_ids.as_slice(), NanMode::Propagate).unwrap();
+        assert_eq!(result, max);
+    });
+}
+
+fn filter_sum_f32(c: &mut Criterion, col: &[f32], row_ids: &FilterType) {
+    // f32 sums over this many rows are quite inaccurate without compensation,
+    // which is partly the point of benching the Kahan version.
+    let exp = filter_float::filter_sum_f32_kahan(col, row_ids.as_slice());
+    let check = |result: f32| assert!((result - exp).abs() / exp < 1e-2);
+
+    bench_impl(c, "filter_sum_f32_rust_idiomatic", row_ids, || {
+        check(filter_float::filter_sum_f32(col, row_ids.as_slice()))
+    });
+
+    let col_arr = arrow::array::Float32Array::from(col.to_owned());
+    let row_ids_arr = bool_filter(col.len(), row_ids);
+    bench_impl(c, "filter_sum_f32_arrow", row_ids, || {
+        check(filter_float::filter_sum_f32_arrow(&col_arr, &row_ids_arr))
+    });
+
+    bench_impl(c, "filter_sum_f32_simd", row_ids, || {
+        check(filter_float::filter_sum_f32_simd(col, row_ids.as_slice()).unwrap())
+    });
+    bench_impl(c, "filter_sum_f32_simd_kahan", row_ids, || {
+        check(filter_float::filter_sum_f32_kahan_simd(col, row_ids.as_slice()).unwrap())
+    });
+}
+
+fn filter_max_f32(c: &mut Criterion, col: &[f32], row_ids: &FilterType) {
+    // for assertion
+    let max = filter_float::filter_max_f32(col, row_ids.as_slice(), NanMode::Ignore);
+
+    bench_impl(c, "filter_max_f32_rust_idiomatic", row_ids, || {
+        let result = filter_float::filter_max_f32(col, row_ids.as_slice(), NanMode::Ignore);
+        assert_eq!(result, max);
+    });
+
+    let col_arr = arrow::array::Float32Array::from(col.to_owned());
+    let row_ids_arr = bool_filter(col.len(), row_ids);
+    bench_impl(c, "filter_max_f32_arrow", row_ids, || {
+        let result = filter_float::filter_max_f32_arrow(&col_arr, &row_ids_arr);
+        assert_eq!(result, max);
+    });
+
+    bench_impl(c, "filter_max_f32_simd", row_ids, || {
+        let result =
+            filter_float::filter_max_f32_simd(col, row_ids.as_slice(), NanMode::Ignore).unwrap();
+        assert_eq!(result, max);
+    });
+}
+
+criterion_group!(benches, bench_filter_float);
+criterion_main!(benches);
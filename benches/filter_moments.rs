@@ -0,0 +1,75 @@
+mod common;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::{distributions, Rng};
+
+use common::{bench_impl, loaded_filters, random_filters, FilterType};
+use rust_arrow_benches::{dataset, filter_moments, filter_sum};
+
+const ROWS: usize = 1_000_003; // ~1 million values in the column for now. (3 encourages non-chunking edge cases)
+
+fn bench_filter_moments(c: &mut Criterion) {
+    let mut rng = rand::thread_rng();
+
+    // load a column and filters from a dataset file if one is given, otherwise
+    // generate them. A loaded column's sums of squares must fit in a `u128`
+    // (any values below `2^48` do), and values of `2^32` or more fall back to
+    // the idiomatic implementation.
+    let (col, filter_types) = match dataset::from_env() {
+        Some(data) => (data.values, loaded_filters(data.filters)),
+        None => {
+            // initialise a column of metric values, small enough that the sums
+            // of squares can be computed exactly in SIMD.
+            let col = rng
+                .sample_iter(distributions::Uniform::from(0..1_000_000))
+                .take(ROWS)
+                .collect::<Vec<u64>>();
+
+            // initialise different filters on the above column (create a set of row_ids to apply to col)
+            let filter_types = random_filters(&mut rng, ROWS);
+            (col, filter_types)
+        }
+    };
+
+    for filter_type in &filter_types {
+        filter_moments(c, &col, filter_type);
+    }
+}
+
+fn filter_moments(c: &mut Criterion, col: &[u64], row_ids: &FilterType) {
+    // for assertion
+    let exp = filter_moments::filter_moments(col, row_ids.as_slice()).unwrap();
+
+    bench_impl(c, "filter_moments_rust_idiomatic", row_ids, || {
+        let result = filter_moments::filter_moments(col, row_ids.as_slice());
+        assert_eq!(result, Some(exp));
+    });
+
+    bench_impl(c, "filter_moments_simd", row_ids, || {
+        let result = filter_moments::filter_moments_simd(col, row_ids.as_slice()).unwrap();
+        assert_eq!(result, Some(exp));
+    });
+
+    bench_impl(c, "filter_moments_welford_rust_idiomatic", row_ids, || {
+        let result = filter_moments::filter_welford(col, row_ids.as_slice());
+        assert_eq!(result.count, exp.count);
+    });
+
+    // `filter_sum` then a second pass for the sum of squares.
+    bench_impl(c, "filter_moments_two_pass_rust_idiomatic", row_ids, || {
+        let sum = filter_sum::filter_sum(col, row_ids.as_slice());
+        let sum_squares = filter_moments::filter_sum_squares(col, row_ids.as_slice()).unwrap();
+        assert_eq!((sum as u128, sum_squares), (exp.sum, exp.sum_squares));
+    });
+
+    bench_impl(c, "filter_moments_two_pass_simd", row_ids, || {
+        let sum = filter_sum::filter_sum_simd(col, row_ids.as_slice()).unwrap();
+        let sum_squares = filter_moments::filter_sum_squares_simd(col, row_ids.as_slice())
+            .unwrap()
+            .unwrap();
+        assert_eq!((sum as u128, sum_squares), (exp.sum, exp.sum_squares));
+    });
+}
+
+criterion_group!(benches, bench_filter_moments);
+criterion_main!(benches);
@@ -0,0 +1,154 @@
+mod common;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::{distributions, Rng};
+
+use common::{bench_impl, loaded_filters, random_filters, FilterType};
+use rust_arrow_benches::{
+    dataset, filter, filter_max, filter_narrow, filter_sum, narrow_gather::NarrowValue,
+};
+
+const ROWS: usize = 1_000_003; // ~1 million values in the column for now. (3 encourages non-chunking edge cases)
+
+fn bench_filter_narrow(c: &mut Criterion) {
+    let mut rng = rand::thread_rng();
+
+    // load a column and filters from a dataset file if one is given, otherwise
+    // generate them. Every width must hold the same logical data, so a loaded
+    // column's values are folded into the `u8` range.
+    let (col, filter_types) = match dataset::from_env() {
+        Some(data) => {
+            let col = data.values.iter().map(|&v| v as u8 as u64).collect();
+            (col, loaded_filters(data.filters))
+        }
+        None => {
+            // initialise a column with random values small enough for every
+            // width.
+            let col = rng
+                .sample_iter(distributions::Uniform::from(0..256))
+                .take(ROWS)
+                .collect::<Vec<u64>>();
+
+            // initialise different filters on the above column (create a set of row_ids to apply to col)
+            let filter_types = random_filters(&mut rng, ROWS);
+            (col, filter_types)
+        }
+    };
+    let col_u8 = col.iter().map(|&v| v as u8).collect::<Vec<_>>();
+    let col_u16 = col.iter().map(|&v| v as u16).collect::<Vec<_>>();
+    let col_u32 = col.iter().map(|&v| v as u32).collect::<Vec<_>>();
+
+    for filter_type in &filter_types {
+        // there's no max of no rows, and a loaded filter can select nothing.
+        if filter_type.len() == 0 {
+            continue;
+        }
+        filter_materialise_u64(c, &col, filter_type);
+        filter_materialise_narrow(c, "u8", &col_u8, filter_type);
+        filter_materialise_narrow(c, "u16", &col_u16, filter_type);
+        filter_materialise_narrow(c, "u32", &col_u32, filter_type);
+
+        let sum = filter_sum::filter_sum(&col, filter_type.as_slice());
+        bench_impl(c, "filter_sum_u64_simd", filter_type, || {
+            let result = filter_sum::filter_sum_simd(&col, filter_type.as_slice()).unwrap();
+            assert_eq!(result, sum);
+        });
+        filter_sum_narrow(c, "u8", &col_u8, filter_type, sum);
+        filter_sum_narrow(c, "u16", &col_u16, filter_type, sum);
+        filter_sum_narrow(c, "u32", &col_u32, filter_type, sum);
+
+        let max = filter_max::filter_max(&col, filter_type.as_slice());
+        bench_impl(c, "filter_max_u64_simd", filter_type, || {
+            let result = filter_max::filter_max_simd(&col, filter_type.as_slice()).unwrap();
+            assert_eq!(result, max);
+        });
+        filter_max_narrow(c, "u8", &col_u8, filter_type, max);
+        filter_max_narrow(c, "u16", &col_u16, filter_type, max);
+        filter_max_narrow(c, "u32", &col_u32, filter_type, max);
+    }
+}
+
+fn filter_materialise_u64(c: &mut Criterion, col: &[u64], row_ids: &FilterType) {
+    // TODO(edd): like the other materialise benchmarks these don't re-use the
+    // `dst` buffer.
+    bench_impl(c, "filter_materialise_u64_simd", row_ids, || {
+        let dst = filter::filter_materialise_values_simd(col, row_ids.as_slice(), vec![]).unwrap();
+        assert_eq!(dst.len(), row_ids.len());
+    });
+}
+
+fn filter_materialise_narrow<K: NarrowValue>(
+    c: &mut Criterion,
+    width: &str,
+    col: &[K],
+    row_ids: &FilterType,
+) {
+    bench_impl(
+        c,
+        &format!("filter_materialise_{}_rust_idiomatic", width),
+        row_ids,
+        || {
+            let dst = filter_narrow::filter_materialise_values(col, row_ids.as_slice(), vec![]);
+            assert_eq!(dst.len(), row_ids.len());
+        },
+    );
+    bench_impl(
+        c,
+        &format!("filter_materialise_{}_simd", width),
+        row_ids,
+        || {
+            let dst =
+                filter_narrow::filter_materialise_values_simd(col, row_ids.as_slice(), vec![])
+                    .unwrap();
+            assert_eq!(dst.len(), row_ids.len());
+        },
+    );
+}
+
+fn filter_sum_narrow<K: NarrowValue>(
+    c: &mut Criterion,
+    width: &str,
+    col: &[K],
+    row_ids: &FilterType,
+    sum: u64,
+) {
+    bench_impl(
+        c,
+        &format!("filter_sum_{}_rust_idiomatic", width),
+        row_ids,
+        || {
+            assert_eq!(filter_narrow::filter_sum(col, row_ids.as_slice()), sum);
+        },
+    );
+    bench_impl(c, &format!("filter_sum_{}_simd", width), row_ids, || {
+        assert_eq!(
+            filter_narrow::filter_sum_simd(col, row_ids.as_slice()).unwrap(),
+            sum
+        );
+    });
+}
+
+fn filter_max_narrow<K: NarrowValue + Ord>(
+    c: &mut Criterion,
+    width: &str,
+    col: &[K],
+    row_ids: &FilterType,
+    max: u64,
+) {
+    bench_impl(
+        c,
+        &format!("filter_max_{}_rust_idiomatic", width),
+        row_ids,
+        || {
+            let result = filter_narrow::filter_max(col, row_ids.as_slice());
+            assert_eq!(result.as_usize() as u64, max);
+        },
+    );
+    bench_impl(c, &format!("filter_max_{}_simd", width), row_ids, || {
+        let result = filter_narrow::filter_max_simd(col, row_ids.as_slice()).unwrap();
+        assert_eq!(result.as_usize() as u64, max);
+    });
+}
+
+criterion_group!(benches, bench_filter_narrow);
+criterion_main!(benches);
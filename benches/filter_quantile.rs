@@ -0,0 +1,119 @@
+mod common;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::Rng;
+
+use common::{bench_impl, loaded_filters, random_filters, FilterType};
+use rust_arrow_benches::{
+    dataset,
+    filter_quantile::{self, KllSketch},
+};
+
+const ROWS: usize = 1_000_003; // ~1 million values in the column for now. (3 encourages non-chunking edge cases)
+
+const QUANTILES: [f64; 3] = [0.5, 0.95, 0.99];
+
+fn bench_filter_quantile(c: &mut Criterion) {
+    let mut rng = rand::thread_rng();
+
+    // load a column and filters from a dataset file if one is given, otherwise
+    // generate them.
+    let (col, filter_types) = match dataset::from_env() {
+        Some(data) => (data.values, loaded_filters(data.filters)),
+        None => {
+            // initialise a column of latency-like values: mostly small, with a
+            // long tail.
+            let col = (0..ROWS)
+                .map(|_| (rng.gen::<f64>().powi(4) * 1e9) as u64)
+                .collect::<Vec<u64>>();
+
+            // initialise different filters on the above column (create a set of row_ids to apply to col)
+            let filter_types = random_filters(&mut rng, ROWS);
+            (col, filter_types)
+        }
+    };
+
+    for filter_type in &filter_types {
+        // there are no quantiles of no rows, and a loaded filter can select
+        // nothing.
+        if filter_type.len() == 0 {
+            continue;
+        }
+        filter_quantiles_exact(c, &col, filter_type);
+        for &k in &[64, 256] {
+            filter_quantiles_kll(c, k, &col, filter_type);
+        }
+    }
+}
+
+fn filter_quantiles_exact(c: &mut Criterion, col: &[u64], row_ids: &FilterType) {
+    // for assertion
+    let (exp, _) =
+        filter_quantile::filter_quantiles_sort(col, row_ids.as_slice(), &QUANTILES, vec![]);
+
+    let mut dst = Vec::with_capacity(row_ids.len());
+    bench_impl(c, "filter_quantiles_sort_rust_idiomatic", row_ids, || {
+        let (result, buf) = filter_quantile::filter_quantiles_sort(
+            col,
+            row_ids.as_slice(),
+            &QUANTILES,
+            std::mem::take(&mut dst),
+        );
+        dst = buf;
+        assert_eq!(result, exp);
+    });
+
+    let mut dst = Vec::with_capacity(row_ids.len());
+    bench_impl(c, "filter_quantiles_select_simd", row_ids, || {
+        let (result, buf) = filter_quantile::filter_quantiles_select(
+            col,
+            row_ids.as_slice(),
+            &QUANTILES,
+            std::mem::take(&mut dst),
+        )
+        .unwrap();
+        dst = buf;
+        assert_eq!(result, exp);
+    });
+}
+
+fn filter_quantiles_kll(c: &mut Criterion, k: usize, col: &[u64], row_ids: &FilterType) {
+    // report how far the rank of each estimated quantile is from the true
+    // rank, and how many values the sketch holds.
+    let (_, sorted) = filter_quantile::filter_quantiles_sort(col, row_ids.as_slice(), &[], vec![]);
+    let mut sketch = KllSketch::new(k);
+    filter_quantile::filter_kll(col, row_ids.as_slice(), &mut sketch);
+    let errors = QUANTILES
+        .iter()
+        .zip(sketch.quantiles(&QUANTILES).unwrap())
+        .map(|(&q, got)| {
+            let rank = sorted.partition_point(|&v| v <= got) as f64 / sorted.len() as f64;
+            format!("p{} {:.2}%", q * 100.0, (rank - q).abs() * 100.0)
+        })
+        .collect::<Vec<_>>();
+    println!(
+        "kll k={} {}: rank error {}, {} of {} values retained",
+        k,
+        row_ids,
+        errors.join(", "),
+        sketch.retained(),
+        sorted.len()
+    );
+
+    let name = format!("filter_quantiles_kll_{}_rust_idiomatic", k);
+    bench_impl(c, &name, row_ids, || {
+        let mut sketch = KllSketch::new(k);
+        filter_quantile::filter_kll(col, row_ids.as_slice(), &mut sketch);
+        assert_eq!(sketch.len(), row_ids.len() as u64);
+    });
+
+    let name = format!("filter_quantiles_kll_{}_simd", k);
+    bench_impl(c, &name, row_ids, || {
+        let mut sketch = KllSketch::new(k);
+        filter_quantile::filter_kll_simd(col, row_ids.as_slice(), &mut sketch).unwrap();
+        assert_eq!(sketch.len(), row_ids.len() as u64);
+    });
+}
+
+criterion_group!(benches, bench_filter_quantile);
+criterion_main!(benches);
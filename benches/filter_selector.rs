@@ -0,0 +1,154 @@
+mod common;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rand::{distributions, Rng};
+
+use common::{loaded_filters, random_filters, FilterType};
+use rust_arrow_benches::{dataset, filter_selector};
+
+const ROWS: usize = 1_000_003; // ~1 million values in the column for now. (3 encourages non-chunking edge cases)
+
+type Selector = fn(&[u64], &[u32]) -> (u32, u64);
+type ArrowSelector = fn(
+    &arrow::array::UInt64Array,
+    &arrow::array::UInt32Array,
+    &arrow::array::BooleanArray,
+) -> (u32, u64);
+
+fn bench_filter_selector(c: &mut Criterion) {
+    let mut rng = rand::thread_rng();
+
+    // load a column and filters from a dataset file if one is given, otherwise
+    // generate them.
+    let (col, filter_types) = match dataset::from_env() {
+        Some(data) => (data.values, loaded_filters(data.filters)),
+        None => {
+            // initialise column with random values.
+            let col = rng
+                .sample_iter(distributions::Uniform::from(0..100000))
+                .take(ROWS)
+                .collect::<Vec<_>>();
+
+            // initialise different filters on the above column (create a set of row_ids to apply to col)
+            let filter_types = random_filters(&mut rng, ROWS);
+            (col, filter_types)
+        }
+    };
+
+    let selectors: Vec<(&str, Selector, ArrowSelector, Selector)> = vec![
+        (
+            "filter_arg_max",
+            filter_selector::filter_arg_max,
+            filter_selector::filter_arg_max_arrow,
+            |values, row_ids| filter_selector::filter_arg_max_simd(values, row_ids).unwrap(),
+        ),
+        (
+            "filter_arg_min",
+            filter_selector::filter_arg_min,
+            filter_selector::filter_arg_min_arrow,
+            |values, row_ids| filter_selector::filter_arg_min_simd(values, row_ids).unwrap(),
+        ),
+        (
+            "filter_first",
+            filter_selector::filter_first,
+            filter_selector::filter_first_arrow,
+            filter_selector::filter_first_simd,
+        ),
+        (
+            "filter_last",
+            filter_selector::filter_last,
+            filter_selector::filter_last_arrow,
+            filter_selector::filter_last_simd,
+        ),
+    ];
+
+    for (name, idiomatic, arrow, simd) in &selectors {
+        for filter_type in &filter_types {
+            // nothing can be selected from no rows, and a loaded filter can
+            // select nothing.
+            if filter_type.len() == 0 {
+                continue;
+            }
+            filter_selector_rust_idiomatic(c, name, *idiomatic, &col, filter_type);
+            filter_selector_arrow(c, name, *idiomatic, *arrow, &col, filter_type);
+            filter_selector_simd(c, name, *idiomatic, *simd, &col, filter_type);
+        }
+    }
+}
+
+fn filter_selector_rust_idiomatic(
+    c: &mut Criterion,
+    name: &str,
+    selector: Selector,
+    col: &[u64],
+    row_ids: &FilterType,
+) {
+    let mut group = c.benchmark_group(format!("{}_rust_idiomatic", name));
+
+    // selected values can legitimately be zero, so compare against a result
+    // computed up front instead.
+    let exp = selector(col, row_ids.as_slice());
+    group.throughput(Throughput::Elements(row_ids.len() as u64));
+    group.bench_function(BenchmarkId::from_parameter(format!("{}", row_ids)), |b| {
+        b.iter(|| {
+            let result = selector(col, row_ids.as_slice());
+            assert_eq!(result, exp); // ensure bench doesn't get optimised away
+        });
+    });
+}
+
+fn filter_selector_arrow(
+    c: &mut Criterion,
+    name: &str,
+    oracle: Selector,
+    selector: ArrowSelector,
+    col: &[u64],
+    row_ids: &FilterType,
+) {
+    let mut group = c.benchmark_group(format!("{}_arrow", name));
+
+    // for assertion
+    let exp = oracle(col, row_ids.as_slice());
+
+    group.throughput(Throughput::Elements(row_ids.len() as u64));
+
+    let col_arr = arrow::array::UInt64Array::from(col.to_owned());
+    let row_id_arr = arrow::array::UInt32Array::from((0..col.len() as u32).collect::<Vec<_>>());
+    let mut filter = Vec::with_capacity(col_arr.len());
+    filter.resize(col_arr.len(), false);
+    for &row_id in row_ids.as_slice().iter() {
+        filter[row_id as usize] = true;
+    }
+    let row_ids_arr = arrow::array::BooleanArray::from(filter);
+
+    group.bench_function(BenchmarkId::from_parameter(format!("{}", row_ids)), |b| {
+        b.iter(|| {
+            let result = selector(&col_arr, &row_id_arr, &row_ids_arr);
+            assert_eq!(result, exp); // ensure bench not optimised away
+        });
+    });
+}
+
+fn filter_selector_simd(
+    c: &mut Criterion,
+    name: &str,
+    oracle: Selector,
+    selector: Selector,
+    col: &[u64],
+    row_ids: &FilterType,
+) {
+    let mut group = c.benchmark_group(format!("{}_simd", name));
+
+    // for assertion
+    let exp = oracle(col, row_ids.as_slice());
+    group.throughput(Throughput::Elements(row_ids.len() as u64));
+    group.bench_function(BenchmarkId::from_parameter(format!("{}", row_ids)), |b| {
+        b.iter(|| {
+            let result = selector(col, row_ids.as_slice());
+            assert_eq!(result, exp);
+        });
+    });
+}
+
+criterion_group!(benches, bench_filter_selector);
+criterion_main!(benches);
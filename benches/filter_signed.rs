@@ -0,0 +1,184 @@
+mod common;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::{distributions, Rng};
+
+use common::{bench_impl, loaded_filters, random_filters, FilterType};
+use rust_arrow_benches::{dataset, filter_max, filter_signed, filter_sum};
+
+const ROWS: usize = 1_000_003; // ~1 million values in the column for now. (3 encourages non-chunking edge cases)
+
+fn bench_filter_signed(c: &mut Criterion) {
+    let mut rng = rand::thread_rng();
+
+    // load a column and filters from a dataset file if one is given, otherwise
+    // generate them. Every width is checked against the same sum, so a loaded
+    // column's values are folded into the generated range.
+    let (col, filter_types) = match dataset::from_env() {
+        Some(data) => {
+            let col = data
+                .values
+                .iter()
+                .map(|&v| (v % 200000) as i64 - 100000)
+                .collect::<Vec<_>>();
+            (col, loaded_filters(data.filters))
+        }
+        None => {
+            // initialise columns with random values either side of zero.
+            let col = rng
+                .sample_iter(distributions::Uniform::from(-100000..100000))
+                .take(ROWS)
+                .collect::<Vec<i64>>();
+
+            // initialise different filters on the above column (create a set of row_ids to apply to col)
+            let filter_types = random_filters(&mut rng, ROWS);
+            (col, filter_types)
+        }
+    };
+    let col_i32 = col.iter().map(|&v| v as i32).collect::<Vec<_>>();
+
+    // the same values shifted to be positive, for the u64 kernels.
+    let col_u64 = col.iter().map(|&v| (v + 100000) as u64).collect::<Vec<_>>();
+
+    for filter_type in &filter_types {
+        // there's no max or min of no rows, and a loaded filter can select
+        // nothing.
+        if filter_type.len() == 0 {
+            continue;
+        }
+        filter_sum_signed(c, &col, &col_i32, &col_u64, filter_type);
+        filter_max_signed(c, &col, &col_i32, &col_u64, filter_type);
+        filter_min_signed(c, &col, &col_i32, filter_type);
+    }
+}
+
+fn bool_filter(len: usize, row_ids: &FilterType) -> arrow::array::BooleanArray {
+    let mut filter = Vec::with_capacity(len);
+    filter.resize(len, false);
+    for &row_id in row_ids.as_slice().iter() {
+        filter[row_id as usize] = true;
+    }
+    arrow::array::BooleanArray::from(filter)
+}
+
+fn filter_sum_signed(
+    c: &mut Criterion,
+    col: &[i64],
+    col_i32: &[i32],
+    col_u64: &[u64],
+    row_ids: &FilterType,
+) {
+    // for assertion
+    let sum = filter_signed::filter_sum_i64(col, row_ids.as_slice()).unwrap();
+    let sum_u64 = filter_sum::filter_sum(col_u64, row_ids.as_slice());
+
+    // the u64 kernel for comparison.
+    bench_impl(c, "filter_sum_u64_simd", row_ids, || {
+        let result = filter_sum::filter_sum_simd(col_u64, row_ids.as_slice()).unwrap();
+        assert_eq!(result, sum_u64);
+    });
+
+    bench_impl(c, "filter_sum_i64_rust_idiomatic", row_ids, || {
+        let result = filter_signed::filter_sum_i64(col, row_ids.as_slice());
+        assert_eq!(result, Some(sum));
+    });
+
+    let col_arr = arrow::array::Int64Array::from(col.to_owned());
+    let row_ids_arr = bool_filter(col.len(), row_ids);
+    bench_impl(c, "filter_sum_i64_arrow", row_ids, || {
+        let result = filter_signed::filter_sum_i64_arrow(&col_arr, &row_ids_arr);
+        assert_eq!(result, sum);
+    });
+
+    bench_impl(c, "filter_sum_i64_simd", row_ids, || {
+        let result = filter_signed::filter_sum_i64_simd(col, row_ids.as_slice()).unwrap();
+        assert_eq!(result, Some(sum));
+    });
+
+    bench_impl(c, "filter_sum_i32_rust_idiomatic", row_ids, || {
+        let result = filter_signed::filter_sum_i32(col_i32, row_ids.as_slice());
+        assert_eq!(result, sum);
+    });
+    bench_impl(c, "filter_sum_i32_simd", row_ids, || {
+        let result = filter_signed::filter_sum_i32_simd(col_i32, row_ids.as_slice()).unwrap();
+        assert_eq!(result, sum);
+    });
+}
+
+fn filter_max_signed(
+    c: &mut Criterion,
+    col: &[i64],
+    col_i32: &[i32],
+    col_u64: &[u64],
+    row_ids: &FilterType,
+) {
+    // for assertion
+    let max = filter_signed::filter_max_i64(col, row_ids.as_slice());
+    let max_u64 = filter_max::filter_max(col_u64, row_ids.as_slice());
+
+    // the u64 kernel for comparison.
+    bench_impl(c, "filter_max_u64_simd", row_ids, || {
+        let result = filter_max::filter_max_simd(col_u64, row_ids.as_slice()).unwrap();
+        assert_eq!(result, max_u64);
+    });
+
+    bench_impl(c, "filter_max_i64_rust_idiomatic", row_ids, || {
+        let result = filter_signed::filter_max_i64(col, row_ids.as_slice());
+        assert_eq!(result, max);
+    });
+
+    let col_arr = arrow::array::Int64Array::from(col.to_owned());
+    let row_ids_arr = bool_filter(col.len(), row_ids);
+    bench_impl(c, "filter_max_i64_arrow", row_ids, || {
+        let result = filter_signed::filter_max_i64_arrow(&col_arr, &row_ids_arr);
+        assert_eq!(result, max);
+    });
+
+    bench_impl(c, "filter_max_i64_simd", row_ids, || {
+        let result = filter_signed::filter_max_i64_simd(col, row_ids.as_slice()).unwrap();
+        assert_eq!(result, max);
+    });
+
+    bench_impl(c, "filter_max_i32_rust_idiomatic", row_ids, || {
+        let result = filter_signed::filter_max_i32(col_i32, row_ids.as_slice());
+        assert_eq!(result as i64, max);
+    });
+    bench_impl(c, "filter_max_i32_simd", row_ids, || {
+        let result = filter_signed::filter_max_i32_simd(col_i32, row_ids.as_slice()).unwrap();
+        assert_eq!(result as i64, max);
+    });
+}
+
+fn filter_min_signed(c: &mut Criterion, col: &[i64], col_i32: &[i32], row_ids: &FilterType) {
+    // for assertion
+    let min = filter_signed::filter_min_i64(col, row_ids.as_slice());
+
+    bench_impl(c, "filter_min_i64_rust_idiomatic", row_ids, || {
+        let result = filter_signed::filter_min_i64(col, row_ids.as_slice());
+        assert_eq!(result, min);
+    });
+
+    let col_arr = arrow::array::Int64Array::from(col.to_owned());
+    let row_ids_arr = bool_filter(col.len(), row_ids);
+    bench_impl(c, "filter_min_i64_arrow", row_ids, || {
+        let result = filter_signed::filter_min_i64_arrow(&col_arr, &row_ids_arr);
+        assert_eq!(result, min);
+    });
+
+    bench_impl(c, "filter_min_i64_simd", row_ids, || {
+        let result = filter_signed::filter_min_i64_simd(col, row_ids.as_slice()).unwrap();
+        assert_eq!(result, min);
+    });
+
+    bench_impl(c, "filter_min_i32_rust_idiomatic", row_ids, || {
+        let result = filter_signed::filter_min_i32(col_i32, row_ids.as_slice());
+        assert_eq!(result as i64, min);
+    });
+    bench_impl(c, "filter_min_i32_simd", row_ids, || {
+        let result = filter_signed::filter_min_i32_simd(col_i32, row_ids.as_slice()).unwrap();
+        assert_eq!(result as i64, min);
+    });
+}
+
+criterion_group!(benches, bench_filter_signed);
+criterion_main!(benches);
@@ -0,0 +1,155 @@
+mod common;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::{distributions, Rng};
+
+use common::{bench_impl, loaded_filters, random_filters, FilterType};
+use rust_arrow_benches::{
+    dataset,
+    filter_time_bucket::{self, TimeBuckets},
+};
+
+const ROWS: usize = 1_000_003; // ~1 million values in the column for now. (3 encourages non-chunking edge cases)
+
+fn bench_filter_time_bucket(c: &mut Criterion) {
+    let mut rng = rand::thread_rng();
+
+    // load a column of values and filters from a dataset file if one is given,
+    // otherwise generate them.
+    let (values, filter_types) = match dataset::from_env() {
+        Some(data) => (data.values, loaded_filters(data.filters)),
+        None => {
+            // initialise a column of random values.
+            let values = rng
+                .sample_iter(distributions::Uniform::from(0..10000))
+                .take(ROWS)
+                .collect::<Vec<u64>>();
+
+            // initialise different filters on the above column (create a set of row_ids to apply to col)
+            let filter_types = random_filters(&mut rng, ROWS);
+            (values, filter_types)
+        }
+    };
+
+    // initialise a column of nanosecond timestamps over a day, in order, with
+    // one timestamp per value.
+    let rows = values.len();
+    let start = 1_600_000_000_000_000_000_i64;
+    let step = 86_400_000_000_000 / rows as i64;
+    let timestamps = (0..rows as i64)
+        .map(|i| start + i * step + rng.gen_range(0, step))
+        .collect::<Vec<i64>>();
+
+    // one-second and one-minute buckets.
+    for &(name, width) in &[("second", 1_000_000_000), ("minute", 60_000_000_000)] {
+        let buckets = TimeBuckets::new(0, width);
+        for filter_type in &filter_types {
+            filter_bucket_indexes(c, name, &timestamps, buckets, filter_type);
+            filter_aggregate_buckets(c, name, &timestamps, &values, buckets, filter_type);
+        }
+    }
+}
+
+fn bool_filter(len: usize, row_ids: &FilterType) -> arrow::array::BooleanArray {
+    let mut filter = Vec::with_capacity(len);
+    filter.resize(len, false);
+    for &row_id in row_ids.as_slice().iter() {
+        filter[row_id as usize] = true;
+    }
+    arrow::array::BooleanArray::from(filter)
+}
+
+fn filter_bucket_indexes(
+    c: &mut Criterion,
+    width_name: &str,
+    timestamps: &[i64],
+    buckets: TimeBuckets<i64>,
+    row_ids: &FilterType,
+) {
+    // for assertion
+    let exp =
+        filter_time_bucket::filter_bucket_indexes(timestamps, row_ids.as_slice(), buckets, vec![]);
+
+    let name = format!("filter_bucket_indexes_{}_rust_idiomatic", width_name);
+    let mut dst = Vec::with_capacity(row_ids.len());
+    bench_impl(c, &name, row_ids, || {
+        dst = filter_time_bucket::filter_bucket_indexes(
+            timestamps,
+            row_ids.as_slice(),
+            buckets,
+            std::mem::take(&mut dst),
+        );
+        assert_eq!(dst.len(), exp.len());
+    });
+
+    let name = format!("filter_bucket_indexes_{}_simd", width_name);
+    let mut dst = Vec::with_capacity(row_ids.len());
+    bench_impl(c, &name, row_ids, || {
+        dst = filter_time_bucket::filter_bucket_indexes_simd(
+            timestamps,
+            row_ids.as_slice(),
+            buckets,
+            std::mem::take(&mut dst),
+        )
+        .unwrap();
+        assert_eq!(dst.len(), exp.len());
+    });
+    assert_eq!(dst, exp);
+}
+
+fn filter_aggregate_buckets(
+    c: &mut Criterion,
+    width_name: &str,
+    timestamps: &[i64],
+    values: &[u64],
+    buckets: TimeBuckets<i64>,
+    row_ids: &FilterType,
+) {
+    // for assertion
+    let exp = filter_time_bucket::filter_aggregate_buckets(
+        timestamps,
+        values,
+        row_ids.as_slice(),
+        buckets,
+    );
+
+    let name = format!("filter_aggregate_buckets_{}_rust_idiomatic", width_name);
+    bench_impl(c, &name, row_ids, || {
+        let result = filter_time_bucket::filter_aggregate_buckets(
+            timestamps,
+            values,
+            row_ids.as_slice(),
+            buckets,
+        );
+        assert_eq!(result.len(), exp.len());
+    });
+
+    let timestamps_arr = arrow::array::TimestampNanosecondArray::from(timestamps.to_owned());
+    let values_arr = arrow::array::UInt64Array::from(values.to_owned());
+    let row_ids_arr = bool_filter(timestamps.len(), row_ids);
+    let name = format!("filter_aggregate_buckets_{}_arrow", width_name);
+    bench_impl(c, &name, row_ids, || {
+        let result = filter_time_bucket::filter_aggregate_buckets_arrow(
+            &timestamps_arr,
+            &values_arr,
+            &row_ids_arr,
+            buckets,
+        );
+        assert_eq!(result.len(), exp.len());
+    });
+
+    let name = format!("filter_aggregate_buckets_{}_simd", width_name);
+    bench_impl(c, &name, row_ids, || {
+        let result = filter_time_bucket::filter_aggregate_buckets_simd(
+            timestamps,
+            values,
+            row_ids.as_slice(),
+            buckets,
+        )
+        .unwrap();
+        assert_eq!(result.len(), exp.len());
+    });
+}
+
+criterion_group!(benches, bench_filter_time_bucket);
+criterion_main!(benches);
@@ -0,0 +1,163 @@
+mod common;
+
+use std::fmt;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rand::{distributions, rngs::ThreadRng, Rng};
+
+use common::{random_filters, FilterType};
+use rust_arrow_benches::filter_var_len;
+
+const ROWS: usize = 1_000_003; // ~1 million values in the column for now. (3 encourages non-chunking edge cases)
+
+// The distribution of string lengths in a column.
+enum LengthDist {
+    // every string is the same length.
+    Fixed(usize),
+
+    // string lengths are uniformly distributed in `[0, max)`.
+    Uniform(usize),
+
+    // most strings are `short` bytes long, but 1% of them are `long` bytes long.
+    Skewed(usize, usize),
+}
+
+impl LengthDist {
+    fn sample(&self, rng: &mut ThreadRng) -> usize {
+        match self {
+            LengthDist::Fixed(len) => *len,
+            LengthDist::Uniform(max) => rng.gen_range(0, max),
+            LengthDist::Skewed(short, long) => {
+                if rng.gen_range(0, 100) == 0 {
+                    *long
+                } else {
+                    *short
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for LengthDist {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LengthDist::Fixed(len) => write!(f, "len_fixed_{}", len),
+            LengthDist::Uniform(max) => write!(f, "len_uniform_0_{}", max),
+            LengthDist::Skewed(short, long) => write!(f, "len_skewed_{}_{}", short, long),
+        }
+    }
+}
+
+// Create a column of random lowercase strings with lengths drawn from `dist`.
+fn random_strings(rng: &mut ThreadRng, n: usize, dist: &LengthDist) -> Vec<String> {
+    let letters = distributions::Uniform::from(b'a'..=b'z');
+    (0..n)
+        .map(|_| {
+            let len = dist.sample(rng);
+            let bytes = rng.sample_iter(letters).take(len).collect::<Vec<_>>();
+            String::from_utf8(bytes).unwrap()
+        })
+        .collect()
+}
+
+fn bench_filter_var_len(c: &mut Criterion) {
+    let mut rng = rand::thread_rng();
+
+    // initialise different filters on the columns (create a set of row_ids to apply to col)
+    let filter_types = random_filters(&mut rng, ROWS);
+
+    let dists = vec![
+        LengthDist::Fixed(8),
+        LengthDist::Uniform(32),
+        LengthDist::Uniform(256),
+        LengthDist::Skewed(8, 1024),
+    ];
+
+    for dist in &dists {
+        // initialise column with random strings.
+        let strs = random_strings(&mut rng, ROWS, dist);
+        let mut offsets = Vec::with_capacity(ROWS + 1);
+        let mut values = vec![];
+        offsets.push(0);
+        for s in &strs {
+            values.extend_from_slice(s.as_bytes());
+            offsets.push(values.len() as i32);
+        }
+        let col_arr =
+            arrow::array::StringArray::from(strs.iter().map(|s| s.as_str()).collect::<Vec<_>>());
+
+        for filter_type in &filter_types {
+            filter_var_len(c, dist, &offsets, &values, filter_type);
+            filter_var_len_arrow(c, dist, &col_arr, filter_type);
+        }
+    }
+}
+
+fn filter_var_len(
+    c: &mut Criterion,
+    dist: &LengthDist,
+    offsets: &[i32],
+    values: &[u8],
+    row_ids: &FilterType,
+) {
+    type Impl = fn(&[i32], &[u8], &[u32], Vec<i32>, Vec<u8>) -> (Vec<i32>, Vec<u8>);
+    let impls: Vec<(&str, Impl)> = vec![
+        ("rust_idiomatic", filter_var_len::filter_materialise_var_len),
+        ("rust_runs", filter_var_len::filter_materialise_var_len_runs),
+        (
+            "simd",
+            |offsets, values, row_ids, dst_offsets, dst_values| {
+                filter_var_len::filter_materialise_var_len_simd(
+                    offsets,
+                    values,
+                    row_ids,
+                    dst_offsets,
+                    dst_values,
+                )
+                .unwrap()
+            },
+        ),
+    ];
+
+    for (name, f) in impls {
+        let mut group = c.benchmark_group(format!("filter_var_len_{}_{}", name, dist));
+
+        group.throughput(Throughput::Elements(row_ids.len() as u64));
+        group.bench_function(BenchmarkId::from_parameter(format!("{}", row_ids)), |b| {
+            b.iter(|| {
+                // TODO(edd): this benchmark isn't re-using the `dst` buffers, when in reality
+                // it likely would. Need to fix this.
+                let (dst_offsets, _) = f(offsets, values, row_ids.as_slice(), vec![], vec![]);
+                assert_eq!(dst_offsets.len(), row_ids.len() + 1);
+            });
+        });
+    }
+}
+
+fn filter_var_len_arrow(
+    c: &mut Criterion,
+    dist: &LengthDist,
+    col_arr: &arrow::array::StringArray,
+    row_ids: &FilterType,
+) {
+    let mut group = c.benchmark_group(format!("filter_var_len_arrow_{}", dist));
+
+    group.throughput(Throughput::Elements(row_ids.len() as u64));
+
+    let mut filter = Vec::with_capacity(col_arr.len());
+    filter.resize(col_arr.len(), false);
+    for &row_id in row_ids.as_slice().iter() {
+        filter[row_id as usize] = true;
+    }
+    let row_ids_arr = arrow::array::BooleanArray::from(filter);
+
+    group.bench_function(BenchmarkId::from_parameter(format!("{}", row_ids)), |b| {
+        b.iter(|| {
+            let dst = filter_var_len::filter_materialise_var_len_arrow(col_arr, &row_ids_arr);
+            assert_eq!(dst.len(), row_ids.len());
+        });
+    });
+}
+
+criterion_group!(benches, bench_filter_var_len);
+criterion_main!(benches);
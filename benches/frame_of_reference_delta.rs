@@ -0,0 +1,151 @@
+mod common;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::{distributions, Rng};
+
+use common::{bench_impl, loaded_filters, random_filters, FilterType};
+use rust_arrow_benches::{dataset, delta, filter, filter_max, filter_sum, frame_of_reference};
+
+const ROWS: usize = 1_000_003; // ~1 million values in the column for now. (3 encourages non-chunking edge cases)
+
+fn bench_frame_of_reference_delta(c: &mut Criterion) {
+    let mut rng = rand::thread_rng();
+
+    // load a column and filters from a dataset file if one is given, otherwise
+    // generate them.
+    let (col, filter_types) = match dataset::from_env() {
+        Some(data) => (data.values, loaded_filters(data.filters)),
+        None => {
+            // initialise different filters on the columns (create a set of row_ids to apply to col)
+            let filter_types = random_filters(&mut rng, ROWS);
+
+            // initialise a timestamp-like column: sorted millisecond timestamps
+            // with up to a second between each row. The sum of the column fits
+            // in a u64.
+            let mut ts = 1_600_000_000_000_u64;
+            let col = rng
+                .sample_iter(distributions::Uniform::from(0..1000_u64))
+                .take(ROWS)
+                .map(|step| {
+                    ts += step;
+                    ts
+                })
+                .collect::<Vec<_>>();
+            (col, filter_types)
+        }
+    };
+    let for_col = frame_of_reference::FrameOfReferenceColumn::new(&col);
+    let delta_col = delta::DeltaColumn::new(&col);
+
+    for filter_type in &filter_types {
+        filter_sum_frame_of_reference(c, &col, &for_col, filter_type);
+        filter_max_frame_of_reference(c, &col, &for_col, filter_type);
+        filter_materialise_delta(c, &col, &delta_col, filter_type);
+    }
+}
+
+fn filter_sum_frame_of_reference(
+    c: &mut Criterion,
+    col: &[u64],
+    for_col: &frame_of_reference::FrameOfReferenceColumn,
+    row_ids: &FilterType,
+) {
+    // for assertion
+    let sum = filter_sum::filter_sum(col, row_ids.as_slice());
+
+    bench_impl(c, "for_sum_plain_rust_idiomatic", row_ids, || {
+        assert_eq!(filter_sum::filter_sum(col, row_ids.as_slice()), sum);
+    });
+    bench_impl(c, "for_sum_plain_simd", row_ids, || {
+        assert_eq!(
+            filter_sum::filter_sum_simd(col, row_ids.as_slice()).unwrap(),
+            sum
+        );
+    });
+    bench_impl(c, "for_sum_rust_idiomatic", row_ids, || {
+        assert_eq!(
+            frame_of_reference::filter_sum(for_col, row_ids.as_slice()),
+            sum
+        );
+    });
+    bench_impl(c, "for_sum_simd", row_ids, || {
+        assert_eq!(
+            frame_of_reference::filter_sum_simd(for_col, row_ids.as_slice()).unwrap(),
+            sum
+        );
+    });
+}
+
+fn filter_max_frame_of_reference(
+    c: &mut Criterion,
+    col: &[u64],
+    for_col: &frame_of_reference::FrameOfReferenceColumn,
+    row_ids: &FilterType,
+) {
+    // for assertion
+    let max = filter_max::filter_max(col, row_ids.as_slice());
+
+    bench_impl(c, "for_max_plain_rust_idiomatic", row_ids, || {
+        assert_eq!(filter_max::filter_max(col, row_ids.as_slice()), max);
+    });
+    bench_impl(c, "for_max_plain_simd", row_ids, || {
+        assert_eq!(
+            filter_max::filter_max_simd(col, row_ids.as_slice()).unwrap(),
+            max
+        );
+    });
+    bench_impl(c, "for_max_rust_idiomatic", row_ids, || {
+        assert_eq!(
+            frame_of_reference::filter_max(for_col, row_ids.as_slice()),
+            max
+        );
+    });
+    bench_impl(c, "for_max_simd", row_ids, || {
+        assert_eq!(
+            frame_of_reference::filter_max_simd(for_col, row_ids.as_slice()).unwrap(),
+            max
+        );
+    });
+}
+
+fn filter_materialise_delta(
+    c: &mut Criterion,
+    col: &[u64],
+    delta_col: &delta::DeltaColumn,
+    row_ids: &FilterType,
+) {
+    let check = |dst: Vec<u64>| assert_eq!(dst.len(), row_ids.len());
+
+    // TODO(edd): like the other materialise benchmarks these don't re-use the
+    // `dst` buffer.
+    bench_impl(c, "delta_materialise_plain_rust_idiomatic", row_ids, || {
+        check(filter::filter_materialise_values(
+            col,
+            row_ids.as_slice(),
+            vec![],
+        ))
+    });
+    bench_impl(c, "delta_materialise_plain_simd", row_ids, || {
+        check(filter::filter_materialise_values_simd(col, row_ids.as_slice(), vec![]).unwrap())
+    });
+    bench_impl(c, "delta_materialise_rust_idiomatic", row_ids, || {
+        check(delta::filter_materialise_values(
+            delta_col,
+            row_ids.as_slice(),
+            vec![],
+        ))
+    });
+    bench_impl(c, "delta_materialise_prefix_sum", row_ids, || {
+        check(delta::filter_materialise_values_prefix_sum(
+            delta_col,
+            row_ids.as_slice(),
+            vec![],
+        ))
+    });
+    bench_impl(c, "delta_materialise_simd", row_ids, || {
+        check(delta::filter_materialise_values_simd(delta_col, row_ids.as_slice(), vec![]).unwrap())
+    });
+}
+
+criterion_group!(benches, bench_frame_of_reference_delta);
+criterion_main!(benches);
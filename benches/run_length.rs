@@ -0,0 +1,195 @@
+mod common;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::{distributions, Rng};
+
+use common::{bench_impl, loaded_filters, random_filters, FilterType};
+use rust_arrow_benches::{dataset, filter, filter_max, filter_sum, run_length};
+
+const ROWS: usize = 1_000_003; // ~1 million values in the column for now. (3 encourages non-chunking edge cases)
+
+fn bench_run_length(c: &mut Criterion) {
+    // encode a column and use the filters from a dataset file if one is given.
+    if let Some(data) = dataset::from_env() {
+        let rle = run_length::RunLengthColumn::new(&data.values);
+        let mean_run = rle.len() / rle.runs().max(1);
+        for filter_type in &loaded_filters(data.filters) {
+            filter_materialise_run_length(c, &rle, mean_run, filter_type);
+            filter_sum_run_length(c, &rle, mean_run, filter_type);
+            filter_max_run_length(c, &rle, mean_run, filter_type);
+        }
+        return;
+    }
+
+    let mut rng = rand::thread_rng();
+
+    // initialise different filters on the columns (create a set of row_ids to apply to col)
+    let filter_types = random_filters(&mut rng, ROWS);
+
+    for &mean_run in &[10_usize, 1000] {
+        // initialise a sorted column with runs of random lengths averaging
+        // `mean_run` rows.
+        let dist = distributions::Uniform::from(1..mean_run * 2);
+        let mut col = Vec::with_capacity(ROWS);
+        let mut v = 0;
+        while col.len() < ROWS {
+            let n = rng.sample(dist).min(ROWS - col.len());
+            col.resize(col.len() + n, v);
+            v += rng.gen_range(1, 100);
+        }
+        let rle = run_length::RunLengthColumn::new(&col);
+
+        for filter_type in &filter_types {
+            filter_materialise_run_length(c, &rle, mean_run, filter_type);
+            filter_sum_run_length(c, &rle, mean_run, filter_type);
+            filter_max_run_length(c, &rle, mean_run, filter_type);
+        }
+    }
+}
+
+fn filter_materialise_run_length(
+    c: &mut Criterion,
+    col: &run_length::RunLengthColumn,
+    mean_run: usize,
+    row_ids: &FilterType,
+) {
+    let check = |dst: Vec<u64>| assert_eq!(dst.len(), row_ids.len());
+
+    // TODO(edd): like the other materialise benchmarks these don't re-use the
+    // `dst` buffer.
+    bench_impl(
+        c,
+        &format!("rle_materialise_decode_simd_run{}", mean_run),
+        row_ids,
+        || {
+            let decoded = col.decode();
+            check(
+                filter::filter_materialise_values_simd(&decoded, row_ids.as_slice(), vec![])
+                    .unwrap(),
+            )
+        },
+    );
+    bench_impl(
+        c,
+        &format!("rle_materialise_rust_idiomatic_run{}", mean_run),
+        row_ids,
+        || {
+            check(run_length::filter_materialise_values(
+                col,
+                row_ids.as_slice(),
+                vec![],
+            ))
+        },
+    );
+    bench_impl(
+        c,
+        &format!("rle_materialise_runs_run{}", mean_run),
+        row_ids,
+        || {
+            check(
+                run_length::filter_materialise_values_runs(col, row_ids.as_slice(), vec![])
+                    .unwrap(),
+            )
+        },
+    );
+    bench_impl(
+        c,
+        &format!("rle_materialise_simd_run{}", mean_run),
+        row_ids,
+        || {
+            check(
+                run_length::filter_materialise_values_simd(col, row_ids.as_slice(), vec![])
+                    .unwrap(),
+            )
+        },
+    );
+}
+
+fn filter_sum_run_length(
+    c: &mut Criterion,
+    col: &run_length::RunLengthColumn,
+    mean_run: usize,
+    row_ids: &FilterType,
+) {
+    // for assertion
+    let sum = run_length::filter_sum(col, row_ids.as_slice());
+
+    bench_impl(
+        c,
+        &format!("rle_sum_decode_simd_run{}", mean_run),
+        row_ids,
+        || {
+            let decoded = col.decode();
+            assert_eq!(
+                filter_sum::filter_sum_simd(&decoded, row_ids.as_slice()).unwrap(),
+                sum
+            );
+        },
+    );
+    bench_impl(
+        c,
+        &format!("rle_sum_rust_idiomatic_run{}", mean_run),
+        row_ids,
+        || {
+            assert_eq!(run_length::filter_sum(col, row_ids.as_slice()), sum);
+        },
+    );
+    bench_impl(c, &format!("rle_sum_runs_run{}", mean_run), row_ids, || {
+        assert_eq!(
+            run_length::filter_sum_runs(col, row_ids.as_slice()).unwrap(),
+            sum
+        );
+    });
+    bench_impl(c, &format!("rle_sum_simd_run{}", mean_run), row_ids, || {
+        assert_eq!(
+            run_length::filter_sum_simd(col, row_ids.as_slice()).unwrap(),
+            sum
+        );
+    });
+}
+
+fn filter_max_run_length(
+    c: &mut Criterion,
+    col: &run_length::RunLengthColumn,
+    mean_run: usize,
+    row_ids: &FilterType,
+) {
+    // for assertion
+    let max = run_length::filter_max(col, row_ids.as_slice());
+
+    bench_impl(
+        c,
+        &format!("rle_max_decode_simd_run{}", mean_run),
+        row_ids,
+        || {
+            let decoded = col.decode();
+            assert_eq!(
+                filter_max::filter_max_simd(&decoded, row_ids.as_slice()).unwrap(),
+                max
+            );
+        },
+    );
+    bench_impl(
+        c,
+        &format!("rle_max_rust_idiomatic_run{}", mean_run),
+        row_ids,
+        || {
+            assert_eq!(run_length::filter_max(col, row_ids.as_slice()), max);
+        },
+    );
+    bench_impl(c, &format!("rle_max_runs_run{}", mean_run), row_ids, || {
+        assert_eq!(
+            run_length::filter_max_runs(col, row_ids.as_slice()).unwrap(),
+            max
+        );
+    });
+    bench_impl(c, &format!("rle_max_simd_run{}", mean_run), row_ids, || {
+        assert_eq!(
+            run_length::filter_max_simd(col, row_ids.as_slice()).unwrap(),
+            max
+        );
+    });
+}
+
+criterion_group!(benches, bench_run_length);
+criterion_main!(benches);
@@ -0,0 +1,177 @@
+mod common;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::{distributions, Rng};
+
+use common::{bench_impl, loaded_filters, random_filters, FilterType};
+use rust_arrow_benches::{dataset, filter, filter_max, filter_sum, wide_row_ids};
+
+const ROWS: usize = 1_000_003; // ~1 million values in the column for now. (3 encourages non-chunking edge cases)
+
+fn bench_wide_row_ids(c: &mut Criterion) {
+    let mut rng = rand::thread_rng();
+
+    // load a column and filters from a dataset file if one is given, otherwise
+    // generate them.
+    let (col, filter_types) = match dataset::from_env() {
+        Some(data) => (data.values, loaded_filters(data.filters)),
+        None => {
+            // initialise different filters on the columns (create a set of row_ids to apply to col)
+            let filter_types = random_filters(&mut rng, ROWS);
+
+            // initialise column with random values. They're small enough that
+            // the sums don't overflow.
+            let col = rng
+                .sample_iter(distributions::Uniform::from(0..u32::MAX as u64))
+                .take(ROWS)
+                .collect::<Vec<_>>();
+            (col, filter_types)
+        }
+    };
+
+    // Every u64 and segmented group has a `wide_*_u32_*` baseline running the
+    // u32 kernel on the same filter, so the groups can be compared directly.
+    //
+    // A column with more than u32::MAX rows doesn't fit in memory here, so the
+    // wide and segmented row ids address the same column as the u32 ones. That
+    // still measures what the wider indexes cost: twice the bytes of row ids
+    // to read, and for segmented row ids the per-segment overhead (the small
+    // segment size stands in for a large column with many segments).
+    for filter_type in &filter_types {
+        let wide = filter_type
+            .as_slice()
+            .iter()
+            .map(|&id| id as u64)
+            .collect::<Vec<_>>();
+        let segmented = [wide_row_ids::SEGMENT_ROWS, 65536]
+            .iter()
+            .map(|&rows| {
+                (
+                    rows,
+                    wide_row_ids::SegmentedRowIds::with_segment_rows(&wide, rows),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        filter_materialise_wide(c, &col, filter_type, &wide, &segmented);
+        filter_sum_wide(c, &col, filter_type, &wide, &segmented);
+        filter_max_wide(c, &col, filter_type, &wide, &segmented);
+    }
+}
+
+// The name of the group for segments of `rows` rows.
+fn segmented_name(op: &str, rows: usize) -> String {
+    if rows == wide_row_ids::SEGMENT_ROWS {
+        return format!("wide_{}_segmented_simd", op);
+    }
+    format!("wide_{}_segmented{}_simd", op, rows)
+}
+
+fn filter_materialise_wide(
+    c: &mut Criterion,
+    col: &[u64],
+    row_ids: &FilterType,
+    wide: &[u64],
+    segmented: &[(usize, wide_row_ids::SegmentedRowIds)],
+) {
+    let check = |dst: Vec<u64>| assert_eq!(dst.len(), row_ids.len());
+
+    // TODO(edd): like the other materialise benchmarks these don't re-use the
+    // `dst` buffer.
+    bench_impl(c, "wide_materialise_u32_rust_idiomatic", row_ids, || {
+        check(filter::filter_materialise_values(
+            col,
+            row_ids.as_slice(),
+            vec![],
+        ))
+    });
+    bench_impl(c, "wide_materialise_u32_simd", row_ids, || {
+        check(filter::filter_materialise_values_simd(col, row_ids.as_slice(), vec![]).unwrap())
+    });
+    bench_impl(c, "wide_materialise_u64_rust_idiomatic", row_ids, || {
+        check(wide_row_ids::filter_materialise_values(col, wide, vec![]))
+    });
+    bench_impl(c, "wide_materialise_u64_simd", row_ids, || {
+        check(wide_row_ids::filter_materialise_values_simd(col, wide, vec![]).unwrap())
+    });
+    for (rows, segmented) in segmented {
+        bench_impl(c, &segmented_name("materialise", *rows), row_ids, || {
+            check(
+                wide_row_ids::filter_materialise_values_segmented(col, segmented, vec![]).unwrap(),
+            )
+        });
+    }
+}
+
+fn filter_sum_wide(
+    c: &mut Criterion,
+    col: &[u64],
+    row_ids: &FilterType,
+    wide: &[u64],
+    segmented: &[(usize, wide_row_ids::SegmentedRowIds)],
+) {
+    // for assertion
+    let sum = filter_sum::filter_sum(col, row_ids.as_slice());
+
+    bench_impl(c, "wide_sum_u32_rust_idiomatic", row_ids, || {
+        assert_eq!(filter_sum::filter_sum(col, row_ids.as_slice()), sum);
+    });
+    bench_impl(c, "wide_sum_u32_simd", row_ids, || {
+        assert_eq!(
+            filter_sum::filter_sum_simd(col, row_ids.as_slice()).unwrap(),
+            sum
+        );
+    });
+    bench_impl(c, "wide_sum_u64_rust_idiomatic", row_ids, || {
+        assert_eq!(wide_row_ids::filter_sum(col, wide), sum);
+    });
+    bench_impl(c, "wide_sum_u64_simd", row_ids, || {
+        assert_eq!(wide_row_ids::filter_sum_simd(col, wide).unwrap(), sum);
+    });
+    for (rows, segmented) in segmented {
+        bench_impl(c, &segmented_name("sum", *rows), row_ids, || {
+            assert_eq!(
+                wide_row_ids::filter_sum_segmented(col, segmented).unwrap(),
+                sum
+            );
+        });
+    }
+}
+
+fn filter_max_wide(
+    c: &mut Criterion,
+    col: &[u64],
+    row_ids: &FilterType,
+    wide: &[u64],
+    segmented: &[(usize, wide_row_ids::SegmentedRowIds)],
+) {
+    // for assertion
+    let max = filter_max::filter_max(col, row_ids.as_slice());
+
+    bench_impl(c, "wide_max_u32_rust_idiomatic", row_ids, || {
+        assert_eq!(filter_max::filter_max(col, row_ids.as_slice()), max);
+    });
+    bench_impl(c, "wide_max_u32_simd", row_ids, || {
+        assert_eq!(
+            filter_max::filter_max_simd(col, row_ids.as_slice()).unwrap(),
+            max
+        );
+    });
+    bench_impl(c, "wide_max_u64_rust_idiomatic", row_ids, || {
+        assert_eq!(wide_row_ids::filter_max(col, wide), max);
+    });
+    bench_impl(c, "wide_max_u64_simd", row_ids, || {
+        assert_eq!(wide_row_ids::filter_max_simd(col, wide).unwrap(), max);
+    });
+    for (rows, segmented) in segmented {
+        bench_impl(c, &segmented_name("max", *rows), row_ids, || {
+            assert_eq!(
+                wide_row_ids::filter_max_segmented(col, segmented).unwrap(),
+                max
+            );
+        });
+    }
+}
+
+criterion_group!(benches, bench_wide_row_ids);
+criterion_main!(benches);
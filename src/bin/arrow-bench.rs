@@ -0,0 +1,453 @@
+//! A command line runner for a selection of the kernels, e.g.:
+//!
+//! ```text
+//! $ arrow-bench run --op sum --impl simd,arrow --density 10,50 --rows 1e6,1e8
+//! ```
+//!
+//! Each selected kernel is timed on each filter, and its output is checked
+//! against the idiomatic implementation. The runner exits with a non-zero
+//! status if any check fails.
+//!
+//! Results can be written to a JSON or CSV file with `--output`, and the
+//! README's results tables regenerated from those files:
+//!
+//! ```text
+//! $ arrow-bench run --output results.json
+//! $ arrow-bench tables results.json --readme README.md
+//! ```
+//!
+//! Two result files can be compared, exiting with a non-zero status if any
+//! benchmark got significantly slower:
+//!
+//! ```text
+//! $ arrow-bench compare baseline.json candidate.json --threshold 5
+//! ```
+//!
+//! The README's benchmarks saved by `cargo bench` can be written to a result
+//! file too, from the latest run or a baseline saved with `--save-baseline`:
+//!
+//! ```text
+//! $ cargo bench --bench filter --bench filter_sum --bench filter_max
+//! $ arrow-bench export-criterion results.json --baseline new
+//! ```
+//!
+//! Or any two criterion baselines compared directly, using all their samples:
+//!
+//! ```text
+//! $ arrow-bench compare-criterion --baseline main --candidate new
+//! ```
+use std::{fs, path::PathBuf, process, time::Duration};
+
+use rand::{rngs::StdRng, SeedableRng};
+use structopt::StructOpt;
+
+use rust_arrow_benches::{
+    compare, dataset,
+    results::{self, Environment, Results},
+    runner::{self, Column, Filter, Impl, Op},
+};
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "arrow-bench", about = "Run filter kernel benchmarks")]
+enum Command {
+    /// Time the selected kernels on the selected filters.
+    Run(RunOpts),
+
+    /// Generate the README's results tables from result files.
+    Tables(TablesOpts),
+
+    /// Compare a candidate result file to a baseline.
+    Compare(CompareOpts),
+
+    /// Write the README's benchmarks saved by `cargo bench` to a result file.
+    ExportCriterion(ExportCriterionOpts),
+
+    /// Compare two baselines saved by `cargo bench`.
+    CompareCriterion(CompareCriterionOpts),
+}
+
+#[derive(Debug, StructOpt)]
+struct RunOpts {
+    /// The operations to run (materialise, sum, max).
+    #[structopt(long, use_delimiter = true, default_value = "materialise,sum,max")]
+    op: Vec<Op>,
+
+    /// The implementations to run (idiomatic, arrow, simd).
+    #[structopt(
+        long = "impl",
+        use_delimiter = true,
+        default_value = "idiomatic,arrow,simd"
+    )]
+    imp: Vec<Impl>,
+
+    /// The percentage of rows each filter selects, from 1 to 100.
+    #[structopt(long, use_delimiter = true, default_value = "10,50,75", parse(try_from_str = parse_density))]
+    density: Vec<usize>,
+
+    /// The length of the runs of selected rows. 1 selects rows uniformly.
+    #[structopt(long, use_delimiter = true, default_value = "1")]
+    run_size: Vec<usize>,
+
+    /// The number of rows in the column, e.g., 1000000 or 1e6.
+    #[structopt(long, use_delimiter = true, default_value = "1e6", parse(try_from_str = parse_rows))]
+    rows: Vec<usize>,
+
+    /// The minimum time to run each kernel for, in seconds.
+    #[structopt(long, default_value = "1")]
+    min_time: f64,
+
+    /// The seed for the column and filter generators.
+    #[structopt(long, default_value = "0")]
+    seed: u64,
+
+    /// Load the column and filters from an Arrow IPC or Parquet file instead
+    /// of generating them. `--rows`, `--density` and `--run-size` are ignored.
+    #[structopt(long, parse(from_os_str))]
+    data: Option<PathBuf>,
+
+    /// Write the results to a `.json` or `.csv` file.
+    #[structopt(long, parse(from_os_str))]
+    output: Option<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+struct TablesOpts {
+    /// The `.json` or `.csv` result files to generate the tables from.
+    #[structopt(required = true, parse(from_os_str))]
+    files: Vec<PathBuf>,
+
+    /// Replace the "Current Results" section of this README instead of
+    /// printing the tables.
+    #[structopt(long, parse(from_os_str))]
+    readme: Option<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+struct CompareOpts {
+    /// The baseline `.json` or `.csv` result file.
+    #[structopt(parse(from_os_str))]
+    baseline: PathBuf,
+
+    /// The candidate `.json` or `.csv` result file.
+    #[structopt(parse(from_os_str))]
+    candidate: PathBuf,
+
+    /// The percentage slowdown above which a significant change is a
+    /// regression.
+    #[structopt(long, default_value = "5")]
+    threshold: f64,
+
+    /// The p-value below which a change is significant.
+    #[structopt(long, default_value = "0.05")]
+    significance: f64,
+}
+
+#[derive(Debug, StructOpt)]
+struct ExportCriterionOpts {
+    /// The `.json` or `.csv` file to write the results to.
+    #[structopt(parse(from_os_str))]
+    output: PathBuf,
+
+    /// Criterion's output directory.
+    #[structopt(long, default_value = "target/criterion", parse(from_os_str))]
+    dir: PathBuf,
+
+    /// The criterion baseline to export: `new` for the latest run, `base` for
+    /// the one before it, or a name given to `--save-baseline`.
+    #[structopt(long, default_value = "new")]
+    baseline: String,
+
+    /// The `RUSTFLAGS` the benchmarks were built with, which are recorded with
+    /// the results.
+    #[structopt(long)]
+    rustflags: String,
+}
+
+#[derive(Debug, StructOpt)]
+struct CompareCriterionOpts {
+    /// Criterion's output directory.
+    #[structopt(long, default_value = "target/criterion", parse(from_os_str))]
+    dir: PathBuf,
+
+    /// The baseline's criterion baseline name.
+    #[structopt(long, default_value = "base")]
+    baseline: String,
+
+    /// The candidate's criterion baseline name.
+    #[structopt(long, default_value = "new")]
+    candidate: String,
+
+    /// The percentage slowdown above which a significant change is a
+    /// regression.
+    #[structopt(long, default_value = "5")]
+    threshold: f64,
+
+    /// The p-value below which a change is significant.
+    #[structopt(long, default_value = "0.05")]
+    significance: f64,
+}
+
+// Parse a row count, allowing scientific notation.
+fn parse_rows(s: &str) -> Result<usize, String> {
+    let rows = s
+        .parse::<f64>()
+        .map_err(|e| format!("invalid row count {:?}: {}", s, e))?;
+    if rows < 1.0 || rows > u32::MAX as f64 || rows.fract() != 0.0 {
+        return Err(format!("row count {:?} must be between 1 and u32::MAX", s));
+    }
+    Ok(rows as usize)
+}
+
+// Parse a filter density, as a percentage of the rows.
+fn parse_density(s: &str) -> Result<usize, String> {
+    let density = s
+        .parse::<usize>()
+        .map_err(|e| format!("invalid density {:?}: {}", s, e))?;
+    if !(1..=100).contains(&density) {
+        return Err(format!("density {:?} must be between 1 and 100", s));
+    }
+    Ok(density)
+}
+
+fn run(opts: RunOpts) -> bool {
+    let mut rng = StdRng::seed_from_u64(opts.seed);
+    let min_time = Duration::from_secs_f64(opts.min_time);
+
+    // a column and its filters for each row count.
+    let inputs = match &opts.data {
+        Some(path) => {
+            let data = dataset::load(path);
+            let rows = data.values.len();
+            let filters = data
+                .filters
+                .into_iter()
+                .map(|(name, row_ids)| Filter::new(format!("loaded_{}", name), rows, row_ids))
+                .collect::<Vec<_>>();
+            vec![(Column::new(data.values), filters)]
+        }
+        None => opts
+            .rows
+            .iter()
+            .map(|&rows| {
+                let col = Column::random(&mut rng, rows);
+                let mut filters = vec![];
+                for &run_size in &opts.run_size {
+                    for &density in &opts.density {
+                        filters.push(Filter::random(&mut rng, rows, density, run_size));
+                    }
+                }
+                (col, filters)
+            })
+            .collect(),
+    };
+
+    println!(
+        "{:<12} {:<10} {:<36} {:>10} {:>10} {:>12} {:>10} {:>8}",
+        "op", "impl", "filter", "rows", "selected", "time/iter", "Melem/s", "GB/s"
+    );
+
+    let mut measurements = vec![];
+    for (col, filters) in &inputs {
+        for filter in filters {
+            for &op in &opts.op {
+                for &imp in &opts.imp {
+                    // like the benches, skip the max of a filter that selects
+                    // nothing: there isn't one.
+                    let m = match runner::measure(op, imp, col, filter, min_time) {
+                        Some(m) => m,
+                        None => {
+                            eprintln!("skipping {} {}: {} selects no rows", op, imp, filter.name());
+                            continue;
+                        }
+                    };
+                    println!(
+                        "{:<12} {:<10} {:<36} {:>10} {:>10} {:>12} {:>10.1} {:>8.2}{}",
+                        m.op.to_string(),
+                        m.imp.to_string(),
+                        m.filter,
+                        m.rows,
+                        m.selected,
+                        format!("{:.3?}", m.per_iteration()),
+                        m.melem_per_sec(),
+                        m.gb_per_sec(),
+                        if m.correct { "" } else { "  INCORRECT" }
+                    );
+                    measurements.push(m);
+                }
+            }
+        }
+    }
+
+    if let Some(path) = &opts.output {
+        Results::new(Environment::current(opts.seed), &measurements).write(path);
+    }
+    let ok = measurements.iter().all(|m| m.correct);
+    if !ok {
+        eprintln!("some results did not match the idiomatic implementation");
+    }
+    ok
+}
+
+fn tables(opts: TablesOpts) {
+    let results = opts
+        .files
+        .iter()
+        .map(|path| Results::read(path))
+        .collect::<Vec<_>>();
+    let tables = results::markdown_tables(&results);
+
+    match &opts.readme {
+        Some(path) => {
+            let readme =
+                fs::read_to_string(path).unwrap_or_else(|e| panic!("can't read {:?}: {}", path, e));
+            fs::write(path, results::update_readme(&readme, &tables)).unwrap();
+        }
+        None => print!("{}", tables),
+    }
+}
+
+fn compare(opts: CompareOpts) -> bool {
+    let baseline = Results::read(&opts.baseline);
+    let candidate = Results::read(&opts.candidate);
+    if baseline.environment != candidate.environment {
+        eprintln!(
+            "warning: the results were measured in different environments:\n  {:?}\n  {:?}",
+            baseline.environment, candidate.environment
+        );
+    }
+
+    let report = compare::compare(
+        &baseline,
+        &candidate,
+        opts.threshold / 100.0,
+        opts.significance,
+    );
+
+    println!(
+        "{:<12} {:<10} {:<36} {:>10} {:>12} {:>12} {:>9} {:>8}  status",
+        "op", "impl", "filter", "rows", "baseline", "candidate", "change", "p"
+    );
+    for c in &report.comparisons {
+        println!(
+            "{:<12} {:<10} {:<36} {:>10} {:>12} {:>12} {:>+8.2}% {:>8.3}  {}",
+            c.op.to_string(),
+            c.imp.to_string(),
+            c.filter,
+            c.rows,
+            format!("{:.3?}", Duration::from_secs_f64(c.baseline_ns / 1e9)),
+            format!("{:.3?}", Duration::from_secs_f64(c.candidate_ns / 1e9)),
+            c.change * 100.0,
+            c.p_value,
+            c.status
+        );
+    }
+    for (which, records) in &[
+        ("baseline", &report.only_baseline),
+        ("candidate", &report.only_candidate),
+    ] {
+        for r in records.iter() {
+            println!(
+                "{:<12} {:<10} {:<36} {:>10}  only in {}",
+                r.op.to_string(),
+                r.imp.to_string(),
+                r.filter,
+                r.rows,
+                which
+            );
+        }
+    }
+
+    let regressions = report.regressions().count();
+    if regressions > 0 {
+        eprintln!(
+            "{} benchmark(s) regressed by more than {}%",
+            regressions, opts.threshold
+        );
+    }
+    regressions == 0
+}
+
+fn export_criterion(opts: ExportCriterionOpts) -> bool {
+    let benchmarks = results::read_criterion(&opts.dir, &opts.baseline);
+    // the benches don't use a fixed seed, and were built separately from this
+    // binary so its `RUSTFLAGS` may not be theirs.
+    let environment = Environment::with_rustflags(&opts.rustflags, None);
+    let results = Results::from_criterion(environment, &benchmarks);
+    if results.records.is_empty() {
+        eprintln!(
+            "no {:?} results for the README's benchmarks in {:?}",
+            opts.baseline, opts.dir
+        );
+        return false;
+    }
+
+    results.write(&opts.output);
+    eprintln!(
+        "wrote {} of {} benchmarks to {:?}",
+        results.records.len(),
+        benchmarks.len(),
+        opts.output
+    );
+    true
+}
+
+fn compare_criterion(opts: CompareCriterionOpts) -> bool {
+    let baseline = results::read_criterion(&opts.dir, &opts.baseline);
+    let candidate = results::read_criterion(&opts.dir, &opts.candidate);
+    let report = compare::compare_criterion(
+        &baseline,
+        &candidate,
+        opts.threshold / 100.0,
+        opts.significance,
+    );
+
+    println!(
+        "{:<60} {:>12} {:>12} {:>9} {:>8}  status",
+        "benchmark", "baseline", "candidate", "change", "p"
+    );
+    for c in &report.comparisons {
+        println!(
+            "{:<60} {:>12} {:>12} {:>+8.2}% {:>8.3}  {}",
+            c.id,
+            format!("{:.3?}", Duration::from_secs_f64(c.baseline_ns / 1e9)),
+            format!("{:.3?}", Duration::from_secs_f64(c.candidate_ns / 1e9)),
+            c.change * 100.0,
+            c.p_value,
+            c.status
+        );
+    }
+    for (which, ids) in &[
+        (&opts.baseline, &report.only_baseline),
+        (&opts.candidate, &report.only_candidate),
+    ] {
+        for id in ids.iter() {
+            println!("{:<60}  only in {}", id, which);
+        }
+    }
+
+    let regressions = report.regressions().count();
+    if regressions > 0 {
+        eprintln!(
+            "{} benchmark(s) regressed by more than {}%",
+            regressions, opts.threshold
+        );
+    }
+    regressions == 0
+}
+
+fn main() {
+    let ok = match Command::from_args() {
+        Command::Run(opts) => run(opts),
+        Command::Tables(opts) => {
+            tables(opts);
+            true
+        }
+        Command::Compare(opts) => compare(opts),
+        Command::ExportCriterion(opts) => export_criterion(opts),
+        Command::CompareCriterion(opts) => compare_criterion(opts),
+    };
+
+    if !ok {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,532 @@
+//! A bit-packed column of unsigned integers, and filter functions that only
+//! unpack the rows they need.
+//!
+//! Each value in a `BitPackedColumn` is stored using `width` bits (anywhere
+//! from 1 to 64), back to back in a vector of `u64` words. Value `i` starts at
+//! bit `i * width`, and may straddle two words.
+//!
+//! I care about these because storage often bit-packs integer columns, and
+//! decoding the entire column before filtering wastes a lot of memory
+//! bandwidth when only some of the rows are needed. There are two strategies
+//! for unpacking only the rows that are needed:
+//!
+//! - Random access: each selected value is unpacked on its own. The SIMD
+//!   version gathers the two words that could hold four values and shifts
+//!   each lane into place. This suits sparse selections.
+//! - Block unpack: the block of `BLOCK_SIZE` values holding a selected row is
+//!   unpacked (with SIMD) into a small buffer, and the values are picked out
+//!   of the buffer. Each block is unpacked once for sorted row ids, which suits
+//!   dense selections.
+//!
+//! The `_simd` functions pick between the two based on how dense the selection
+//! is. The `_gather_simd` and `_block_simd` functions use one strategy only.
+//! They all check their row ids with `row_ids::check` before unpacking
+//! anything, and return `RowIdOutOfBounds` if any are out of range: the
+//! gathers aren't bounds checked, and a padded row would silently read as
+//! zero.
+use std::arch::x86_64::*;
+
+use crate::row_ids::{self, RowIdOutOfBounds};
+
+/// The number of values unpacked at a time by the block unpack strategy.
+pub const BLOCK_SIZE: usize = 256;
+
+/// Selections with at least one row id per `DENSE_RATIO` rows use block
+/// unpacking in the `_simd` functions.
+pub const DENSE_RATIO: usize = 8;
+
+#[derive(Debug, Clone)]
+pub struct BitPackedColumn {
+    width: u32,
+    len: usize,
+
+    // The packed values. This is padded so that whole blocks can always be
+    // unpacked, and so that reading the word after any value's first word is
+    // always in bounds.
+    data: Vec<u64>,
+}
+
+impl BitPackedColumn {
+    /// Bit-pack `values` using `width` bits per value. Panics if `width` is not
+    /// in `1..=64` or if any value doesn't fit in `width` bits.
+    pub fn new(values: &[u64], width: u32) -> Self {
+        assert!((1..=64).contains(&width), "width must be in 1..=64");
+        let mask = Self::mask_for(width);
+
+        let padded_len = values.len().div_ceil(BLOCK_SIZE) * BLOCK_SIZE;
+        let mut data = vec![0_u64; (padded_len * width as usize).div_ceil(64) + 1];
+
+        for (i, &v) in values.iter().enumerate() {
+            assert_eq!(v & !mask, 0, "value {} doesn't fit in {} bits", v, width);
+
+            let bit = i * width as usize;
+            let (word, shift) = (bit / 64, bit % 64);
+            data[word] |= v << shift;
+            if shift + width as usize > 64 {
+                data[word + 1] |= v >> (64 - shift);
+            }
+        }
+
+        Self {
+            width,
+            len: values.len(),
+            data,
+        }
+    }
+
+    /// Bit-pack `values` using the fewest bits that can hold the largest value.
+    pub fn from_values(values: &[u64]) -> Self {
+        let max = values.iter().copied().max().unwrap_or(0);
+        Self::new(values, (64 - max.leading_zeros()).max(1))
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The packed size of the column in bytes.
+    pub fn size(&self) -> usize {
+        self.data.len() * 8
+    }
+
+    fn mask_for(width: u32) -> u64 {
+        if width == 64 {
+            u64::MAX
+        } else {
+            (1 << width) - 1
+        }
+    }
+
+    fn mask(&self) -> u64 {
+        Self::mask_for(self.width)
+    }
+
+    /// Unpack the value at row `i`.
+    pub fn get(&self, i: usize) -> u64 {
+        assert!(i < self.len, "row {} out of bounds ({} rows)", i, self.len);
+
+        let bit = i * self.width as usize;
+        let (word, shift) = (bit / 64, bit % 64);
+
+        // the double shift of the next word avoids an overflowing shift by 64
+        // when `shift` is zero.
+        let lo = self.data[word] >> shift;
+        let hi = (self.data[word + 1] << 1) << (63 - shift);
+        (lo | hi) & self.mask()
+    }
+
+    /// Unpack every value in the column.
+    pub fn decode(&self) -> Vec<u64> {
+        (0..self.len).map(|i| self.get(i)).collect()
+    }
+
+    // Unpack four values with row ids in the lanes of `ids` (64-bit lanes). The
+    // row ids must be within the padded length of the column.
+    #[inline]
+    unsafe fn unpack_lanes(&self, ids: __m256i) -> __m256i {
+        let base_ptr = self.data.as_ptr() as *const i64;
+
+        let bits = _mm256_mul_epu32(ids, _mm256_set1_epi64x(self.width as i64));
+        let words = _mm256_srli_epi64(bits, 6);
+        let shifts = _mm256_and_si256(bits, _mm256_set1_epi64x(63));
+
+        let lo = _mm256_i64gather_epi64(base_ptr, words, 8);
+        let hi =
+            _mm256_i64gather_epi64(base_ptr, _mm256_add_epi64(words, _mm256_set1_epi64x(1)), 8);
+
+        // variable shifts by 64 or more produce zero, so when `shift` is zero
+        // the next word contributes nothing.
+        let lo = _mm256_srlv_epi64(lo, shifts);
+        let hi = _mm256_sllv_epi64(hi, _mm256_sub_epi64(_mm256_set1_epi64x(64), shifts));
+        _mm256_and_si256(
+            _mm256_or_si256(lo, hi),
+            _mm256_set1_epi64x(self.mask() as i64),
+        )
+    }
+
+    // Unpack four values at the row ids in `chunk`.
+    #[inline]
+    unsafe fn unpack_row_ids(&self, chunk: &[u32]) -> __m256i {
+        let ids = _mm256_cvtepu32_epi64(_mm_loadu_si128(chunk.as_ptr() as *const __m128i));
+        self.unpack_lanes(ids)
+    }
+
+    // Unpack the block `block` into `dst` using SIMD.
+    pub(crate) fn unpack_block(&self, block: usize, dst: &mut [u64; BLOCK_SIZE]) {
+        assert!(block * BLOCK_SIZE < self.len.max(1));
+
+        unsafe {
+            let mut ids = _mm256_add_epi64(
+                _mm256_set1_epi64x((block * BLOCK_SIZE) as i64),
+                _mm256_setr_epi64x(0, 1, 2, 3),
+            );
+            let four = _mm256_set1_epi64x(4);
+
+            for chunk in dst.chunks_exact_mut(4) {
+                _mm256_storeu_si256(chunk.as_mut_ptr() as *mut __m256i, self.unpack_lanes(ids));
+                ids = _mm256_add_epi64(ids, four);
+            }
+        }
+    }
+
+    // Returns true if the selection is dense enough to unpack whole blocks.
+    fn is_dense(&self, row_ids: &[u32]) -> bool {
+        row_ids.len() * DENSE_RATIO >= self.len
+    }
+}
+
+/// This is a relatively idiomatic Rust implementation of filter on a
+/// bit-packed column. Each selected value is unpacked on its own.
+pub fn filter_materialise_values(
+    col: &BitPackedColumn,
+    row_ids: &[u32],
+    mut dst: Vec<u64>,
+) -> Vec<u64> {
+    dst.clear();
+    dst.reserve(row_ids.len());
+
+    for &id in row_ids.iter() {
+        dst.push(col.get(id as usize));
+    }
+
+    assert_eq!(dst.len(), row_ids.len());
+    dst
+}
+
+/// This is a relatively idiomatic Rust implementation of filter_sum on a
+/// bit-packed column.
+pub fn filter_sum(col: &BitPackedColumn, row_ids: &[u32]) -> u64 {
+    let mut result = 0;
+    for &id in row_ids.iter() {
+        result += col.get(id as usize);
+    }
+    result
+}
+
+/// This is a relatively idiomatic Rust implementation of filter_max on a
+/// bit-packed column.
+pub fn filter_max(col: &BitPackedColumn, row_ids: &[u32]) -> u64 {
+    row_ids
+        .iter()
+        .map(|&id| col.get(id as usize))
+        .max()
+        .unwrap()
+}
+
+/// This is an implementation of filter on a bit-packed column using SIMD
+/// random access: four values are unpacked at a time from gathered words.
+pub fn filter_materialise_values_gather_simd(
+    col: &BitPackedColumn,
+    row_ids: &[u32],
+    mut dst: Vec<u64>,
+) -> Result<Vec<u64>, RowIdOutOfBounds> {
+    row_ids::check(row_ids, col.len())?;
+    dst.clear();
+    dst.reserve(row_ids.len());
+
+    unsafe {
+        for chunk in row_ids.chunks_exact(4) {
+            _mm256_storeu_si256(
+                dst.as_mut_ptr().add(dst.len()) as *mut __m256i,
+                col.unpack_row_ids(chunk),
+            );
+            dst.set_len(dst.len() + 4);
+        }
+    }
+
+    // materialise any remainder - maximum of three values.
+    let rem = row_ids.len() - (row_ids.len() % 4);
+    for &id in row_ids.iter().skip(rem) {
+        dst.push(col.get(id as usize));
+    }
+
+    assert_eq!(dst.len(), row_ids.len());
+    Ok(dst)
+}
+
+/// This is an implementation of filter on a bit-packed column using SIMD
+/// block unpacking. Row ids can be in any order, but each block is only
+/// unpacked once if they're sorted.
+pub fn filter_materialise_values_block_simd(
+    col: &BitPackedColumn,
+    row_ids: &[u32],
+    mut dst: Vec<u64>,
+) -> Result<Vec<u64>, RowIdOutOfBounds> {
+    row_ids::check(row_ids, col.len())?;
+    dst.clear();
+    dst.reserve(row_ids.len());
+
+    let mut block = [0_u64; BLOCK_SIZE];
+    let mut current = usize::MAX;
+    for &id in row_ids.iter() {
+        let id = id as usize;
+        if id / BLOCK_SIZE != current {
+            current = id / BLOCK_SIZE;
+            col.unpack_block(current, &mut block);
+        }
+        dst.push(block[id % BLOCK_SIZE]);
+    }
+
+    assert_eq!(dst.len(), row_ids.len());
+    Ok(dst)
+}
+
+/// This is an implementation of filter on a bit-packed column using SIMD. It
+/// uses block unpacking for dense selections and random access otherwise.
+pub fn filter_materialise_values_simd(
+    col: &BitPackedColumn,
+    row_ids: &[u32],
+    dst: Vec<u64>,
+) -> Result<Vec<u64>, RowIdOutOfBounds> {
+    if col.is_dense(row_ids) {
+        filter_materialise_values_block_simd(col, row_ids, dst)
+    } else {
+        filter_materialise_values_gather_simd(col, row_ids, dst)
+    }
+}
+
+/// This is an implementation of filter then sum on a bit-packed column using
+/// SIMD random access.
+pub fn filter_sum_gather_simd(
+    col: &BitPackedColumn,
+    row_ids: &[u32],
+) -> Result<u64, RowIdOutOfBounds> {
+    row_ids::check(row_ids, col.len())?;
+
+    unsafe {
+        let mut sum_lanes = _mm256_setzero_si256(); // u64x4
+        for chunk in row_ids.chunks_exact(4) {
+            sum_lanes = _mm256_add_epi64(sum_lanes, col.unpack_row_ids(chunk));
+        }
+
+        // sum any remainder - maximum of three values.
+        let rem = row_ids.len() - (row_ids.len() % 4);
+        let rem_sum = row_ids
+            .iter()
+            .skip(rem)
+            .map(|&id| col.get(id as usize))
+            .sum::<u64>();
+
+        let result: (u64, u64, u64, u64) = std::mem::transmute(sum_lanes);
+        Ok(result.0 + result.1 + result.2 + result.3 + rem_sum)
+    }
+}
+
+/// This is an implementation of filter then sum on a bit-packed column using
+/// SIMD block unpacking.
+pub fn filter_sum_block_simd(
+    col: &BitPackedColumn,
+    row_ids: &[u32],
+) -> Result<u64, RowIdOutOfBounds> {
+    row_ids::check(row_ids, col.len())?;
+
+    let mut block = [0_u64; BLOCK_SIZE];
+    let mut current = usize::MAX;
+    let mut result = 0;
+    for &id in row_ids.iter() {
+        let id = id as usize;
+        if id / BLOCK_SIZE != current {
+            current = id / BLOCK_SIZE;
+            col.unpack_block(current, &mut block);
+        }
+        result += block[id % BLOCK_SIZE];
+    }
+    Ok(result)
+}
+
+/// This is an implementation of filter then sum on a bit-packed column using
+/// SIMD. It uses block unpacking for dense selections and random access
+/// otherwise.
+pub fn filter_sum_simd(col: &BitPackedColumn, row_ids: &[u32]) -> Result<u64, RowIdOutOfBounds> {
+    if col.is_dense(row_ids) {
+        filter_sum_block_simd(col, row_ids)
+    } else {
+        filter_sum_gather_simd(col, row_ids)
+    }
+}
+
+/// This is an implementation of filter then max on a bit-packed column using
+/// SIMD random access. Values are compared with their high bit flipped so that
+/// 64-bit wide columns are handled correctly.
+pub fn filter_max_gather_simd(
+    col: &BitPackedColumn,
+    row_ids: &[u32],
+) -> Result<u64, RowIdOutOfBounds> {
+    row_ids::check(row_ids, col.len())?;
+    if row_ids.len() < 4 {
+        return Ok(filter_max(col, row_ids));
+    }
+
+    unsafe {
+        let sign_bit = _mm256_set1_epi64x(i64::MIN);
+        let mut max_lanes = _mm256_xor_si256(col.unpack_row_ids(row_ids), sign_bit);
+
+        for chunk in row_ids.chunks_exact(4).skip(1) {
+            let row_values = _mm256_xor_si256(col.unpack_row_ids(chunk), sign_bit);
+            let max_mask = _mm256_cmpgt_epi64(row_values, max_lanes);
+            max_lanes = _mm256_blendv_epi8(max_lanes, row_values, max_mask);
+        }
+
+        let result: [u64; 4] = std::mem::transmute(_mm256_xor_si256(max_lanes, sign_bit));
+
+        // find the max in any remainder - at most three values.
+        let rem = row_ids.len() - (row_ids.len() % 4);
+        Ok(row_ids
+            .iter()
+            .skip(rem)
+            .map(|&id| col.get(id as usize))
+            .chain(result.iter().copied())
+            .max()
+            .unwrap())
+    }
+}
+
+/// This is an implementation of filter then max on a bit-packed column using
+/// SIMD block unpacking.
+pub fn filter_max_block_simd(
+    col: &BitPackedColumn,
+    row_ids: &[u32],
+) -> Result<u64, RowIdOutOfBounds> {
+    assert!(!row_ids.is_empty());
+    row_ids::check(row_ids, col.len())?;
+
+    let mut block = [0_u64; BLOCK_SIZE];
+    let mut current = usize::MAX;
+    let mut result = 0;
+    for &id in row_ids.iter() {
+        let id = id as usize;
+        if id / BLOCK_SIZE != current {
+            current = id / BLOCK_SIZE;
+            col.unpack_block(current, &mut block);
+        }
+        result = result.max(block[id % BLOCK_SIZE]);
+    }
+    Ok(result)
+}
+
+/// This is an implementation of filter then max on a bit-packed column using
+/// SIMD. It uses block unpacking for dense selections and random access
+/// otherwise.
+pub fn filter_max_simd(col: &BitPackedColumn, row_ids: &[u32]) -> Result<u64, RowIdOutOfBounds> {
+    if col.is_dense(row_ids) {
+        filter_max_block_simd(col, row_ids)
+    } else {
+        filter_max_gather_simd(col, row_ids)
+    }
+}
+
+mod test {
+
+    // values that use the full width of `width` bits.
+    fn values_for_width(width: u32, n: u64) -> Vec<u64> {
+        let mask = if width == 64 {
+            u64::MAX
+        } else {
+            (1 << width) - 1
+        };
+        (0..n)
+            .map(|i| i.wrapping_mul(0x9E37_79B9_7F4A_7C15) & mask)
+            .collect()
+    }
+
+    #[test]
+    fn bit_packed_column() {
+        for width in 1..=64 {
+            let values = values_for_width(width, 1000);
+            let col = super::BitPackedColumn::new(&values, width);
+            assert_eq!(col.len(), 1000);
+            assert_eq!(col.decode(), values);
+        }
+
+        let col = super::BitPackedColumn::from_values(&[3, 1, 7, 0]);
+        assert_eq!(col.width(), 3);
+        assert_eq!(col.decode(), vec![3, 1, 7, 0]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn bit_packed_column_value_too_wide() {
+        super::BitPackedColumn::new(&[1, 2, 8], 3);
+    }
+
+    #[test]
+    fn filter_bit_packed() {
+        let cases = vec![
+            vec![0_u32, 1, 2, 3],
+            vec![999],
+            vec![998, 3, 3, 500, 256, 255, 4],
+            (0..1000).collect::<Vec<_>>(),
+            (0..1000).step_by(3).collect::<Vec<_>>(),
+            (200..230).chain(700..701).collect::<Vec<_>>(),
+        ];
+
+        for width in 1..=64 {
+            let values = values_for_width(width, 1000);
+            let col = super::BitPackedColumn::new(&values, width);
+
+            for row_ids in &cases {
+                let exp = row_ids
+                    .iter()
+                    .map(|&id| values[id as usize])
+                    .collect::<Vec<_>>();
+                let exp_sum = exp.iter().fold(0_u64, |a, &b| a.wrapping_add(b));
+                let exp_max = *exp.iter().max().unwrap();
+
+                assert_eq!(super::filter_materialise_values(&col, row_ids, vec![]), exp);
+                assert_eq!(
+                    super::filter_materialise_values_gather_simd(&col, row_ids, vec![]).unwrap(),
+                    exp
+                );
+                assert_eq!(
+                    super::filter_materialise_values_block_simd(&col, row_ids, vec![]).unwrap(),
+                    exp
+                );
+                assert_eq!(super::filter_max(&col, row_ids), exp_max);
+                assert_eq!(
+                    super::filter_max_gather_simd(&col, row_ids).unwrap(),
+                    exp_max
+                );
+                assert_eq!(
+                    super::filter_max_block_simd(&col, row_ids).unwrap(),
+                    exp_max
+                );
+
+                // wide columns overflow, which the scalar sums catch in debug
+                // builds.
+                if width <= 48 {
+                    assert_eq!(super::filter_sum(&col, row_ids), exp_sum);
+                    assert_eq!(
+                        super::filter_sum_gather_simd(&col, row_ids).unwrap(),
+                        exp_sum
+                    );
+                    assert_eq!(
+                        super::filter_sum_block_simd(&col, row_ids).unwrap(),
+                        exp_sum
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn filter_bit_packed_out_of_bounds() {
+        // the padded rows beyond the column's length must not be readable.
+        let col = super::BitPackedColumn::new(&[1, 2, 3, 4, 5], 3);
+        let err = super::filter_sum_gather_simd(&col, &[0, 1, 2, 5]).unwrap_err();
+        assert_eq!((err.row_id, err.rows), (5, 5));
+
+        // short selections fall back to the scalar max, but are still checked.
+        let err = super::filter_max_gather_simd(&col, &[7]).unwrap_err();
+        assert_eq!((err.row_id, err.rows), (7, 5));
+        let err = super::filter_max_simd(&col, &[0, 1, 2, 3, 4, 6]).unwrap_err();
+        assert_eq!((err.row_id, err.rows), (6, 5));
+    }
+}
@@ -0,0 +1,427 @@
+//! A chunked column, and filter functions over it.
+//!
+//! Real columns often arrive as several chunks (e.g., one per record batch)
+//! rather than as one contiguous `&[u64]`. Row ids are still global though, so
+//! each one has to be mapped to a chunk and an offset within that chunk.
+//!
+//! Looking up every row id on its own costs a binary search over the chunks
+//! per row. When the row ids are sorted they can instead be split into one
+//! contiguous sub-slice per chunk with a binary search over the row ids, the
+//! sub-slice rebased onto the chunk, and then handed to the existing
+//! contiguous kernels (`filter`, `filter_sum` and `filter_max`).
+use std::arch::x86_64::*;
+
+use crate::{
+    filter, filter_max, filter_sum,
+    row_ids::{self, RowIdOutOfBounds},
+};
+
+#[derive(Debug, Clone)]
+pub struct ChunkedColumn {
+    chunks: Vec<Vec<u64>>,
+
+    // the global row id of the first row in each chunk, followed by the number
+    // of rows in the column.
+    starts: Vec<usize>,
+}
+
+impl ChunkedColumn {
+    pub fn new(chunks: Vec<Vec<u64>>) -> Self {
+        let mut starts = Vec::with_capacity(chunks.len() + 1);
+        starts.push(0);
+        for chunk in &chunks {
+            starts.push(starts.last().unwrap() + chunk.len());
+        }
+        assert!(*starts.last().unwrap() <= u32::MAX as usize);
+
+        Self { chunks, starts }
+    }
+
+    /// Split `values` into chunks of `chunk_size` rows (the last chunk may be
+    /// smaller).
+    pub fn from_values(values: &[u64], chunk_size: usize) -> Self {
+        Self::new(values.chunks(chunk_size).map(|c| c.to_vec()).collect())
+    }
+
+    pub fn chunks(&self) -> &[Vec<u64>] {
+        &self.chunks
+    }
+
+    pub fn len(&self) -> usize {
+        *self.starts.last().unwrap()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Map the global row `i` to its chunk and the offset within that chunk.
+    pub fn locate(&self, i: usize) -> (usize, usize) {
+        assert!(
+            i < self.len(),
+            "row {} out of bounds ({} rows)",
+            i,
+            self.len()
+        );
+
+        // empty chunks share a start with the next chunk, so find the last
+        // chunk starting at or before `i`.
+        let chunk = upper_bound(&self.starts, i) - 1;
+        (chunk, i - self.starts[chunk])
+    }
+
+    pub fn get(&self, i: usize) -> u64 {
+        let (chunk, offset) = self.locate(i);
+        self.chunks[chunk][offset]
+    }
+}
+
+// The number of leading values in sorted `values` that are less than `x`.
+fn lower_bound<T: Ord>(values: &[T], x: T) -> usize {
+    let (mut lo, mut hi) = (0, values.len());
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if values[mid] < x {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    lo
+}
+
+// The number of leading values in sorted `values` that are less than or equal
+// to `x`.
+fn upper_bound<T: Ord>(values: &[T], x: T) -> usize {
+    let (mut lo, mut hi) = (0, values.len());
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if values[mid] <= x {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    lo
+}
+
+fn rebase(row_ids: &[u32], start: u32, dst: &mut Vec<u32>) {
+    dst.clear();
+    dst.extend(row_ids.iter().map(|&id| id - start));
+}
+
+// Like `rebase`, but subtracts `start` from eight row ids at a time.
+fn rebase_simd(row_ids: &[u32], start: u32, dst: &mut Vec<u32>) {
+    dst.clear();
+    dst.reserve(row_ids.len());
+
+    unsafe {
+        let start_lanes = _mm256_set1_epi32(start as i32);
+        for chunk in row_ids.chunks_exact(8) {
+            let ids = _mm256_loadu_si256(chunk.as_ptr() as *const __m256i);
+            _mm256_storeu_si256(
+                dst.as_mut_ptr().add(dst.len()) as *mut __m256i,
+                _mm256_sub_epi32(ids, start_lanes),
+            );
+            dst.set_len(dst.len() + 8);
+        }
+    }
+
+    // rebase any remainder - maximum of seven row ids.
+    let rem = row_ids.len() - (row_ids.len() % 8);
+    dst.extend(row_ids.iter().skip(rem).map(|&id| id - start));
+}
+
+// Calls `f(chunk, row_ids)` for each chunk with at least one selected row,
+// where `row_ids` are the selected rows rebased onto the chunk.
+//
+// Splitting the row ids per chunk relies on them being sorted, so that's
+// checked first. Unsorted row ids are handled a row at a time, calling `f`
+// with the chunk and the single rebased row id.
+fn for_each_chunk(
+    col: &ChunkedColumn,
+    row_ids: &[u32],
+    rebase: impl Fn(&[u32], u32, &mut Vec<u32>),
+    mut f: impl FnMut(&[u64], &[u32]) -> Result<(), RowIdOutOfBounds>,
+) -> Result<(), RowIdOutOfBounds> {
+    row_ids::check(row_ids, col.len())?;
+    if !row_ids.windows(2).all(|w| w[0] <= w[1]) {
+        for &id in row_ids.iter() {
+            let (chunk, offset) = col.locate(id as usize);
+            f(&col.chunks[chunk], &[offset as u32])?;
+        }
+        return Ok(());
+    }
+
+    let mut local_ids = vec![];
+    let mut rest = row_ids;
+    while let Some(&first) = rest.first() {
+        let (chunk, _) = col.locate(first as usize);
+        let n = lower_bound(rest, col.starts[chunk + 1] as u32);
+        let (ids, tail) = rest.split_at(n);
+
+        // the first chunk's row ids don't need rebasing.
+        if chunk == 0 {
+            f(&col.chunks[chunk], ids)?;
+        } else {
+            rebase(ids, col.starts[chunk] as u32, &mut local_ids);
+            f(&col.chunks[chunk], &local_ids)?;
+        }
+        rest = tail;
+    }
+    Ok(())
+}
+
+/// This is a relatively idiomatic Rust implementation of filter on a chunked
+/// column. Each row id is mapped to its chunk with a binary search.
+pub fn filter_materialise_values(
+    col: &ChunkedColumn,
+    row_ids: &[u32],
+    mut dst: Vec<u64>,
+) -> Vec<u64> {
+    dst.clear();
+    dst.reserve(row_ids.len());
+
+    for &id in row_ids.iter() {
+        dst.push(col.get(id as usize));
+    }
+
+    assert_eq!(dst.len(), row_ids.len());
+    dst
+}
+
+/// This is a relatively idiomatic Rust implementation of filter_sum on a
+/// chunked column. It serves as a baseline.
+pub fn filter_sum(col: &ChunkedColumn, row_ids: &[u32]) -> u64 {
+    let mut result = 0;
+    for &id in row_ids.iter() {
+        result += col.get(id as usize);
+    }
+    result
+}
+
+/// This is a relatively idiomatic Rust implementation of filter_max on a
+/// chunked column. It serves as a baseline.
+pub fn filter_max(col: &ChunkedColumn, row_ids: &[u32]) -> u64 {
+    row_ids
+        .iter()
+        .map(|&id| col.get(id as usize))
+        .max()
+        .unwrap()
+}
+
+/// This is an implementation of filter on a chunked column that splits the
+/// sorted row ids per chunk and runs `filter::filter_materialise_values` on
+/// each chunk.
+///
+/// Unsorted row ids are looked up a row at a time, and `RowIdOutOfBounds` is
+/// returned if any are out of range.
+pub fn filter_materialise_values_split(
+    col: &ChunkedColumn,
+    row_ids: &[u32],
+    mut dst: Vec<u64>,
+) -> Result<Vec<u64>, RowIdOutOfBounds> {
+    dst.clear();
+    dst.reserve(row_ids.len());
+
+    let mut chunk_dst = vec![];
+    for_each_chunk(col, row_ids, rebase, |values, ids| {
+        chunk_dst = filter::filter_materialise_values(values, ids, std::mem::take(&mut chunk_dst));
+        dst.extend_from_slice(&chunk_dst);
+        Ok(())
+    })?;
+
+    assert_eq!(dst.len(), row_ids.len());
+    Ok(dst)
+}
+
+/// This is an implementation of filter_sum on a chunked column that splits the
+/// sorted row ids per chunk and runs `filter_sum::filter_sum` on each chunk.
+///
+/// Unsorted row ids are looked up a row at a time, and `RowIdOutOfBounds` is
+/// returned if any are out of range.
+pub fn filter_sum_split(col: &ChunkedColumn, row_ids: &[u32]) -> Result<u64, RowIdOutOfBounds> {
+    let mut result = 0;
+    for_each_chunk(col, row_ids, rebase, |values, ids| {
+        result += filter_sum::filter_sum(values, ids);
+        Ok(())
+    })?;
+    Ok(result)
+}
+
+/// This is an implementation of filter_max on a chunked column that splits the
+/// sorted row ids per chunk and runs `filter_max::filter_max` on each chunk.
+///
+/// Unsorted row ids are looked up a row at a time, and `RowIdOutOfBounds` is
+/// returned if any are out of range.
+pub fn filter_max_split(col: &ChunkedColumn, row_ids: &[u32]) -> Result<u64, RowIdOutOfBounds> {
+    let mut result = None;
+    for_each_chunk(col, row_ids, rebase, |values, ids| {
+        result = result.max(Some(filter_max::filter_max(values, ids)));
+        Ok(())
+    })?;
+    Ok(result.unwrap())
+}
+
+/// This is an implementation of filter on a chunked column that splits the
+/// sorted row ids per chunk, rebases them eight at a time, and runs
+/// `filter::filter_materialise_values_simd` on each chunk.
+///
+/// Unsorted row ids are looked up a row at a time, and `RowIdOutOfBounds` is
+/// returned if any are out of range.
+pub fn filter_materialise_values_simd(
+    col: &ChunkedColumn,
+    row_ids: &[u32],
+    mut dst: Vec<u64>,
+) -> Result<Vec<u64>, RowIdOutOfBounds> {
+    dst.clear();
+    dst.reserve(row_ids.len());
+
+    let mut chunk_dst = vec![];
+    for_each_chunk(col, row_ids, rebase_simd, |values, ids| {
+        chunk_dst =
+            filter::filter_materialise_values_simd(values, ids, std::mem::take(&mut chunk_dst))?;
+        dst.extend_from_slice(&chunk_dst);
+        Ok(())
+    })?;
+
+    assert_eq!(dst.len(), row_ids.len());
+    Ok(dst)
+}
+
+/// This is an implementation of filter_sum on a chunked column that splits the
+/// sorted row ids per chunk, rebases them eight at a time, and runs
+/// `filter_sum::filter_sum_simd` on each chunk.
+///
+/// Unsorted row ids are looked up a row at a time, and `RowIdOutOfBounds` is
+/// returned if any are out of range.
+pub fn filter_sum_simd(col: &ChunkedColumn, row_ids: &[u32]) -> Result<u64, RowIdOutOfBounds> {
+    let mut result = 0;
+    for_each_chunk(col, row_ids, rebase_simd, |values, ids| {
+        result += filter_sum::filter_sum_simd(values, ids)?;
+        Ok(())
+    })?;
+    Ok(result)
+}
+
+/// This is an implementation of filter_max on a chunked column that splits the
+/// sorted row ids per chunk, rebases them eight at a time, and runs
+/// `filter_max::filter_max_simd` on each chunk.
+///
+/// Unsorted row ids are looked up a row at a time, and `RowIdOutOfBounds` is
+/// returned if any are out of range.
+pub fn filter_max_simd(col: &ChunkedColumn, row_ids: &[u32]) -> Result<u64, RowIdOutOfBounds> {
+    let mut result = None;
+    for_each_chunk(col, row_ids, rebase_simd, |values, ids| {
+        result = result.max(Some(filter_max::filter_max_simd(values, ids)?));
+        Ok(())
+    })?;
+    Ok(result.unwrap())
+}
+
+mod test {
+
+    #[test]
+    fn chunked_column() {
+        let col = super::ChunkedColumn::new(vec![vec![1, 2, 3], vec![], vec![4], vec![5, 6]]);
+
+        assert_eq!(col.len(), 6);
+        assert_eq!(col.locate(0), (0, 0));
+        assert_eq!(col.locate(2), (0, 2));
+        assert_eq!(col.locate(3), (2, 0));
+        assert_eq!(col.locate(5), (3, 1));
+        assert_eq!(
+            (0..6).map(|i| col.get(i)).collect::<Vec<_>>(),
+            vec![1, 2, 3, 4, 5, 6]
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn chunked_column_out_of_bounds() {
+        super::ChunkedColumn::from_values(&[1, 2, 3], 2).locate(3);
+    }
+
+    #[test]
+    fn filter_chunked() {
+        let values = (0..1000_u64).map(|i| (i * 7919) % 1013).collect::<Vec<_>>();
+
+        let cases = vec![
+            vec![0_u32],
+            vec![0, 1, 2, 3],
+            vec![999],
+            vec![1, 1, 5, 5, 5, 700],
+            (0..1000).collect::<Vec<_>>(),
+            (0..1000).step_by(3).collect::<Vec<_>>(),
+            (60..70).chain(500..513).collect::<Vec<_>>(),
+        ];
+
+        for &chunk_size in &[1, 7, 64, 1000, 2000] {
+            let col = super::ChunkedColumn::from_values(&values, chunk_size);
+            for row_ids in &cases {
+                let exp = row_ids
+                    .iter()
+                    .map(|&id| values[id as usize])
+                    .collect::<Vec<_>>();
+                let sum = exp.iter().sum::<u64>();
+                let max = *exp.iter().max().unwrap();
+
+                assert_eq!(super::filter_materialise_values(&col, row_ids, vec![]), exp);
+                assert_eq!(
+                    super::filter_materialise_values_split(&col, row_ids, vec![]).unwrap(),
+                    exp
+                );
+                assert_eq!(
+                    super::filter_materialise_values_simd(&col, row_ids, vec![]).unwrap(),
+                    exp
+                );
+
+                assert_eq!(super::filter_sum(&col, row_ids), sum);
+                assert_eq!(super::filter_sum_split(&col, row_ids).unwrap(), sum);
+                assert_eq!(super::filter_sum_simd(&col, row_ids).unwrap(), sum);
+
+                assert_eq!(super::filter_max(&col, row_ids), max);
+                assert_eq!(super::filter_max_split(&col, row_ids).unwrap(), max);
+                assert_eq!(super::filter_max_simd(&col, row_ids).unwrap(), max);
+            }
+        }
+    }
+
+    #[test]
+    fn filter_chunked_unsorted() {
+        let values = (0..100_u64).map(|i| (i * 7919) % 1013).collect::<Vec<_>>();
+        let col = super::ChunkedColumn::from_values(&values, 7);
+        let row_ids = vec![50_u32, 3, 3, 99, 0, 8];
+
+        let exp = row_ids
+            .iter()
+            .map(|&id| values[id as usize])
+            .collect::<Vec<_>>();
+        let sum = exp.iter().sum::<u64>();
+        let max = *exp.iter().max().unwrap();
+
+        assert_eq!(
+            super::filter_materialise_values_split(&col, &row_ids, vec![]).unwrap(),
+            exp
+        );
+        assert_eq!(
+            super::filter_materialise_values_simd(&col, &row_ids, vec![]).unwrap(),
+            exp
+        );
+        assert_eq!(super::filter_sum_split(&col, &row_ids).unwrap(), sum);
+        assert_eq!(super::filter_sum_simd(&col, &row_ids).unwrap(), sum);
+        assert_eq!(super::filter_max_split(&col, &row_ids).unwrap(), max);
+        assert_eq!(super::filter_max_simd(&col, &row_ids).unwrap(), max);
+    }
+
+    #[test]
+    fn filter_chunked_out_of_bounds() {
+        let col = super::ChunkedColumn::from_values(&[1, 2, 3], 2);
+        let err = super::filter_sum_simd(&col, &[0, 3]).unwrap_err();
+        assert_eq!((err.row_id, err.rows), (3, 3));
+
+        // unsorted row ids are checked too.
+        let err = super::filter_max_split(&col, &[5, 0]).unwrap_err();
+        assert_eq!((err.row_id, err.rows), (5, 3));
+    }
+}
@@ -0,0 +1,481 @@
+//! Comparing two sets of results, e.g., a baseline and a candidate after
+//! changing a kernel or bumping the Arrow revision.
+//!
+//! Like criterion, a change is only reported when it is statistically
+//! significant: each benchmark's samples are compared with Welch's t-test, and
+//! the change must also be larger than a threshold to count as a regression or
+//! an improvement.
+//!
+//! `compare` compares two result files, and `compare_criterion` two criterion
+//! baselines (see `results::read_criterion`), using every sample criterion
+//! saved.
+use std::fmt;
+
+use crate::{
+    results::{CriterionBenchmark, Record, Results},
+    runner::{Impl, Op},
+};
+
+/// Whether a benchmark got faster or slower.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Improved,
+    Regressed,
+    NoChange,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Improved => write!(f, "improved"),
+            Self::Regressed => write!(f, "regressed"),
+            Self::NoChange => write!(f, "no change"),
+        }
+    }
+}
+
+/// The change in one benchmark between the baseline and the candidate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub op: Op,
+    pub imp: Impl,
+    pub filter: String,
+    pub rows: usize,
+    pub baseline_ns: f64,
+    pub candidate_ns: f64,
+
+    // the relative change in time per iteration; positive is slower.
+    pub change: f64,
+    pub p_value: f64,
+    pub status: Status,
+}
+
+/// The benchmarks in both result sets, compared, and those only in one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub comparisons: Vec<Comparison>,
+    pub only_baseline: Vec<Record>,
+    pub only_candidate: Vec<Record>,
+}
+
+impl Report {
+    pub fn regressions(&self) -> impl Iterator<Item = &Comparison> {
+        self.comparisons
+            .iter()
+            .filter(|c| c.status == Status::Regressed)
+    }
+}
+
+fn same_benchmark(a: &Record, b: &Record) -> bool {
+    a.op == b.op && a.imp == b.imp && a.filter == b.filter && a.rows == b.rows
+}
+
+/// Compare each benchmark in `candidate` to the same benchmark in `baseline`.
+///
+/// A change is significant if the t-test's p-value is below `significance`
+/// (e.g., `0.05`), and is a regression or improvement if it's also larger
+/// than `threshold` (e.g., `0.05` for 5%).
+pub fn compare(
+    baseline: &Results,
+    candidate: &Results,
+    threshold: f64,
+    significance: f64,
+) -> Report {
+    let mut comparisons = vec![];
+    let mut only_candidate = vec![];
+    for c in &candidate.records {
+        let b = match baseline.records.iter().find(|b| same_benchmark(b, c)) {
+            Some(b) => b,
+            None => {
+                only_candidate.push(c.clone());
+                continue;
+            }
+        };
+
+        let change = (c.ns_per_iter - b.ns_per_iter) / b.ns_per_iter;
+        let p_value = welch_t_test(
+            (b.ns_per_iter, b.std_dev_ns, b.samples),
+            (c.ns_per_iter, c.std_dev_ns, c.samples),
+        );
+
+        let status = status(change, p_value, threshold, significance);
+
+        comparisons.push(Comparison {
+            op: c.op,
+            imp: c.imp,
+            filter: c.filter.clone(),
+            rows: c.rows,
+            baseline_ns: b.ns_per_iter,
+            candidate_ns: c.ns_per_iter,
+            change,
+            p_value,
+            status,
+        });
+    }
+
+    let only_baseline = baseline
+        .records
+        .iter()
+        .filter(|b| !candidate.records.iter().any(|c| same_benchmark(b, c)))
+        .cloned()
+        .collect();
+
+    Report {
+        comparisons,
+        only_baseline,
+        only_candidate,
+    }
+}
+
+/// The change in one criterion benchmark between two baselines.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CriterionComparison {
+    pub id: String,
+    pub baseline_ns: f64,
+    pub candidate_ns: f64,
+
+    // the relative change in criterion's mean time per iteration; positive is
+    // slower.
+    pub change: f64,
+    pub p_value: f64,
+    pub status: Status,
+}
+
+/// The criterion benchmarks in both baselines, compared, and the ids of those
+/// only in one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CriterionReport {
+    pub comparisons: Vec<CriterionComparison>,
+    pub only_baseline: Vec<String>,
+    pub only_candidate: Vec<String>,
+}
+
+impl CriterionReport {
+    pub fn regressions(&self) -> impl Iterator<Item = &CriterionComparison> {
+        self.comparisons
+            .iter()
+            .filter(|c| c.status == Status::Regressed)
+    }
+}
+
+/// Compare each criterion benchmark in `candidate` to the one with the same id
+/// in `baseline`, like `compare`. The t-test is run on the time per iteration
+/// of each of criterion's samples.
+pub fn compare_criterion(
+    baseline: &[CriterionBenchmark],
+    candidate: &[CriterionBenchmark],
+    threshold: f64,
+    significance: f64,
+) -> CriterionReport {
+    let mut comparisons = vec![];
+    let mut only_candidate = vec![];
+    for c in candidate {
+        let b = match baseline.iter().find(|b| b.id == c.id) {
+            Some(b) => b,
+            None => {
+                only_candidate.push(c.id.clone());
+                continue;
+            }
+        };
+
+        let change = (c.mean_ns - b.mean_ns) / b.mean_ns;
+        let p_value = welch_t_test(summary(&b.samples_ns), summary(&c.samples_ns));
+        comparisons.push(CriterionComparison {
+            id: c.id.clone(),
+            baseline_ns: b.mean_ns,
+            candidate_ns: c.mean_ns,
+            change,
+            p_value,
+            status: status(change, p_value, threshold, significance),
+        });
+    }
+
+    let only_baseline = baseline
+        .iter()
+        .filter(|b| !candidate.iter().any(|c| c.id == b.id))
+        .map(|b| b.id.clone())
+        .collect();
+
+    CriterionReport {
+        comparisons,
+        only_baseline,
+        only_candidate,
+    }
+}
+
+fn status(change: f64, p_value: f64, threshold: f64, significance: f64) -> Status {
+    match () {
+        _ if p_value >= significance => Status::NoChange,
+        _ if change > threshold => Status::Regressed,
+        _ if change < -threshold => Status::Improved,
+        _ => Status::NoChange,
+    }
+}
+
+// The (mean, standard deviation, size) of `samples`.
+fn summary(samples: &[f64]) -> (f64, f64, usize) {
+    let n = samples.len();
+    if n < 2 {
+        return (samples.first().copied().unwrap_or(0.0), 0.0, n);
+    }
+    let mean = samples.iter().sum::<f64>() / n as f64;
+    let var = samples.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / (n - 1) as f64;
+    (mean, var.sqrt(), n)
+}
+
+// The two-sided p-value of Welch's t-test for the difference between two
+// means, given each sample's (mean, standard deviation, size).
+fn welch_t_test(a: (f64, f64, usize), b: (f64, f64, usize)) -> f64 {
+    let ((mean_a, sd_a, n_a), (mean_b, sd_b, n_b)) = (a, b);
+    if n_a < 2 || n_b < 2 {
+        // no estimate of the noise, so nothing is significant.
+        return 1.0;
+    }
+
+    let (var_a, var_b) = (sd_a * sd_a / n_a as f64, sd_b * sd_b / n_b as f64);
+    if var_a + var_b == 0.0 {
+        return if mean_a == mean_b { 1.0 } else { 0.0 };
+    }
+
+    let t = (mean_a - mean_b) / (var_a + var_b).sqrt();
+    let df = (var_a + var_b).powi(2)
+        / (var_a.powi(2) / (n_a - 1) as f64 + var_b.powi(2) / (n_b - 1) as f64);
+
+    // the Student's t distribution's two tails.
+    incomplete_beta(df / 2.0, 0.5, df / (df + t * t))
+}
+
+// The regularised incomplete beta function I_x(a, b), using the continued
+// fraction in Numerical Recipes (6.4).
+fn incomplete_beta(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    } else if x >= 1.0 {
+        return 1.0;
+    }
+
+    let ln_front = ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln();
+    // the continued fraction converges quickly on this side of the mean.
+    if x < (a + 1.0) / (a + b + 2.0) {
+        ln_front.exp() * beta_continued_fraction(a, b, x) / a
+    } else {
+        1.0 - ln_front.exp() * beta_continued_fraction(b, a, 1.0 - x) / b
+    }
+}
+
+// Evaluate the incomplete beta function's continued fraction with the
+// modified Lentz method.
+fn beta_continued_fraction(a: f64, b: f64, x: f64) -> f64 {
+    const TINY: f64 = 1e-300;
+
+    let mut c = 1.0;
+    let mut d = 1.0 - (a + b) * x / (a + 1.0);
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1.0 / d;
+    let mut h = d;
+
+    for m in 1..300 {
+        let m = m as f64;
+
+        // the even and odd steps of the recurrence.
+        for &num in &[
+            m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m)),
+            -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0)),
+        ] {
+            d = 1.0 + num * d;
+            if d.abs() < TINY {
+                d = TINY;
+            }
+            c = 1.0 + num / c;
+            if c.abs() < TINY {
+                c = TINY;
+            }
+            d = 1.0 / d;
+            h *= d * c;
+        }
+
+        if (d * c - 1.0).abs() < 1e-12 {
+            break;
+        }
+    }
+    h
+}
+
+// ln(Γ(x)) for x > 0, using the Lanczos approximation (g = 7, n = 9).
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+
+    if x < 0.5 {
+        // the reflection formula.
+        let pi = std::f64::consts::PI;
+        return (pi / (pi * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+
+    let x = x - 1.0;
+    let mut sum = COEFFICIENTS[0];
+    for (i, &c) in COEFFICIENTS.iter().enumerate().skip(1) {
+        sum += c / (x + i as f64);
+    }
+    let t = x + 7.5;
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+mod test {
+    use crate::{
+        results::{CriterionBenchmark, Environment, Record, Results},
+        runner::{Impl, Op},
+    };
+
+    fn results(records: Vec<(&str, f64, f64)>) -> Results {
+        Results {
+            environment: Environment::current(0),
+            records: records
+                .into_iter()
+                .map(|(filter, ns_per_iter, std_dev_ns)| Record {
+                    op: Op::Sum,
+                    imp: Impl::Simd,
+                    filter: filter.to_owned(),
+                    rows: 1000,
+                    selected: 100,
+                    iterations: 1000,
+                    samples: 20,
+                    ns_per_iter,
+                    std_dev_ns,
+                    melem_per_sec: 100.0 / ns_per_iter * 1e3,
+                    gb_per_sec: 100.0 / ns_per_iter * 8.0,
+                    correct: true,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn welch_t_test() {
+        // t = 2.228 with 10 degrees of freedom is the 5% two-sided critical
+        // value. Equal variances and sizes give (n_a + n_b - 2) degrees of
+        // freedom, and a standard error of sqrt(2 * 3 / 6) = 1.
+        let sd = 3.0_f64.sqrt();
+        let p = super::welch_t_test((2.228, sd, 6), (0.0, sd, 6));
+        assert!((p - 0.05).abs() < 1e-3, "p = {}", p);
+
+        assert_eq!(super::welch_t_test((1.0, 0.0, 6), (1.0, 0.0, 6)), 1.0);
+        assert_eq!(super::welch_t_test((1.0, 0.5, 1), (9.0, 0.5, 1)), 1.0);
+        assert!(super::welch_t_test((1.0, 0.1, 20), (1.0, 0.1, 20)) > 0.99);
+    }
+
+    #[test]
+    fn ln_gamma() {
+        for &(x, exp) in &[(0.5, std::f64::consts::PI.sqrt()), (1.0, 1.0), (5.0, 24.0)] {
+            assert!((super::ln_gamma(x) - f64::ln(exp)).abs() < 1e-10, "{}", x);
+        }
+    }
+
+    #[test]
+    fn compare() {
+        let baseline = results(vec![
+            ("slower", 100.0, 1.0),
+            ("faster", 100.0, 1.0),
+            ("noisy", 100.0, 50.0),
+            ("small", 100.0, 0.1),
+            ("removed", 100.0, 1.0),
+        ]);
+        let candidate = results(vec![
+            ("slower", 120.0, 1.0),
+            ("faster", 80.0, 1.0),
+            ("noisy", 120.0, 50.0),
+            ("small", 101.0, 0.1),
+            ("added", 100.0, 1.0),
+        ]);
+
+        let report = super::compare(&baseline, &candidate, 0.05, 0.05);
+        let statuses = report
+            .comparisons
+            .iter()
+            .map(|c| (c.filter.as_str(), c.status))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            statuses,
+            vec![
+                ("slower", super::Status::Regressed),
+                ("faster", super::Status::Improved),
+                ("noisy", super::Status::NoChange),
+                ("small", super::Status::NoChange),
+            ]
+        );
+        assert!((report.comparisons[0].change - 0.2).abs() < 1e-9);
+        assert_eq!(report.regressions().count(), 1);
+        assert_eq!(report.only_baseline[0].filter, "removed");
+        assert_eq!(report.only_candidate[0].filter, "added");
+    }
+
+    fn criterion(id: &str, samples_ns: Vec<f64>) -> CriterionBenchmark {
+        let mean_ns = samples_ns.iter().sum::<f64>() / samples_ns.len() as f64;
+        CriterionBenchmark {
+            id: id.to_owned(),
+            group: id.split('/').next().unwrap().to_owned(),
+            parameter: id.split('/').nth(1).unwrap().to_owned(),
+            elements: Some(100),
+            iterations: 1000,
+            samples_ns,
+            mean_ns,
+            std_dev_ns: 0.0,
+        }
+    }
+
+    #[test]
+    fn summary() {
+        let (mean, sd, n) = super::summary(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]);
+        assert_eq!((mean, n), (5.0, 8));
+        assert!((sd - (32.0_f64 / 7.0).sqrt()).abs() < 1e-12);
+        assert_eq!(super::summary(&[3.0]), (3.0, 0.0, 1));
+    }
+
+    #[test]
+    fn compare_criterion() {
+        let steady = |ns: f64| vec![ns - 1.0, ns, ns + 1.0, ns - 0.5, ns + 0.5];
+        let noisy = |ns: f64| vec![ns - 50.0, ns + 40.0, ns, ns + 60.0, ns - 50.0];
+        let baseline = vec![
+            criterion("a/slower", steady(100.0)),
+            criterion("a/faster", steady(100.0)),
+            criterion("a/noisy", noisy(100.0)),
+            criterion("a/removed", steady(100.0)),
+        ];
+        let candidate = vec![
+            criterion("a/slower", steady(120.0)),
+            criterion("a/faster", steady(80.0)),
+            criterion("a/noisy", noisy(120.0)),
+            criterion("a/added", steady(100.0)),
+        ];
+
+        let report = super::compare_criterion(&baseline, &candidate, 0.05, 0.05);
+        let statuses = report
+            .comparisons
+            .iter()
+            .map(|c| (c.id.as_str(), c.status))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            statuses,
+            vec![
+                ("a/slower", super::Status::Regressed),
+                ("a/faster", super::Status::Improved),
+                ("a/noisy", super::Status::NoChange),
+            ]
+        );
+        assert!((report.comparisons[0].change - 0.2).abs() < 1e-9);
+        assert_eq!(report.regressions().count(), 1);
+        assert_eq!(report.only_baseline, vec!["a/removed"]);
+        assert_eq!(report.only_candidate, vec!["a/added"]);
+    }
+}
@@ -0,0 +1,264 @@
+//! Loading a column and its filters from an Arrow IPC or Parquet file, so the
+//! benchmarks can run against real data rather than the synthetic columns.
+//!
+//! A dataset file holds one column of values and any number of filters on it:
+//!
+//! - the values come from the column called `values`, or the first column
+//!   that isn't a `Boolean` column. Unsigned and non-negative signed integer
+//!   columns (including nanosecond timestamps) are supported.
+//! - every `Boolean` column is a filter, named after the column. Null is
+//!   treated as false.
+//!
+//! The benchmarks load the file named by the `ARROW_BENCH_DATA` environment
+//! variable, and fall back to their synthetic generators when it's not set.
+//! Parquet files need the `parquet` feature.
+use std::{fs::File, path::Path, sync::Arc};
+
+use arrow::{
+    array::{self, Array},
+    datatypes::{DataType, Field, Schema, TimeUnit},
+    ipc,
+    record_batch::RecordBatch,
+};
+
+/// The environment variable the benchmarks read a dataset path from.
+pub const DATA_ENV: &str = "ARROW_BENCH_DATA";
+
+/// A column and a set of named filters on it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dataset {
+    pub values: Vec<u64>,
+    pub filters: Vec<(String, Vec<u32>)>,
+}
+
+impl Dataset {
+    /// Build a dataset from record batches (e.g., each row group of a file).
+    pub fn from_batches(batches: &[RecordBatch]) -> Self {
+        let mut dataset = Self {
+            values: vec![],
+            filters: vec![],
+        };
+        let schema = match batches.first() {
+            Some(batch) => batch.schema(),
+            None => return dataset,
+        };
+
+        let fields = schema.fields();
+        let values_idx = fields
+            .iter()
+            .position(|f| f.name() == "values")
+            .or_else(|| {
+                fields
+                    .iter()
+                    .position(|f| f.data_type() != &DataType::Boolean)
+            })
+            .expect("dataset has no values column");
+
+        let filter_idxs = (0..fields.len())
+            .filter(|&i| i != values_idx && fields[i].data_type() == &DataType::Boolean)
+            .collect::<Vec<_>>();
+        for &i in &filter_idxs {
+            dataset.filters.push((fields[i].name().clone(), vec![]));
+        }
+
+        for batch in batches {
+            let offset = dataset.values.len() as u32;
+            append_values(batch.column(values_idx), &mut dataset.values);
+
+            for (&i, (_, row_ids)) in filter_idxs.iter().zip(dataset.filters.iter_mut()) {
+                let filter = batch
+                    .column(i)
+                    .as_any()
+                    .downcast_ref::<array::BooleanArray>()
+                    .unwrap();
+                row_ids.extend(
+                    (0..filter.len())
+                        .filter(|&j| !filter.is_null(j) && filter.value(j))
+                        .map(|j| offset + j as u32),
+                );
+            }
+        }
+
+        assert!(dataset.values.len() <= u32::MAX as usize);
+        dataset
+    }
+
+    /// Build a single record batch holding the dataset, with the values as a
+    /// `UInt64` column and each filter as a `Boolean` column.
+    pub fn to_batch(&self) -> RecordBatch {
+        let mut fields = vec![Field::new("values", DataType::UInt64, false)];
+        let mut columns: Vec<array::ArrayRef> =
+            vec![Arc::new(array::UInt64Array::from(self.values.clone()))];
+
+        for (name, row_ids) in &self.filters {
+            let mut filter = vec![false; self.values.len()];
+            for &id in row_ids {
+                filter[id as usize] = true;
+            }
+            fields.push(Field::new(name, DataType::Boolean, false));
+            columns.push(Arc::new(array::BooleanArray::from(filter)));
+        }
+
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), columns).unwrap()
+    }
+}
+
+// Append the values of an integer column to `dst` as `u64`s.
+fn append_values(column: &array::ArrayRef, dst: &mut Vec<u64>) {
+    assert_eq!(column.null_count(), 0, "values column can't contain nulls");
+
+    macro_rules! append {
+        ($array_type:ty) => {{
+            let column = column.as_any().downcast_ref::<$array_type>().unwrap();
+            dst.extend((0..column.len()).map(|i| column.value(i) as u64));
+        }};
+        ($array_type:ty, signed) => {{
+            let column = column.as_any().downcast_ref::<$array_type>().unwrap();
+            for i in 0..column.len() {
+                let v = column.value(i);
+                assert!(v >= 0, "values column can't contain negative values");
+                dst.push(v as u64);
+            }
+        }};
+    }
+
+    match column.data_type() {
+        DataType::UInt8 => append!(array::UInt8Array),
+        DataType::UInt16 => append!(array::UInt16Array),
+        DataType::UInt32 => append!(array::UInt32Array),
+        DataType::UInt64 => append!(array::UInt64Array),
+        DataType::Int32 => append!(array::Int32Array, signed),
+        DataType::Int64 => append!(array::Int64Array, signed),
+        DataType::Timestamp(TimeUnit::Nanosecond, _) => {
+            append!(array::TimestampNanosecondArray, signed)
+        }
+        t => panic!("unsupported values column type {:?}", t),
+    }
+}
+
+/// Load a dataset from an Arrow IPC file.
+pub fn load_ipc(path: &Path) -> Dataset {
+    let file = File::open(path).unwrap_or_else(|e| panic!("can't open {:?}: {}", path, e));
+    let reader = ipc::reader::FileReader::try_new(file).unwrap();
+    let batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+    Dataset::from_batches(&batches)
+}
+
+/// Load a dataset from a Parquet file.
+#[cfg(feature = "parquet")]
+pub fn load_parquet(path: &Path) -> Dataset {
+    use parquet::{
+        arrow::{ArrowReader, ParquetFileArrowReader},
+        file::reader::SerializedFileReader,
+    };
+
+    let file = File::open(path).unwrap_or_else(|e| panic!("can't open {:?}: {}", path, e));
+    let file_reader = SerializedFileReader::new(file).unwrap();
+    let mut reader = ParquetFileArrowReader::new(Arc::new(file_reader));
+    let batches = reader
+        .get_record_reader(64 * 1024)
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    Dataset::from_batches(&batches)
+}
+
+/// Load a dataset from a Parquet file.
+#[cfg(not(feature = "parquet"))]
+pub fn load_parquet(path: &Path) -> Dataset {
+    panic!(
+        "can't load {:?}: build with `--features parquet` to load Parquet files",
+        path
+    )
+}
+
+/// Load a dataset from an Arrow IPC (`.arrow`, `.ipc` or `.feather`) or
+/// Parquet (`.parquet`) file.
+pub fn load(path: &Path) -> Dataset {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("parquet") => load_parquet(path),
+        Some("arrow") | Some("ipc") | Some("feather") => load_ipc(path),
+        _ => panic!("unknown dataset file type {:?}", path),
+    }
+}
+
+/// Load the dataset named by the `ARROW_BENCH_DATA` environment variable, if
+/// it's set.
+pub fn from_env() -> Option<Dataset> {
+    std::env::var_os(DATA_ENV).map(|path| load(Path::new(&path)))
+}
+
+/// Write a dataset to an Arrow IPC file, e.g., to check a distribution into
+/// `benches/data`.
+pub fn write_ipc(path: &Path, dataset: &Dataset) {
+    let batch = dataset.to_batch();
+    let file = File::create(path).unwrap_or_else(|e| panic!("can't create {:?}: {}", path, e));
+    let mut writer = ipc::writer::FileWriter::try_new(file, &batch.schema()).unwrap();
+    writer.write(&batch).unwrap();
+    writer.finish().unwrap();
+}
+
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{
+        array,
+        datatypes::{DataType, Field, Schema},
+        record_batch::RecordBatch,
+    };
+
+    // A batch with a timestamp-like column and two filters on it.
+    fn batch(values: Vec<i64>) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("even", DataType::Boolean, false),
+            Field::new("ts", DataType::Int64, false),
+            Field::new("big", DataType::Boolean, false),
+        ]));
+
+        let even = values.iter().map(|v| v % 2 == 0).collect::<Vec<_>>();
+        let big = values.iter().map(|&v| v > 100).collect::<Vec<_>>();
+        let columns: Vec<array::ArrayRef> = vec![
+            Arc::new(array::BooleanArray::from(even)),
+            Arc::new(array::Int64Array::from(values)),
+            Arc::new(array::BooleanArray::from(big)),
+        ];
+        RecordBatch::try_new(schema, columns).unwrap()
+    }
+
+    #[test]
+    fn from_batches() {
+        let dataset = super::Dataset::from_batches(&[batch(vec![1, 2, 200]), batch(vec![4, 101])]);
+        assert_eq!(dataset.values, vec![1, 2, 200, 4, 101]);
+        assert_eq!(
+            dataset.filters,
+            vec![
+                ("even".to_owned(), vec![1, 2, 3]),
+                ("big".to_owned(), vec![2, 4])
+            ]
+        );
+    }
+
+    #[test]
+    fn ipc_round_trip() {
+        let dataset = super::Dataset {
+            values: (0..100).map(|v| v * 3).collect(),
+            filters: vec![
+                ("tenth".to_owned(), (0..100).step_by(10).collect()),
+                ("none".to_owned(), vec![]),
+            ],
+        };
+
+        // a path of its own, so concurrent test runs don't share the file.
+        let path = std::env::temp_dir().join(format!(
+            "rust_arrow_benches_dataset_{}_{}.arrow",
+            std::process::id(),
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos(),
+        ));
+        super::write_ipc(&path, &dataset);
+        assert_eq!(super::load(&path), dataset);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,282 @@
+//! A delta-encoded column, and filter functions that materialise values from
+//! it using checkpointed prefix sums.
+//!
+//! A delta column stores each row as the difference from the previous row.
+//! The deltas of sorted or timestamp columns are small so they're bit-packed
+//! (see `bit_packed`). Deltas are wrapping, so any `u64` column can be
+//! encoded, though unsorted columns won't compress.
+//!
+//! Decoding row `i` means summing every delta before it, so the absolute value
+//! of every `CHECKPOINT_INTERVAL`th row is kept as a checkpoint. Decoding a row
+//! then only needs the deltas since its checkpoint. The checkpoint interval is
+//! the same as the bit-packed block size, so a whole checkpoint interval can be
+//! decoded by unpacking one block of deltas and prefix-summing it.
+use std::arch::x86_64::*;
+
+use crate::{
+    bit_packed::{BitPackedColumn, BLOCK_SIZE},
+    row_ids::{self, RowIdOutOfBounds},
+};
+
+/// The number of rows between checkpoints.
+pub const CHECKPOINT_INTERVAL: usize = BLOCK_SIZE;
+
+#[derive(Debug, Clone)]
+pub struct DeltaColumn {
+    // the value of every `CHECKPOINT_INTERVAL`th row.
+    checkpoints: Vec<u64>,
+
+    // the delta from the previous row for every row. The delta of a
+    // checkpointed row is always zero.
+    deltas: BitPackedColumn,
+}
+
+impl DeltaColumn {
+    pub fn new(values: &[u64]) -> Self {
+        let mut checkpoints = Vec::with_capacity(values.len() / CHECKPOINT_INTERVAL + 1);
+        let mut deltas = Vec::with_capacity(values.len());
+
+        for (i, &v) in values.iter().enumerate() {
+            if i % CHECKPOINT_INTERVAL == 0 {
+                checkpoints.push(v);
+                deltas.push(0);
+            } else {
+                deltas.push(v.wrapping_sub(values[i - 1]));
+            }
+        }
+
+        Self {
+            checkpoints,
+            deltas: BitPackedColumn::from_values(&deltas),
+        }
+    }
+
+    /// The bit-packed deltas.
+    pub fn deltas(&self) -> &BitPackedColumn {
+        &self.deltas
+    }
+
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    /// Decode the value at row `i` by summing the deltas since its checkpoint.
+    pub fn get(&self, i: usize) -> u64 {
+        let checkpoint = i / CHECKPOINT_INTERVAL;
+        ((checkpoint * CHECKPOINT_INTERVAL + 1)..=i).fold(self.checkpoints[checkpoint], |v, j| {
+            v.wrapping_add(self.deltas.get(j))
+        })
+    }
+
+    /// Decode every value in the column.
+    pub fn decode(&self) -> Vec<u64> {
+        let mut result = Vec::with_capacity(self.len());
+        let mut v = 0_u64;
+        for i in 0..self.len() {
+            v = if i % CHECKPOINT_INTERVAL == 0 {
+                self.checkpoints[i / CHECKPOINT_INTERVAL]
+            } else {
+                v.wrapping_add(self.deltas.get(i))
+            };
+            result.push(v);
+        }
+        result
+    }
+
+    // Decode every value in the checkpoint interval `checkpoint` into `dst`.
+    // The deltas are unpacked with SIMD and then prefix-summed four lanes at a
+    // time.
+    fn decode_interval_simd(&self, checkpoint: usize, dst: &mut [u64; CHECKPOINT_INTERVAL]) {
+        self.deltas.unpack_block(checkpoint, dst);
+
+        unsafe {
+            let zero = _mm256_setzero_si256();
+            let mut carry = _mm256_set1_epi64x(self.checkpoints[checkpoint] as i64);
+
+            for chunk in dst.chunks_exact_mut(4) {
+                let ptr = chunk.as_mut_ptr() as *mut __m256i;
+                let mut x = _mm256_loadu_si256(ptr);
+
+                // [a, b, c, d] -> [a, a+b, b+c, c+d] -> [a, a+b, a+b+c, a+b+c+d]
+                x = _mm256_add_epi64(
+                    x,
+                    _mm256_blend_epi32(
+                        _mm256_permute4x64_epi64(x, 0b10_01_00_00),
+                        zero,
+                        0b0000_0011,
+                    ),
+                );
+                x = _mm256_add_epi64(
+                    x,
+                    _mm256_blend_epi32(
+                        _mm256_permute4x64_epi64(x, 0b01_00_00_00),
+                        zero,
+                        0b0000_1111,
+                    ),
+                );
+                x = _mm256_add_epi64(x, carry);
+
+                _mm256_storeu_si256(ptr, x);
+
+                // broadcast the last lane as the carry into the next chunk.
+                carry = _mm256_permute4x64_epi64(x, 0b11_11_11_11);
+            }
+        }
+    }
+}
+
+/// This is a relatively idiomatic Rust implementation of filter on a delta
+/// column. Each selected value is decoded on its own from its checkpoint.
+pub fn filter_materialise_values(
+    col: &DeltaColumn,
+    row_ids: &[u32],
+    mut dst: Vec<u64>,
+) -> Vec<u64> {
+    dst.clear();
+    dst.reserve(row_ids.len());
+
+    for &id in row_ids.iter() {
+        dst.push(col.get(id as usize));
+    }
+
+    assert_eq!(dst.len(), row_ids.len());
+    dst
+}
+
+/// This is an implementation of filter on a delta column that keeps a running
+/// prefix sum. When the next row id is ahead of the previous one in the same
+/// checkpoint interval decoding carries on from the previous row, otherwise
+/// it starts again from the checkpoint. For sorted row ids every delta is
+/// unpacked at most once.
+pub fn filter_materialise_values_prefix_sum(
+    col: &DeltaColumn,
+    row_ids: &[u32],
+    mut dst: Vec<u64>,
+) -> Vec<u64> {
+    dst.clear();
+    dst.reserve(row_ids.len());
+
+    // the row and value the running sum has got to.
+    let mut row = usize::MAX;
+    let mut value = 0_u64;
+
+    for &id in row_ids.iter() {
+        let id = id as usize;
+        let checkpoint = id / CHECKPOINT_INTERVAL;
+        if row == usize::MAX || row / CHECKPOINT_INTERVAL != checkpoint || row > id {
+            row = checkpoint * CHECKPOINT_INTERVAL;
+            value = col.checkpoints[checkpoint];
+        }
+
+        while row < id {
+            row += 1;
+            value = value.wrapping_add(col.deltas.get(row));
+        }
+        dst.push(value);
+    }
+
+    assert_eq!(dst.len(), row_ids.len());
+    dst
+}
+
+/// This is an implementation of filter on a delta column using SIMD
+/// intrinsics. The checkpoint interval holding each selected row is decoded
+/// as a whole, by unpacking a block of deltas and prefix-summing them four
+/// lanes at a time. For sorted row ids each interval is decoded at most once.
+///
+/// The row ids are checked with `row_ids::check` before any intervals are
+/// decoded, and `RowIdOutOfBounds` is returned if any are out of range.
+pub fn filter_materialise_values_simd(
+    col: &DeltaColumn,
+    row_ids: &[u32],
+    mut dst: Vec<u64>,
+) -> Result<Vec<u64>, RowIdOutOfBounds> {
+    row_ids::check(row_ids, col.len())?;
+    dst.clear();
+    dst.reserve(row_ids.len());
+
+    let mut interval = [0_u64; CHECKPOINT_INTERVAL];
+    let mut current = usize::MAX;
+    for &id in row_ids.iter() {
+        let id = id as usize;
+        if id / CHECKPOINT_INTERVAL != current {
+            current = id / CHECKPOINT_INTERVAL;
+            col.decode_interval_simd(current, &mut interval);
+        }
+        dst.push(interval[id % CHECKPOINT_INTERVAL]);
+    }
+
+    assert_eq!(dst.len(), row_ids.len());
+    Ok(dst)
+}
+
+mod test {
+
+    #[test]
+    fn delta_column() {
+        let values = (0..1000_u64)
+            .map(|i| 1_600_000_000_000_000_000 + i * 1_000_000 + i % 7)
+            .collect::<Vec<_>>();
+        let col = super::DeltaColumn::new(&values);
+
+        assert_eq!(col.deltas().width(), 20);
+        assert_eq!(col.decode(), values);
+        for (i, &v) in values.iter().enumerate() {
+            assert_eq!(col.get(i), v);
+        }
+
+        // unsorted values still round trip.
+        let values = vec![10, 3, u64::MAX, 0, 99];
+        assert_eq!(super::DeltaColumn::new(&values).decode(), values);
+    }
+
+    #[test]
+    fn filter_delta() {
+        let values = (0..1000_u64)
+            .map(|i| 1_000_000 + i * 50 + (i * 7919) % 13)
+            .collect::<Vec<_>>();
+        let unsorted = (0..1000_u64).map(|i| (i * 7919) % 1000).collect::<Vec<_>>();
+
+        let cases = vec![
+            vec![0_u32, 1, 2, 3],
+            vec![999],
+            vec![998, 3, 3, 500, 256, 255, 4],
+            (0..1000).collect::<Vec<_>>(),
+            (0..1000).step_by(3).collect::<Vec<_>>(),
+            (250..260).chain(511..513).collect::<Vec<_>>(),
+        ];
+
+        for values in &[values, unsorted] {
+            let col = super::DeltaColumn::new(values);
+            for row_ids in &cases {
+                let exp = row_ids
+                    .iter()
+                    .map(|&id| values[id as usize])
+                    .collect::<Vec<_>>();
+
+                assert_eq!(super::filter_materialise_values(&col, row_ids, vec![]), exp);
+                assert_eq!(
+                    super::filter_materialise_values_prefix_sum(&col, row_ids, vec![]),
+                    exp
+                );
+                assert_eq!(
+                    super::filter_materialise_values_simd(&col, row_ids, vec![]).unwrap(),
+                    exp
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn filter_delta_out_of_bounds() {
+        // rows past the end of the last checkpoint interval are still out of
+        // range.
+        let col = super::DeltaColumn::new(&[1, 2, 3]);
+        let err = super::filter_materialise_values_simd(&col, &[0, 3, 1], vec![]).unwrap_err();
+        assert_eq!((err.row_id, err.rows), (3, 3));
+    }
+}
@@ -2,6 +2,8 @@ use std::arch::x86_64::*;
 
 use arrow::{array, compute::kernels};
 
+use crate::row_ids::{self, RowIdOutOfBounds};
+
 /// Filter and materialise functions are those that materialise a non-contiguous
 /// sub-set of values in some array, which are defined by a filter (another
 /// vector of indexes).
@@ -11,7 +13,8 @@ use arrow::{array, compute::kernels};
 /// are left with a set of indexes (`row_ids`) to materialise.
 ///
 /// In my case at least it's OK to put a maximum row limit on a column of
-/// u32::MAX so I use `u32` as row ids.
+/// u32::MAX so I use `u32` as row ids. `wide_row_ids` has versions of these
+/// functions for larger columns.
 
 /// This is a relatively idiomatic Rust implementation of filter. It serves as a
 /// baseline. I have arbitrarily picked 64-bit values since those are the most
@@ -46,7 +49,28 @@ pub fn filter_materialise_values_arrow(
 /// common scalar types I deal with. In Rust it would not be a huge amount of
 /// work to make this SIMD implementation generic (which is what Arrow does).
 ///
+/// The row ids are checked with `row_ids::check` before any values are
+/// gathered, and `RowIdOutOfBounds` is returned if any are out of range.
 pub fn filter_materialise_values_simd(
+    values: &[u64],
+    row_ids: &[u32],
+    dst: Vec<u64>,
+) -> Result<Vec<u64>, RowIdOutOfBounds> {
+    row_ids::check(row_ids, values.len())?;
+    if values.len() > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_materialise_values(values, row_ids, dst));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_materialise_values_simd_unchecked(values, row_ids, dst) })
+}
+
+/// This is `filter_materialise_values_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `values.len()` and at most `i32::MAX`.
+pub unsafe fn filter_materialise_values_simd_unchecked(
     values: &[u64],
     row_ids: &[u32],
     mut dst: Vec<u64>,
@@ -54,23 +78,21 @@ pub fn filter_materialise_values_simd(
     dst.clear();
     dst.reserve(row_ids.len());
 
-    unsafe {
-        let base_ptr = values.as_ptr() as *const i64;
+    let base_ptr = values.as_ptr() as *const i64;
 
-        for chunk in row_ids.chunks_exact(4) {
-            let chunk_ptr = chunk.as_ptr() as *const __m128i;
-            let mat_values = _mm256_i32gather_epi64(base_ptr, _mm_loadu_si128(chunk_ptr), 8);
+    for chunk in row_ids.chunks_exact(4) {
+        let chunk_ptr = chunk.as_ptr() as *const __m128i;
+        let mat_values = _mm256_i32gather_epi64(base_ptr, _mm_loadu_si128(chunk_ptr), 8);
 
-            _mm256_storeu_si256(dst.as_mut_ptr().add(dst.len()) as *mut __m256i, mat_values);
-            dst.set_len(dst.len() + 4);
-        }
+        _mm256_storeu_si256(dst.as_mut_ptr().add(dst.len()) as *mut __m256i, mat_values);
+        dst.set_len(dst.len() + 4);
+    }
 
-        // materialise any remainder - maximum of three values. Not much value
-        // in doing this in a SIMD register
-        let rem = row_ids.len() - (row_ids.len() % 4);
-        for &id in row_ids.iter().skip(rem) {
-            dst.push(values[id as usize]);
-        }
+    // materialise any remainder - maximum of three values. Not much value
+    // in doing this in a SIMD register
+    let rem = row_ids.len() - (row_ids.len() % 4);
+    for &id in row_ids.iter().skip(rem) {
+        dst.push(values[id as usize]);
     }
     assert_eq!(dst.len(), row_ids.len());
     dst
@@ -149,9 +171,33 @@ mod test {
 
         for (values, row_ids, exp) in &cases {
             assert_eq!(
-                &super::filter_materialise_values_simd(values, row_ids, vec![]),
+                &super::filter_materialise_values_simd(values, row_ids, vec![]).unwrap(),
+                exp
+            );
+            assert_eq!(
+                &unsafe {
+                    super::filter_materialise_values_simd_unchecked(values, row_ids, vec![])
+                },
                 exp
             );
         }
     }
+
+    #[test]
+    fn filter_materialise_values_simd_out_of_bounds() {
+        let values = (100..110).collect::<Vec<_>>();
+        for row_ids in &[
+            vec![0_u32, 1, 2, 10],
+            vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10],
+        ] {
+            let err = super::filter_materialise_values_simd(&values, row_ids, vec![]).unwrap_err();
+            assert_eq!(
+                err,
+                crate::row_ids::RowIdOutOfBounds {
+                    row_id: 10,
+                    rows: 10
+                }
+            );
+        }
+    }
 }
@@ -0,0 +1,249 @@
+//! Filter then compute several aggregates of the same column at once.
+//!
+//! Computing `SUM`, `MIN`, `MAX` and `COUNT` of a column with the single
+//! aggregate kernels (`filter_sum::filter_sum_simd`,
+//! `filter_max::filter_max_simd`, ...) gathers every selected value once per
+//! aggregate. `filter_aggregates_simd` gathers each value once, and keeps a
+//! set of lane accumulators (four `u64` lanes each) for each of the requested
+//! aggregates. `COUNT` is just the number of row ids, so it needs no
+//! accumulator at all.
+//!
+//! Which accumulators are updated is decided once per call rather than per
+//! value: the branches in the loop always go the same way, so they're
+//! predicted perfectly.
+//!
+//! Min and max compare unsigned values by flipping their high bit, like
+//! `filter_max::filter_max_simd`.
+use std::arch::x86_64::*;
+
+use crate::row_ids::{self, RowIdOutOfBounds};
+
+/// An aggregate of a column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    Count,
+    Sum,
+    Min,
+    Max,
+}
+
+// The aggregates computed by a pass over the values.
+struct Accumulators {
+    count: u64,
+    sum: u64,
+    min: Option<u64>,
+    max: Option<u64>,
+}
+
+impl Accumulators {
+    // The result of each aggregate in `aggregates`. The min and max of no
+    // values are `None`.
+    fn results(&self, aggregates: &[Aggregate]) -> Vec<Option<u64>> {
+        aggregates
+            .iter()
+            .map(|aggregate| match aggregate {
+                Aggregate::Count => Some(self.count),
+                Aggregate::Sum => Some(self.sum),
+                Aggregate::Min => self.min,
+                Aggregate::Max => self.max,
+            })
+            .collect()
+    }
+}
+
+/// This is a relatively idiomatic Rust implementation of filter then several
+/// aggregates. The results are in the same order as `aggregates`; the min and
+/// max of no values are `None`. Sums wrap on overflow, so that asking for the
+/// max of large values can't panic on a sum no one asked for.
+pub fn filter_aggregates(
+    values: &[u64],
+    row_ids: &[u32],
+    aggregates: &[Aggregate],
+) -> Vec<Option<u64>> {
+    let mut acc = Accumulators {
+        count: row_ids.len() as u64,
+        sum: 0,
+        min: None,
+        max: None,
+    };
+
+    for &id in row_ids.iter() {
+        let v = values[id as usize];
+        acc.sum = acc.sum.wrapping_add(v);
+        acc.min = Some(acc.min.map_or(v, |min| min.min(v)));
+        acc.max = Some(acc.max.map_or(v, |max| max.max(v)));
+    }
+    acc.results(aggregates)
+}
+
+/// This is an implementation of filter then several aggregates using SIMD
+/// intrinsics, which gathers each value once. See the module docs.
+pub fn filter_aggregates_simd(
+    values: &[u64],
+    row_ids: &[u32],
+    aggregates: &[Aggregate],
+) -> Result<Vec<Option<u64>>, RowIdOutOfBounds> {
+    row_ids::check(row_ids, values.len())?;
+    if values.len() > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_aggregates(values, row_ids, aggregates));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_aggregates_simd_unchecked(values, row_ids, aggregates) })
+}
+
+/// This is `filter_aggregates_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `values.len()` and at most `i32::MAX`.
+pub unsafe fn filter_aggregates_simd_unchecked(
+    values: &[u64],
+    row_ids: &[u32],
+    aggregates: &[Aggregate],
+) -> Vec<Option<u64>> {
+    let want_sum = aggregates.contains(&Aggregate::Sum);
+    let want_min = aggregates.contains(&Aggregate::Min);
+    let want_max = aggregates.contains(&Aggregate::Max);
+
+    let base_ptr = values.as_ptr() as *const i64;
+    let sign_bit = _mm256_set1_epi64x(i64::MIN);
+
+    let mut sum_lanes = _mm256_setzero_si256();
+    let mut min_lanes = _mm256_set1_epi64x(i64::MAX); // u64::MAX, flipped
+    let mut max_lanes = sign_bit; // zero, flipped
+
+    // the lanes only need updating if there's something other than the count
+    // to compute.
+    let chunks = if want_sum || want_min || want_max {
+        row_ids.chunks_exact(4)
+    } else {
+        row_ids[..0].chunks_exact(4)
+    };
+    for chunk in chunks {
+        let chunk_ptr = chunk.as_ptr() as *const __m128i;
+        let row_values = _mm256_i32gather_epi64(base_ptr, _mm_loadu_si128(chunk_ptr), 8);
+
+        if want_sum {
+            sum_lanes = _mm256_add_epi64(sum_lanes, row_values);
+        }
+
+        let flipped = _mm256_xor_si256(row_values, sign_bit);
+        if want_min {
+            let min_mask = _mm256_cmpgt_epi64(min_lanes, flipped);
+            min_lanes = _mm256_blendv_epi8(min_lanes, flipped, min_mask);
+        }
+        if want_max {
+            let max_mask = _mm256_cmpgt_epi64(flipped, max_lanes);
+            max_lanes = _mm256_blendv_epi8(max_lanes, flipped, max_mask);
+        }
+    }
+
+    let sums: [u64; 4] = std::mem::transmute(sum_lanes);
+    let mins: [u64; 4] = std::mem::transmute(_mm256_xor_si256(min_lanes, sign_bit));
+    let maxs: [u64; 4] = std::mem::transmute(_mm256_xor_si256(max_lanes, sign_bit));
+
+    // combine the lanes with any remainder - at most three values. The lanes
+    // only hold values if there was at least one chunk.
+    let rem = row_ids.len() - (row_ids.len() % 4);
+    let rem_values = row_ids.iter().skip(rem).map(|&id| values[id as usize]);
+    let lanes = if rem == 0 { 0 } else { 4 };
+    let acc = Accumulators {
+        count: row_ids.len() as u64,
+        sum: sums
+            .iter()
+            .copied()
+            .chain(rem_values.clone())
+            .fold(0, u64::wrapping_add),
+        min: mins[..lanes]
+            .iter()
+            .copied()
+            .chain(rem_values.clone())
+            .min(),
+        max: maxs[..lanes].iter().copied().chain(rem_values).max(),
+    };
+    acc.results(aggregates)
+}
+
+mod test {
+    #[test]
+    fn filter_aggregates() {
+        let values = (0..1000_u64).map(|v| v * 7919 % 1000).collect::<Vec<_>>();
+        let all = [
+            super::Aggregate::Count,
+            super::Aggregate::Sum,
+            super::Aggregate::Min,
+            super::Aggregate::Max,
+        ];
+        let cases = vec![
+            (vec![], vec![Some(0), Some(0), None, None]),
+            (vec![1_u32], vec![Some(1), Some(919), Some(919), Some(919)]),
+            (
+                vec![0, 1, 2, 3, 4],
+                vec![Some(5), Some(3190), Some(0), Some(919)],
+            ),
+            (
+                (0..1000).collect(),
+                vec![Some(1000), Some(499500), Some(0), Some(999)],
+            ),
+            (
+                (1..1000).rev().step_by(2).collect(),
+                vec![Some(500), Some(250000), Some(1), Some(999)],
+            ),
+        ];
+
+        for (row_ids, exp) in &cases {
+            assert_eq!(&super::filter_aggregates(&values, row_ids, &all), exp);
+            assert_eq!(
+                &super::filter_aggregates_simd(&values, row_ids, &all).unwrap(),
+                exp
+            );
+        }
+    }
+
+    #[test]
+    fn filter_aggregates_subsets() {
+        // results are in the requested order, and don't depend on which other
+        // aggregates were requested.
+        let values = vec![7, u64::MAX, 3, 1 << 63, 9, 2, 8, 4, 6];
+        let row_ids = vec![0, 2, 3, 4, 5, 6, 7, 8];
+        let cases = vec![
+            (vec![], vec![]),
+            (vec![super::Aggregate::Count], vec![Some(8)]),
+            (
+                vec![super::Aggregate::Max, super::Aggregate::Min],
+                vec![Some(1 << 63), Some(2)],
+            ),
+            (
+                vec![super::Aggregate::Min, super::Aggregate::Min],
+                vec![Some(2), Some(2)],
+            ),
+            (
+                vec![super::Aggregate::Sum, super::Aggregate::Count],
+                vec![Some((1 << 63) + 39), Some(8)],
+            ),
+        ];
+
+        for (aggregates, exp) in &cases {
+            assert_eq!(
+                &super::filter_aggregates(&values, &row_ids, aggregates),
+                exp
+            );
+            assert_eq!(
+                &super::filter_aggregates_simd(&values, &row_ids, aggregates).unwrap(),
+                exp
+            );
+        }
+
+        let row_ids = vec![1, 0, 2, 3];
+        assert_eq!(
+            super::filter_aggregates_simd(
+                &values,
+                &row_ids,
+                &[super::Aggregate::Max, super::Aggregate::Min]
+            ),
+            Ok(vec![Some(u64::MAX), Some(3)])
+        );
+        assert!(super::filter_aggregates_simd(&values, &[9], &[super::Aggregate::Count]).is_err());
+    }
+}
@@ -0,0 +1,307 @@
+//! Filter and projection functions for Arrow `RecordBatch`es.
+//!
+//! The Arrow filter kernel works on one array at a time, so filtering a batch
+//! means calling it once per column, and each call works out which rows are
+//! selected from the `BooleanArray` again. The functions here work out the
+//! selected rows once, as a `Selection` of row ids, and then apply it to every
+//! projected column.
+//!
+//! Columns with nulls, or of types without a specialised implementation here,
+//! are gathered with the Arrow take kernel instead, so any selection works for
+//! any column.
+use std::sync::Arc;
+
+use arrow::{
+    array::{self, Array},
+    compute::kernels,
+    datatypes::{self, DataType, Schema, TimeUnit},
+    record_batch::RecordBatch,
+};
+
+use crate::row_ids::{self, RowIdOutOfBounds};
+
+/// A set of selected rows that can be applied to several columns.
+#[derive(Debug, Clone)]
+pub struct Selection {
+    row_ids: Vec<u32>,
+}
+
+impl Selection {
+    /// Select the rows in `row_ids`, in that order.
+    pub fn from_row_ids(row_ids: Vec<u32>) -> Self {
+        Self { row_ids }
+    }
+
+    /// Select the rows where `filter` is true. Null is treated as false.
+    pub fn from_boolean_array(filter: &array::BooleanArray) -> Self {
+        let row_ids = (0..filter.len())
+            .filter(|&i| !filter.is_null(i) && filter.value(i))
+            .map(|i| i as u32)
+            .collect();
+        Self { row_ids }
+    }
+
+    pub fn row_ids(&self) -> &[u32] {
+        &self.row_ids
+    }
+
+    pub fn len(&self) -> usize {
+        self.row_ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.row_ids.is_empty()
+    }
+
+    // Build a `UInt32Array` of the selected row ids, for the columns that fall
+    // back to the Arrow take kernel. Unlike a `BooleanArray` filter, it can
+    // express row ids that are unsorted or repeated.
+    fn to_indices(&self) -> array::UInt32Array {
+        array::UInt32Array::from(self.row_ids.clone())
+    }
+}
+
+/// Apply `selection` to the columns at the indexes in `projection` (in that
+/// order), returning a new `RecordBatch` containing only those columns. The
+/// selection is checked with `row_ids::check` first, and `RowIdOutOfBounds`
+/// is returned if any of its rows are not in the batch.
+pub fn filter_record_batch(
+    batch: &RecordBatch,
+    selection: &Selection,
+    projection: &[usize],
+) -> Result<RecordBatch, RowIdOutOfBounds> {
+    row_ids::check(selection.row_ids(), batch.num_rows())?;
+
+    // only built if a column needs the Arrow kernel, and then reused.
+    let mut indices = None;
+
+    let schema = batch.schema();
+    let mut fields = Vec::with_capacity(projection.len());
+    let mut columns = Vec::with_capacity(projection.len());
+    for &i in projection {
+        let column = batch.column(i);
+        let row_ids = selection.row_ids();
+
+        let filtered = match column.data_type() {
+            _ if column.null_count() > 0 => None,
+            DataType::Boolean => Some(filter_boolean(column, row_ids)),
+            DataType::Int8 => Some(filter_primitive::<datatypes::Int8Type>(column, row_ids)),
+            DataType::Int16 => Some(filter_primitive::<datatypes::Int16Type>(column, row_ids)),
+            DataType::Int32 => Some(filter_primitive::<datatypes::Int32Type>(column, row_ids)),
+            DataType::Int64 => Some(filter_primitive::<datatypes::Int64Type>(column, row_ids)),
+            DataType::UInt8 => Some(filter_primitive::<datatypes::UInt8Type>(column, row_ids)),
+            DataType::UInt16 => Some(filter_primitive::<datatypes::UInt16Type>(column, row_ids)),
+            DataType::UInt32 => Some(filter_primitive::<datatypes::UInt32Type>(column, row_ids)),
+            DataType::UInt64 => Some(filter_primitive::<datatypes::UInt64Type>(column, row_ids)),
+            DataType::Float32 => Some(filter_primitive::<datatypes::Float32Type>(column, row_ids)),
+            DataType::Float64 => Some(filter_primitive::<datatypes::Float64Type>(column, row_ids)),
+            DataType::Timestamp(TimeUnit::Nanosecond, None) => Some(filter_primitive::<
+                datatypes::TimestampNanosecondType,
+            >(column, row_ids)),
+            DataType::Utf8 => Some(filter_utf8(column, row_ids)),
+            _ => None,
+        };
+
+        let filtered = filtered.unwrap_or_else(|| {
+            let indices = indices.get_or_insert_with(|| selection.to_indices());
+            kernels::take::take(column, indices, None).unwrap()
+        });
+
+        fields.push(schema.field(i).clone());
+        columns.push(filtered);
+    }
+
+    Ok(RecordBatch::try_new(Arc::new(Schema::new(fields)), columns).unwrap())
+}
+
+/// This is an implementation of filter and projection on a `RecordBatch`
+/// using the Arrow filter kernel once for each projected column.
+pub fn filter_record_batch_arrow(
+    batch: &RecordBatch,
+    filter: &array::BooleanArray,
+    projection: &[usize],
+) -> RecordBatch {
+    let schema = batch.schema();
+    let fields = projection
+        .iter()
+        .map(|&i| schema.field(i).clone())
+        .collect::<Vec<_>>();
+    let columns = projection
+        .iter()
+        .map(|&i| kernels::filter::filter(batch.column(i).as_ref(), filter).unwrap())
+        .collect::<Vec<_>>();
+
+    RecordBatch::try_new(Arc::new(Schema::new(fields)), columns).unwrap()
+}
+
+fn filter_primitive<T: datatypes::ArrowPrimitiveType>(
+    column: &array::ArrayRef,
+    row_ids: &[u32],
+) -> array::ArrayRef
+where
+    array::PrimitiveArray<T>: From<Vec<T::Native>>,
+{
+    let column = column
+        .as_any()
+        .downcast_ref::<array::PrimitiveArray<T>>()
+        .unwrap();
+
+    let values = row_ids
+        .iter()
+        .map(|&id| column.value(id as usize))
+        .collect::<Vec<_>>();
+    Arc::new(array::PrimitiveArray::<T>::from(values))
+}
+
+fn filter_boolean(column: &array::ArrayRef, row_ids: &[u32]) -> array::ArrayRef {
+    let column = column
+        .as_any()
+        .downcast_ref::<array::BooleanArray>()
+        .unwrap();
+
+    let values = row_ids
+        .iter()
+        .map(|&id| column.value(id as usize))
+        .collect::<Vec<_>>();
+    Arc::new(array::BooleanArray::from(values))
+}
+
+fn filter_utf8(column: &array::ArrayRef, row_ids: &[u32]) -> array::ArrayRef {
+    let column = column
+        .as_any()
+        .downcast_ref::<array::StringArray>()
+        .unwrap();
+
+    let values = row_ids
+        .iter()
+        .map(|&id| column.value(id as usize))
+        .collect::<Vec<_>>();
+    Arc::new(array::StringArray::from(values))
+}
+
+mod test {
+    use std::sync::Arc;
+
+    use arrow::{
+        array,
+        datatypes::{DataType, Field, Schema},
+        record_batch::RecordBatch,
+    };
+
+    fn batch() -> RecordBatch {
+        let schema = Schema::new(vec![
+            Field::new("u64", DataType::UInt64, false),
+            Field::new("i32", DataType::Int32, false),
+            Field::new("f64", DataType::Float64, false),
+            Field::new("str", DataType::Utf8, false),
+            Field::new("bool", DataType::Boolean, false),
+        ]);
+
+        let columns: Vec<array::ArrayRef> = vec![
+            Arc::new(array::UInt64Array::from((0..100).collect::<Vec<u64>>())),
+            Arc::new(array::Int32Array::from(
+                (0..100).map(|i| -i).collect::<Vec<i32>>(),
+            )),
+            Arc::new(array::Float64Array::from(
+                (0..100).map(|i| i as f64 / 2.0).collect::<Vec<_>>(),
+            )),
+            Arc::new(array::StringArray::from(
+                (0..100)
+                    .map(|i| if i % 3 == 0 { "a" } else { "bb" })
+                    .collect::<Vec<_>>(),
+            )),
+            Arc::new(array::BooleanArray::from(
+                (0..100).map(|i| i % 2 == 0).collect::<Vec<_>>(),
+            )),
+        ];
+
+        RecordBatch::try_new(Arc::new(schema), columns).unwrap()
+    }
+
+    fn downcast<T: 'static>(batch: &RecordBatch, i: usize) -> &T {
+        batch.column(i).as_any().downcast_ref::<T>().unwrap()
+    }
+
+    #[test]
+    fn filter_record_batch() {
+        let batch = batch();
+        let row_ids = vec![0_u32, 3, 4, 50, 99];
+        let filter =
+            array::BooleanArray::from((0..100).map(|i| row_ids.contains(&i)).collect::<Vec<_>>());
+
+        let selections = vec![
+            super::Selection::from_row_ids(row_ids.clone()),
+            super::Selection::from_boolean_array(&filter),
+        ];
+        for selection in &selections {
+            assert_eq!(selection.row_ids(), row_ids.as_slice());
+
+            let projection = vec![3, 0, 4, 2, 1];
+            let got = super::filter_record_batch(&batch, selection, &projection).unwrap();
+            let exp = super::filter_record_batch_arrow(&batch, &filter, &projection);
+
+            assert_eq!(got.schema(), exp.schema());
+            assert_eq!(got.num_rows(), 5);
+            assert_eq!(
+                downcast::<array::StringArray>(&got, 0),
+                downcast::<array::StringArray>(&exp, 0)
+            );
+            assert_eq!(
+                downcast::<array::UInt64Array>(&got, 1),
+                downcast::<array::UInt64Array>(&exp, 1)
+            );
+            assert_eq!(
+                downcast::<array::BooleanArray>(&got, 2),
+                downcast::<array::BooleanArray>(&exp, 2)
+            );
+            assert_eq!(
+                downcast::<array::Float64Array>(&got, 3),
+                downcast::<array::Float64Array>(&exp, 3)
+            );
+            assert_eq!(
+                downcast::<array::Int32Array>(&got, 4),
+                downcast::<array::Int32Array>(&exp, 4)
+            );
+        }
+    }
+
+    #[test]
+    fn filter_record_batch_projection() {
+        let batch = batch();
+        let selection = super::Selection::from_row_ids(vec![10, 2, 2]);
+
+        let got = super::filter_record_batch(&batch, &selection, &[0]).unwrap();
+        assert_eq!(got.num_columns(), 1);
+        assert_eq!(got.schema().field(0).name(), "u64");
+        assert_eq!(
+            downcast::<array::UInt64Array>(&got, 0),
+            &array::UInt64Array::from(vec![10, 2, 2])
+        );
+    }
+
+    #[test]
+    fn filter_record_batch_take_fallback() {
+        // columns with nulls use the take kernel, which handles unsorted and
+        // repeated row ids too.
+        let values = (0..10)
+            .map(|i| if i % 4 == 0 { None } else { Some(i) })
+            .collect::<Vec<_>>();
+        let schema = Schema::new(vec![Field::new("i32", DataType::Int32, true)]);
+        let columns: Vec<array::ArrayRef> = vec![Arc::new(array::Int32Array::from(values))];
+        let batch = RecordBatch::try_new(Arc::new(schema), columns).unwrap();
+
+        let selection = super::Selection::from_row_ids(vec![9, 4, 1, 1, 0]);
+        let got = super::filter_record_batch(&batch, &selection, &[0]).unwrap();
+        assert_eq!(
+            downcast::<array::Int32Array>(&got, 0),
+            &array::Int32Array::from(vec![Some(9), None, Some(1), Some(1), None])
+        );
+    }
+
+    #[test]
+    fn filter_record_batch_out_of_bounds() {
+        let selection = super::Selection::from_row_ids(vec![1, 100]);
+        let err = super::filter_record_batch(&batch(), &selection, &[0]).unwrap_err();
+        assert_eq!((err.row_id, err.rows), (100, 100));
+    }
+}
@@ -0,0 +1,340 @@
+//! Filter functions that materialise several columns through the same row
+//! ids. A projection of N columns would otherwise call a materialise function
+//! N times, reading `row_ids` (and checking it) once per column.
+//!
+//! `filter_materialise_columns_simd` checks the row ids once, then loads each
+//! chunk of eight row ids into a register once and gathers from every column
+//! with it, writing into one `dst` buffer per column. The columns can have
+//! different element widths: `u64` columns are gathered four at a time from
+//! each half of the chunk, and the narrow columns (`u8`, `u16` and `u32`) are
+//! gathered eight at a time like `filter_narrow`, including falling back to
+//! scalar for any chunk close enough to the end of the column for a 32-bit
+//! gather to read past it.
+use std::arch::x86_64::*;
+
+use crate::narrow_gather::{any_beyond, gather, gather_limit, store, NarrowValue};
+use crate::row_ids::{self, RowIdOutOfBounds};
+
+/// A column to materialise. All the columns passed to a materialise function
+/// must have the same length.
+#[derive(Debug, Clone, Copy)]
+pub enum Column<'a> {
+    U8(&'a [u8]),
+    U16(&'a [u16]),
+    U32(&'a [u32]),
+    U64(&'a [u64]),
+}
+
+impl<'a> Column<'a> {
+    pub fn len(&self) -> usize {
+        match self {
+            Column::U8(values) => values.len(),
+            Column::U16(values) => values.len(),
+            Column::U32(values) => values.len(),
+            Column::U64(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The materialised values of a column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ColumnBuffer {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+    U64(Vec<u64>),
+}
+
+impl ColumnBuffer {
+    pub fn len(&self) -> usize {
+        match self {
+            ColumnBuffer::U8(values) => values.len(),
+            ColumnBuffer::U16(values) => values.len(),
+            ColumnBuffer::U32(values) => values.len(),
+            ColumnBuffer::U64(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// The length shared by all the columns, which must be the same.
+fn columns_len(columns: &[Column<'_>]) -> usize {
+    let len = columns.first().map_or(0, Column::len);
+    assert!(
+        columns.iter().all(|column| column.len() == len),
+        "columns must have the same length"
+    );
+    len
+}
+
+// An empty buffer for each column with room for `rows` values. The buffers in
+// `dst` are reused where their element width matches the column's.
+fn buffers(columns: &[Column<'_>], rows: usize, dst: Vec<ColumnBuffer>) -> Vec<ColumnBuffer> {
+    let mut dst = dst.into_iter();
+    columns
+        .iter()
+        .map(|column| {
+            let mut buffer = match (column, dst.next()) {
+                (Column::U8(_), Some(ColumnBuffer::U8(v))) => ColumnBuffer::U8(v),
+                (Column::U16(_), Some(ColumnBuffer::U16(v))) => ColumnBuffer::U16(v),
+                (Column::U32(_), Some(ColumnBuffer::U32(v))) => ColumnBuffer::U32(v),
+                (Column::U64(_), Some(ColumnBuffer::U64(v))) => ColumnBuffer::U64(v),
+                (Column::U8(_), _) => ColumnBuffer::U8(vec![]),
+                (Column::U16(_), _) => ColumnBuffer::U16(vec![]),
+                (Column::U32(_), _) => ColumnBuffer::U32(vec![]),
+                (Column::U64(_), _) => ColumnBuffer::U64(vec![]),
+            };
+            match &mut buffer {
+                ColumnBuffer::U8(v) => reset(v, rows),
+                ColumnBuffer::U16(v) => reset(v, rows),
+                ColumnBuffer::U32(v) => reset(v, rows),
+                ColumnBuffer::U64(v) => reset(v, rows),
+            }
+            buffer
+        })
+        .collect()
+}
+
+fn reset<T>(dst: &mut Vec<T>, rows: usize) {
+    dst.clear();
+    dst.reserve(rows);
+}
+
+/// This is a relatively idiomatic Rust implementation of filter over several
+/// columns. Each column is materialised in turn, so `row_ids` is read once per
+/// column. There's a buffer in the result for each column, in the same order;
+/// the buffers in `dst` are reused where their element width matches.
+pub fn filter_materialise_columns(
+    columns: &[Column<'_>],
+    row_ids: &[u32],
+    dst: Vec<ColumnBuffer>,
+) -> Vec<ColumnBuffer> {
+    columns_len(columns);
+    let mut dst = buffers(columns, row_ids.len(), dst);
+
+    for (column, buffer) in columns.iter().zip(dst.iter_mut()) {
+        match (column, buffer) {
+            (Column::U8(values), ColumnBuffer::U8(dst)) => push_rows(values, row_ids, dst),
+            (Column::U16(values), ColumnBuffer::U16(dst)) => push_rows(values, row_ids, dst),
+            (Column::U32(values), ColumnBuffer::U32(dst)) => push_rows(values, row_ids, dst),
+            (Column::U64(values), ColumnBuffer::U64(dst)) => push_rows(values, row_ids, dst),
+            _ => unreachable!(),
+        }
+    }
+    dst
+}
+
+fn push_rows<T: Copy>(values: &[T], row_ids: &[u32], dst: &mut Vec<T>) {
+    for &id in row_ids {
+        dst.push(values[id as usize]);
+    }
+}
+
+/// This is an implementation of filter over several columns using SIMD
+/// intrinsics, which reads each chunk of row ids once for all the columns.
+/// See the module docs.
+///
+/// The row ids are checked once, against the length shared by all the
+/// columns. It panics if the columns have different lengths.
+pub fn filter_materialise_columns_simd(
+    columns: &[Column<'_>],
+    row_ids: &[u32],
+    dst: Vec<ColumnBuffer>,
+) -> Result<Vec<ColumnBuffer>, RowIdOutOfBounds> {
+    let len = columns_len(columns);
+    if columns.is_empty() {
+        return Ok(vec![]);
+    }
+    row_ids::check(row_ids, len)?;
+    if len > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_materialise_columns(columns, row_ids, dst));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_materialise_columns_simd_unchecked(columns, row_ids, dst) })
+}
+
+/// This is `filter_materialise_columns_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than the length of every column and at most
+/// `i32::MAX`.
+pub unsafe fn filter_materialise_columns_simd_unchecked(
+    columns: &[Column<'_>],
+    row_ids: &[u32],
+    dst: Vec<ColumnBuffer>,
+) -> Vec<ColumnBuffer> {
+    let mut dst = buffers(columns, row_ids.len(), dst);
+
+    // the row id a 32-bit gather from each narrow column can safely read.
+    let limits = columns
+        .iter()
+        .map(|column| match column {
+            Column::U8(values) => gather_limit(values),
+            Column::U16(values) => gather_limit(values),
+            Column::U32(values) => gather_limit(values),
+            Column::U64(_) => u32::MAX,
+        })
+        .map(|limit| _mm256_set1_epi32(limit as i32))
+        .collect::<Vec<_>>();
+
+    for chunk in row_ids.chunks_exact(8) {
+        let ids = _mm256_loadu_si256(chunk.as_ptr() as *const __m256i);
+
+        for ((column, buffer), &limit) in columns.iter().zip(dst.iter_mut()).zip(&limits) {
+            match (column, buffer) {
+                (Column::U8(values), ColumnBuffer::U8(dst)) => {
+                    gather_narrow(values, chunk, ids, limit, dst)
+                }
+                (Column::U16(values), ColumnBuffer::U16(dst)) => {
+                    gather_narrow(values, chunk, ids, limit, dst)
+                }
+                (Column::U32(values), ColumnBuffer::U32(dst)) => {
+                    gather_narrow(values, chunk, ids, limit, dst)
+                }
+                (Column::U64(values), ColumnBuffer::U64(dst)) => gather_u64(values, ids, dst),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    // materialise any remainder - maximum of seven values per column.
+    let rem = &row_ids[row_ids.len() - (row_ids.len() % 8)..];
+    for (column, buffer) in columns.iter().zip(dst.iter_mut()) {
+        match (column, buffer) {
+            (Column::U8(values), ColumnBuffer::U8(dst)) => push_rows(values, rem, dst),
+            (Column::U16(values), ColumnBuffer::U16(dst)) => push_rows(values, rem, dst),
+            (Column::U32(values), ColumnBuffer::U32(dst)) => push_rows(values, rem, dst),
+            (Column::U64(values), ColumnBuffer::U64(dst)) => push_rows(values, rem, dst),
+            _ => unreachable!(),
+        }
+    }
+    dst
+}
+
+// Gather the eight values of a narrow column at `ids` and append them to
+// `dst`.
+#[inline]
+unsafe fn gather_narrow<K: NarrowValue>(
+    values: &[K],
+    chunk: &[u32],
+    ids: __m256i,
+    limit: __m256i,
+    dst: &mut Vec<K>,
+) {
+    if any_beyond(ids, limit) {
+        push_rows(values, chunk, dst);
+        return;
+    }
+
+    store(gather(values, ids), dst.as_mut_ptr().add(dst.len()));
+    dst.set_len(dst.len() + 8);
+}
+
+// Gather the eight values of a `u64` column at `ids` and append them to
+// `dst`, four from each half of the chunk.
+#[inline]
+unsafe fn gather_u64(values: &[u64], ids: __m256i, dst: &mut Vec<u64>) {
+    let base_ptr = values.as_ptr() as *const i64;
+    let lo = _mm256_i32gather_epi64(base_ptr, _mm256_castsi256_si128(ids), 8);
+    let hi = _mm256_i32gather_epi64(base_ptr, _mm256_extracti128_si256(ids, 1), 8);
+
+    let dst_ptr = dst.as_mut_ptr().add(dst.len()) as *mut __m256i;
+    _mm256_storeu_si256(dst_ptr, lo);
+    _mm256_storeu_si256(dst_ptr.add(1), hi);
+    dst.set_len(dst.len() + 8);
+}
+
+mod test {
+    #[test]
+    fn filter_materialise_columns() {
+        let u8s = (0..100_u32).map(|v| (v * 7) as u8).collect::<Vec<_>>();
+        let u16s = (0..100_u32).map(|v| (v * 911) as u16).collect::<Vec<_>>();
+        let u32s = (0..100_u32).map(|v| v * 104729).collect::<Vec<_>>();
+        let u64s = (0..100_u64).map(|v| v << 40 | v).collect::<Vec<_>>();
+        let columns = vec![
+            super::Column::U64(&u64s),
+            super::Column::U8(&u8s),
+            super::Column::U32(&u32s),
+            super::Column::U16(&u16s),
+            super::Column::U8(&u8s),
+        ];
+
+        let cases: Vec<Vec<u32>> = vec![
+            vec![],
+            vec![99],
+            (0..8).collect(),
+            (0..100).collect(),
+            (0..100).rev().step_by(3).collect(),
+            // the last rows of the narrow columns take the scalar path.
+            vec![99, 98, 97, 96, 1, 0, 99, 99, 50],
+        ];
+
+        for row_ids in &cases {
+            let exp = vec![
+                super::ColumnBuffer::U64(row_ids.iter().map(|&id| u64s[id as usize]).collect()),
+                super::ColumnBuffer::U8(row_ids.iter().map(|&id| u8s[id as usize]).collect()),
+                super::ColumnBuffer::U32(row_ids.iter().map(|&id| u32s[id as usize]).collect()),
+                super::ColumnBuffer::U16(row_ids.iter().map(|&id| u16s[id as usize]).collect()),
+                super::ColumnBuffer::U8(row_ids.iter().map(|&id| u8s[id as usize]).collect()),
+            ];
+
+            assert_eq!(
+                super::filter_materialise_columns(&columns, row_ids, vec![]),
+                exp
+            );
+            assert_eq!(
+                super::filter_materialise_columns_simd(&columns, row_ids, vec![]).unwrap(),
+                exp
+            );
+        }
+    }
+
+    #[test]
+    fn filter_materialise_columns_reuses_dst() {
+        let u16s = vec![1_u16, 2, 3];
+        let u64s = vec![4_u64, 5, 6];
+        let columns = vec![super::Column::U16(&u16s), super::Column::U64(&u64s)];
+
+        // the first buffer's width matches, the second doesn't and is
+        // replaced, and the extra buffer is dropped.
+        let dst = vec![
+            super::ColumnBuffer::U16(vec![9; 10]),
+            super::ColumnBuffer::U8(vec![9; 10]),
+            super::ColumnBuffer::U64(vec![9; 10]),
+        ];
+        let exp = vec![
+            super::ColumnBuffer::U16(vec![3, 1]),
+            super::ColumnBuffer::U64(vec![6, 4]),
+        ];
+        assert_eq!(
+            super::filter_materialise_columns_simd(&columns, &[2, 0], dst.clone()).unwrap(),
+            exp
+        );
+        assert_eq!(
+            super::filter_materialise_columns(&columns, &[2, 0], dst),
+            exp
+        );
+
+        assert!(super::filter_materialise_columns_simd(&columns, &[3], vec![]).is_err());
+        assert_eq!(
+            super::filter_materialise_columns_simd(&[], &[3], vec![]),
+            Ok(vec![])
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn filter_materialise_columns_different_lengths() {
+        let columns = [super::Column::U8(&[1, 2]), super::Column::U64(&[1])];
+        super::filter_materialise_columns(&columns, &[0], vec![]);
+    }
+}
@@ -0,0 +1,506 @@
+//! Filter and aggregate functions over 128-bit decimal columns.
+//!
+//! Arrow stores a `Decimal128` value as a 16-byte little-endian two's
+//! complement integer (the unscaled value), along with a precision (the
+//! number of decimal digits, up to 38) and a scale (how many of those digits
+//! are after the decimal point). `Decimal128Column` uses the same layout: each
+//! value is two 64-bit limbs, the low (unsigned) limb followed by the high
+//! (signed) limb.
+//!
+//! AVX2 has no 128-bit integer arithmetic, so the SIMD implementations work on
+//! the limbs. The low and high limbs of four values are gathered into two
+//! registers, then:
+//!
+//! - sum: the low limbs are added with unsigned carry detection, and the carries
+//!   are counted per lane. The high limbs are added with signed overflow
+//!   detection, and the wraps are counted per lane like
+//!   `filter_signed::filter_sum_i64_simd`. The lanes, carries and wraps are
+//!   combined into the exact sum at the end.
+//! - min/max: values are compared on their high limbs (signed), and on their
+//!   low limbs (unsigned) when the high limbs are equal.
+//!
+//! A sum has the column's scale and a precision of `MAX_PRECISION`. Sums whose
+//! magnitude needs more than `MAX_PRECISION` digits are an overflow, even if
+//! they would fit in an `i128`. Overflow detection is exact: an intermediate
+//! sum may overflow as long as the final sum fits.
+//!
+//! The idiomatic implementations decode each selected value to an `i128` and
+//! aggregate them in a plain loop.
+use std::{arch::x86_64::*, fmt};
+
+use crate::row_ids::{self, RowIdOutOfBounds};
+
+/// The maximum precision of a `Decimal128` value.
+pub const MAX_PRECISION: u8 = 38;
+
+/// The number of rows the SIMD gathers can address: each value is two limbs,
+/// so the limb offset of a row id must fit in a signed 32-bit offset.
+pub const MAX_GATHER_ROWS: usize = row_ids::MAX_GATHER_ROWS / 2;
+
+/// The errors returned when building a `Decimal128Column` or summing one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecimalError {
+    /// The precision isn't in `1..=MAX_PRECISION`.
+    InvalidPrecision(u8),
+
+    /// The scale is larger than the precision.
+    InvalidScale { precision: u8, scale: u8 },
+
+    /// A value has more digits than the precision allows.
+    ValueOutOfRange { row: usize, value: i128 },
+
+    /// The sum has more than `MAX_PRECISION` digits.
+    Overflow,
+
+    /// A row id isn't a row in the column.
+    RowIdOutOfBounds(RowIdOutOfBounds),
+}
+
+impl fmt::Display for DecimalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidPrecision(precision) => {
+                write!(f, "precision {} not in 1..={}", precision, MAX_PRECISION)
+            }
+            Self::InvalidScale { precision, scale } => {
+                write!(f, "scale {} larger than precision {}", scale, precision)
+            }
+            Self::ValueOutOfRange { row, value } => {
+                write!(f, "row {} value {} out of range for precision", row, value)
+            }
+            Self::Overflow => write!(f, "sum overflows precision {}", MAX_PRECISION),
+            Self::RowIdOutOfBounds(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for DecimalError {}
+
+impl From<RowIdOutOfBounds> for DecimalError {
+    fn from(e: RowIdOutOfBounds) -> Self {
+        Self::RowIdOutOfBounds(e)
+    }
+}
+
+// The largest unscaled value with `precision` digits.
+fn max_value(precision: u8) -> i128 {
+    10_i128.pow(precision as u32) - 1
+}
+
+/// A column of 128-bit decimal values with a fixed precision and scale.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decimal128Column {
+    // the low and high limb of each value.
+    limbs: Vec<u64>,
+    precision: u8,
+    scale: u8,
+}
+
+impl Decimal128Column {
+    /// Build a column from unscaled values, checking the precision and scale,
+    /// and that every value fits in the precision.
+    pub fn try_new(values: &[i128], precision: u8, scale: u8) -> Result<Self, DecimalError> {
+        if precision == 0 || precision > MAX_PRECISION {
+            return Err(DecimalError::InvalidPrecision(precision));
+        }
+        if scale > precision {
+            return Err(DecimalError::InvalidScale { precision, scale });
+        }
+
+        let max = max_value(precision);
+        let mut limbs = Vec::with_capacity(values.len() * 2);
+        for (row, &value) in values.iter().enumerate() {
+            if value > max || value < -max {
+                return Err(DecimalError::ValueOutOfRange { row, value });
+            }
+            limbs.push(value as u64);
+            limbs.push((value >> 64) as u64);
+        }
+
+        Ok(Self {
+            limbs,
+            precision,
+            scale,
+        })
+    }
+
+    /// Decode the value of row `i`.
+    pub fn value(&self, i: usize) -> i128 {
+        let (lo, hi) = (self.limbs[2 * i], self.limbs[2 * i + 1]);
+        ((hi as i128) << 64) | lo as i128
+    }
+
+    pub fn precision(&self) -> u8 {
+        self.precision
+    }
+
+    pub fn scale(&self) -> u8 {
+        self.scale
+    }
+
+    pub fn len(&self) -> usize {
+        self.limbs.len() / 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.limbs.is_empty()
+    }
+}
+
+// Check that an exact sum fits in `MAX_PRECISION` digits.
+fn check_sum(sum: i128) -> Result<i128, DecimalError> {
+    let max = max_value(MAX_PRECISION);
+    if sum > max || sum < -max {
+        return Err(DecimalError::Overflow);
+    }
+    Ok(sum)
+}
+
+/// This is a relatively idiomatic Rust implementation of filter then sum for
+/// a decimal column. Each value is decoded to an `i128`, and the number of
+/// times the `i128` sum wraps is counted so that overflow is exact.
+pub fn filter_sum(col: &Decimal128Column, row_ids: &[u32]) -> Result<i128, DecimalError> {
+    let mut sum = 0_i128;
+    let mut wraps = 0;
+    for &id in row_ids.iter() {
+        let value = col.value(id as usize);
+        let (next, wrapped) = sum.overflowing_add(value);
+        if wrapped {
+            wraps += value.signum();
+        }
+        sum = next;
+    }
+
+    if wraps != 0 {
+        return Err(DecimalError::Overflow);
+    }
+    check_sum(sum)
+}
+
+/// This is a relatively idiomatic Rust implementation of filter then max for
+/// a decimal column. It panics if `row_ids` is empty.
+pub fn filter_max(col: &Decimal128Column, row_ids: &[u32]) -> i128 {
+    row_ids
+        .iter()
+        .map(|&id| col.value(id as usize))
+        .max()
+        .unwrap()
+}
+
+/// This is a relatively idiomatic Rust implementation of filter then min for
+/// a decimal column. It panics if `row_ids` is empty.
+pub fn filter_min(col: &Decimal128Column, row_ids: &[u32]) -> i128 {
+    row_ids
+        .iter()
+        .map(|&id| col.value(id as usize))
+        .min()
+        .unwrap()
+}
+
+// Gather the low and high limbs of the four values at `ids`.
+#[inline]
+unsafe fn gather_limbs(col: &Decimal128Column, ids: &[u32]) -> (__m256i, __m256i) {
+    let base_ptr = col.limbs.as_ptr() as *const i64;
+    let offsets = _mm_slli_epi32(_mm_loadu_si128(ids.as_ptr() as *const __m128i), 1);
+    (
+        _mm256_i32gather_epi64(base_ptr, offsets, 8),
+        _mm256_i32gather_epi64(base_ptr.add(1), offsets, 8),
+    )
+}
+
+/// This is an implementation of filter then sum for a decimal column using
+/// SIMD intrinsics on two 64-bit limbs. See the module docs.
+pub fn filter_sum_simd(col: &Decimal128Column, row_ids: &[u32]) -> Result<i128, DecimalError> {
+    row_ids::check(row_ids, col.len())?;
+    if col.len() > MAX_GATHER_ROWS {
+        return filter_sum(col, row_ids);
+    }
+
+    // SAFETY: every row id was checked above.
+    unsafe { filter_sum_simd_unchecked(col, row_ids) }
+}
+
+/// This is `filter_sum_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `col.len()` and `MAX_GATHER_ROWS`.
+pub unsafe fn filter_sum_simd_unchecked(
+    col: &Decimal128Column,
+    row_ids: &[u32],
+) -> Result<i128, DecimalError> {
+    let zero = _mm256_setzero_si256();
+    let one = _mm256_set1_epi64x(1);
+    let sign_bit = _mm256_set1_epi64x(i64::MIN);
+
+    let mut lo_lanes = zero; // u64x4
+    let mut carry_lanes = zero; // the carries out of each low lane
+    let mut hi_lanes = zero; // i64x4
+    let mut wrap_lanes = zero; // +1/-1 for each high lane wrap, like filter_signed
+
+    for chunk in row_ids.chunks_exact(4) {
+        let (lo, hi) = gather_limbs(col, chunk);
+
+        // the low limbs carry when the unsigned sum is smaller than the value
+        // added. Flipping the high bit makes the signed compare unsigned.
+        let lo_sum = _mm256_add_epi64(lo_lanes, lo);
+        let carry = _mm256_cmpgt_epi64(
+            _mm256_xor_si256(lo, sign_bit),
+            _mm256_xor_si256(lo_sum, sign_bit),
+        );
+        carry_lanes = _mm256_sub_epi64(carry_lanes, carry); // carry is -1
+        lo_lanes = lo_sum;
+
+        let hi_sum = _mm256_add_epi64(hi_lanes, hi);
+        let overflow = _mm256_cmpgt_epi64(
+            zero,
+            _mm256_and_si256(
+                _mm256_xor_si256(hi_lanes, hi_sum),
+                _mm256_xor_si256(hi, hi_sum),
+            ),
+        );
+        let direction = _mm256_or_si256(_mm256_cmpgt_epi64(zero, hi), one); // -1 or 1
+        wrap_lanes = _mm256_add_epi64(wrap_lanes, _mm256_and_si256(overflow, direction));
+        hi_lanes = hi_sum;
+    }
+
+    let los: [u64; 4] = std::mem::transmute(lo_lanes);
+    let carries: [i64; 4] = std::mem::transmute(carry_lanes);
+    let his: [i64; 4] = std::mem::transmute(hi_lanes);
+    let wraps: [i64; 4] = std::mem::transmute(wrap_lanes);
+
+    // the sum is `high * 2^64 + low`, where both parts are far from
+    // overflowing an i128.
+    let mut low = 0_i128;
+    let mut high = 0_i128;
+    for i in 0..4 {
+        low += los[i] as i128 + ((carries[i] as i128) << 64);
+        high += his[i] as i128 + ((wraps[i] as i128) << 64);
+    }
+
+    // sum any remainder - maximum of three values.
+    let rem = row_ids.len() - (row_ids.len() % 4);
+    for &id in row_ids.iter().skip(rem) {
+        low += col.limbs[2 * id as usize] as i128;
+        high += col.limbs[2 * id as usize + 1] as i64 as i128;
+    }
+
+    // move the low part's carries into the high part, and then check the
+    // high part can be shifted into place.
+    high += low >> 64;
+    let low = low & u64::MAX as i128;
+    let sum = high
+        .checked_mul(1 << 64)
+        .and_then(|high| high.checked_add(low))
+        .ok_or(DecimalError::Overflow)?;
+    check_sum(sum)
+}
+
+/// This is an implementation of filter then max for a decimal column using
+/// SIMD intrinsics on two 64-bit limbs. It panics if `row_ids` is empty.
+pub fn filter_max_simd(col: &Decimal128Column, row_ids: &[u32]) -> Result<i128, RowIdOutOfBounds> {
+    row_ids::check(row_ids, col.len())?;
+    if col.len() > MAX_GATHER_ROWS {
+        return Ok(filter_max(col, row_ids));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_max_simd_unchecked(col, row_ids) })
+}
+
+/// This is `filter_max_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `col.len()` and `MAX_GATHER_ROWS`.
+pub unsafe fn filter_max_simd_unchecked(col: &Decimal128Column, row_ids: &[u32]) -> i128 {
+    filter_select_simd(col, row_ids, true)
+}
+
+/// This is an implementation of filter then min for a decimal column using
+/// SIMD intrinsics on two 64-bit limbs. It panics if `row_ids` is empty.
+pub fn filter_min_simd(col: &Decimal128Column, row_ids: &[u32]) -> Result<i128, RowIdOutOfBounds> {
+    row_ids::check(row_ids, col.len())?;
+    if col.len() > MAX_GATHER_ROWS {
+        return Ok(filter_min(col, row_ids));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_min_simd_unchecked(col, row_ids) })
+}
+
+/// This is `filter_min_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `col.len()` and `MAX_GATHER_ROWS`.
+pub unsafe fn filter_min_simd_unchecked(col: &Decimal128Column, row_ids: &[u32]) -> i128 {
+    filter_select_simd(col, row_ids, false)
+}
+
+// The max (or min) of the selected values. Each lane keeps the high and low
+// limbs of its current max, and a gathered value replaces it when it's
+// greater (or less).
+unsafe fn filter_select_simd(col: &Decimal128Column, row_ids: &[u32], max: bool) -> i128 {
+    if row_ids.len() < 4 {
+        return if max {
+            filter_max(col, row_ids)
+        } else {
+            filter_min(col, row_ids)
+        };
+    }
+
+    let sign_bit = _mm256_set1_epi64x(i64::MIN);
+    let (lo, mut hi_lanes) = gather_limbs(col, row_ids);
+    let mut lo_lanes = _mm256_xor_si256(lo, sign_bit); // compared as unsigned
+
+    for chunk in row_ids.chunks_exact(4).skip(1) {
+        let (lo, hi) = gather_limbs(col, chunk);
+        let lo = _mm256_xor_si256(lo, sign_bit);
+
+        // (hi, lo) > (hi_lanes, lo_lanes), or < for min.
+        let (a_hi, a_lo, b_hi, b_lo) = if max {
+            (hi, lo, hi_lanes, lo_lanes)
+        } else {
+            (hi_lanes, lo_lanes, hi, lo)
+        };
+        let replace = _mm256_or_si256(
+            _mm256_cmpgt_epi64(a_hi, b_hi),
+            _mm256_and_si256(
+                _mm256_cmpeq_epi64(a_hi, b_hi),
+                _mm256_cmpgt_epi64(a_lo, b_lo),
+            ),
+        );
+        hi_lanes = _mm256_blendv_epi8(hi_lanes, hi, replace);
+        lo_lanes = _mm256_blendv_epi8(lo_lanes, lo, replace);
+    }
+
+    let his: [i64; 4] = std::mem::transmute(hi_lanes);
+    let los: [u64; 4] = std::mem::transmute(_mm256_xor_si256(lo_lanes, sign_bit));
+    let lanes = his
+        .iter()
+        .zip(los.iter())
+        .map(|(&hi, &lo)| ((hi as i128) << 64) | lo as i128);
+
+    // the remainder - at most three values.
+    let rem = row_ids.len() - (row_ids.len() % 4);
+    let values = lanes.chain(row_ids.iter().skip(rem).map(|&id| col.value(id as usize)));
+    if max {
+        values.max().unwrap()
+    } else {
+        values.min().unwrap()
+    }
+}
+
+mod test {
+    fn column(values: &[i128]) -> super::Decimal128Column {
+        super::Decimal128Column::try_new(values, 38, 2).unwrap()
+    }
+
+    #[test]
+    fn try_new() {
+        let col = super::Decimal128Column::try_new(&[12345, -99999, 0], 5, 2).unwrap();
+        assert_eq!((col.len(), col.precision(), col.scale()), (3, 5, 2));
+        assert_eq!(col.value(1), -99999);
+
+        assert_eq!(
+            super::Decimal128Column::try_new(&[], 0, 0),
+            Err(super::DecimalError::InvalidPrecision(0))
+        );
+        assert_eq!(
+            super::Decimal128Column::try_new(&[], 39, 0),
+            Err(super::DecimalError::InvalidPrecision(39))
+        );
+        assert_eq!(
+            super::Decimal128Column::try_new(&[], 5, 6),
+            Err(super::DecimalError::InvalidScale {
+                precision: 5,
+                scale: 6
+            })
+        );
+        assert_eq!(
+            super::Decimal128Column::try_new(&[1, -100000], 5, 2),
+            Err(super::DecimalError::ValueOutOfRange {
+                row: 1,
+                value: -100000
+            })
+        );
+    }
+
+    #[test]
+    fn filter_sum() {
+        let max = super::max_value(38);
+        let big = 1_i128 << 100;
+        let cases = vec![
+            (vec![1_i128, -2, 3], vec![0_u32, 1, 2], Ok(2)),
+            (vec![], vec![], Ok(0)),
+            // low limbs that carry, and high limbs of both signs.
+            (
+                vec![u64::MAX as i128, -(u64::MAX as i128), big, -big - 1],
+                vec![0, 0, 0, 0, 2, 2, 3, 1, 1],
+                Ok(4 * u64::MAX as i128 + big - 1 - 2 * u64::MAX as i128),
+            ),
+            (vec![max, 1], vec![0, 1], Err(super::DecimalError::Overflow)),
+            (vec![max, -max], vec![0, 1, 0, 0, 1], Ok(max)),
+            // the intermediate sums overflow an i128 but the sum fits.
+            (vec![max, -max], vec![0, 0, 0, 0, 1, 1, 1, 1], Ok(0)),
+            (
+                vec![max; 5],
+                (0..5).collect(),
+                Err(super::DecimalError::Overflow),
+            ),
+            (
+                vec![-max; 12],
+                (0..12).collect(),
+                Err(super::DecimalError::Overflow),
+            ),
+        ];
+
+        for (values, row_ids, exp) in &cases {
+            let col = column(values);
+            assert_eq!(&super::filter_sum(&col, row_ids), exp, "{:?}", row_ids);
+            assert_eq!(&super::filter_sum_simd(&col, row_ids), exp, "{:?}", row_ids);
+        }
+    }
+
+    #[test]
+    fn filter_min_max() {
+        let max = super::max_value(38);
+        let values = vec![
+            -1,
+            1,
+            u64::MAX as i128,
+            u64::MAX as i128 + 1,
+            -(u64::MAX as i128),
+            max,
+            -max,
+            0,
+        ];
+        let col = column(&values);
+        let cases = vec![
+            (
+                vec![0_u32, 1, 2, 3, 4, 7],
+                (-(u64::MAX as i128), u64::MAX as i128 + 1),
+            ),
+            (vec![7, 2, 1, 0, 3], (-1, u64::MAX as i128 + 1)),
+            ((0..8).collect(), (-max, max)),
+            (vec![2, 2, 2, 2, 1, 2, 2, 2, 2], (1, u64::MAX as i128)),
+        ];
+
+        for (row_ids, (min, max)) in &cases {
+            assert_eq!(&super::filter_min(&col, row_ids), min);
+            assert_eq!(&super::filter_max(&col, row_ids), max);
+            assert_eq!(&super::filter_min_simd(&col, row_ids).unwrap(), min);
+            assert_eq!(&super::filter_max_simd(&col, row_ids).unwrap(), max);
+        }
+    }
+
+    #[test]
+    fn out_of_bounds() {
+        let col = column(&[1, 2, 3, 4]);
+        let err = super::filter_sum_simd(&col, &[0, 1, 2, 4]).unwrap_err();
+        assert_eq!(err.to_string(), "row 4 out of bounds (4 rows)");
+        assert!(super::filter_max_simd(&col, &[0, 1, 2, 4]).is_err());
+        assert!(super::filter_min_simd(&col, &[0, 1, 2, 4]).is_err());
+    }
+}
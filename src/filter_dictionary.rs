@@ -0,0 +1,442 @@
+//! Filter and materialise functions for dictionary-encoded columns. A
+//! dictionary-encoded column is a vector of small integer keys (`u8`, `u16` or
+//! `u32`) and a dictionary of distinct values that the keys index into. This
+//! is how Arrow's `DictionaryArray` lays things out too.
+//!
+//! I care about these because many string and tag columns are dictionary
+//! encoded, and materialising them can either mean producing the keys alone
+//! (e.g., so they can be grouped on or passed on still encoded) or producing
+//! the fully decoded values.
+//!
+//! The SIMD implementations gather eight keys at a time with
+//! `narrow_gather`. For `u8` and `u16` keys that reads up to three bytes past
+//! the key, so any chunk of row ids that gets too close to the end of the keys
+//! (or beyond it) is materialised with the scalar (bounds checked) path.
+use std::arch::x86_64::*;
+
+use arrow::{array, compute::kernels, datatypes::UInt32Type};
+
+use crate::{
+    narrow_gather::{any_beyond, gather, gather_limit, store, NarrowValue},
+    row_ids::{self, RowIdOutOfBounds},
+};
+
+/// The integer types that can be used as dictionary keys.
+pub trait DictionaryKey: NarrowValue {}
+
+impl DictionaryKey for u8 {}
+impl DictionaryKey for u16 {}
+impl DictionaryKey for u32 {}
+
+/// This is a relatively idiomatic Rust implementation of filter over
+/// dictionary keys. The keys are materialised without being decoded.
+pub fn filter_materialise_keys<K: DictionaryKey>(
+    keys: &[K],
+    row_ids: &[u32],
+    mut dst: Vec<K>,
+) -> Vec<K> {
+    dst.clear();
+    dst.reserve(row_ids.len());
+
+    for &id in row_ids.iter() {
+        dst.push(keys[id as usize]);
+    }
+
+    assert_eq!(dst.len(), row_ids.len());
+    dst
+}
+
+/// This is a relatively idiomatic Rust implementation of filter over a
+/// dictionary-encoded column that materialises decoded values.
+pub fn filter_materialise_decoded<K: DictionaryKey, V: Copy>(
+    keys: &[K],
+    dictionary: &[V],
+    row_ids: &[u32],
+    mut dst: Vec<V>,
+) -> Vec<V> {
+    dst.clear();
+    dst.reserve(row_ids.len());
+
+    for &id in row_ids.iter() {
+        dst.push(dictionary[keys[id as usize].as_usize()]);
+    }
+
+    assert_eq!(dst.len(), row_ids.len());
+    dst
+}
+
+/// This is an implementation of filter on a dictionary array using Arrow
+/// arrays and kernels. The result is still a dictionary array, i.e., only the
+/// keys are materialised.
+pub fn filter_materialise_keys_arrow(
+    values: &array::DictionaryArray<UInt32Type>,
+    row_ids: &array::BooleanArray,
+) -> array::ArrayRef {
+    kernels::filter::filter(values, row_ids).unwrap()
+}
+
+/// This is an implementation of filter on a dictionary array using Arrow
+/// arrays and kernels, where the result is decoded by casting it to the
+/// dictionary's value type.
+pub fn filter_materialise_decoded_arrow(
+    values: &array::DictionaryArray<UInt32Type>,
+    row_ids: &array::BooleanArray,
+) -> array::ArrayRef {
+    let filter_result = kernels::filter::filter(values, row_ids).unwrap();
+    kernels::cast::cast(&filter_result, values.values().data_type()).unwrap()
+}
+
+/// This is an implementation of filter over dictionary keys using SIMD
+/// intrinsics. Keys are gathered eight at a time into 32-bit lanes, and packed
+/// back down to the key width when they're stored.
+///
+/// The row ids are checked with `row_ids::check` before any keys are
+/// gathered, and `RowIdOutOfBounds` is returned if any are out of range.
+pub fn filter_materialise_keys_simd<K: DictionaryKey>(
+    keys: &[K],
+    row_ids: &[u32],
+    dst: Vec<K>,
+) -> Result<Vec<K>, RowIdOutOfBounds> {
+    row_ids::check(row_ids, keys.len())?;
+    if keys.len() > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_materialise_keys(keys, row_ids, dst));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_materialise_keys_simd_unchecked(keys, row_ids, dst) })
+}
+
+/// This is `filter_materialise_keys_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `keys.len()` and at most `i32::MAX`.
+pub unsafe fn filter_materialise_keys_simd_unchecked<K: DictionaryKey>(
+    keys: &[K],
+    row_ids: &[u32],
+    mut dst: Vec<K>,
+) -> Vec<K> {
+    dst.clear();
+    dst.reserve(row_ids.len());
+
+    let limit = _mm256_set1_epi32(gather_limit(keys) as i32);
+
+    for chunk in row_ids.chunks_exact(8) {
+        let ids = _mm256_loadu_si256(chunk.as_ptr() as *const __m256i);
+        if any_beyond(ids, limit) {
+            for &id in chunk {
+                dst.push(keys[id as usize]);
+            }
+            continue;
+        }
+
+        let mat_keys = gather(keys, ids);
+        store(mat_keys, dst.as_mut_ptr().add(dst.len()));
+        dst.set_len(dst.len() + 8);
+    }
+
+    // materialise any remainder - maximum of seven values.
+    let rem = row_ids.len() - (row_ids.len() % 8);
+    for &id in row_ids.iter().skip(rem) {
+        dst.push(keys[id as usize]);
+    }
+
+    assert_eq!(dst.len(), row_ids.len());
+    dst
+}
+
+/// This is an implementation of filter over a dictionary-encoded column that
+/// materialises decoded values using SIMD intrinsics. Keys are gathered eight
+/// at a time and then used to look the values up in the dictionary.
+///
+/// The row ids are checked with `row_ids::check` before any keys are
+/// gathered, and `RowIdOutOfBounds` is returned if any are out of range.
+pub fn filter_materialise_decoded_simd<K: DictionaryKey, V: Copy>(
+    keys: &[K],
+    dictionary: &[V],
+    row_ids: &[u32],
+    dst: Vec<V>,
+) -> Result<Vec<V>, RowIdOutOfBounds> {
+    row_ids::check(row_ids, keys.len())?;
+    if keys.len() > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_materialise_decoded(keys, dictionary, row_ids, dst));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_materialise_decoded_simd_unchecked(keys, dictionary, row_ids, dst) })
+}
+
+/// This is `filter_materialise_decoded_simd` without the bounds check. The
+/// keys are still checked against the dictionary.
+///
+/// # Safety
+///
+/// Every row id must be less than `keys.len()` and at most `i32::MAX`.
+pub unsafe fn filter_materialise_decoded_simd_unchecked<K: DictionaryKey, V: Copy>(
+    keys: &[K],
+    dictionary: &[V],
+    row_ids: &[u32],
+    mut dst: Vec<V>,
+) -> Vec<V> {
+    dst.clear();
+    dst.reserve(row_ids.len());
+
+    let limit = _mm256_set1_epi32(gather_limit(keys) as i32);
+
+    for chunk in row_ids.chunks_exact(8) {
+        let ids = _mm256_loadu_si256(chunk.as_ptr() as *const __m256i);
+        if any_beyond(ids, limit) {
+            for &id in chunk {
+                dst.push(dictionary[keys[id as usize].as_usize()]);
+            }
+            continue;
+        }
+
+        let mat_keys: [u32; 8] = std::mem::transmute(gather(keys, ids));
+        for &key in mat_keys.iter() {
+            dst.push(dictionary[K::from_lane(key).as_usize()]);
+        }
+    }
+
+    let rem = row_ids.len() - (row_ids.len() % 8);
+    for &id in row_ids.iter().skip(rem) {
+        dst.push(dictionary[keys[id as usize].as_usize()]);
+    }
+
+    assert_eq!(dst.len(), row_ids.len());
+    dst
+}
+
+/// This is an implementation of filter over a dictionary-encoded column with
+/// `u64` dictionary values using SIMD intrinsics. Both steps are gathers: the
+/// keys are gathered with `_mm256_i32gather_epi32` and then used as the indexes
+/// of two `_mm256_i32gather_epi64`s into the dictionary.
+///
+/// The row ids are checked with `row_ids::check` before any keys are
+/// gathered, and `RowIdOutOfBounds` is returned if any are out of range. The
+/// gathered keys are checked against the size of the dictionary too: a chunk
+/// with a key that isn't in the dictionary is decoded with the scalar path,
+/// which panics like `filter_materialise_decoded`.
+pub fn filter_materialise_decoded_u64_simd<K: DictionaryKey>(
+    keys: &[K],
+    dictionary: &[u64],
+    row_ids: &[u32],
+    dst: Vec<u64>,
+) -> Result<Vec<u64>, RowIdOutOfBounds> {
+    row_ids::check(row_ids, keys.len())?;
+    if keys.len() > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_materialise_decoded(keys, dictionary, row_ids, dst));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_materialise_decoded_u64_simd_unchecked(keys, dictionary, row_ids, dst) })
+}
+
+/// This is `filter_materialise_decoded_u64_simd` without the row id bounds
+/// check. The keys are still checked against the dictionary.
+///
+/// # Safety
+///
+/// Every row id must be less than `keys.len()` and at most `i32::MAX`.
+pub unsafe fn filter_materialise_decoded_u64_simd_unchecked<K: DictionaryKey>(
+    keys: &[K],
+    dictionary: &[u64],
+    row_ids: &[u32],
+    mut dst: Vec<u64>,
+) -> Vec<u64> {
+    dst.clear();
+    dst.reserve(row_ids.len());
+
+    let limit = _mm256_set1_epi32(gather_limit(keys) as i32);
+    // keys at or above this aren't in the dictionary, or can't be used as a
+    // (signed) gather offset.
+    let dict_limit = _mm256_set1_epi32(dictionary.len().min(i32::MAX as usize) as i32);
+    let dict_ptr = dictionary.as_ptr() as *const i64;
+
+    for chunk in row_ids.chunks_exact(8) {
+        let ids = _mm256_loadu_si256(chunk.as_ptr() as *const __m256i);
+        let mat_keys = if any_beyond(ids, limit) {
+            None
+        } else {
+            Some(gather(keys, ids))
+        };
+
+        match mat_keys {
+            Some(mat_keys) if !any_beyond(mat_keys, dict_limit) => {
+                let lo = _mm256_i32gather_epi64(dict_ptr, _mm256_castsi256_si128(mat_keys), 8);
+                let hi = _mm256_i32gather_epi64(dict_ptr, _mm256_extracti128_si256(mat_keys, 1), 8);
+
+                let dst_ptr = dst.as_mut_ptr().add(dst.len()) as *mut __m256i;
+                _mm256_storeu_si256(dst_ptr, lo);
+                _mm256_storeu_si256(dst_ptr.add(1), hi);
+                dst.set_len(dst.len() + 8);
+            }
+            _ => {
+                for &id in chunk {
+                    dst.push(dictionary[keys[id as usize].as_usize()]);
+                }
+            }
+        }
+    }
+
+    let rem = row_ids.len() - (row_ids.len() % 8);
+    for &id in row_ids.iter().skip(rem) {
+        dst.push(dictionary[keys[id as usize].as_usize()]);
+    }
+
+    assert_eq!(dst.len(), row_ids.len());
+    dst
+}
+
+mod test {
+
+    #[test]
+    fn filter_materialise_keys() {
+        let keys_u8 = (0..300).map(|x| (x % 256) as u8).collect::<Vec<_>>();
+        let keys_u16 = (0..300).map(|x| (x * 211) as u16).collect::<Vec<_>>();
+        let keys_u32 = (0..300).map(|x| x * 100_003).collect::<Vec<u32>>();
+
+        let cases = vec![
+            vec![0_u32, 1, 2, 3],
+            vec![299],
+            (0..300).collect::<Vec<_>>(),
+            // the tail of the column must fall back to the scalar path for
+            // narrow keys.
+            (290..300).rev().collect::<Vec<_>>(),
+            vec![3, 3, 3, 3, 3, 3, 3, 3, 3, 298, 0, 17, 200, 255, 256, 299, 1],
+        ];
+
+        for row_ids in &cases {
+            let exp_u8 = row_ids
+                .iter()
+                .map(|&id| keys_u8[id as usize])
+                .collect::<Vec<_>>();
+            let exp_u16 = row_ids
+                .iter()
+                .map(|&id| keys_u16[id as usize])
+                .collect::<Vec<_>>();
+            let exp_u32 = row_ids
+                .iter()
+                .map(|&id| keys_u32[id as usize])
+                .collect::<Vec<_>>();
+
+            assert_eq!(
+                super::filter_materialise_keys(&keys_u8, row_ids, vec![]),
+                exp_u8
+            );
+            assert_eq!(
+                super::filter_materialise_keys_simd(&keys_u8, row_ids, vec![]).unwrap(),
+                exp_u8
+            );
+            assert_eq!(
+                super::filter_materialise_keys(&keys_u16, row_ids, vec![]),
+                exp_u16
+            );
+            assert_eq!(
+                super::filter_materialise_keys_simd(&keys_u16, row_ids, vec![]).unwrap(),
+                exp_u16
+            );
+            assert_eq!(
+                super::filter_materialise_keys(&keys_u32, row_ids, vec![]),
+                exp_u32
+            );
+            assert_eq!(
+                super::filter_materialise_keys_simd(&keys_u32, row_ids, vec![]).unwrap(),
+                exp_u32
+            );
+        }
+    }
+
+    #[test]
+    fn filter_materialise_keys_simd_out_of_bounds() {
+        let keys = vec![1_u8; 16];
+        let row_ids = [0, 1, 2, 3, 4, 5, 6, 16];
+        let err = super::filter_materialise_keys_simd(&keys, &row_ids, vec![]).unwrap_err();
+        assert_eq!((err.row_id, err.rows), (16, 16));
+
+        let err = super::filter_materialise_decoded_simd(&keys, &["a", "b"], &row_ids, vec![])
+            .unwrap_err();
+        assert_eq!((err.row_id, err.rows), (16, 16));
+    }
+
+    #[test]
+    fn filter_materialise_decoded() {
+        let dictionary = vec!["cpu", "mem", "disk", "net"];
+        let dictionary_u64 = vec![10_u64, 20, u64::MAX, 40];
+        let keys = (0..45).map(|x| (x % 4) as u16).collect::<Vec<_>>();
+
+        let cases = vec![
+            (vec![0_u32, 1, 2, 3], vec![0, 1, 2, 3]),
+            (vec![44, 43], vec![0, 3]),
+            (
+                vec![1, 2, 5, 6, 9, 10, 13, 14, 17, 40, 41, 42],
+                vec![1, 2, 1, 2, 1, 2, 1, 2, 1, 0, 1, 2],
+            ),
+        ];
+
+        for (row_ids, exp_keys) in &cases {
+            let exp = exp_keys.iter().map(|&k| dictionary[k]).collect::<Vec<_>>();
+            let exp_u64 = exp_keys
+                .iter()
+                .map(|&k| dictionary_u64[k])
+                .collect::<Vec<_>>();
+
+            assert_eq!(
+                super::filter_materialise_decoded(&keys, &dictionary, row_ids, vec![]),
+                exp
+            );
+            assert_eq!(
+                super::filter_materialise_decoded_simd(&keys, &dictionary, row_ids, vec![])
+                    .unwrap(),
+                exp
+            );
+            assert_eq!(
+                super::filter_materialise_decoded_u64_simd(&keys, &dictionary_u64, row_ids, vec![])
+                    .unwrap(),
+                exp_u64
+            );
+        }
+
+        assert!(
+            super::filter_materialise_decoded_u64_simd(&keys, &dictionary_u64, &[45], vec![])
+                .is_err()
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn filter_materialise_decoded_u64_simd_bad_key() {
+        // a key beyond the dictionary, and one that would be a negative
+        // gather offset, in a chunk that would otherwise be gathered.
+        let keys = vec![0, 1, 2, 3, 4, 1 << 31, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0_u32];
+        let dictionary = vec![10_u64, 20, 30, 40];
+        let _ = super::filter_materialise_decoded_u64_simd(
+            &keys,
+            &dictionary,
+            &[0, 1, 2, 3, 4, 5, 6, 7],
+            vec![],
+        );
+    }
+
+    #[test]
+    fn filter_materialise_arrow() {
+        let values = vec!["cpu", "mem", "cpu", "disk", "net", "mem", "mem"]
+            .into_iter()
+            .collect::<arrow::array::DictionaryArray<arrow::datatypes::UInt32Type>>();
+        let row_ids =
+            arrow::array::BooleanArray::from(vec![false, true, true, false, true, false, true]);
+
+        let keys = super::filter_materialise_keys_arrow(&values, &row_ids);
+        assert_eq!(keys.len(), 4);
+
+        let decoded = super::filter_materialise_decoded_arrow(&values, &row_ids);
+        let decoded = decoded
+            .as_any()
+            .downcast_ref::<arrow::array::StringArray>()
+            .unwrap();
+        let decoded = (0..decoded.len())
+            .map(|i| decoded.value(i))
+            .collect::<Vec<_>>();
+        assert_eq!(decoded, vec!["mem", "cpu", "net", "mem"]);
+    }
+}
@@ -0,0 +1,425 @@
+//! Filter then count distinct values, exactly and approximately.
+//!
+//! Both are built on the same 64-bit hash of each value: the finaliser from
+//! MurmurHash3 (`fmix64`), which mixes every input bit into every output bit
+//! with two multiplies and three shifts. The SIMD implementations gather four
+//! values at a time and hash them in a register. AVX2 has no 64-bit multiply,
+//! so each multiply is three 32-bit multiplies
+//! (`simd_u64::mullo_epi64`).
+//!
+//! The exact count inserts the values into a `DistinctSet`, an open
+//! addressing hash table with linear probing that takes the value's hash
+//! rather than hashing it again. The idiomatic implementation uses a
+//! `HashSet` instead.
+//!
+//! The approximate count is a `HyperLogLog`. Each value's hash picks one of
+//! `2^precision` registers with its top `precision` bits, and the register
+//! keeps the largest number of leading zeros (plus one) seen in the rest of
+//! the hash. The registers of two sketches with the same precision can be
+//! merged by taking the largest of each, so chunks of a column (or rows
+//! handled by different threads) can be sketched separately and merged. The
+//! standard error of the estimate is about `1.04 / sqrt(2^precision)`, which
+//! is 0.8% for `DEFAULT_PRECISION`.
+use std::{arch::x86_64::*, collections::HashSet};
+
+use crate::{
+    row_ids::{self, RowIdOutOfBounds},
+    simd_u64::mullo_epi64,
+};
+
+const MIX_1: u64 = 0xff51_afd7_ed55_8ccd;
+const MIX_2: u64 = 0xc4ce_b9fe_1a85_ec53;
+
+/// The `HyperLogLog` precision used by the benchmarks: 2^14 registers.
+pub const DEFAULT_PRECISION: u8 = 14;
+
+/// The 64-bit hash of a value.
+pub fn hash(value: u64) -> u64 {
+    let mut h = value;
+    h ^= h >> 33;
+    h = h.wrapping_mul(MIX_1);
+    h ^= h >> 33;
+    h = h.wrapping_mul(MIX_2);
+    h ^ (h >> 33)
+}
+
+// `hash` on four values at once.
+#[inline]
+unsafe fn hash_simd(values: __m256i) -> __m256i {
+    let mix_1 = _mm256_set1_epi64x(MIX_1 as i64);
+    let mix_2 = _mm256_set1_epi64x(MIX_2 as i64);
+
+    let mut h = values;
+    h = _mm256_xor_si256(h, _mm256_srli_epi64(h, 33));
+    h = mullo_epi64(h, mix_1, _mm256_srli_epi64(mix_1, 32));
+    h = _mm256_xor_si256(h, _mm256_srli_epi64(h, 33));
+    h = mullo_epi64(h, mix_2, _mm256_srli_epi64(mix_2, 32));
+    _mm256_xor_si256(h, _mm256_srli_epi64(h, 33))
+}
+
+// Gather four values at a time and call `f` with each value and its hash,
+// then do the same for the remainder with the scalar `hash`.
+#[inline]
+unsafe fn for_each_hashed(values: &[u64], row_ids: &[u32], mut f: impl FnMut(u64, u64)) {
+    let base_ptr = values.as_ptr() as *const i64;
+
+    for chunk in row_ids.chunks_exact(4) {
+        let ids = _mm_loadu_si128(chunk.as_ptr() as *const __m128i);
+        let row_values = _mm256_i32gather_epi64(base_ptr, ids, 8);
+        let row_hashes = hash_simd(row_values);
+
+        let row_values: [u64; 4] = std::mem::transmute(row_values);
+        let row_hashes: [u64; 4] = std::mem::transmute(row_hashes);
+        for i in 0..4 {
+            f(row_values[i], row_hashes[i]);
+        }
+    }
+
+    // any remainder - maximum of three values.
+    let rem = row_ids.len() - (row_ids.len() % 4);
+    for &id in row_ids.iter().skip(rem) {
+        let value = values[id as usize];
+        f(value, hash(value));
+    }
+}
+
+/// A set of `u64` values, for counting them. It's an open addressing hash
+/// table keyed by `hash`, where an empty slot is zero (so the value zero is
+/// tracked separately). It grows to keep at most half of the slots full.
+#[derive(Debug, Clone)]
+pub struct DistinctSet {
+    slots: Vec<u64>,
+    len: usize,
+    has_zero: bool,
+}
+
+impl Default for DistinctSet {
+    fn default() -> Self {
+        Self {
+            slots: vec![0; 1024],
+            len: 0,
+            has_zero: false,
+        }
+    }
+}
+
+impl DistinctSet {
+    /// The number of distinct values inserted.
+    pub fn len(&self) -> usize {
+        self.len + self.has_zero as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn insert(&mut self, value: u64) {
+        self.insert_hashed(value, hash(value));
+    }
+
+    /// Insert every value in `other`, e.g. to combine the sets of each chunk
+    /// of a column.
+    pub fn merge(&mut self, other: &DistinctSet) {
+        self.has_zero |= other.has_zero;
+        for &value in other.slots.iter().filter(|&&v| v != 0) {
+            self.insert(value);
+        }
+    }
+
+    // `hash` must be `hash(value)`.
+    fn insert_hashed(&mut self, value: u64, hash: u64) {
+        if value == 0 {
+            self.has_zero = true;
+            return;
+        }
+        if (self.len + 1) * 2 > self.slots.len() {
+            self.grow();
+        }
+
+        let mask = self.slots.len() - 1;
+        let mut i = hash as usize & mask;
+        loop {
+            match self.slots[i] {
+                slot if slot == value => return,
+                0 => {
+                    self.slots[i] = value;
+                    self.len += 1;
+                    return;
+                }
+                _ => i = (i + 1) & mask,
+            }
+        }
+    }
+
+    fn grow(&mut self) {
+        let slots = vec![0; self.slots.len() * 2];
+        let slots = std::mem::replace(&mut self.slots, slots);
+        self.len = 0;
+        for value in slots.into_iter().filter(|&v| v != 0) {
+            self.insert(value);
+        }
+    }
+}
+
+/// This is a relatively idiomatic Rust implementation of filter then count
+/// distinct, using a `HashSet`.
+pub fn filter_count_distinct(values: &[u64], row_ids: &[u32]) -> usize {
+    row_ids
+        .iter()
+        .map(|&id| values[id as usize])
+        .collect::<HashSet<_>>()
+        .len()
+}
+
+/// This is an implementation of filter then count distinct that hashes the
+/// values using SIMD intrinsics, and counts them with a `DistinctSet`.
+pub fn filter_count_distinct_simd(
+    values: &[u64],
+    row_ids: &[u32],
+) -> Result<usize, RowIdOutOfBounds> {
+    row_ids::check(row_ids, values.len())?;
+    if values.len() > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_count_distinct(values, row_ids));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_count_distinct_simd_unchecked(values, row_ids) })
+}
+
+/// This is `filter_count_distinct_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `values.len()` and at most `i32::MAX`.
+pub unsafe fn filter_count_distinct_simd_unchecked(values: &[u64], row_ids: &[u32]) -> usize {
+    let mut set = DistinctSet::default();
+    for_each_hashed(values, row_ids, |value, hash| {
+        set.insert_hashed(value, hash)
+    });
+    set.len()
+}
+
+/// A HyperLogLog sketch of a set of values. See the module docs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HyperLogLog {
+    precision: u8,
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    /// A sketch with `2^precision` registers. Panics unless `precision` is in
+    /// `4..=16`.
+    pub fn new(precision: u8) -> Self {
+        assert!((4..=16).contains(&precision), "invalid precision");
+        Self {
+            precision,
+            registers: vec![0; 1 << precision],
+        }
+    }
+
+    pub fn precision(&self) -> u8 {
+        self.precision
+    }
+
+    pub fn insert(&mut self, value: u64) {
+        self.insert_hash(hash(value));
+    }
+
+    fn insert_hash(&mut self, hash: u64) {
+        let index = (hash >> (64 - self.precision)) as usize;
+        // the rest of the hash, with a stop bit so the rank is at most
+        // 65 - precision.
+        let rest = (hash << self.precision) | (1 << (self.precision - 1));
+        let rank = rest.leading_zeros() as u8 + 1;
+        let register = &mut self.registers[index];
+        *register = (*register).max(rank);
+    }
+
+    /// Merge the values in `other` into this sketch. Panics if the sketches
+    /// have different precisions.
+    pub fn merge(&mut self, other: &HyperLogLog) {
+        assert_eq!(self.precision, other.precision, "different precisions");
+        for (register, &other) in self.registers.iter_mut().zip(other.registers.iter()) {
+            *register = (*register).max(other);
+        }
+    }
+
+    /// The estimated number of distinct values inserted.
+    pub fn estimate(&self) -> f64 {
+        let m = self.registers.len() as f64;
+        let alpha = match self.registers.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+        let sum = self
+            .registers
+            .iter()
+            .map(|&r| 2_f64.powi(-(r as i32)))
+            .sum::<f64>();
+        let estimate = alpha * m * m / sum;
+
+        // small cardinalities are estimated better from the empty registers
+        // (linear counting). The hash is 64 bits, so there's no large range
+        // correction.
+        let zeros = self.registers.iter().filter(|&&r| r == 0).count();
+        if estimate <= 2.5 * m && zeros > 0 {
+            m * (m / zeros as f64).ln()
+        } else {
+            estimate
+        }
+    }
+}
+
+/// This is a relatively idiomatic Rust implementation of filter then adding
+/// the values to a `HyperLogLog`.
+pub fn filter_hyperloglog(values: &[u64], row_ids: &[u32], hll: &mut HyperLogLog) {
+    for &id in row_ids.iter() {
+        hll.insert(values[id as usize]);
+    }
+}
+
+/// This is an implementation of filter then adding the values to a
+/// `HyperLogLog` that hashes the values using SIMD intrinsics.
+pub fn filter_hyperloglog_simd(
+    values: &[u64],
+    row_ids: &[u32],
+    hll: &mut HyperLogLog,
+) -> Result<(), RowIdOutOfBounds> {
+    row_ids::check(row_ids, values.len())?;
+    if values.len() > row_ids::MAX_GATHER_ROWS {
+        filter_hyperloglog(values, row_ids, hll);
+        return Ok(());
+    }
+
+    // SAFETY: every row id was checked above.
+    unsafe { filter_hyperloglog_simd_unchecked(values, row_ids, hll) };
+    Ok(())
+}
+
+/// This is `filter_hyperloglog_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `values.len()` and at most `i32::MAX`.
+pub unsafe fn filter_hyperloglog_simd_unchecked(
+    values: &[u64],
+    row_ids: &[u32],
+    hll: &mut HyperLogLog,
+) {
+    for_each_hashed(values, row_ids, |_, hash| hll.insert_hash(hash));
+}
+
+mod test {
+
+    #[test]
+    fn hash_simd() {
+        let values = [0, 1, u64::MAX, 0x0123_4567_89ab_cdef];
+        let got: [u64; 4] = unsafe {
+            std::mem::transmute(super::hash_simd(std::arch::x86_64::_mm256_loadu_si256(
+                values.as_ptr() as *const _,
+            )))
+        };
+        for (&value, &got) in values.iter().zip(got.iter()) {
+            assert_eq!(got, super::hash(value));
+        }
+        assert_eq!(super::hash(0), 0);
+        assert_ne!(super::hash(1), super::hash(2));
+    }
+
+    #[test]
+    fn filter_count_distinct() {
+        let values = (0..10000_u64).map(|v| v * 7919 % 1000).collect::<Vec<_>>();
+        let cases = vec![
+            (vec![], 0),
+            (vec![0_u32, 1, 2], 3),
+            (vec![0, 1000, 2000, 1, 1001], 2),
+            ((0..10000).collect(), 1000),
+            ((0..10000).step_by(3).collect(), 1000),
+        ];
+
+        for (row_ids, exp) in &cases {
+            assert_eq!(&super::filter_count_distinct(&values, row_ids), exp);
+            assert_eq!(
+                &super::filter_count_distinct_simd(&values, row_ids).unwrap(),
+                exp
+            );
+        }
+
+        // zero, and enough values to grow the set several times.
+        let values = (0..10000_u64).collect::<Vec<_>>();
+        let row_ids = (0..10000).rev().collect::<Vec<u32>>();
+        assert_eq!(
+            super::filter_count_distinct_simd(&values, &row_ids),
+            Ok(10000)
+        );
+
+        let err = super::filter_count_distinct_simd(&values, &[0, 10000]).unwrap_err();
+        assert_eq!(err.row_id, 10000);
+    }
+
+    #[test]
+    fn distinct_set_merge() {
+        let mut a = super::DistinctSet::default();
+        let mut b = super::DistinctSet::default();
+        (0..3000).for_each(|v| a.insert(v));
+        (2000..5000).for_each(|v| b.insert(v));
+        a.merge(&b);
+        assert_eq!(a.len(), 5000);
+    }
+
+    #[test]
+    fn filter_hyperloglog() {
+        let values = (0..200_000_u64)
+            .map(|v| v * 7919 % 100_000)
+            .collect::<Vec<_>>();
+        let row_ids = (0..200_000).collect::<Vec<u32>>();
+
+        let mut hll = super::HyperLogLog::new(super::DEFAULT_PRECISION);
+        super::filter_hyperloglog(&values, &row_ids, &mut hll);
+        let mut hll_simd = super::HyperLogLog::new(super::DEFAULT_PRECISION);
+        super::filter_hyperloglog_simd(&values, &row_ids, &mut hll_simd).unwrap();
+        assert_eq!(hll, hll_simd);
+
+        // within four standard errors.
+        let error = (hll.estimate() - 100_000.0).abs() / 100_000.0;
+        assert!(error < 4.0 * 1.04 / 128.0, "{}", hll.estimate());
+
+        // small cardinalities use linear counting.
+        let mut small = super::HyperLogLog::new(super::DEFAULT_PRECISION);
+        super::filter_hyperloglog(&values, &row_ids[..100], &mut small);
+        assert!(
+            (small.estimate() - 100.0).abs() < 2.0,
+            "{}",
+            small.estimate()
+        );
+
+        let empty = super::HyperLogLog::new(4);
+        assert_eq!(empty.estimate(), 0.0);
+    }
+
+    #[test]
+    fn hyperloglog_merge() {
+        let values = (0..50_000_u64).collect::<Vec<_>>();
+        let row_ids = (0..50_000).collect::<Vec<u32>>();
+
+        let mut all = super::HyperLogLog::new(10);
+        super::filter_hyperloglog(&values, &row_ids, &mut all);
+
+        // sketch each chunk separately then merge them.
+        let mut merged = super::HyperLogLog::new(10);
+        for chunk in row_ids.chunks(7000) {
+            let mut hll = super::HyperLogLog::new(10);
+            super::filter_hyperloglog_simd(&values, chunk, &mut hll).unwrap();
+            merged.merge(&hll);
+        }
+        assert_eq!(merged, all);
+    }
+
+    #[test]
+    #[should_panic]
+    fn hyperloglog_merge_precision() {
+        super::HyperLogLog::new(10).merge(&super::HyperLogLog::new(11));
+    }
+}
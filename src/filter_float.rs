@@ -0,0 +1,827 @@
+//! Filter and aggregate functions over floating-point columns. These are the
+//! `f64` and `f32` versions of `filter_sum` and `filter_max` (plus min), which
+//! only deal with `u64` values.
+//!
+//! NaN handling is explicit for min and max via `NanMode`:
+//!
+//! - `NanMode::Propagate`: any selected NaN makes the result NaN.
+//! - `NanMode::Ignore`: NaNs are skipped. The result is only NaN when nothing
+//!   but NaNs (or nothing at all) was selected.
+//!
+//! Arrow's `min` and `max` kernels (at the pinned rev) are implemented with
+//! plain `<`/`>` comparisons, so a NaN never replaces the current min/max and
+//! is effectively ignored, unless it happens to be the first selected value,
+//! in which case it sticks. `NanMode::Ignore` is the consistent version of
+//! Arrow's behaviour and the `_arrow` functions here simply report what Arrow
+//! returns.
+//!
+//! Sums always propagate NaN (it's just IEEE addition), as Arrow's `sum` does.
+//! Summing floats in a different order gives (slightly) different results, so
+//! the SIMD sums won't match the idiomatic ones bit-for-bit. The `_kahan`
+//! variants use Kahan-compensated summation, per lane in the SIMD case, which
+//! is far less sensitive to both ordering and magnitude differences. Once a
+//! compensated sum reaches infinity its compensation term becomes `inf - inf`,
+//! i.e., NaN, so when a compensated sum comes out as NaN the plain sum is
+//! returned instead: it's only NaN if a NaN (or infinities of both signs) was
+//! selected.
+//!
+//! The SIMD implementations check their row ids with `row_ids::check` before
+//! gathering, like the `u64` ones, and have `_unchecked` variants.
+use std::arch::x86_64::*;
+
+use arrow::{array, compute::kernels};
+
+use crate::row_ids::{self, RowIdOutOfBounds};
+
+/// How min and max treat NaN values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NanMode {
+    /// Any selected NaN makes the result NaN.
+    Propagate,
+    /// Selected NaNs are skipped.
+    Ignore,
+}
+
+/// This is a relatively idiomatic Rust implementation of filter_sum for `f64`
+/// values. It serves as a baseline.
+pub fn filter_sum_f64(values: &[f64], row_ids: &[u32]) -> f64 {
+    let mut result = 0.0;
+    for &id in row_ids.iter() {
+        result += values[id as usize];
+    }
+    result
+}
+
+/// This is a Kahan-compensated implementation of filter_sum for `f64` values.
+/// A running compensation term recovers the low-order bits lost on each
+/// addition.
+pub fn filter_sum_f64_kahan(values: &[f64], row_ids: &[u32]) -> f64 {
+    let mut sum = 0.0;
+    let mut c = 0.0;
+    for &id in row_ids.iter() {
+        let y = values[id as usize] - c;
+        let t = sum + y;
+        c = (t - sum) - y;
+        sum = t;
+    }
+
+    if sum.is_nan() {
+        return filter_sum_f64(values, row_ids);
+    }
+    sum
+}
+
+/// This is a relatively idiomatic Rust implementation of filter_max for `f64`
+/// values. It serves as a baseline.
+pub fn filter_max_f64(values: &[f64], row_ids: &[u32], nan: NanMode) -> f64 {
+    // `f64::max` ignores NaN, so starting with NaN means the result stays NaN
+    // only if there are no other values.
+    let mut result = f64::NAN;
+    for &id in row_ids.iter() {
+        let v = values[id as usize];
+        if v.is_nan() && nan == NanMode::Propagate {
+            return f64::NAN;
+        }
+        result = result.max(v);
+    }
+    result
+}
+
+/// This is a relatively idiomatic Rust implementation of filter_min for `f64`
+/// values. It serves as a baseline.
+pub fn filter_min_f64(values: &[f64], row_ids: &[u32], nan: NanMode) -> f64 {
+    let mut result = f64::NAN;
+    for &id in row_ids.iter() {
+        let v = values[id as usize];
+        if v.is_nan() && nan == NanMode::Propagate {
+            return f64::NAN;
+        }
+        result = result.min(v);
+    }
+    result
+}
+
+/// This is a relatively idiomatic Rust implementation of filter_sum for `f32`
+/// values. The sum is accumulated as an `f32`.
+pub fn filter_sum_f32(values: &[f32], row_ids: &[u32]) -> f32 {
+    let mut result = 0.0;
+    for &id in row_ids.iter() {
+        result += values[id as usize];
+    }
+    result
+}
+
+/// This is a Kahan-compensated implementation of filter_sum for `f32` values.
+pub fn filter_sum_f32_kahan(values: &[f32], row_ids: &[u32]) -> f32 {
+    let mut sum = 0.0;
+    let mut c = 0.0;
+    for &id in row_ids.iter() {
+        let y = values[id as usize] - c;
+        let t = sum + y;
+        c = (t - sum) - y;
+        sum = t;
+    }
+
+    if sum.is_nan() {
+        return filter_sum_f32(values, row_ids);
+    }
+    sum
+}
+
+/// This is a relatively idiomatic Rust implementation of filter_max for `f32`
+/// values.
+pub fn filter_max_f32(values: &[f32], row_ids: &[u32], nan: NanMode) -> f32 {
+    let mut result = f32::NAN;
+    for &id in row_ids.iter() {
+        let v = values[id as usize];
+        if v.is_nan() && nan == NanMode::Propagate {
+            return f32::NAN;
+        }
+        result = result.max(v);
+    }
+    result
+}
+
+/// This is a relatively idiomatic Rust implementation of filter_min for `f32`
+/// values.
+pub fn filter_min_f32(values: &[f32], row_ids: &[u32], nan: NanMode) -> f32 {
+    let mut result = f32::NAN;
+    for &id in row_ids.iter() {
+        let v = values[id as usize];
+        if v.is_nan() && nan == NanMode::Propagate {
+            return f32::NAN;
+        }
+        result = result.min(v);
+    }
+    result
+}
+
+/// This is an implementation of filter and sum using Arrow arrays and kernels.
+/// An empty selection sums to `0.0`.
+pub fn filter_sum_f64_arrow(values: &array::Float64Array, row_ids: &array::BooleanArray) -> f64 {
+    let filter_result = kernels::filter::filter(values, row_ids).unwrap();
+    kernels::aggregate::sum(
+        filter_result
+            .as_any()
+            .downcast_ref::<array::Float64Array>()
+            .unwrap(),
+    )
+    .unwrap_or(0.0)
+}
+
+/// This is an implementation of filter and max using Arrow arrays and kernels.
+/// See the module docs for how Arrow treats NaN. An empty selection is NaN.
+pub fn filter_max_f64_arrow(values: &array::Float64Array, row_ids: &array::BooleanArray) -> f64 {
+    let filter_result = kernels::filter::filter(values, row_ids).unwrap();
+    kernels::aggregate::max(
+        filter_result
+            .as_any()
+            .downcast_ref::<array::Float64Array>()
+            .unwrap(),
+    )
+    .unwrap_or(f64::NAN)
+}
+
+/// This is an implementation of filter and min using Arrow arrays and kernels.
+/// See the module docs for how Arrow treats NaN. An empty selection is NaN.
+pub fn filter_min_f64_arrow(values: &array::Float64Array, row_ids: &array::BooleanArray) -> f64 {
+    let filter_result = kernels::filter::filter(values, row_ids).unwrap();
+    kernels::aggregate::min(
+        filter_result
+            .as_any()
+            .downcast_ref::<array::Float64Array>()
+            .unwrap(),
+    )
+    .unwrap_or(f64::NAN)
+}
+
+/// This is an implementation of filter and sum using Arrow arrays and kernels.
+pub fn filter_sum_f32_arrow(values: &array::Float32Array, row_ids: &array::BooleanArray) -> f32 {
+    let filter_result = kernels::filter::filter(values, row_ids).unwrap();
+    kernels::aggregate::sum(
+        filter_result
+            .as_any()
+            .downcast_ref::<array::Float32Array>()
+            .unwrap(),
+    )
+    .unwrap_or(0.0)
+}
+
+/// This is an implementation of filter and max using Arrow arrays and kernels.
+pub fn filter_max_f32_arrow(values: &array::Float32Array, row_ids: &array::BooleanArray) -> f32 {
+    let filter_result = kernels::filter::filter(values, row_ids).unwrap();
+    kernels::aggregate::max(
+        filter_result
+            .as_any()
+            .downcast_ref::<array::Float32Array>()
+            .unwrap(),
+    )
+    .unwrap_or(f32::NAN)
+}
+
+/// This is an implementation of filter and min using Arrow arrays and kernels.
+pub fn filter_min_f32_arrow(values: &array::Float32Array, row_ids: &array::BooleanArray) -> f32 {
+    let filter_result = kernels::filter::filter(values, row_ids).unwrap();
+    kernels::aggregate::min(
+        filter_result
+            .as_any()
+            .downcast_ref::<array::Float32Array>()
+            .unwrap(),
+    )
+    .unwrap_or(f32::NAN)
+}
+
+/// This is an implementation of filter then sum for `f64` values using SIMD
+/// intrinsics. Four lanes are gathered at a time with `_mm256_i32gather_pd`.
+pub fn filter_sum_f64_simd(values: &[f64], row_ids: &[u32]) -> Result<f64, RowIdOutOfBounds> {
+    row_ids::check(row_ids, values.len())?;
+    if values.len() > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_sum_f64(values, row_ids));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_sum_f64_simd_unchecked(values, row_ids) })
+}
+
+/// This is `filter_sum_f64_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `values.len()` and at most `i32::MAX`.
+pub unsafe fn filter_sum_f64_simd_unchecked(values: &[f64], row_ids: &[u32]) -> f64 {
+    let base_ptr = values.as_ptr();
+    let mut sum_lanes = _mm256_setzero_pd();
+
+    for chunk in row_ids.chunks_exact(4) {
+        let chunk_ptr = chunk.as_ptr() as *const __m128i;
+        let row_values = _mm256_i32gather_pd(base_ptr, _mm_loadu_si128(chunk_ptr), 8);
+        sum_lanes = _mm256_add_pd(sum_lanes, row_values);
+    }
+
+    // sum any remainder - maximum of three values. Not much value
+    // in doing this in a SIMD register
+    let rem = row_ids.len() - (row_ids.len() % 4);
+    let rem_sum = row_ids
+        .iter()
+        .skip(rem)
+        .map(|&id| values[id as usize])
+        .sum::<f64>();
+
+    let result: [f64; 4] = std::mem::transmute(sum_lanes);
+    result[0] + result[1] + result[2] + result[3] + rem_sum
+}
+
+/// This is a Kahan-compensated implementation of filter then sum for `f64`
+/// values using SIMD intrinsics. Each lane keeps its own compensation term and
+/// the lanes are combined with compensation too.
+pub fn filter_sum_f64_kahan_simd(values: &[f64], row_ids: &[u32]) -> Result<f64, RowIdOutOfBounds> {
+    row_ids::check(row_ids, values.len())?;
+    if values.len() > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_sum_f64_kahan(values, row_ids));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_sum_f64_kahan_simd_unchecked(values, row_ids) })
+}
+
+/// This is `filter_sum_f64_kahan_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `values.len()` and at most `i32::MAX`.
+pub unsafe fn filter_sum_f64_kahan_simd_unchecked(values: &[f64], row_ids: &[u32]) -> f64 {
+    let base_ptr = values.as_ptr();
+    let mut sum_lanes = _mm256_setzero_pd();
+    let mut c_lanes = _mm256_setzero_pd();
+
+    for chunk in row_ids.chunks_exact(4) {
+        let chunk_ptr = chunk.as_ptr() as *const __m128i;
+        let row_values = _mm256_i32gather_pd(base_ptr, _mm_loadu_si128(chunk_ptr), 8);
+
+        let y = _mm256_sub_pd(row_values, c_lanes);
+        let t = _mm256_add_pd(sum_lanes, y);
+        c_lanes = _mm256_sub_pd(_mm256_sub_pd(t, sum_lanes), y);
+        sum_lanes = t;
+    }
+
+    let sums: [f64; 4] = std::mem::transmute(sum_lanes);
+    let cs: [f64; 4] = std::mem::transmute(c_lanes);
+
+    // carry on the compensated sum over the lanes and then the remainder.
+    let mut sum = 0.0;
+    let mut c = 0.0;
+    let rem = row_ids.len() - (row_ids.len() % 4);
+    let lane_values = sums.iter().zip(cs.iter()).map(|(s, c)| s - c);
+    for v in lane_values.chain(row_ids.iter().skip(rem).map(|&id| values[id as usize])) {
+        let y = v - c;
+        let t = sum + y;
+        c = (t - sum) - y;
+        sum = t;
+    }
+
+    if sum.is_nan() {
+        return filter_sum_f64_simd_unchecked(values, row_ids);
+    }
+    sum
+}
+
+// Combine the outcome of a SIMD min/max with the NaN handling rules.
+// `any_nan` is whether a NaN was seen and `any_value` whether a non-NaN value
+// was seen.
+fn resolve_nan_f64(result: f64, any_nan: bool, any_value: bool, nan: NanMode) -> f64 {
+    if (any_nan && nan == NanMode::Propagate) || !any_value {
+        return f64::NAN;
+    }
+    result
+}
+
+fn resolve_nan_f32(result: f32, any_nan: bool, any_value: bool, nan: NanMode) -> f32 {
+    if (any_nan && nan == NanMode::Propagate) || !any_value {
+        return f32::NAN;
+    }
+    result
+}
+
+/// This is an implementation of filter then max for `f64` values using SIMD
+/// intrinsics.
+///
+/// `_mm256_max_pd(a, b)` returns `b` whenever either operand is NaN, so with
+/// the gathered values as `a` a NaN never makes it into the max lanes. Whether
+/// any NaNs (or any non-NaN values) were seen is tracked separately with
+/// ordered/unordered compares, and `nan` is applied at the end.
+pub fn filter_max_f64_simd(
+    values: &[f64],
+    row_ids: &[u32],
+    nan: NanMode,
+) -> Result<f64, RowIdOutOfBounds> {
+    row_ids::check(row_ids, values.len())?;
+    if values.len() > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_max_f64(values, row_ids, nan));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_max_f64_simd_unchecked(values, row_ids, nan) })
+}
+
+/// This is `filter_max_f64_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `values.len()` and at most `i32::MAX`.
+pub unsafe fn filter_max_f64_simd_unchecked(values: &[f64], row_ids: &[u32], nan: NanMode) -> f64 {
+    let base_ptr = values.as_ptr();
+    let mut max_lanes = _mm256_set1_pd(f64::NEG_INFINITY);
+    let mut nan_lanes = _mm256_setzero_pd();
+    let mut ord_lanes = _mm256_setzero_pd();
+
+    for chunk in row_ids.chunks_exact(4) {
+        let chunk_ptr = chunk.as_ptr() as *const __m128i;
+        let row_values = _mm256_i32gather_pd(base_ptr, _mm_loadu_si128(chunk_ptr), 8);
+
+        max_lanes = _mm256_max_pd(row_values, max_lanes);
+        nan_lanes = _mm256_or_pd(
+            nan_lanes,
+            _mm256_cmp_pd(row_values, row_values, _CMP_UNORD_Q),
+        );
+        ord_lanes = _mm256_or_pd(ord_lanes, _mm256_cmp_pd(row_values, row_values, _CMP_ORD_Q));
+    }
+
+    let lanes: [f64; 4] = std::mem::transmute(max_lanes);
+    let mut result = lanes.iter().fold(f64::NEG_INFINITY, |a, &b| a.max(b));
+    let mut any_nan = _mm256_movemask_pd(nan_lanes) != 0;
+    let mut any_value = _mm256_movemask_pd(ord_lanes) != 0;
+
+    // find the max in any remainder - at most three values.
+    let rem = row_ids.len() - (row_ids.len() % 4);
+    for &id in row_ids.iter().skip(rem) {
+        let v = values[id as usize];
+        any_nan |= v.is_nan();
+        any_value |= !v.is_nan();
+        result = result.max(v);
+    }
+
+    resolve_nan_f64(result, any_nan, any_value, nan)
+}
+
+/// This is an implementation of filter then min for `f64` values using SIMD
+/// intrinsics. See `filter_max_f64_simd`.
+pub fn filter_min_f64_simd(
+    values: &[f64],
+    row_ids: &[u32],
+    nan: NanMode,
+) -> Result<f64, RowIdOutOfBounds> {
+    row_ids::check(row_ids, values.len())?;
+    if values.len() > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_min_f64(values, row_ids, nan));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_min_f64_simd_unchecked(values, row_ids, nan) })
+}
+
+/// This is `filter_min_f64_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `values.len()` and at most `i32::MAX`.
+pub unsafe fn filter_min_f64_simd_unchecked(values: &[f64], row_ids: &[u32], nan: NanMode) -> f64 {
+    let base_ptr = values.as_ptr();
+    let mut min_lanes = _mm256_set1_pd(f64::INFINITY);
+    let mut nan_lanes = _mm256_setzero_pd();
+    let mut ord_lanes = _mm256_setzero_pd();
+
+    for chunk in row_ids.chunks_exact(4) {
+        let chunk_ptr = chunk.as_ptr() as *const __m128i;
+        let row_values = _mm256_i32gather_pd(base_ptr, _mm_loadu_si128(chunk_ptr), 8);
+
+        min_lanes = _mm256_min_pd(row_values, min_lanes);
+        nan_lanes = _mm256_or_pd(
+            nan_lanes,
+            _mm256_cmp_pd(row_values, row_values, _CMP_UNORD_Q),
+        );
+        ord_lanes = _mm256_or_pd(ord_lanes, _mm256_cmp_pd(row_values, row_values, _CMP_ORD_Q));
+    }
+
+    let lanes: [f64; 4] = std::mem::transmute(min_lanes);
+    let mut result = lanes.iter().fold(f64::INFINITY, |a, &b| a.min(b));
+    let mut any_nan = _mm256_movemask_pd(nan_lanes) != 0;
+    let mut any_value = _mm256_movemask_pd(ord_lanes) != 0;
+
+    let rem = row_ids.len() - (row_ids.len() % 4);
+    for &id in row_ids.iter().skip(rem) {
+        let v = values[id as usize];
+        any_nan |= v.is_nan();
+        any_value |= !v.is_nan();
+        result = result.min(v);
+    }
+
+    resolve_nan_f64(result, any_nan, any_value, nan)
+}
+
+/// This is an implementation of filter then sum for `f32` values using SIMD
+/// intrinsics. Eight lanes are gathered at a time with `_mm256_i32gather_ps`.
+pub fn filter_sum_f32_simd(values: &[f32], row_ids: &[u32]) -> Result<f32, RowIdOutOfBounds> {
+    row_ids::check(row_ids, values.len())?;
+    if values.len() > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_sum_f32(values, row_ids));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_sum_f32_simd_unchecked(values, row_ids) })
+}
+
+/// This is `filter_sum_f32_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `values.len()` and at most `i32::MAX`.
+pub unsafe fn filter_sum_f32_simd_unchecked(values: &[f32], row_ids: &[u32]) -> f32 {
+    let base_ptr = values.as_ptr();
+    let mut sum_lanes = _mm256_setzero_ps();
+
+    for chunk in row_ids.chunks_exact(8) {
+        let chunk_ptr = chunk.as_ptr() as *const __m256i;
+        let row_values = _mm256_i32gather_ps(base_ptr, _mm256_loadu_si256(chunk_ptr), 4);
+        sum_lanes = _mm256_add_ps(sum_lanes, row_values);
+    }
+
+    let rem = row_ids.len() - (row_ids.len() % 8);
+    let rem_sum = row_ids
+        .iter()
+        .skip(rem)
+        .map(|&id| values[id as usize])
+        .sum::<f32>();
+
+    let result: [f32; 8] = std::mem::transmute(sum_lanes);
+    result.iter().sum::<f32>() + rem_sum
+}
+
+/// This is a Kahan-compensated implementation of filter then sum for `f32`
+/// values using SIMD intrinsics. See `filter_sum_f64_kahan_simd`.
+pub fn filter_sum_f32_kahan_simd(values: &[f32], row_ids: &[u32]) -> Result<f32, RowIdOutOfBounds> {
+    row_ids::check(row_ids, values.len())?;
+    if values.len() > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_sum_f32_kahan(values, row_ids));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_sum_f32_kahan_simd_unchecked(values, row_ids) })
+}
+
+/// This is `filter_sum_f32_kahan_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `values.len()` and at most `i32::MAX`.
+pub unsafe fn filter_sum_f32_kahan_simd_unchecked(values: &[f32], row_ids: &[u32]) -> f32 {
+    let base_ptr = values.as_ptr();
+    let mut sum_lanes = _mm256_setzero_ps();
+    let mut c_lanes = _mm256_setzero_ps();
+
+    for chunk in row_ids.chunks_exact(8) {
+        let chunk_ptr = chunk.as_ptr() as *const __m256i;
+        let row_values = _mm256_i32gather_ps(base_ptr, _mm256_loadu_si256(chunk_ptr), 4);
+
+        let y = _mm256_sub_ps(row_values, c_lanes);
+        let t = _mm256_add_ps(sum_lanes, y);
+        c_lanes = _mm256_sub_ps(_mm256_sub_ps(t, sum_lanes), y);
+        sum_lanes = t;
+    }
+
+    let sums: [f32; 8] = std::mem::transmute(sum_lanes);
+    let cs: [f32; 8] = std::mem::transmute(c_lanes);
+
+    let mut sum = 0.0;
+    let mut c = 0.0;
+    let rem = row_ids.len() - (row_ids.len() % 8);
+    let lane_values = sums.iter().zip(cs.iter()).map(|(s, c)| s - c);
+    for v in lane_values.chain(row_ids.iter().skip(rem).map(|&id| values[id as usize])) {
+        let y = v - c;
+        let t = sum + y;
+        c = (t - sum) - y;
+        sum = t;
+    }
+
+    if sum.is_nan() {
+        return filter_sum_f32_simd_unchecked(values, row_ids);
+    }
+    sum
+}
+
+/// This is an implementation of filter then max for `f32` values using SIMD
+/// intrinsics. See `filter_max_f64_simd`.
+pub fn filter_max_f32_simd(
+    values: &[f32],
+    row_ids: &[u32],
+    nan: NanMode,
+) -> Result<f32, RowIdOutOfBounds> {
+    row_ids::check(row_ids, values.len())?;
+    if values.len() > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_max_f32(values, row_ids, nan));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_max_f32_simd_unchecked(values, row_ids, nan) })
+}
+
+/// This is `filter_max_f32_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `values.len()` and at most `i32::MAX`.
+pub unsafe fn filter_max_f32_simd_unchecked(values: &[f32], row_ids: &[u32], nan: NanMode) -> f32 {
+    let base_ptr = values.as_ptr();
+    let mut max_lanes = _mm256_set1_ps(f32::NEG_INFINITY);
+    let mut nan_lanes = _mm256_setzero_ps();
+    let mut ord_lanes = _mm256_setzero_ps();
+
+    for chunk in row_ids.chunks_exact(8) {
+        let chunk_ptr = chunk.as_ptr() as *const __m256i;
+        let row_values = _mm256_i32gather_ps(base_ptr, _mm256_loadu_si256(chunk_ptr), 4);
+
+        max_lanes = _mm256_max_ps(row_values, max_lanes);
+        nan_lanes = _mm256_or_ps(
+            nan_lanes,
+            _mm256_cmp_ps(row_values, row_values, _CMP_UNORD_Q),
+        );
+        ord_lanes = _mm256_or_ps(ord_lanes, _mm256_cmp_ps(row_values, row_values, _CMP_ORD_Q));
+    }
+
+    let lanes: [f32; 8] = std::mem::transmute(max_lanes);
+    let mut result = lanes.iter().fold(f32::NEG_INFINITY, |a, &b| a.max(b));
+    let mut any_nan = _mm256_movemask_ps(nan_lanes) != 0;
+    let mut any_value = _mm256_movemask_ps(ord_lanes) != 0;
+
+    let rem = row_ids.len() - (row_ids.len() % 8);
+    for &id in row_ids.iter().skip(rem) {
+        let v = values[id as usize];
+        any_nan |= v.is_nan();
+        any_value |= !v.is_nan();
+        result = result.max(v);
+    }
+
+    resolve_nan_f32(result, any_nan, any_value, nan)
+}
+
+/// This is an implementation of filter then min for `f32` values using SIMD
+/// intrinsics. See `filter_max_f64_simd`.
+pub fn filter_min_f32_simd(
+    values: &[f32],
+    row_ids: &[u32],
+    nan: NanMode,
+) -> Result<f32, RowIdOutOfBounds> {
+    row_ids::check(row_ids, values.len())?;
+    if values.len() > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_min_f32(values, row_ids, nan));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_min_f32_simd_unchecked(values, row_ids, nan) })
+}
+
+/// This is `filter_min_f32_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `values.len()` and at most `i32::MAX`.
+pub unsafe fn filter_min_f32_simd_unchecked(values: &[f32], row_ids: &[u32], nan: NanMode) -> f32 {
+    let base_ptr = values.as_ptr();
+    let mut min_lanes = _mm256_set1_ps(f32::INFINITY);
+    let mut nan_lanes = _mm256_setzero_ps();
+    let mut ord_lanes = _mm256_setzero_ps();
+
+    for chunk in row_ids.chunks_exact(8) {
+        let chunk_ptr = chunk.as_ptr() as *const __m256i;
+        let row_values = _mm256_i32gather_ps(base_ptr, _mm256_loadu_si256(chunk_ptr), 4);
+
+        min_lanes = _mm256_min_ps(row_values, min_lanes);
+        nan_lanes = _mm256_or_ps(
+            nan_lanes,
+            _mm256_cmp_ps(row_values, row_values, _CMP_UNORD_Q),
+        );
+        ord_lanes = _mm256_or_ps(ord_lanes, _mm256_cmp_ps(row_values, row_values, _CMP_ORD_Q));
+    }
+
+    let lanes: [f32; 8] = std::mem::transmute(min_lanes);
+    let mut result = lanes.iter().fold(f32::INFINITY, |a, &b| a.min(b));
+    let mut any_nan = _mm256_movemask_ps(nan_lanes) != 0;
+    let mut any_value = _mm256_movemask_ps(ord_lanes) != 0;
+
+    let rem = row_ids.len() - (row_ids.len() % 8);
+    for &id in row_ids.iter().skip(rem) {
+        let v = values[id as usize];
+        any_nan |= v.is_nan();
+        any_value |= !v.is_nan();
+        result = result.min(v);
+    }
+
+    resolve_nan_f32(result, any_nan, any_value, nan)
+}
+
+mod test {
+    fn bool_filter(len: usize, row_ids: &[u32]) -> arrow::array::BooleanArray {
+        let mut filter = Vec::with_capacity(len);
+        filter.resize(len, false);
+        for &i in row_ids.iter() {
+            filter[i as usize] = true;
+        }
+        arrow::array::BooleanArray::from(filter)
+    }
+
+    #[test]
+    fn filter_sum_f64() {
+        let values = (0..1234).map(|x| x as f64 * 0.5).collect::<Vec<_>>();
+        let cases = vec![
+            (vec![], 0.0),
+            (vec![0_u32, 1, 2, 3], 3.0),
+            (vec![2, 10, 1000], 506.0),
+            (
+                (2..653).collect::<Vec<_>>(),
+                (2..653).sum::<u32>() as f64 * 0.5,
+            ),
+        ];
+
+        for (row_ids, exp) in &cases {
+            assert_eq!(&super::filter_sum_f64(&values, row_ids), exp);
+            assert_eq!(&super::filter_sum_f64_kahan(&values, row_ids), exp);
+            assert_eq!(&super::filter_sum_f64_simd(&values, row_ids).unwrap(), exp);
+            assert_eq!(
+                &super::filter_sum_f64_kahan_simd(&values, row_ids).unwrap(),
+                exp
+            );
+
+            let arr = arrow::array::Float64Array::from(values.clone());
+            let filter = bool_filter(values.len(), row_ids);
+            assert_eq!(&super::filter_sum_f64_arrow(&arr, &filter), exp);
+        }
+
+        let values = vec![1.0, f64::NAN, 2.0, 3.0, 4.0, 5.0];
+        assert!(super::filter_sum_f64_simd(&values, &[0, 1, 2, 3, 4])
+            .unwrap()
+            .is_nan());
+        assert!(super::filter_sum_f64_kahan_simd(&values, &[0, 1, 2, 3, 4])
+            .unwrap()
+            .is_nan());
+        assert!(super::filter_sum_f64_simd(&values, &[0, 6]).is_err());
+    }
+
+    #[test]
+    fn filter_sum_kahan() {
+        // 1.0 followed by lots of values that are each lost when added to 1.0
+        let mut values = vec![1.0_f64];
+        values.extend(vec![1e-16; 10_000]);
+        let row_ids = (0..values.len() as u32).collect::<Vec<_>>();
+
+        let exp = 1.0 + 1e-12;
+        assert_eq!(super::filter_sum_f64(&values, &row_ids), 1.0);
+        assert!((super::filter_sum_f64_kahan(&values, &row_ids) - exp).abs() < 1e-15);
+        assert!((super::filter_sum_f64_kahan_simd(&values, &row_ids).unwrap() - exp).abs() < 1e-15);
+
+        let mut values = vec![1.0_f32];
+        values.extend(vec![1e-8; 10_000]);
+        let row_ids = (0..values.len() as u32).collect::<Vec<_>>();
+
+        let exp = 1.0 + 1e-4;
+        assert_eq!(super::filter_sum_f32(&values, &row_ids), 1.0);
+        assert!((super::filter_sum_f32_kahan(&values, &row_ids) - exp).abs() < 1e-6);
+        assert!((super::filter_sum_f32_kahan_simd(&values, &row_ids).unwrap() - exp).abs() < 1e-6);
+    }
+
+    #[test]
+    fn filter_min_max_f64() {
+        let nan = f64::NAN;
+        let values = vec![
+            3.0, -1.5, nan, 12.25, 7.0, nan, -20.0, 0.0, 11.0, 12.0, 4.0, nan,
+        ];
+        // (row_ids, min ignoring NaN, max ignoring NaN, contains NaN)
+        let cases = vec![
+            (vec![0_u32, 1, 3, 4], -1.5, 12.25, false),
+            (vec![0, 1, 3, 4, 6, 7, 8, 9, 10], -20.0, 12.25, false),
+            (vec![0, 1, 2, 3, 4, 6, 7, 8, 9, 10], -20.0, 12.25, true),
+            (vec![2, 3, 4, 5, 11], 7.0, 12.25, true),
+            (vec![9], 12.0, 12.0, false),
+        ];
+
+        type MinMax = fn(&[f64], &[u32], super::NanMode) -> f64;
+        let min_simd: MinMax = |v, r, n| super::filter_min_f64_simd(v, r, n).unwrap();
+        let max_simd: MinMax = |v, r, n| super::filter_max_f64_simd(v, r, n).unwrap();
+
+        for (row_ids, min, max, has_nan) in &cases {
+            for &f in &[super::filter_min_f64, min_simd] {
+                assert_eq!(f(&values, row_ids, super::NanMode::Ignore), *min);
+                assert_eq!(
+                    f(&values, row_ids, super::NanMode::Propagate).is_nan(),
+                    *has_nan
+                );
+            }
+            for &f in &[super::filter_max_f64, max_simd] {
+                assert_eq!(f(&values, row_ids, super::NanMode::Ignore), *max);
+                assert_eq!(
+                    f(&values, row_ids, super::NanMode::Propagate).is_nan(),
+                    *has_nan
+                );
+            }
+        }
+
+        // nothing but NaNs
+        for &f in &[
+            super::filter_min_f64,
+            min_simd,
+            super::filter_max_f64,
+            max_simd,
+        ] {
+            assert!(f(&values, &[2, 5, 11, 5, 2], super::NanMode::Ignore).is_nan());
+            assert!(f(&values, &[], super::NanMode::Ignore).is_nan());
+        }
+
+        let arr = arrow::array::Float64Array::from(values.clone());
+        let filter = bool_filter(values.len(), &[0, 1, 3, 4, 6]);
+        assert_eq!(super::filter_min_f64_arrow(&arr, &filter), -20.0);
+        assert_eq!(super::filter_max_f64_arrow(&arr, &filter), 12.25);
+    }
+
+    #[test]
+    fn filter_min_max_f32() {
+        let nan = f32::NAN;
+        let values = (0..100)
+            .map(|x| if x % 7 == 0 { nan } else { x as f32 - 50.0 })
+            .collect::<Vec<_>>();
+        let row_ids = (1..97).collect::<Vec<u32>>();
+
+        assert_eq!(
+            super::filter_min_f32_simd(&values, &row_ids, super::NanMode::Ignore).unwrap(),
+            -49.0
+        );
+        assert_eq!(
+            super::filter_max_f32_simd(&values, &row_ids, super::NanMode::Ignore).unwrap(),
+            46.0
+        );
+        assert!(
+            super::filter_min_f32_simd(&values, &row_ids, super::NanMode::Propagate)
+                .unwrap()
+                .is_nan()
+        );
+        assert!(super::filter_max_f32(&values, &row_ids, super::NanMode::Propagate).is_nan());
+        assert_eq!(
+            super::filter_min_f32(&values, &row_ids, super::NanMode::Ignore),
+            -49.0
+        );
+
+        let row_ids = vec![1_u32, 2, 3, 4, 5, 6, 8, 9, 10, 11];
+        assert_eq!(
+            super::filter_max_f32_simd(&values, &row_ids, super::NanMode::Propagate).unwrap(),
+            -39.0
+        );
+        assert_eq!(
+            super::filter_sum_f32_simd(&values, &row_ids).unwrap(),
+            -441.0
+        );
+        assert_eq!(super::filter_sum_f32(&values, &row_ids), -441.0);
+
+        let arr = arrow::array::Float32Array::from(values.clone());
+        let filter = bool_filter(values.len(), &row_ids);
+        assert_eq!(super::filter_sum_f32_arrow(&arr, &filter), -441.0);
+        assert_eq!(super::filter_min_f32_arrow(&arr, &filter), -49.0);
+        assert_eq!(super::filter_max_f32_arrow(&arr, &filter), -39.0);
+    }
+}
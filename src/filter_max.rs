@@ -2,6 +2,8 @@ use std::arch::x86_64::*;
 
 use arrow::{array, compute::kernels};
 
+use crate::row_ids::{self, RowIdOutOfBounds};
+
 /// Filter and aggregate functions are those that aggregate over a
 /// non-contiguous sub-set of values in some array, where the set of values to
 /// aggregate is defined by a filter (another vector of indexes).
@@ -11,7 +13,8 @@ use arrow::{array, compute::kernels};
 /// from applying predicates to other columns.
 ///
 /// In my case at least it's OK to put a maximum row limit on a column of
-/// u32::MAX so I use `u32` as row ids.
+/// u32::MAX so I use `u32` as row ids. `wide_row_ids` has versions of these
+/// functions for larger columns.
 
 /// This is a relatively idiomatic Rust implementation of filter_min. It serves
 /// as a baseline. I have arbitrarily picked 64-bit values since those are the
@@ -40,51 +43,71 @@ pub fn filter_max_arrow(values: &array::UInt64Array, row_ids: &array::BooleanArr
 /// with. In Rust it would not be a huge amount of work to make this SIMD
 /// implementation generic (which is what Arrow does).
 ///
-/// NOTE!!! This implementation is not correct for large unsigned values. The
-/// SIMD intrinsics work on signed integers. Once you set the high bit on an
-/// unsigned value it will be treated as a negative number.
+/// The SIMD intrinsics compare signed integers, so an unsigned value with the
+/// high bit set would be treated as a negative number. Flipping the high bit
+/// of every value before comparing (and back again after) maps unsigned order
+/// onto signed order.
+///
+/// The row ids are checked with `row_ids::check` before any values are
+/// gathered, and `RowIdOutOfBounds` is returned if any are out of range. Like
+/// `filter_max` it panics if `row_ids` is empty.
+pub fn filter_max_simd(values: &[u64], row_ids: &[u32]) -> Result<u64, RowIdOutOfBounds> {
+    row_ids::check(row_ids, values.len())?;
+    if values.len() > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_max(values, row_ids));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_max_simd_unchecked(values, row_ids) })
+}
+
+/// This is `filter_max_simd` without the bounds check.
 ///
-/// One way around that might be to unset the high bit on all values to be
-/// compared and somehow set it back after. Need to think about that and/or do
-/// some reading.
+/// # Safety
 ///
-pub fn filter_max_simd(values: &[u64], row_ids: &[u32]) -> u64 {
+/// Every row id must be less than `values.len()` and at most `i32::MAX`.
+pub unsafe fn filter_max_simd_unchecked(values: &[u64], row_ids: &[u32]) -> u64 {
     if row_ids.len() < 4 {
         return filter_max(values, row_ids);
     }
 
-    unsafe {
-        let base_ptr = values.as_ptr() as *const i64;
+    let base_ptr = values.as_ptr() as *const i64;
+    let sign_bit = _mm256_set1_epi64x(i64::MIN);
 
-        let mut max_lanes = _mm256_i32gather_epi64(
+    let mut max_lanes = _mm256_xor_si256(
+        _mm256_i32gather_epi64(
             base_ptr,
             _mm_loadu_si128(row_ids.as_ptr() as *const __m128i),
             8,
+        ),
+        sign_bit,
+    );
+
+    for chunk in row_ids.chunks_exact(4).skip(1) {
+        let chunk_ptr = chunk.as_ptr() as *const __m128i;
+        let row_values = _mm256_xor_si256(
+            _mm256_i32gather_epi64(base_ptr, _mm_loadu_si128(chunk_ptr), 8),
+            sign_bit,
         );
 
-        for chunk in row_ids.chunks_exact(4).skip(1) {
-            let chunk_ptr = chunk.as_ptr() as *const __m128i;
-            let row_values = _mm256_i32gather_epi64(base_ptr, _mm_loadu_si128(chunk_ptr), 8);
-
-            let max_mask = _mm256_cmpgt_epi64(row_values, max_lanes);
-            max_lanes = _mm256_blendv_epi8(max_lanes, row_values, max_mask);
-        }
+        let max_mask = _mm256_cmpgt_epi64(row_values, max_lanes);
+        max_lanes = _mm256_blendv_epi8(max_lanes, row_values, max_mask);
+    }
 
-        let result: [u64; 4] = std::mem::transmute(max_lanes);
+    let result: [u64; 4] = std::mem::transmute(_mm256_xor_si256(max_lanes, sign_bit));
 
-        // find the max in any remainder - at most three values. Not much value
-        // in doing this in a SIMD register
-        let rem = row_ids.len() - (row_ids.len() % 4);
-        let rem_max = row_ids
-            .iter()
-            .skip(rem)
-            .map(|&id| values[id as usize])
-            .max();
+    // find the max in any remainder - at most three values. Not much value
+    // in doing this in a SIMD register
+    let rem = row_ids.len() - (row_ids.len() % 4);
+    let rem_max = row_ids
+        .iter()
+        .skip(rem)
+        .map(|&id| values[id as usize])
+        .max();
 
-        match rem_max {
-            Some(rm) => rm.max(*result.iter().max().unwrap()),
-            None => *result.iter().max().unwrap(),
-        }
+    match rem_max {
+        Some(rm) => rm.max(*result.iter().max().unwrap()),
+        None => *result.iter().max().unwrap(),
     }
 }
 
@@ -148,10 +171,29 @@ mod test {
                 vec![11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 24],
                 78251,
             ),
+            // values with the high bit set.
+            (
+                vec![u64::MAX, 1, 2, 3, 4, 5, 6, 1 << 63],
+                vec![0, 1, 2, 3, 4, 5, 6, 7],
+                u64::MAX,
+            ),
         ];
 
         for (values, row_ids, exp) in &cases {
-            assert_eq!(&super::filter_max_simd(values, row_ids), exp);
+            assert_eq!(&super::filter_max_simd(values, row_ids).unwrap(), exp);
+            assert_eq!(
+                &unsafe { super::filter_max_simd_unchecked(values, row_ids) },
+                exp
+            );
+        }
+    }
+
+    #[test]
+    fn filter_max_simd_out_of_bounds() {
+        let values = (100..110).collect::<Vec<_>>();
+        for row_ids in &[vec![12_u32], vec![0, 1, 2, 12, 4, 5, 6, 7, 8]] {
+            let err = super::filter_max_simd(&values, row_ids).unwrap_err();
+            assert_eq!((err.row_id, err.rows), (12, 10));
         }
     }
 }
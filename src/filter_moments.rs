@@ -0,0 +1,395 @@
+//! Filter then count, sum and sum of squares in one pass, for variance and
+//! standard deviation.
+//!
+//! `Moments` accumulates exactly in `u128`s, and computes the mean and
+//! variance from them at the end. The sum of squares of `u64` values can
+//! overflow a `u128` after just two values, so every implementation returns
+//! `None` if it does; with values below `2^32` it can't overflow for any
+//! number of `u32` row ids. `filter_welford` handles values of any size.
+//!
+//! The SIMD implementation gathers four values at a time, adds them to `u64`
+//! sum lanes, and squares them with `_mm256_mul_epu32` (which multiplies the
+//! low 32 bits of each lane). The squares are added to `u64` lanes with the
+//! carries counted like `filter_decimal::filter_sum_simd`. It also ORs the
+//! gathered values together, and if any of them turns out to be `2^32` or
+//! more the squares were wrong, so it falls back to the idiomatic
+//! implementation.
+//!
+//! `Welford` is the usual alternative for values of any size: a running mean
+//! and sum of squared differences from it in `f64`, which is numerically
+//! stable but inexact.
+//!
+//! `filter_sum_squares` and `filter_sum_squares_simd` are a second pass over
+//! the row ids, to compare against `filter_sum::filter_sum` followed by a
+//! sum of squares.
+use std::arch::x86_64::*;
+
+use crate::row_ids::{self, RowIdOutOfBounds};
+
+/// The count, sum and sum of squares of some values.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Moments {
+    pub count: u64,
+    pub sum: u128,
+    pub sum_squares: u128,
+}
+
+impl Moments {
+    pub fn mean(&self) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        Some(self.sum as f64 / self.count as f64)
+    }
+
+    /// The population variance.
+    pub fn variance(&self) -> Option<f64> {
+        self.squared_deviations().map(|d| d / self.count as f64)
+    }
+
+    /// The sample variance, which needs at least two values.
+    pub fn sample_variance(&self) -> Option<f64> {
+        if self.count < 2 {
+            return None;
+        }
+        self.squared_deviations()
+            .map(|d| d / (self.count - 1) as f64)
+    }
+
+    /// The population standard deviation.
+    pub fn stddev(&self) -> Option<f64> {
+        self.variance().map(f64::sqrt)
+    }
+
+    // The sum of squared differences from the mean, `sum_squares - sum^2 / n`.
+    // It's computed as `(n * sum_squares - sum^2) / n` exactly when that fits
+    // in a `u128`, since the subtraction can cancel out most of the digits.
+    fn squared_deviations(&self) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        let n = self.count as u128;
+        let exact = n
+            .checked_mul(self.sum_squares)
+            .zip(self.sum.checked_mul(self.sum))
+            .map(|(a, b)| (a - b) as f64 / n as f64);
+        Some(exact.unwrap_or_else(|| {
+            (self.sum_squares as f64 - (self.sum as f64).powi(2) / n as f64).max(0.0)
+        }))
+    }
+}
+
+/// This is a relatively idiomatic Rust implementation of filter then count,
+/// sum and sum of squares. It returns `None` if the sum of squares overflows.
+pub fn filter_moments(values: &[u64], row_ids: &[u32]) -> Option<Moments> {
+    let mut result = Moments::default();
+    for &id in row_ids.iter() {
+        let v = values[id as usize] as u128;
+        result.count += 1;
+        result.sum += v;
+        result.sum_squares = result.sum_squares.checked_add(v * v)?;
+    }
+    Some(result)
+}
+
+/// This is an implementation of filter then count, sum and sum of squares
+/// using SIMD intrinsics. See the module docs.
+pub fn filter_moments_simd(
+    values: &[u64],
+    row_ids: &[u32],
+) -> Result<Option<Moments>, RowIdOutOfBounds> {
+    row_ids::check(row_ids, values.len())?;
+    if values.len() > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_moments(values, row_ids));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_moments_simd_unchecked(values, row_ids) })
+}
+
+/// This is `filter_moments_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `values.len()` and at most `i32::MAX`.
+pub unsafe fn filter_moments_simd_unchecked(values: &[u64], row_ids: &[u32]) -> Option<Moments> {
+    let base_ptr = values.as_ptr() as *const i64;
+    let sign_bit = _mm256_set1_epi64x(i64::MIN);
+
+    let mut sum_lanes = _mm256_setzero_si256(); // u64x4
+    let mut square_lanes = _mm256_setzero_si256(); // u64x4
+    let mut carry_lanes = _mm256_setzero_si256(); // the carries out of each square lane
+    let mut all_bits = _mm256_setzero_si256(); // every gathered value ORed together
+
+    for chunk in row_ids.chunks_exact(4) {
+        let ids = _mm_loadu_si128(chunk.as_ptr() as *const __m128i);
+        let row_values = _mm256_i32gather_epi64(base_ptr, ids, 8);
+        all_bits = _mm256_or_si256(all_bits, row_values);
+        sum_lanes = _mm256_add_epi64(sum_lanes, row_values);
+
+        let squares = _mm256_mul_epu32(row_values, row_values);
+        let square_sum = _mm256_add_epi64(square_lanes, squares);
+        let carry = _mm256_cmpgt_epi64(
+            _mm256_xor_si256(squares, sign_bit),
+            _mm256_xor_si256(square_sum, sign_bit),
+        );
+        carry_lanes = _mm256_sub_epi64(carry_lanes, carry); // carry is -1
+        square_lanes = square_sum;
+    }
+
+    // a value was too large to square in 64 bits.
+    let all_bits: [u64; 4] = std::mem::transmute(all_bits);
+    if all_bits.iter().any(|&bits| bits > u32::MAX as u64) {
+        return filter_moments(values, row_ids);
+    }
+
+    let sums: [u64; 4] = std::mem::transmute(sum_lanes);
+    let squares: [u64; 4] = std::mem::transmute(square_lanes);
+    let carries: [u64; 4] = std::mem::transmute(carry_lanes);
+
+    // any remainder - maximum of three values.
+    let rem = row_ids.len() - (row_ids.len() % 4);
+    // every value is below `2^32`, so none of this can overflow.
+    let mut result = filter_moments(values, &row_ids[rem..])?;
+    result.count += rem as u64;
+    for i in 0..4 {
+        result.sum += sums[i] as u128;
+        result.sum_squares += squares[i] as u128 + ((carries[i] as u128) << 64);
+    }
+    Some(result)
+}
+
+/// This is a relatively idiomatic Rust implementation of filter then sum of
+/// squares. It returns `None` if the sum overflows.
+pub fn filter_sum_squares(values: &[u64], row_ids: &[u32]) -> Option<u128> {
+    let mut result = 0_u128;
+    for &id in row_ids.iter() {
+        let v = values[id as usize] as u128;
+        result = result.checked_add(v * v)?;
+    }
+    Some(result)
+}
+
+/// This is an implementation of filter then sum of squares using SIMD
+/// intrinsics, squaring like `filter_moments_simd`.
+pub fn filter_sum_squares_simd(
+    values: &[u64],
+    row_ids: &[u32],
+) -> Result<Option<u128>, RowIdOutOfBounds> {
+    row_ids::check(row_ids, values.len())?;
+    if values.len() > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_sum_squares(values, row_ids));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_sum_squares_simd_unchecked(values, row_ids) })
+}
+
+/// This is `filter_sum_squares_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `values.len()` and at most `i32::MAX`.
+pub unsafe fn filter_sum_squares_simd_unchecked(values: &[u64], row_ids: &[u32]) -> Option<u128> {
+    let base_ptr = values.as_ptr() as *const i64;
+    let sign_bit = _mm256_set1_epi64x(i64::MIN);
+
+    let mut square_lanes = _mm256_setzero_si256(); // u64x4
+    let mut carry_lanes = _mm256_setzero_si256();
+    let mut all_bits = _mm256_setzero_si256();
+
+    for chunk in row_ids.chunks_exact(4) {
+        let ids = _mm_loadu_si128(chunk.as_ptr() as *const __m128i);
+        let row_values = _mm256_i32gather_epi64(base_ptr, ids, 8);
+        all_bits = _mm256_or_si256(all_bits, row_values);
+
+        let squares = _mm256_mul_epu32(row_values, row_values);
+        let square_sum = _mm256_add_epi64(square_lanes, squares);
+        let carry = _mm256_cmpgt_epi64(
+            _mm256_xor_si256(squares, sign_bit),
+            _mm256_xor_si256(square_sum, sign_bit),
+        );
+        carry_lanes = _mm256_sub_epi64(carry_lanes, carry);
+        square_lanes = square_sum;
+    }
+
+    let all_bits: [u64; 4] = std::mem::transmute(all_bits);
+    if all_bits.iter().any(|&bits| bits > u32::MAX as u64) {
+        return filter_sum_squares(values, row_ids);
+    }
+
+    let squares: [u64; 4] = std::mem::transmute(square_lanes);
+    let carries: [u64; 4] = std::mem::transmute(carry_lanes);
+
+    // any remainder - maximum of three values.
+    let rem = row_ids.len() - (row_ids.len() % 4);
+    let mut result = filter_sum_squares(values, &row_ids[rem..])?;
+    for i in 0..4 {
+        result += squares[i] as u128 + ((carries[i] as u128) << 64);
+    }
+    Some(result)
+}
+
+/// A running count, mean and sum of squared differences from the mean, with
+/// Welford's algorithm.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Welford {
+    pub count: u64,
+    pub mean: f64,
+    pub m2: f64,
+}
+
+impl Welford {
+    pub fn add(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    /// The population variance.
+    pub fn variance(&self) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        Some(self.m2 / self.count as f64)
+    }
+
+    /// The sample variance, which needs at least two values.
+    pub fn sample_variance(&self) -> Option<f64> {
+        if self.count < 2 {
+            return None;
+        }
+        Some(self.m2 / (self.count - 1) as f64)
+    }
+
+    /// The population standard deviation.
+    pub fn stddev(&self) -> Option<f64> {
+        self.variance().map(f64::sqrt)
+    }
+}
+
+/// This is a relatively idiomatic Rust implementation of filter then
+/// Welford's algorithm, for values of any size.
+pub fn filter_welford(values: &[u64], row_ids: &[u32]) -> Welford {
+    let mut result = Welford::default();
+    for &id in row_ids.iter() {
+        result.add(values[id as usize] as f64);
+    }
+    result
+}
+
+mod test {
+
+    #[test]
+    fn filter_moments() {
+        let values = (0..1000_u64).map(|v| v * 7919 % 1000).collect::<Vec<_>>();
+        let cases = vec![
+            vec![],
+            vec![0_u32],
+            vec![0_u32, 1, 2],
+            (0..1000).collect(),
+            (0..1000).rev().step_by(3).collect(),
+        ];
+
+        for row_ids in &cases {
+            let selected = row_ids.iter().map(|&id| values[id as usize] as u128);
+            let exp = super::Moments {
+                count: row_ids.len() as u64,
+                sum: selected.clone().sum(),
+                sum_squares: selected.map(|v| v * v).sum(),
+            };
+            assert_eq!(super::filter_moments(&values, row_ids), Some(exp));
+            assert_eq!(
+                super::filter_moments_simd(&values, row_ids).unwrap(),
+                Some(exp)
+            );
+            assert_eq!(
+                super::filter_sum_squares(&values, row_ids),
+                Some(exp.sum_squares)
+            );
+            assert_eq!(
+                super::filter_sum_squares_simd(&values, row_ids).unwrap(),
+                Some(exp.sum_squares)
+            );
+        }
+    }
+
+    #[test]
+    fn filter_moments_wide() {
+        // squares that carry out of the u64 lanes.
+        let values = vec![u32::MAX as u64; 16];
+        let row_ids = (0..16).collect::<Vec<u32>>();
+        let square = (u32::MAX as u128).pow(2);
+        let got = super::filter_moments_simd(&values, &row_ids)
+            .unwrap()
+            .unwrap();
+        assert_eq!(got.sum_squares, 16 * square);
+        assert_eq!(got.variance(), Some(0.0));
+
+        // values too large to square in 64 bits fall back to the scalar path.
+        let values = vec![3, 1 << 40, 5, 7, 1 << 32];
+        let row_ids = vec![0, 1, 2, 3, 4];
+        let exp = super::filter_moments(&values, &row_ids).unwrap();
+        assert_eq!(
+            super::filter_moments_simd(&values, &row_ids).unwrap(),
+            Some(exp)
+        );
+        assert_eq!(exp.sum_squares, (1 << 80) + (1 << 64) + 83);
+        assert_eq!(
+            super::filter_sum_squares_simd(&values, &row_ids).unwrap(),
+            Some(exp.sum_squares)
+        );
+
+        assert!(super::filter_moments_simd(&values, &[5]).is_err());
+    }
+
+    #[test]
+    fn filter_moments_overflow() {
+        let values = vec![u64::MAX; 8];
+        let row_ids = (0..8).collect::<Vec<u32>>();
+        assert_eq!(super::filter_moments(&values, &row_ids), None);
+        assert_eq!(super::filter_moments_simd(&values, &row_ids).unwrap(), None);
+        assert_eq!(super::filter_sum_squares(&values, &row_ids), None);
+        assert_eq!(
+            super::filter_sum_squares_simd(&values, &row_ids).unwrap(),
+            None
+        );
+
+        // Welford's algorithm still works.
+        let welford = super::filter_welford(&values, &row_ids);
+        assert_eq!(welford.variance(), Some(0.0));
+    }
+
+    #[test]
+    fn variance() {
+        let values = vec![2, 4, 4, 4, 5, 5, 7, 9];
+        let row_ids = (0..8).collect::<Vec<u32>>();
+
+        let moments = super::filter_moments(&values, &row_ids).unwrap();
+        assert_eq!(moments.mean(), Some(5.0));
+        assert_eq!(moments.variance(), Some(4.0));
+        assert_eq!(moments.stddev(), Some(2.0));
+        assert_eq!(moments.sample_variance(), Some(32.0 / 7.0));
+
+        let welford = super::filter_welford(&values, &row_ids);
+        assert_eq!(welford.mean, 5.0);
+        assert_eq!(welford.variance(), Some(4.0));
+        assert_eq!(welford.stddev(), Some(2.0));
+        assert_eq!(welford.sample_variance(), Some(32.0 / 7.0));
+
+        let empty = super::filter_moments(&values, &[]).unwrap();
+        assert_eq!((empty.mean(), empty.variance()), (None, None));
+        assert_eq!(super::filter_welford(&values, &[0]).sample_variance(), None);
+
+        // large values with a small variance, where sum_squares - sum^2 / n
+        // in f64 loses every digit.
+        let values = vec![(1 << 50) + 1, (1 << 50) + 3];
+        let moments = super::filter_moments(&values, &[0, 1]).unwrap();
+        assert_eq!(moments.variance(), Some(1.0));
+        assert_eq!(
+            super::filter_welford(&values, &[0, 1]).variance(),
+            Some(1.0)
+        );
+    }
+}
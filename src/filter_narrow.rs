@@ -0,0 +1,333 @@
+//! Filter functions over narrow unsigned integer columns (`u8`, `u16` and
+//! `u32`). Many columns only need 8–32 bits per value, and storing them as
+//! `u64` means reading two to eight times as many bytes for the same rows.
+//!
+//! The SIMD implementations gather eight values at a time into 32-bit lanes
+//! with `narrow_gather`, like `filter_dictionary` does for its keys. AVX2 has
+//! no 8-bit or 16-bit gather, so for `u8` and `u16` a 32-bit word is gathered
+//! at the value's byte offset and the unwanted high bytes are masked off. Any
+//! chunk of row ids close enough to the end of the column for that to read
+//! past it is handled by the scalar path instead.
+//!
+//! Like the `u64` kernels, the SIMD implementations check their row ids with
+//! `row_ids::check` first, and have `_unchecked` variants.
+//!
+//! Sums are widened into `u64` accumulators: a sum of fewer than 2^32 `u32`
+//! values can't overflow a `u64`.
+use std::arch::x86_64::*;
+
+use crate::{
+    narrow_gather::{any_beyond, gather, gather_limit, store, NarrowValue},
+    row_ids::{self, RowIdOutOfBounds},
+};
+
+/// This is a relatively idiomatic Rust implementation of filter over a narrow
+/// column.
+pub fn filter_materialise_values<K: NarrowValue>(
+    values: &[K],
+    row_ids: &[u32],
+    mut dst: Vec<K>,
+) -> Vec<K> {
+    dst.clear();
+    dst.reserve(row_ids.len());
+
+    for &id in row_ids.iter() {
+        dst.push(values[id as usize]);
+    }
+
+    assert_eq!(dst.len(), row_ids.len());
+    dst
+}
+
+/// This is a relatively idiomatic Rust implementation of filter then sum over
+/// a narrow column, summed as `u64`.
+pub fn filter_sum<K: NarrowValue>(values: &[K], row_ids: &[u32]) -> u64 {
+    let mut result = 0;
+    for &id in row_ids.iter() {
+        result += values[id as usize].as_usize() as u64;
+    }
+    result
+}
+
+/// This is a relatively idiomatic Rust implementation of filter then max over
+/// a narrow column. It panics if `row_ids` is empty.
+pub fn filter_max<K: NarrowValue + Ord>(values: &[K], row_ids: &[u32]) -> K {
+    row_ids.iter().map(|&id| values[id as usize]).max().unwrap()
+}
+
+/// This is an implementation of filter over a narrow column using SIMD
+/// intrinsics. The 32-bit lanes are packed back down to the value width when
+/// they're stored.
+///
+/// The row ids are checked with `row_ids::check` before any values are
+/// gathered, and `RowIdOutOfBounds` is returned if any are out of range.
+pub fn filter_materialise_values_simd<K: NarrowValue>(
+    values: &[K],
+    row_ids: &[u32],
+    dst: Vec<K>,
+) -> Result<Vec<K>, RowIdOutOfBounds> {
+    row_ids::check(row_ids, values.len())?;
+    if values.len() > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_materialise_values(values, row_ids, dst));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_materialise_values_simd_unchecked(values, row_ids, dst) })
+}
+
+/// This is `filter_materialise_values_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `values.len()` and at most `i32::MAX`.
+pub unsafe fn filter_materialise_values_simd_unchecked<K: NarrowValue>(
+    values: &[K],
+    row_ids: &[u32],
+    mut dst: Vec<K>,
+) -> Vec<K> {
+    dst.clear();
+    dst.reserve(row_ids.len());
+
+    let limit = _mm256_set1_epi32(gather_limit(values) as i32);
+    for chunk in row_ids.chunks_exact(8) {
+        let ids = _mm256_loadu_si256(chunk.as_ptr() as *const __m256i);
+        if any_beyond(ids, limit) {
+            for &id in chunk {
+                dst.push(values[id as usize]);
+            }
+            continue;
+        }
+
+        store(gather(values, ids), dst.as_mut_ptr().add(dst.len()));
+        dst.set_len(dst.len() + 8);
+    }
+
+    // materialise any remainder - maximum of seven values.
+    let rem = row_ids.len() - (row_ids.len() % 8);
+    for &id in row_ids.iter().skip(rem) {
+        dst.push(values[id as usize]);
+    }
+
+    assert_eq!(dst.len(), row_ids.len());
+    dst
+}
+
+/// This is an implementation of filter then sum over a narrow column using
+/// SIMD intrinsics. Each gathered 32-bit lane is widened to 64 bits
+/// (`_mm256_cvtepu32_epi64`) before it's added.
+///
+/// The row ids are checked with `row_ids::check` before any values are
+/// gathered, and `RowIdOutOfBounds` is returned if any are out of range.
+pub fn filter_sum_simd<K: NarrowValue>(
+    values: &[K],
+    row_ids: &[u32],
+) -> Result<u64, RowIdOutOfBounds> {
+    row_ids::check(row_ids, values.len())?;
+    if values.len() > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_sum(values, row_ids));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_sum_simd_unchecked(values, row_ids) })
+}
+
+/// This is `filter_sum_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `values.len()` and at most `i32::MAX`.
+pub unsafe fn filter_sum_simd_unchecked<K: NarrowValue>(values: &[K], row_ids: &[u32]) -> u64 {
+    let limit = _mm256_set1_epi32(gather_limit(values) as i32);
+    let mut sum_lanes = _mm256_setzero_si256(); // u64x4
+    let mut scalar_sum = 0;
+
+    for chunk in row_ids.chunks_exact(8) {
+        let ids = _mm256_loadu_si256(chunk.as_ptr() as *const __m256i);
+        if any_beyond(ids, limit) {
+            scalar_sum += filter_sum(values, chunk);
+            continue;
+        }
+
+        let row_values = gather(values, ids);
+        let low = _mm256_cvtepu32_epi64(_mm256_castsi256_si128(row_values));
+        let high = _mm256_cvtepu32_epi64(_mm256_extracti128_si256(row_values, 1));
+        sum_lanes = _mm256_add_epi64(sum_lanes, _mm256_add_epi64(low, high));
+    }
+
+    // sum any remainder - maximum of seven values.
+    let rem = row_ids.len() - (row_ids.len() % 8);
+    scalar_sum += filter_sum(values, &row_ids[rem..]);
+
+    let result: [u64; 4] = std::mem::transmute(sum_lanes);
+    result.iter().sum::<u64>() + scalar_sum
+}
+
+/// This is an implementation of filter then max over a narrow column using
+/// SIMD intrinsics. The values are zero-extended into 32-bit lanes, so the
+/// unsigned `_mm256_max_epu32` works for every width.
+///
+/// The row ids are checked with `row_ids::check` before any values are
+/// gathered, and `RowIdOutOfBounds` is returned if any are out of range. Like
+/// `filter_max` it panics if `row_ids` is empty.
+pub fn filter_max_simd<K: NarrowValue + Ord>(
+    values: &[K],
+    row_ids: &[u32],
+) -> Result<K, RowIdOutOfBounds> {
+    row_ids::check(row_ids, values.len())?;
+    if values.len() > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_max(values, row_ids));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_max_simd_unchecked(values, row_ids) })
+}
+
+/// This is `filter_max_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `values.len()` and at most `i32::MAX`.
+pub unsafe fn filter_max_simd_unchecked<K: NarrowValue + Ord>(values: &[K], row_ids: &[u32]) -> K {
+    if row_ids.len() < 8 {
+        return filter_max(values, row_ids);
+    }
+
+    let limit = _mm256_set1_epi32(gather_limit(values) as i32);
+    let mut max_lanes = _mm256_setzero_si256(); // u32x8
+
+    for chunk in row_ids.chunks_exact(8) {
+        let ids = _mm256_loadu_si256(chunk.as_ptr() as *const __m256i);
+        let row_values = if any_beyond(ids, limit) {
+            let mut lanes = [0_u32; 8];
+            for (lane, &id) in lanes.iter_mut().zip(chunk) {
+                *lane = values[id as usize].as_usize() as u32;
+            }
+            _mm256_loadu_si256(lanes.as_ptr() as *const __m256i)
+        } else {
+            gather(values, ids)
+        };
+        max_lanes = _mm256_max_epu32(max_lanes, row_values);
+    }
+
+    let lanes: [u32; 8] = std::mem::transmute(max_lanes);
+    let max = K::from_lane(*lanes.iter().max().unwrap());
+
+    // find the max in any remainder - at most seven values.
+    let rem = row_ids.len() - (row_ids.len() % 8);
+    match row_ids[rem..].iter().map(|&id| values[id as usize]).max() {
+        Some(rem_max) => rem_max.max(max),
+        None => max,
+    }
+}
+
+mod test {
+
+    // Check every implementation against `values` as u64 values.
+    fn check<K: crate::narrow_gather::NarrowValue + Ord>(values: &[K], row_ids: &[u32]) {
+        let wide = values
+            .iter()
+            .map(|v| v.as_usize() as u64)
+            .collect::<Vec<_>>();
+        let exp = crate::filter::filter_materialise_values(&wide, row_ids, vec![]);
+        let widen = |got: Vec<K>| got.iter().map(|v| v.as_usize() as u64).collect::<Vec<_>>();
+
+        assert_eq!(
+            widen(super::filter_materialise_values(values, row_ids, vec![])),
+            exp
+        );
+        assert_eq!(
+            widen(super::filter_materialise_values_simd(values, row_ids, vec![]).unwrap()),
+            exp
+        );
+        assert_eq!(
+            widen(unsafe {
+                super::filter_materialise_values_simd_unchecked(values, row_ids, vec![])
+            }),
+            exp
+        );
+
+        let sum = crate::filter_sum::filter_sum(&wide, row_ids);
+        assert_eq!(super::filter_sum(values, row_ids), sum);
+        assert_eq!(super::filter_sum_simd(values, row_ids).unwrap(), sum);
+        assert_eq!(
+            unsafe { super::filter_sum_simd_unchecked(values, row_ids) },
+            sum
+        );
+
+        if !row_ids.is_empty() {
+            let max = crate::filter_max::filter_max(&wide, row_ids);
+            assert_eq!(super::filter_max(values, row_ids).as_usize() as u64, max);
+            assert_eq!(
+                super::filter_max_simd(values, row_ids).unwrap().as_usize() as u64,
+                max
+            );
+            assert_eq!(
+                unsafe { super::filter_max_simd_unchecked(values, row_ids) }.as_usize() as u64,
+                max
+            );
+        }
+    }
+
+    #[test]
+    fn filter_narrow() {
+        let values = (0..1000_u32).map(|v| v * 7919 % 1000).collect::<Vec<_>>();
+        let cases = vec![
+            vec![],
+            vec![0_u32, 1, 2],
+            (0..8).collect(),
+            (0..1000).step_by(3).collect(),
+            // the last rows, which can't be gathered a word at a time.
+            vec![999, 998, 997, 996, 995, 994, 993, 992, 991],
+            (0..1000).rev().collect(),
+        ];
+
+        for row_ids in &cases {
+            check(
+                &values.iter().map(|&v| v as u8).collect::<Vec<_>>(),
+                row_ids,
+            );
+            check(
+                &values.iter().map(|&v| v as u16).collect::<Vec<_>>(),
+                row_ids,
+            );
+            check(&values, row_ids);
+        }
+    }
+
+    #[test]
+    fn filter_narrow_edges() {
+        // sums widen rather than overflowing the value type.
+        let row_ids = (0..20).collect::<Vec<u32>>();
+        assert_eq!(
+            super::filter_sum_simd(&[u8::MAX; 20], &row_ids),
+            Ok(20 * 255)
+        );
+        assert_eq!(
+            super::filter_sum_simd(&[u32::MAX; 20], &row_ids).unwrap(),
+            20 * u32::MAX as u64
+        );
+
+        // the max is unsigned.
+        let values = vec![1_u32, u32::MAX, 1 << 31, 7, 3, 9, 2, 8, 0];
+        assert_eq!(
+            super::filter_max_simd(&values, &[0, 2, 3, 4, 5, 6, 7, 8]),
+            Ok(1 << 31)
+        );
+        assert_eq!(
+            super::filter_max_simd(&values, &[0, 2, 3, 4, 5, 6, 7, 1]),
+            Ok(u32::MAX)
+        );
+    }
+
+    #[test]
+    fn filter_narrow_simd_out_of_bounds() {
+        for row_ids in &[vec![16_u32], vec![0, 1, 2, 3, 4, 5, 6, 16, 8]] {
+            let err = super::filter_materialise_values_simd(&[1_u8; 16], row_ids, vec![]);
+            assert_eq!(err.map(|_| ()).unwrap_err().row_id, 16);
+            let err = super::filter_sum_simd(&[1_u8; 16], row_ids).unwrap_err();
+            assert_eq!((err.row_id, err.rows), (16, 16));
+            let err = super::filter_max_simd(&[1_u16; 16], row_ids).unwrap_err();
+            assert_eq!((err.row_id, err.rows), (16, 16));
+        }
+    }
+}
@@ -0,0 +1,374 @@
+//! Filter then quantile functions, exact and approximate.
+//!
+//! Quantiles use the nearest-rank definition: the `q` quantile of `n` values
+//! is the value at (0-based) position `ceil(q * n) - 1` once they're sorted,
+//! or the smallest value for `q = 0`.
+//!
+//! The exact implementations materialise the selected values into a `dst`
+//! buffer with `filter::filter_materialise_values`. The idiomatic one sorts
+//! the buffer; `filter_quantiles_select` instead runs introselect
+//! (`select_nth_unstable`) once per quantile, each time on the part of the
+//! buffer after the previous quantile's position, which is linear rather than
+//! `n log n`.
+//!
+//! The approximate implementations add the selected values to a
+//! `KllSketch` as they're gathered, without materialising them. A KLL sketch
+//! keeps a stack of compactors: level `h` holds values that each stand for
+//! `2^h` inserted values. When a level is full it's sorted and every other
+//! value (starting from a random one of the first two) is promoted to the
+//! next level; the rest are dropped. Higher levels get more capacity (the
+//! top level holds `k` values), so the sketch holds `O(k)` values in total.
+//! The error in a quantile's rank shrinks roughly as `1 / k`: in the
+//! benchmarks it's within about 3% for `k = 64` and 1% for `k = 256`. The
+//! random choices come from a seeded xorshift generator, so a sketch built
+//! from the same values in the same order is always the same.
+use std::arch::x86_64::*;
+
+use crate::{
+    filter,
+    row_ids::{self, RowIdOutOfBounds},
+};
+
+/// The (0-based) position of the `q` quantile in `n` sorted values. Panics
+/// unless `q` is in `0..=1` and `n` is at least one.
+pub fn quantile_rank(q: f64, n: usize) -> usize {
+    assert!((0.0..=1.0).contains(&q), "quantile {} not in 0..=1", q);
+    assert!(n > 0, "no values");
+    ((q * n as f64).ceil() as usize).max(1) - 1
+}
+
+/// Find each of `quantiles` in `buf` with introselect, in any order. `buf` is
+/// left partially sorted. Panics if `buf` is empty.
+pub fn select_quantiles(buf: &mut [u64], quantiles: &[f64]) -> Vec<u64> {
+    let mut ranks = quantiles
+        .iter()
+        .enumerate()
+        .map(|(i, &q)| (quantile_rank(q, buf.len()), i))
+        .collect::<Vec<_>>();
+    ranks.sort_unstable();
+
+    // everything before `start` is at most the previous quantile, so each
+    // selection only needs to look at the rest of the buffer.
+    let mut result = vec![0; quantiles.len()];
+    let mut start = 0;
+    for (rank, i) in ranks {
+        if rank >= start {
+            buf[start..].select_nth_unstable(rank - start);
+            start = rank + 1;
+        }
+        result[i] = buf[rank];
+    }
+    result
+}
+
+/// This is a relatively idiomatic Rust implementation of filter then exact
+/// quantiles: the values are materialised into `dst` and sorted. The
+/// quantiles are returned with the buffer. It panics if `row_ids` is empty.
+pub fn filter_quantiles_sort(
+    values: &[u64],
+    row_ids: &[u32],
+    quantiles: &[f64],
+    dst: Vec<u64>,
+) -> (Vec<u64>, Vec<u64>) {
+    let mut dst = filter::filter_materialise_values(values, row_ids, dst);
+    dst.sort_unstable();
+    let result = quantiles
+        .iter()
+        .map(|&q| dst[quantile_rank(q, dst.len())])
+        .collect();
+    (result, dst)
+}
+
+/// This is an implementation of filter then exact quantiles that materialises
+/// the values into `dst` using SIMD intrinsics, then finds each quantile with
+/// `select_quantiles`. It panics if `row_ids` is empty.
+pub fn filter_quantiles_select(
+    values: &[u64],
+    row_ids: &[u32],
+    quantiles: &[f64],
+    dst: Vec<u64>,
+) -> Result<(Vec<u64>, Vec<u64>), RowIdOutOfBounds> {
+    let mut dst = filter::filter_materialise_values_simd(values, row_ids, dst)?;
+    let result = select_quantiles(&mut dst, quantiles);
+    Ok((result, dst))
+}
+
+/// A KLL quantiles sketch of `u64` values. See the module docs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KllSketch {
+    k: usize,
+    // `levels[h]` holds values with a weight of `2^h`.
+    levels: Vec<Vec<u64>>,
+    len: u64,
+    rng: u64,
+}
+
+impl KllSketch {
+    /// A sketch whose top level holds `k` values. Panics if `k` is less than
+    /// eight.
+    pub fn new(k: usize) -> Self {
+        assert!(k >= 8, "k must be at least 8");
+        Self {
+            k,
+            levels: vec![Vec::with_capacity(k)],
+            len: 0,
+            rng: 0x9e37_79b9_7f4a_7c15,
+        }
+    }
+
+    /// The number of values inserted.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The number of values the sketch is holding.
+    pub fn retained(&self) -> usize {
+        self.levels.iter().map(|level| level.len()).sum()
+    }
+
+    pub fn insert(&mut self, value: u64) {
+        self.levels[0].push(value);
+        self.len += 1;
+        if self.levels[0].len() >= self.capacity(0) {
+            self.compact();
+        }
+    }
+
+    // The capacity of level `h`, which shrinks by 2/3 for each level below the
+    // top one.
+    fn capacity(&self, h: usize) -> usize {
+        let depth = (self.levels.len() - 1 - h) as i32;
+        ((self.k as f64 * (2.0_f64 / 3.0).powi(depth)) as usize).max(8)
+    }
+
+    // Compact every level that's full, from the bottom up.
+    fn compact(&mut self) {
+        let mut h = 0;
+        while h < self.levels.len() {
+            if self.levels[h].len() >= self.capacity(h) {
+                if h + 1 == self.levels.len() {
+                    self.levels.push(Vec::with_capacity(self.k));
+                }
+
+                let mut level = std::mem::take(&mut self.levels[h]);
+                level.sort_unstable();
+                // an odd value out stays at this level.
+                let kept = if level.len() % 2 == 1 {
+                    level.pop()
+                } else {
+                    None
+                };
+                let offset = self.coin();
+                self.levels[h + 1].extend(level.iter().skip(offset).step_by(2));
+
+                level.clear();
+                level.extend(kept);
+                self.levels[h] = level;
+            }
+            h += 1;
+        }
+    }
+
+    // A random 0 or 1, from xorshift64.
+    fn coin(&mut self) -> usize {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng >> 63) as usize
+    }
+
+    /// The approximate `q` quantile of the values inserted, or `None` if
+    /// there are none.
+    pub fn quantile(&self, q: f64) -> Option<u64> {
+        self.quantiles(&[q]).map(|result| result[0])
+    }
+
+    /// The approximate value of each of `quantiles`, or `None` if no values
+    /// have been inserted.
+    pub fn quantiles(&self, quantiles: &[f64]) -> Option<Vec<u64>> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let mut weighted = self
+            .levels
+            .iter()
+            .enumerate()
+            .flat_map(|(h, level)| level.iter().map(move |&v| (v, 1_u64 << h)))
+            .collect::<Vec<_>>();
+        weighted.sort_unstable();
+        let total = weighted.iter().map(|&(_, w)| w).sum::<u64>();
+
+        // the first value whose cumulative weight reaches the quantile's rank.
+        let result = quantiles
+            .iter()
+            .map(|&q| {
+                let rank = quantile_rank(q, total as usize) as u64;
+                let mut cumulative = 0;
+                weighted
+                    .iter()
+                    .find(|&&(_, w)| {
+                        cumulative += w;
+                        cumulative > rank
+                    })
+                    .unwrap()
+                    .0
+            })
+            .collect();
+        Some(result)
+    }
+}
+
+/// This is a relatively idiomatic Rust implementation of filter then adding
+/// the values to a `KllSketch`.
+pub fn filter_kll(values: &[u64], row_ids: &[u32], sketch: &mut KllSketch) {
+    for &id in row_ids.iter() {
+        sketch.insert(values[id as usize]);
+    }
+}
+
+/// This is an implementation of filter then adding the values to a
+/// `KllSketch` using SIMD intrinsics: four gathered values at a time are
+/// stored straight into the sketch's bottom level while there's room for
+/// them. The sketch is the same as `filter_kll` would build.
+pub fn filter_kll_simd(
+    values: &[u64],
+    row_ids: &[u32],
+    sketch: &mut KllSketch,
+) -> Result<(), RowIdOutOfBounds> {
+    row_ids::check(row_ids, values.len())?;
+    if values.len() > row_ids::MAX_GATHER_ROWS {
+        filter_kll(values, row_ids, sketch);
+        return Ok(());
+    }
+
+    // SAFETY: every row id was checked above.
+    unsafe { filter_kll_simd_unchecked(values, row_ids, sketch) };
+    Ok(())
+}
+
+/// This is `filter_kll_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `values.len()` and at most `i32::MAX`.
+pub unsafe fn filter_kll_simd_unchecked(values: &[u64], row_ids: &[u32], sketch: &mut KllSketch) {
+    let base_ptr = values.as_ptr() as *const i64;
+    let mut capacity = sketch.capacity(0);
+
+    for chunk in row_ids.chunks_exact(4) {
+        let ids = _mm_loadu_si128(chunk.as_ptr() as *const __m128i);
+        let row_values = _mm256_i32gather_epi64(base_ptr, ids, 8);
+
+        // the level can't fill up, so there's no compaction to do.
+        let level = &mut sketch.levels[0];
+        if level.len() + 4 < capacity {
+            level.reserve(4);
+            _mm256_storeu_si256(
+                level.as_mut_ptr().add(level.len()) as *mut __m256i,
+                row_values,
+            );
+            level.set_len(level.len() + 4);
+            sketch.len += 4;
+            continue;
+        }
+
+        // insert them one at a time, compacting at the same point `insert`
+        // would.
+        let row_values: [u64; 4] = std::mem::transmute(row_values);
+        for &value in row_values.iter() {
+            sketch.insert(value);
+        }
+        capacity = sketch.capacity(0);
+    }
+
+    // any remainder - maximum of three values.
+    let rem = row_ids.len() - (row_ids.len() % 4);
+    for &id in row_ids.iter().skip(rem) {
+        sketch.insert(values[id as usize]);
+    }
+}
+
+mod test {
+
+    #[test]
+    fn quantile_rank() {
+        assert_eq!(super::quantile_rank(0.0, 10), 0);
+        assert_eq!(super::quantile_rank(0.5, 10), 4);
+        assert_eq!(super::quantile_rank(0.51, 10), 5);
+        assert_eq!(super::quantile_rank(0.99, 10), 9);
+        assert_eq!(super::quantile_rank(1.0, 10), 9);
+        assert_eq!(super::quantile_rank(0.95, 1), 0);
+    }
+
+    #[test]
+    fn filter_quantiles() {
+        let values = (0..1000_u64).map(|v| v * 7919 % 1000).collect::<Vec<_>>();
+        let quantiles = [0.99, 0.5, 0.0, 0.95, 0.5, 1.0];
+        let cases = vec![
+            (vec![3_u32], vec![757, 757, 757, 757, 757, 757]),
+            ((0..1000).collect(), vec![989, 499, 0, 949, 499, 999]),
+            ((0..1000).rev().collect(), vec![989, 499, 0, 949, 499, 999]),
+            (vec![0, 1, 2, 3, 4], vec![919, 757, 0, 919, 757, 919]),
+        ];
+
+        for (row_ids, exp) in &cases {
+            let (got, _) = super::filter_quantiles_sort(&values, row_ids, &quantiles, vec![]);
+            assert_eq!(&got, exp);
+            let (got, _) =
+                super::filter_quantiles_select(&values, row_ids, &quantiles, vec![]).unwrap();
+            assert_eq!(&got, exp);
+        }
+
+        assert!(super::filter_quantiles_select(&values, &[1000], &[0.5], vec![]).is_err());
+    }
+
+    #[test]
+    fn kll_sketch() {
+        let mut sketch = super::KllSketch::new(200);
+        assert_eq!(sketch.quantile(0.5), None);
+
+        // small enough to keep every value, so the quantiles are exact.
+        (0..100).rev().for_each(|v| sketch.insert(v));
+        assert_eq!(sketch.quantiles(&[0.0, 0.5, 1.0]), Some(vec![0, 49, 99]));
+
+        // the rank of each quantile is close to the true rank.
+        let n = 1_000_000;
+        let mut sketch = super::KllSketch::new(200);
+        (0..n).for_each(|v| sketch.insert(v * 7919 % n));
+        assert_eq!(sketch.len(), n);
+        assert!(sketch.retained() < 1000, "{}", sketch.retained());
+        for &q in &[0.01, 0.25, 0.5, 0.95, 0.99] {
+            let got = sketch.quantile(q).unwrap() as f64 / n as f64;
+            assert!((got - q).abs() < 0.02, "{} {}", q, got);
+        }
+    }
+
+    #[test]
+    fn filter_kll() {
+        let values = (0..100_000_u64)
+            .map(|v| v * 7919 % 100_000)
+            .collect::<Vec<_>>();
+        let cases = vec![
+            vec![],
+            vec![0_u32, 1, 2],
+            (0..100_000).collect(),
+            (0..100_000).step_by(3).collect(),
+        ];
+
+        for row_ids in &cases {
+            let mut exp = super::KllSketch::new(64);
+            super::filter_kll(&values, row_ids, &mut exp);
+            let mut got = super::KllSketch::new(64);
+            super::filter_kll_simd(&values, row_ids, &mut got).unwrap();
+            assert_eq!(got, exp);
+        }
+
+        let mut sketch = super::KllSketch::new(64);
+        assert!(super::filter_kll_simd(&values, &[100_000], &mut sketch).is_err());
+    }
+}
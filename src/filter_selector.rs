@@ -0,0 +1,508 @@
+//! Selector functions are those that pick a single row out of a
+//! non-contiguous sub-set of values in some array, where the set of rows to
+//! consider is defined by a filter (another vector of indexes). Unlike the
+//! aggregates in `filter_max` they return the row id of the selected value as
+//! well as the value itself.
+//!
+//! I care about these because time-series queries like "the value at the time
+//! of the max" or `last(value)` need the position of the selected row so that
+//! other columns can be materialised for it.
+//!
+//! Ties are always broken in the same way: when more than one selected row
+//! holds the max (or min) value the one with the lowest row id wins. For
+//! sorted `row_ids` that is the first matching row.
+//!
+//! `filter_first` and `filter_last` select the row with the lowest and highest
+//! row id respectively. They don't need to look at the values at all until the
+//! row has been picked.
+//!
+//! All of these functions panic if `row_ids` is empty, like `filter_max`.
+//! The arg max and arg min SIMD implementations gather values, so they check
+//! their row ids with `row_ids::check` first, like `filter_max::filter_max_simd`.
+use std::arch::x86_64::*;
+
+use arrow::{array, compute::kernels};
+
+use crate::row_ids::{self, RowIdOutOfBounds};
+
+// Flipping the high bit of each 64-bit lane maps unsigned order onto signed
+// order, which means the signed `_mm256_cmpgt_epi64` can be used to compare
+// `u64` values correctly.
+const SIGN_BIT: i64 = i64::MIN;
+
+/// This is a relatively idiomatic Rust implementation of arg max. It serves as
+/// a baseline.
+pub fn filter_arg_max(values: &[u64], row_ids: &[u32]) -> (u32, u64) {
+    let mut result = (row_ids[0], values[row_ids[0] as usize]);
+    for &id in row_ids.iter().skip(1) {
+        let v = values[id as usize];
+        if v > result.1 || (v == result.1 && id < result.0) {
+            result = (id, v);
+        }
+    }
+    result
+}
+
+/// This is a relatively idiomatic Rust implementation of arg min. It serves as
+/// a baseline.
+pub fn filter_arg_min(values: &[u64], row_ids: &[u32]) -> (u32, u64) {
+    let mut result = (row_ids[0], values[row_ids[0] as usize]);
+    for &id in row_ids.iter().skip(1) {
+        let v = values[id as usize];
+        if v < result.1 || (v == result.1 && id < result.0) {
+            result = (id, v);
+        }
+    }
+    result
+}
+
+/// This is a relatively idiomatic Rust implementation of first. The row
+/// selected is the one with the lowest row id.
+pub fn filter_first(values: &[u64], row_ids: &[u32]) -> (u32, u64) {
+    let id = *row_ids.iter().min().unwrap();
+    (id, values[id as usize])
+}
+
+/// This is a relatively idiomatic Rust implementation of last. The row
+/// selected is the one with the highest row id.
+pub fn filter_last(values: &[u64], row_ids: &[u32]) -> (u32, u64) {
+    let id = *row_ids.iter().max().unwrap();
+    (id, values[id as usize])
+}
+
+// Arrow doesn't have arg max/min kernels so the row ids have to be carried
+// through the filter alongside the values. `row_id_arr` is `0..values.len()`.
+fn filter_with_row_ids_arrow(
+    values: &array::UInt64Array,
+    row_id_arr: &array::UInt32Array,
+    row_ids: &array::BooleanArray,
+) -> (array::UInt64Array, array::UInt32Array) {
+    let values = kernels::filter::filter(values, row_ids).unwrap();
+    let ids = kernels::filter::filter(row_id_arr, row_ids).unwrap();
+    (
+        values
+            .as_any()
+            .downcast_ref::<array::UInt64Array>()
+            .unwrap()
+            .clone(),
+        ids.as_any()
+            .downcast_ref::<array::UInt32Array>()
+            .unwrap()
+            .clone(),
+    )
+}
+
+/// This is an implementation of filter and arg max using Arrow arrays and
+/// kernels. Arrow has no arg max kernel so after filtering both the values and
+/// an array of row ids the result is found with a scan.
+///
+/// `row_id_arr` should contain the row ids `0..values.len()`; it's passed in so
+/// that it can be built once up front.
+pub fn filter_arg_max_arrow(
+    values: &array::UInt64Array,
+    row_id_arr: &array::UInt32Array,
+    row_ids: &array::BooleanArray,
+) -> (u32, u64) {
+    let (values, ids) = filter_with_row_ids_arrow(values, row_id_arr, row_ids);
+
+    let mut result = (ids.value(0), values.value(0));
+    for i in 1..values.len() {
+        // row ids come out of the filter in ascending order, so only a larger
+        // value can replace the current max.
+        if values.value(i) > result.1 {
+            result = (ids.value(i), values.value(i));
+        }
+    }
+    result
+}
+
+/// This is an implementation of filter and arg min using Arrow arrays and
+/// kernels. See `filter_arg_max_arrow`.
+pub fn filter_arg_min_arrow(
+    values: &array::UInt64Array,
+    row_id_arr: &array::UInt32Array,
+    row_ids: &array::BooleanArray,
+) -> (u32, u64) {
+    let (values, ids) = filter_with_row_ids_arrow(values, row_id_arr, row_ids);
+
+    let mut result = (ids.value(0), values.value(0));
+    for i in 1..values.len() {
+        if values.value(i) < result.1 {
+            result = (ids.value(i), values.value(i));
+        }
+    }
+    result
+}
+
+/// This is an implementation of first using Arrow arrays and kernels. The row
+/// ids are filtered and the lowest one is found with the `min` kernel.
+pub fn filter_first_arrow(
+    values: &array::UInt64Array,
+    row_id_arr: &array::UInt32Array,
+    row_ids: &array::BooleanArray,
+) -> (u32, u64) {
+    let ids = kernels::filter::filter(row_id_arr, row_ids).unwrap();
+    let id = kernels::aggregate::min(ids.as_any().downcast_ref::<array::UInt32Array>().unwrap())
+        .unwrap();
+    (id, values.value(id as usize))
+}
+
+/// This is an implementation of last using Arrow arrays and kernels. The row
+/// ids are filtered and the highest one is found with the `max` kernel.
+pub fn filter_last_arrow(
+    values: &array::UInt64Array,
+    row_id_arr: &array::UInt32Array,
+    row_ids: &array::BooleanArray,
+) -> (u32, u64) {
+    let ids = kernels::filter::filter(row_id_arr, row_ids).unwrap();
+    let id = kernels::aggregate::max(ids.as_any().downcast_ref::<array::UInt32Array>().unwrap())
+        .unwrap();
+    (id, values.value(id as usize))
+}
+
+// Reduce four (value, row id) lanes to the selected pair, applying the
+// lowest-row-id tie break. `better` decides whether a value beats another.
+fn reduce_lanes(vals: [u64; 4], ids: [u64; 4], better: fn(u64, u64) -> bool) -> (u32, u64) {
+    let mut result = (ids[0] as u32, vals[0]);
+    for i in 1..4 {
+        if better(vals[i], result.1) || (vals[i] == result.1 && (ids[i] as u32) < result.0) {
+            result = (ids[i] as u32, vals[i]);
+        }
+    }
+    result
+}
+
+/// This is an implementation of filter then arg max using SIMD intrinsics.
+///
+/// Two sets of lanes are kept: four value lanes and four row id lanes. Each
+/// chunk of gathered values is compared with the current maxes and the
+/// resulting mask is used to blend both the value lanes and the row id lanes,
+/// so the row id always travels with its value.
+///
+/// Values are compared with their high bit flipped so that, like
+/// `filter_max_simd`, large unsigned values are handled correctly.
+pub fn filter_arg_max_simd(
+    values: &[u64],
+    row_ids: &[u32],
+) -> Result<(u32, u64), RowIdOutOfBounds> {
+    row_ids::check(row_ids, values.len())?;
+    if values.len() > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_arg_max(values, row_ids));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_arg_max_simd_unchecked(values, row_ids) })
+}
+
+/// This is `filter_arg_max_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `values.len()` and at most `i32::MAX`.
+pub unsafe fn filter_arg_max_simd_unchecked(values: &[u64], row_ids: &[u32]) -> (u32, u64) {
+    if row_ids.len() < 4 {
+        return filter_arg_max(values, row_ids);
+    }
+
+    let base_ptr = values.as_ptr() as *const i64;
+    let sign_bit = _mm256_set1_epi64x(SIGN_BIT);
+
+    let first_ids = _mm_loadu_si128(row_ids.as_ptr() as *const __m128i);
+    let mut max_lanes = _mm256_xor_si256(_mm256_i32gather_epi64(base_ptr, first_ids, 8), sign_bit);
+    let mut id_lanes = _mm256_cvtepu32_epi64(first_ids);
+
+    for chunk in row_ids.chunks_exact(4).skip(1) {
+        let ids = _mm_loadu_si128(chunk.as_ptr() as *const __m128i);
+        let row_values = _mm256_xor_si256(_mm256_i32gather_epi64(base_ptr, ids, 8), sign_bit);
+        let wide_ids = _mm256_cvtepu32_epi64(ids);
+
+        // replace a lane if the value is bigger, or if it's equal and the
+        // row id is lower.
+        let gt_mask = _mm256_cmpgt_epi64(row_values, max_lanes);
+        let eq_mask = _mm256_and_si256(
+            _mm256_cmpeq_epi64(row_values, max_lanes),
+            _mm256_cmpgt_epi64(id_lanes, wide_ids),
+        );
+        let mask = _mm256_or_si256(gt_mask, eq_mask);
+
+        max_lanes = _mm256_blendv_epi8(max_lanes, row_values, mask);
+        id_lanes = _mm256_blendv_epi8(id_lanes, wide_ids, mask);
+    }
+
+    let vals: [u64; 4] = std::mem::transmute(_mm256_xor_si256(max_lanes, sign_bit));
+    let ids: [u64; 4] = std::mem::transmute(id_lanes);
+    let mut result = reduce_lanes(vals, ids, |a, b| a > b);
+
+    // any remainder - at most three values. Not much value in doing this
+    // in a SIMD register
+    let rem = row_ids.len() - (row_ids.len() % 4);
+    for &id in row_ids.iter().skip(rem) {
+        let v = values[id as usize];
+        if v > result.1 || (v == result.1 && id < result.0) {
+            result = (id, v);
+        }
+    }
+    result
+}
+
+/// This is an implementation of filter then arg min using SIMD intrinsics. See
+/// `filter_arg_max_simd`.
+pub fn filter_arg_min_simd(
+    values: &[u64],
+    row_ids: &[u32],
+) -> Result<(u32, u64), RowIdOutOfBounds> {
+    row_ids::check(row_ids, values.len())?;
+    if values.len() > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_arg_min(values, row_ids));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_arg_min_simd_unchecked(values, row_ids) })
+}
+
+/// This is `filter_arg_min_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `values.len()` and at most `i32::MAX`.
+pub unsafe fn filter_arg_min_simd_unchecked(values: &[u64], row_ids: &[u32]) -> (u32, u64) {
+    if row_ids.len() < 4 {
+        return filter_arg_min(values, row_ids);
+    }
+
+    let base_ptr = values.as_ptr() as *const i64;
+    let sign_bit = _mm256_set1_epi64x(SIGN_BIT);
+
+    let first_ids = _mm_loadu_si128(row_ids.as_ptr() as *const __m128i);
+    let mut min_lanes = _mm256_xor_si256(_mm256_i32gather_epi64(base_ptr, first_ids, 8), sign_bit);
+    let mut id_lanes = _mm256_cvtepu32_epi64(first_ids);
+
+    for chunk in row_ids.chunks_exact(4).skip(1) {
+        let ids = _mm_loadu_si128(chunk.as_ptr() as *const __m128i);
+        let row_values = _mm256_xor_si256(_mm256_i32gather_epi64(base_ptr, ids, 8), sign_bit);
+        let wide_ids = _mm256_cvtepu32_epi64(ids);
+
+        let lt_mask = _mm256_cmpgt_epi64(min_lanes, row_values);
+        let eq_mask = _mm256_and_si256(
+            _mm256_cmpeq_epi64(row_values, min_lanes),
+            _mm256_cmpgt_epi64(id_lanes, wide_ids),
+        );
+        let mask = _mm256_or_si256(lt_mask, eq_mask);
+
+        min_lanes = _mm256_blendv_epi8(min_lanes, row_values, mask);
+        id_lanes = _mm256_blendv_epi8(id_lanes, wide_ids, mask);
+    }
+
+    let vals: [u64; 4] = std::mem::transmute(_mm256_xor_si256(min_lanes, sign_bit));
+    let ids: [u64; 4] = std::mem::transmute(id_lanes);
+    let mut result = reduce_lanes(vals, ids, |a, b| a < b);
+
+    let rem = row_ids.len() - (row_ids.len() % 4);
+    for &id in row_ids.iter().skip(rem) {
+        let v = values[id as usize];
+        if v < result.1 || (v == result.1 && id < result.0) {
+            result = (id, v);
+        }
+    }
+    result
+}
+
+/// This is an implementation of first using SIMD intrinsics. Since the
+/// selected row only depends on the row ids no gathers are needed: the row ids
+/// are reduced eight at a time with an unsigned 32-bit min and the single
+/// value is looked up at the end.
+pub fn filter_first_simd(values: &[u64], row_ids: &[u32]) -> (u32, u64) {
+    if row_ids.len() < 8 {
+        return filter_first(values, row_ids);
+    }
+
+    unsafe {
+        let mut min_lanes = _mm256_loadu_si256(row_ids.as_ptr() as *const __m256i);
+        for chunk in row_ids.chunks_exact(8).skip(1) {
+            let ids = _mm256_loadu_si256(chunk.as_ptr() as *const __m256i);
+            min_lanes = _mm256_min_epu32(min_lanes, ids);
+        }
+
+        let lanes: [u32; 8] = std::mem::transmute(min_lanes);
+        let rem = row_ids.len() - (row_ids.len() % 8);
+        let id = lanes
+            .iter()
+            .chain(row_ids.iter().skip(rem))
+            .copied()
+            .min()
+            .unwrap();
+        (id, values[id as usize])
+    }
+}
+
+/// This is an implementation of last using SIMD intrinsics. See
+/// `filter_first_simd`.
+pub fn filter_last_simd(values: &[u64], row_ids: &[u32]) -> (u32, u64) {
+    if row_ids.len() < 8 {
+        return filter_last(values, row_ids);
+    }
+
+    unsafe {
+        let mut max_lanes = _mm256_loadu_si256(row_ids.as_ptr() as *const __m256i);
+        for chunk in row_ids.chunks_exact(8).skip(1) {
+            let ids = _mm256_loadu_si256(chunk.as_ptr() as *const __m256i);
+            max_lanes = _mm256_max_epu32(max_lanes, ids);
+        }
+
+        let lanes: [u32; 8] = std::mem::transmute(max_lanes);
+        let rem = row_ids.len() - (row_ids.len() % 8);
+        let id = lanes
+            .iter()
+            .chain(row_ids.iter().skip(rem))
+            .copied()
+            .max()
+            .unwrap();
+        (id, values[id as usize])
+    }
+}
+
+mod test {
+
+    fn arrow_inputs(
+        values: &[u64],
+        row_ids: &[u32],
+    ) -> (
+        arrow::array::UInt64Array,
+        arrow::array::UInt32Array,
+        arrow::array::BooleanArray,
+    ) {
+        let mut filter = Vec::with_capacity(values.len());
+        filter.resize(values.len(), false);
+        for &i in row_ids.iter() {
+            filter[i as usize] = true;
+        }
+
+        (
+            arrow::array::UInt64Array::from(values.to_vec()),
+            arrow::array::UInt32Array::from((0..values.len() as u32).collect::<Vec<_>>()),
+            arrow::array::BooleanArray::from(filter),
+        )
+    }
+
+    #[test]
+    fn filter_arg_max() {
+        let cases = vec![
+            (
+                (100..110).collect::<Vec<_>>(),
+                vec![0_u32, 1, 2, 3],
+                (3, 103),
+            ),
+            ((100..113).collect::<Vec<_>>(), vec![0, 12], (12, 112)),
+            (vec![20], vec![0_u32], (0, 20)),
+            // ties go to the lowest row id
+            (vec![7, 9, 3, 9, 9, 1, 9], vec![6, 3, 1, 4, 0], (1, 9)),
+            (
+                vec![1020, u64::MAX, 100, 3498, u64::MAX - 1, 1020, 3, 4, 5],
+                vec![0, 2, 3, 4, 5, 6, 7, 8, 1],
+                (1, u64::MAX),
+            ),
+            (
+                (0..1234).map(|x| x % 100).collect::<Vec<_>>(),
+                (3..1000).rev().collect::<Vec<_>>(),
+                (99, 99),
+            ),
+        ];
+
+        for (values, row_ids, exp) in &cases {
+            assert_eq!(&super::filter_arg_max(values, row_ids), exp);
+            assert_eq!(&super::filter_arg_max_simd(values, row_ids).unwrap(), exp);
+
+            let (values, row_id_arr, filter) = arrow_inputs(values, row_ids);
+            assert_eq!(
+                &super::filter_arg_max_arrow(&values, &row_id_arr, &filter),
+                exp
+            );
+        }
+    }
+
+    #[test]
+    fn filter_arg_min() {
+        let cases = vec![
+            (
+                (100..110).collect::<Vec<_>>(),
+                vec![0_u32, 1, 2, 3],
+                (0, 100),
+            ),
+            ((100..113).collect::<Vec<_>>(), vec![12, 4], (4, 104)),
+            (vec![20], vec![0_u32], (0, 20)),
+            (vec![7, 1, 3, 1, 1, 9, 1], vec![6, 3, 2, 4, 0], (3, 1)),
+            (
+                vec![u64::MAX, u64::MAX - 1, 1 << 63, 3498, 1 << 63, 1020],
+                vec![0, 1, 2, 4],
+                (2, 1 << 63),
+            ),
+            (
+                (0..1234).map(|x| 100 + x % 100).collect::<Vec<_>>(),
+                (3..1000).rev().collect::<Vec<_>>(),
+                (100, 100),
+            ),
+        ];
+
+        for (values, row_ids, exp) in &cases {
+            assert_eq!(&super::filter_arg_min(values, row_ids), exp);
+            assert_eq!(&super::filter_arg_min_simd(values, row_ids).unwrap(), exp);
+
+            let (values, row_id_arr, filter) = arrow_inputs(values, row_ids);
+            assert_eq!(
+                &super::filter_arg_min_arrow(&values, &row_id_arr, &filter),
+                exp
+            );
+        }
+
+        let values = (0..10).collect::<Vec<_>>();
+        assert!(super::filter_arg_max_simd(&values, &[0, 1, 2, 10]).is_err());
+        assert!(super::filter_arg_min_simd(&values, &[10, 1, 2, 0]).is_err());
+    }
+
+    #[test]
+    fn filter_first_last() {
+        let cases = vec![
+            (
+                (100..110).collect::<Vec<_>>(),
+                vec![2_u32],
+                (2, 102),
+                (2, 102),
+            ),
+            (
+                (100..113).collect::<Vec<_>>(),
+                vec![0, 12],
+                (0, 100),
+                (12, 112),
+            ),
+            (
+                (100..1234).collect::<Vec<_>>(),
+                vec![30, 2, 5, 10, 10, 11, 21, 1001, 93, 44, 6],
+                (2, 102),
+                (1001, 1101),
+            ),
+            (
+                (0..1234).collect::<Vec<_>>(),
+                (7..1200).collect::<Vec<_>>(),
+                (7, 7),
+                (1199, 1199),
+            ),
+        ];
+
+        for (values, row_ids, first, last) in &cases {
+            assert_eq!(&super::filter_first(values, row_ids), first);
+            assert_eq!(&super::filter_first_simd(values, row_ids), first);
+            assert_eq!(&super::filter_last(values, row_ids), last);
+            assert_eq!(&super::filter_last_simd(values, row_ids), last);
+
+            let (values, row_id_arr, filter) = arrow_inputs(values, row_ids);
+            assert_eq!(
+                &super::filter_first_arrow(&values, &row_id_arr, &filter),
+                first
+            );
+            assert_eq!(
+                &super::filter_last_arrow(&values, &row_id_arr, &filter),
+                last
+            );
+        }
+    }
+}
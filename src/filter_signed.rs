@@ -0,0 +1,508 @@
+//! Filter and aggregate functions over signed integer columns. These are the
+//! `i64` and `i32` versions of `filter_sum` and `filter_max` (plus min), which
+//! only deal with `u64` values.
+//!
+//! The `u64` SIMD kernels gather through a `*const i64`, and the AVX2 integer
+//! compares are all signed, so unsigned max has to flip the high bit of every
+//! value first (see `filter_max::filter_max_simd`). For signed values the
+//! compares are simply correct: `_mm256_cmpgt_epi64` for `i64`, and
+//! `_mm256_max_epi32`/`_mm256_min_epi32` for `i32`, which also gather eight
+//! values at a time rather than four.
+//!
+//! Overflow is handled differently per type:
+//!
+//! - `i64` sums are exact: they return `None` if, and only if, the sum of the
+//!   selected values doesn't fit in an `i64`. Intermediate sums may overflow
+//!   as long as the final sum fits (e.g., `i64::MAX + 1 - 1`). The idiomatic
+//!   version sums in an `i128`. The SIMD version adds with wrapping lanes and
+//!   counts how many times each lane wraps past `i64::MAX` (or `i64::MIN`), so
+//!   the lanes can be combined into the exact sum at the end.
+//! - `i32` sums are widened to `i64`, which can't overflow for fewer than
+//!   2^32 row ids.
+//!
+//! Arrow's `sum` doesn't detect overflow, so the `_arrow` sum only agrees with
+//! the others when the sum fits.
+//!
+//! The SIMD functions check their row ids like the `u64` ones (see
+//! `row_ids`), and min/max panic if `row_ids` is empty, like `filter_max`.
+use std::{arch::x86_64::*, convert::TryFrom};
+
+use arrow::{array, compute::kernels};
+
+use crate::row_ids::{self, RowIdOutOfBounds};
+
+/// This is a relatively idiomatic Rust implementation of filter then sum for
+/// `i64` values. It returns `None` if the sum doesn't fit in an `i64`.
+pub fn filter_sum_i64(values: &[i64], row_ids: &[u32]) -> Option<i64> {
+    let mut result = 0_i128;
+    for &id in row_ids.iter() {
+        result += values[id as usize] as i128;
+    }
+    i64::try_from(result).ok()
+}
+
+/// This is a relatively idiomatic Rust implementation of filter then max for
+/// `i64` values.
+pub fn filter_max_i64(values: &[i64], row_ids: &[u32]) -> i64 {
+    row_ids.iter().map(|&id| values[id as usize]).max().unwrap()
+}
+
+/// This is a relatively idiomatic Rust implementation of filter then min for
+/// `i64` values.
+pub fn filter_min_i64(values: &[i64], row_ids: &[u32]) -> i64 {
+    row_ids.iter().map(|&id| values[id as usize]).min().unwrap()
+}
+
+/// This is a relatively idiomatic Rust implementation of filter then sum for
+/// `i32` values, summed as `i64`.
+pub fn filter_sum_i32(values: &[i32], row_ids: &[u32]) -> i64 {
+    let mut result = 0;
+    for &id in row_ids.iter() {
+        result += values[id as usize] as i64;
+    }
+    result
+}
+
+/// This is a relatively idiomatic Rust implementation of filter then max for
+/// `i32` values.
+pub fn filter_max_i32(values: &[i32], row_ids: &[u32]) -> i32 {
+    row_ids.iter().map(|&id| values[id as usize]).max().unwrap()
+}
+
+/// This is a relatively idiomatic Rust implementation of filter then min for
+/// `i32` values.
+pub fn filter_min_i32(values: &[i32], row_ids: &[u32]) -> i32 {
+    row_ids.iter().map(|&id| values[id as usize]).min().unwrap()
+}
+
+/// This is an implementation of filter and sum using Arrow arrays and kernels.
+/// An empty selection sums to `0`. See the module docs for overflow.
+pub fn filter_sum_i64_arrow(values: &array::Int64Array, row_ids: &array::BooleanArray) -> i64 {
+    let filter_result = kernels::filter::filter(values, row_ids).unwrap();
+    kernels::aggregate::sum(
+        filter_result
+            .as_any()
+            .downcast_ref::<array::Int64Array>()
+            .unwrap(),
+    )
+    .unwrap_or(0)
+}
+
+/// This is an implementation of filter and max using Arrow arrays and kernels.
+pub fn filter_max_i64_arrow(values: &array::Int64Array, row_ids: &array::BooleanArray) -> i64 {
+    let filter_result = kernels::filter::filter(values, row_ids).unwrap();
+    kernels::aggregate::max(
+        filter_result
+            .as_any()
+            .downcast_ref::<array::Int64Array>()
+            .unwrap(),
+    )
+    .unwrap()
+}
+
+/// This is an implementation of filter and min using Arrow arrays and kernels.
+pub fn filter_min_i64_arrow(values: &array::Int64Array, row_ids: &array::BooleanArray) -> i64 {
+    let filter_result = kernels::filter::filter(values, row_ids).unwrap();
+    kernels::aggregate::min(
+        filter_result
+            .as_any()
+            .downcast_ref::<array::Int64Array>()
+            .unwrap(),
+    )
+    .unwrap()
+}
+
+/// This is an implementation of filter then sum for `i64` values using SIMD
+/// intrinsics, with exact overflow detection. It returns `Ok(None)` if the sum
+/// doesn't fit in an `i64`, and `RowIdOutOfBounds` if a row id is out of
+/// range.
+pub fn filter_sum_i64_simd(
+    values: &[i64],
+    row_ids: &[u32],
+) -> Result<Option<i64>, RowIdOutOfBounds> {
+    row_ids::check(row_ids, values.len())?;
+    if values.len() > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_sum_i64(values, row_ids));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_sum_i64_simd_unchecked(values, row_ids) })
+}
+
+/// This is `filter_sum_i64_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `values.len()` and at most `i32::MAX`.
+pub unsafe fn filter_sum_i64_simd_unchecked(values: &[i64], row_ids: &[u32]) -> Option<i64> {
+    let base_ptr = values.as_ptr();
+    let zero = _mm256_setzero_si256();
+    let one = _mm256_set1_epi64x(1);
+    let mut sum_lanes = zero; // i64x4
+
+    // the number of times each lane has wrapped: +1 past i64::MAX, -1 past
+    // i64::MIN.
+    let mut wrap_lanes = zero;
+
+    for chunk in row_ids.chunks_exact(4) {
+        let chunk_ptr = chunk.as_ptr() as *const __m128i;
+        let row_values = _mm256_i32gather_epi64(base_ptr, _mm_loadu_si128(chunk_ptr), 8);
+        let sum = _mm256_add_epi64(sum_lanes, row_values);
+
+        // an add overflows when both operands have the same sign and the sum
+        // has the other one.
+        let overflow = _mm256_cmpgt_epi64(
+            zero,
+            _mm256_and_si256(
+                _mm256_xor_si256(sum_lanes, sum),
+                _mm256_xor_si256(row_values, sum),
+            ),
+        );
+        let direction = _mm256_or_si256(_mm256_cmpgt_epi64(zero, row_values), one); // -1 or 1
+        wrap_lanes = _mm256_add_epi64(wrap_lanes, _mm256_and_si256(overflow, direction));
+        sum_lanes = sum;
+    }
+
+    let sums: [i64; 4] = std::mem::transmute(sum_lanes);
+    let wraps: [i64; 4] = std::mem::transmute(wrap_lanes);
+    let lanes_sum = sums
+        .iter()
+        .zip(wraps.iter())
+        .map(|(&sum, &wraps)| sum as i128 + wraps as i128 * (1_i128 << 64))
+        .sum::<i128>();
+
+    // sum any remainder - maximum of three values.
+    let rem = row_ids.len() - (row_ids.len() % 4);
+    let rem_sum = row_ids
+        .iter()
+        .skip(rem)
+        .map(|&id| values[id as usize] as i128)
+        .sum::<i128>();
+
+    i64::try_from(lanes_sum + rem_sum).ok()
+}
+
+/// This is an implementation of filter then max for `i64` values using SIMD
+/// intrinsics. `RowIdOutOfBounds` is returned if a row id is out of range.
+pub fn filter_max_i64_simd(values: &[i64], row_ids: &[u32]) -> Result<i64, RowIdOutOfBounds> {
+    row_ids::check(row_ids, values.len())?;
+    if values.len() > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_max_i64(values, row_ids));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_max_i64_simd_unchecked(values, row_ids) })
+}
+
+/// This is `filter_max_i64_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `values.len()` and at most `i32::MAX`.
+pub unsafe fn filter_max_i64_simd_unchecked(values: &[i64], row_ids: &[u32]) -> i64 {
+    if row_ids.len() < 4 {
+        return filter_max_i64(values, row_ids);
+    }
+
+    let base_ptr = values.as_ptr();
+    let mut max_lanes = _mm256_set1_epi64x(i64::MIN);
+
+    for chunk in row_ids.chunks_exact(4) {
+        let chunk_ptr = chunk.as_ptr() as *const __m128i;
+        let row_values = _mm256_i32gather_epi64(base_ptr, _mm_loadu_si128(chunk_ptr), 8);
+
+        let max_mask = _mm256_cmpgt_epi64(row_values, max_lanes);
+        max_lanes = _mm256_blendv_epi8(max_lanes, row_values, max_mask);
+    }
+
+    let result: [i64; 4] = std::mem::transmute(max_lanes);
+
+    // find the max in any remainder - at most three values.
+    let rem = row_ids.len() - (row_ids.len() % 4);
+    result
+        .iter()
+        .copied()
+        .chain(row_ids.iter().skip(rem).map(|&id| values[id as usize]))
+        .max()
+        .unwrap()
+}
+
+/// This is an implementation of filter then min for `i64` values using SIMD
+/// intrinsics. `RowIdOutOfBounds` is returned if a row id is out of range.
+pub fn filter_min_i64_simd(values: &[i64], row_ids: &[u32]) -> Result<i64, RowIdOutOfBounds> {
+    row_ids::check(row_ids, values.len())?;
+    if values.len() > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_min_i64(values, row_ids));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_min_i64_simd_unchecked(values, row_ids) })
+}
+
+/// This is `filter_min_i64_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `values.len()` and at most `i32::MAX`.
+pub unsafe fn filter_min_i64_simd_unchecked(values: &[i64], row_ids: &[u32]) -> i64 {
+    if row_ids.len() < 4 {
+        return filter_min_i64(values, row_ids);
+    }
+
+    let base_ptr = values.as_ptr();
+    let mut min_lanes = _mm256_set1_epi64x(i64::MAX);
+
+    for chunk in row_ids.chunks_exact(4) {
+        let chunk_ptr = chunk.as_ptr() as *const __m128i;
+        let row_values = _mm256_i32gather_epi64(base_ptr, _mm_loadu_si128(chunk_ptr), 8);
+
+        let min_mask = _mm256_cmpgt_epi64(min_lanes, row_values);
+        min_lanes = _mm256_blendv_epi8(min_lanes, row_values, min_mask);
+    }
+
+    let result: [i64; 4] = std::mem::transmute(min_lanes);
+
+    let rem = row_ids.len() - (row_ids.len() % 4);
+    result
+        .iter()
+        .copied()
+        .chain(row_ids.iter().skip(rem).map(|&id| values[id as usize]))
+        .min()
+        .unwrap()
+}
+
+/// This is an implementation of filter then sum for `i32` values using SIMD
+/// intrinsics. Eight values are gathered at a time with
+/// `_mm256_i32gather_epi32` and widened to `i64` lanes to be summed.
+/// `RowIdOutOfBounds` is returned if a row id is out of range.
+pub fn filter_sum_i32_simd(values: &[i32], row_ids: &[u32]) -> Result<i64, RowIdOutOfBounds> {
+    row_ids::check(row_ids, values.len())?;
+    if values.len() > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_sum_i32(values, row_ids));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_sum_i32_simd_unchecked(values, row_ids) })
+}
+
+/// This is `filter_sum_i32_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `values.len()` and at most `i32::MAX`.
+pub unsafe fn filter_sum_i32_simd_unchecked(values: &[i32], row_ids: &[u32]) -> i64 {
+    let base_ptr = values.as_ptr();
+    let mut sum_lanes = _mm256_setzero_si256(); // i64x4
+
+    for chunk in row_ids.chunks_exact(8) {
+        let chunk_ptr = chunk.as_ptr() as *const __m256i;
+        let row_values = _mm256_i32gather_epi32(base_ptr, _mm256_loadu_si256(chunk_ptr), 4);
+
+        let low = _mm256_cvtepi32_epi64(_mm256_castsi256_si128(row_values));
+        let high = _mm256_cvtepi32_epi64(_mm256_extracti128_si256(row_values, 1));
+        sum_lanes = _mm256_add_epi64(sum_lanes, _mm256_add_epi64(low, high));
+    }
+
+    // sum any remainder - maximum of seven values.
+    let rem = row_ids.len() - (row_ids.len() % 8);
+    let rem_sum = row_ids
+        .iter()
+        .skip(rem)
+        .map(|&id| values[id as usize] as i64)
+        .sum::<i64>();
+
+    let result: [i64; 4] = std::mem::transmute(sum_lanes);
+    result.iter().sum::<i64>() + rem_sum
+}
+
+/// This is an implementation of filter then max for `i32` values using SIMD
+/// intrinsics. `RowIdOutOfBounds` is returned if a row id is out of range.
+pub fn filter_max_i32_simd(values: &[i32], row_ids: &[u32]) -> Result<i32, RowIdOutOfBounds> {
+    row_ids::check(row_ids, values.len())?;
+    if values.len() > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_max_i32(values, row_ids));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_max_i32_simd_unchecked(values, row_ids) })
+}
+
+/// This is `filter_max_i32_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `values.len()` and at most `i32::MAX`.
+pub unsafe fn filter_max_i32_simd_unchecked(values: &[i32], row_ids: &[u32]) -> i32 {
+    if row_ids.len() < 8 {
+        return filter_max_i32(values, row_ids);
+    }
+
+    let base_ptr = values.as_ptr();
+    let mut max_lanes = _mm256_set1_epi32(i32::MIN);
+
+    for chunk in row_ids.chunks_exact(8) {
+        let chunk_ptr = chunk.as_ptr() as *const __m256i;
+        let row_values = _mm256_i32gather_epi32(base_ptr, _mm256_loadu_si256(chunk_ptr), 4);
+        max_lanes = _mm256_max_epi32(max_lanes, row_values);
+    }
+
+    let result: [i32; 8] = std::mem::transmute(max_lanes);
+
+    let rem = row_ids.len() - (row_ids.len() % 8);
+    result
+        .iter()
+        .copied()
+        .chain(row_ids.iter().skip(rem).map(|&id| values[id as usize]))
+        .max()
+        .unwrap()
+}
+
+/// This is an implementation of filter then min for `i32` values using SIMD
+/// intrinsics. `RowIdOutOfBounds` is returned if a row id is out of range.
+pub fn filter_min_i32_simd(values: &[i32], row_ids: &[u32]) -> Result<i32, RowIdOutOfBounds> {
+    row_ids::check(row_ids, values.len())?;
+    if values.len() > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_min_i32(values, row_ids));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_min_i32_simd_unchecked(values, row_ids) })
+}
+
+/// This is `filter_min_i32_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `values.len()` and at most `i32::MAX`.
+pub unsafe fn filter_min_i32_simd_unchecked(values: &[i32], row_ids: &[u32]) -> i32 {
+    if row_ids.len() < 8 {
+        return filter_min_i32(values, row_ids);
+    }
+
+    let base_ptr = values.as_ptr();
+    let mut min_lanes = _mm256_set1_epi32(i32::MAX);
+
+    for chunk in row_ids.chunks_exact(8) {
+        let chunk_ptr = chunk.as_ptr() as *const __m256i;
+        let row_values = _mm256_i32gather_epi32(base_ptr, _mm256_loadu_si256(chunk_ptr), 4);
+        min_lanes = _mm256_min_epi32(min_lanes, row_values);
+    }
+
+    let result: [i32; 8] = std::mem::transmute(min_lanes);
+
+    let rem = row_ids.len() - (row_ids.len() % 8);
+    result
+        .iter()
+        .copied()
+        .chain(row_ids.iter().skip(rem).map(|&id| values[id as usize]))
+        .min()
+        .unwrap()
+}
+
+mod test {
+
+    #[test]
+    fn filter_sum_i64() {
+        let cases = vec![
+            (vec![-3_i64, 4, -5, 6], vec![0_u32, 1, 2], Some(-4)),
+            (vec![i64::MAX, 1], vec![0, 1], None),
+            (vec![i64::MIN, -1], vec![0, 1], None),
+            // the intermediate sum overflows but the sum fits.
+            (vec![i64::MAX, 1, -1], vec![0, 1, 2], Some(i64::MAX)),
+            (vec![i64::MIN, i64::MAX], vec![0, 1, 0, 1], Some(-2)),
+            (
+                vec![i64::MAX; 8]
+                    .into_iter()
+                    .chain(vec![i64::MIN; 8])
+                    .collect(),
+                (0..16).collect(),
+                Some(-8),
+            ),
+            (vec![i64::MAX; 9], (0..9).collect(), None),
+            (vec![], vec![], Some(0)),
+        ];
+
+        for (values, row_ids, exp) in &cases {
+            assert_eq!(&super::filter_sum_i64(values, row_ids), exp);
+            assert_eq!(&super::filter_sum_i64_simd(values, row_ids).unwrap(), exp);
+        }
+    }
+
+    #[test]
+    fn filter_min_max_i64() {
+        let cases = vec![
+            (vec![-3_i64, 4, -5, 6], vec![0_u32, 1, 2], (-5, 4)),
+            (vec![i64::MIN, -1, 0, 1, i64::MAX], vec![1, 2, 3], (-1, 1)),
+            (
+                vec![i64::MIN, -1, 0, 1, i64::MAX],
+                vec![4, 3, 2, 1, 0, 1, 2],
+                (i64::MIN, i64::MAX),
+            ),
+            (
+                (-500..500).collect(),
+                (0..1000).step_by(3).collect(),
+                (-500, 499),
+            ),
+        ];
+
+        for (values, row_ids, (min, max)) in &cases {
+            assert_eq!(&super::filter_min_i64(values, row_ids), min);
+            assert_eq!(&super::filter_max_i64(values, row_ids), max);
+            assert_eq!(&super::filter_min_i64_simd(values, row_ids).unwrap(), min);
+            assert_eq!(&super::filter_max_i64_simd(values, row_ids).unwrap(), max);
+        }
+    }
+
+    #[test]
+    fn filter_i64_arrow() {
+        let values = arrow::array::Int64Array::from(vec![-3, 4, -5, 6, i64::MIN]);
+        let row_ids = arrow::array::BooleanArray::from(vec![true, true, true, false, false]);
+
+        assert_eq!(super::filter_sum_i64_arrow(&values, &row_ids), -4);
+        assert_eq!(super::filter_min_i64_arrow(&values, &row_ids), -5);
+        assert_eq!(super::filter_max_i64_arrow(&values, &row_ids), 4);
+    }
+
+    #[test]
+    fn filter_i32() {
+        let cases = vec![
+            (vec![-3_i32, 4, -5, 6], vec![0_u32, 1, 2], (-4, -5, 4)),
+            (
+                vec![i32::MAX; 20],
+                (0..20).collect(),
+                (20 * i32::MAX as i64, i32::MAX, i32::MAX),
+            ),
+            (
+                vec![i32::MIN, -1, 0, 1, i32::MAX, 7, 7, 7, 7],
+                vec![8, 4, 3, 2, 1, 0, 1, 2, 5],
+                (12, i32::MIN, i32::MAX),
+            ),
+            (
+                (-500..500).collect(),
+                (0..1000).step_by(3).collect(),
+                ((-500_i64..500).step_by(3).sum(), -500, 499),
+            ),
+        ];
+
+        for (values, row_ids, (sum, min, max)) in &cases {
+            assert_eq!(&super::filter_sum_i32(values, row_ids), sum);
+            assert_eq!(&super::filter_min_i32(values, row_ids), min);
+            assert_eq!(&super::filter_max_i32(values, row_ids), max);
+            assert_eq!(&super::filter_sum_i32_simd(values, row_ids).unwrap(), sum);
+            assert_eq!(&super::filter_min_i32_simd(values, row_ids).unwrap(), min);
+            assert_eq!(&super::filter_max_i32_simd(values, row_ids).unwrap(), max);
+        }
+    }
+
+    #[test]
+    fn out_of_bounds() {
+        let row_ids = (0..11).collect::<Vec<u32>>();
+        let (values, values_i32) = (vec![1_i64; 10], vec![1_i32; 10]);
+        assert!(super::filter_sum_i64_simd(&values, &row_ids).is_err());
+        assert!(super::filter_max_i64_simd(&values, &row_ids).is_err());
+        assert!(super::filter_min_i64_simd(&values, &row_ids).is_err());
+        assert!(super::filter_sum_i32_simd(&values_i32, &row_ids).is_err());
+        assert!(super::filter_max_i32_simd(&values_i32, &row_ids).is_err());
+        assert!(super::filter_min_i32_simd(&values_i32, &row_ids).is_err());
+    }
+}
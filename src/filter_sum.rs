@@ -2,6 +2,8 @@ use std::arch::x86_64::*;
 
 use arrow::{array, compute::kernels};
 
+use crate::row_ids::{self, RowIdOutOfBounds};
+
 /// Filter and aggregate functions are those that aggregate over a
 /// non-contiguous sub-set of values in some array, where the set of values to
 /// aggregate is defined by a filter (another vector of indexes).
@@ -11,7 +13,8 @@ use arrow::{array, compute::kernels};
 /// applying predicates to other columns.
 ///
 /// In my case at least it's OK to put a maximum row limit on a column of
-/// u32::MAX so I use `u32` as row ids.
+/// u32::MAX so I use `u32` as row ids. `wide_row_ids` has versions of these
+/// functions for larger columns.
 
 ///
 /// *Note* - these implementations all barf in the same way on overflow, so in
@@ -32,7 +35,8 @@ pub fn filter_sum(values: &[u64], row_ids: &[u32]) -> u64 {
 
 /// This is an implementation of filter and sum using Arrow arrays and kernels.
 /// Currently Arrow needs to perform this aggregation operation as two steps
-/// (filter then sum).
+/// (filter then sum). Arrow's sum of nothing is null, which is returned as 0
+/// like the other implementations.
 pub fn filter_sum_arrow(values: &array::UInt64Array, row_ids: &array::BooleanArray) -> u64 {
     let filter_result = kernels::filter::filter(values, row_ids).unwrap();
     kernels::aggregate::sum(
@@ -41,7 +45,7 @@ pub fn filter_sum_arrow(values: &array::UInt64Array, row_ids: &array::BooleanArr
             .downcast_ref::<arrow::array::UInt64Array>()
             .unwrap(),
     )
-    .unwrap()
+    .unwrap_or(0)
 }
 
 /// This is an implementation of filter then sum using SIMD intrinsics. I have
@@ -49,29 +53,44 @@ pub fn filter_sum_arrow(values: &array::UInt64Array, row_ids: &array::BooleanArr
 /// with. In Rust it would not be a huge amount of work to make this SIMD
 /// implementation generic (which is what Arrow does).
 ///
-pub fn filter_sum_simd(values: &[u64], row_ids: &[u32]) -> u64 {
-    unsafe {
-        let base_ptr = values.as_ptr() as *const i64;
-        let mut sum_lanes = _mm256_setzero_si256(); // u64x4
-
-        for chunk in row_ids.chunks_exact(4) {
-            let chunk_ptr = chunk.as_ptr() as *const __m128i;
-            let row_values = _mm256_i32gather_epi64(base_ptr, _mm_loadu_si128(chunk_ptr), 8);
-            sum_lanes = _mm256_add_epi64(sum_lanes, row_values);
-        }
+/// The row ids are checked with `row_ids::check` before any values are
+/// gathered, and `RowIdOutOfBounds` is returned if any are out of range.
+pub fn filter_sum_simd(values: &[u64], row_ids: &[u32]) -> Result<u64, RowIdOutOfBounds> {
+    row_ids::check(row_ids, values.len())?;
+    if values.len() > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_sum(values, row_ids));
+    }
 
-        // sum any remainder - maximum of three values. Not much value
-        // in doing this in a SIMD register
-        let rem = row_ids.len() - (row_ids.len() % 4);
-        let rem_sum = row_ids
-            .iter()
-            .skip(rem)
-            .map(|&id| values[id as usize])
-            .sum::<u64>();
-
-        let result: (u64, u64, u64, u64) = std::mem::transmute(sum_lanes);
-        result.0 + result.1 + result.2 + result.3 + rem_sum
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_sum_simd_unchecked(values, row_ids) })
+}
+
+/// This is `filter_sum_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `values.len()` and at most `i32::MAX`.
+pub unsafe fn filter_sum_simd_unchecked(values: &[u64], row_ids: &[u32]) -> u64 {
+    let base_ptr = values.as_ptr() as *const i64;
+    let mut sum_lanes = _mm256_setzero_si256(); // u64x4
+
+    for chunk in row_ids.chunks_exact(4) {
+        let chunk_ptr = chunk.as_ptr() as *const __m128i;
+        let row_values = _mm256_i32gather_epi64(base_ptr, _mm_loadu_si128(chunk_ptr), 8);
+        sum_lanes = _mm256_add_epi64(sum_lanes, row_values);
     }
+
+    // sum any remainder - maximum of three values. Not much value
+    // in doing this in a SIMD register
+    let rem = row_ids.len() - (row_ids.len() % 4);
+    let rem_sum = row_ids
+        .iter()
+        .skip(rem)
+        .map(|&id| values[id as usize])
+        .sum::<u64>();
+
+    let result: (u64, u64, u64, u64) = std::mem::transmute(sum_lanes);
+    result.0 + result.1 + result.2 + result.3 + rem_sum
 }
 
 mod test {
@@ -129,10 +148,22 @@ mod test {
         ];
 
         for (values, row_ids, exp) in &cases {
-            assert_eq!(&super::filter_sum_simd(values, row_ids), exp);
+            assert_eq!(&super::filter_sum_simd(values, row_ids).unwrap(), exp);
+            assert_eq!(
+                &unsafe { super::filter_sum_simd_unchecked(values, row_ids) },
+                exp
+            );
         }
     }
 
+    #[test]
+    fn filter_sum_simd_out_of_bounds() {
+        let values = (100..110).collect::<Vec<_>>();
+        let err = super::filter_sum_simd(&values, &[0, 1, 2, 3, 4, 5, 6, 7, 8, 99]).unwrap_err();
+        assert_eq!(err.row_id, 99);
+        assert_eq!(err.rows, 10);
+    }
+
     #[test]
     #[should_panic]
     fn filter_sum_overflow() {
@@ -150,6 +181,6 @@ mod test {
     #[test]
     #[should_panic]
     fn filter_sum_simd_overflow() {
-        super::filter_sum_simd(vec![u64::MAX, 1].as_slice(), &[0, 1]);
+        super::filter_sum_simd(vec![u64::MAX, 1].as_slice(), &[0, 1]).unwrap();
     }
 }
@@ -0,0 +1,522 @@
+//! Filter then aggregate functions that group the selected rows into time
+//! buckets.
+//!
+//! A `TimeBuckets` splits time into buckets of a fixed `width` (in the units of
+//! the timestamp column, e.g. nanoseconds), aligned to an `origin`. The bucket
+//! index of a timestamp `t` is `floor((t - origin) / width)`, so timestamps
+//! before the origin are in negative buckets. The count, sum, min and max of a
+//! value column are computed for each bucket that has at least one selected
+//! row, and returned in bucket order. Every timestamp must be within `2^63` of
+//! the origin.
+//!
+//! Timestamps can be `i64` (like Arrow's `TimestampNanosecondArray`) or `u64`.
+//!
+//! AVX2 has no integer division, and no conversions between `i64` and `f64`,
+//! so the SIMD implementation computes four bucket indexes at a time by:
+//!
+//! 1. converting `t - origin` to `f64`, as two exactly converted 32-bit halves;
+//! 2. dividing by the width and rounding down, then converting the estimated
+//!    index back to `i64` with the "magic number" trick;
+//! 3. correcting the estimate, which is at most one bucket out, by computing
+//!    the remainder `(t - origin) - index * width` with 32-bit multiplies.
+//!
+//! The estimate is only that close, and the magic number conversion only
+//! works, when `|index| < 2^51`. That holds for any timestamp when the width
+//! is at least `SIMD_MIN_WIDTH`; narrower buckets use the scalar
+//! implementation.
+//!
+//! The values are aggregated into a dense array of buckets when the selected
+//! rows span no more buckets than there are rows, and into a `BTreeMap` like
+//! the idiomatic implementation otherwise.
+use std::{arch::x86_64::*, collections::BTreeMap, fmt};
+
+use arrow::{
+    array::{self, Array},
+    compute::kernels,
+};
+
+use crate::{
+    row_ids::{self, RowIdOutOfBounds},
+    simd_u64::mullo_epi64,
+};
+
+/// The narrowest bucket width the SIMD implementation handles: with this
+/// width a bucket index is always below `2^50` in magnitude.
+pub const SIMD_MIN_WIDTH: u64 = 1 << 13;
+
+/// A timestamp column type. `u64` timestamps are handled as the equivalent
+/// `i64` bits: the difference between two timestamps (wrapped to 64 bits) is
+/// the same either way.
+pub trait Timestamp: Copy + fmt::Debug + PartialEq {
+    fn to_bits(self) -> i64;
+
+    fn from_bits(bits: i64) -> Self;
+}
+
+impl Timestamp for i64 {
+    fn to_bits(self) -> i64 {
+        self
+    }
+
+    fn from_bits(bits: i64) -> Self {
+        bits
+    }
+}
+
+impl Timestamp for u64 {
+    fn to_bits(self) -> i64 {
+        self as i64
+    }
+
+    fn from_bits(bits: i64) -> Self {
+        bits as u64
+    }
+}
+
+/// Buckets of `width` time units, aligned to `origin`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeBuckets<T> {
+    origin: T,
+    width: i64,
+}
+
+impl<T: Timestamp> TimeBuckets<T> {
+    /// Panics if `width` is zero or more than `i64::MAX`.
+    pub fn new(origin: T, width: u64) -> Self {
+        assert!(width > 0 && width <= i64::MAX as u64, "invalid width");
+        Self {
+            origin,
+            width: width as i64,
+        }
+    }
+
+    pub fn origin(&self) -> T {
+        self.origin
+    }
+
+    pub fn width(&self) -> u64 {
+        self.width as u64
+    }
+
+    /// The index of the bucket `timestamp` is in, counted from the origin.
+    pub fn index(&self, timestamp: T) -> i64 {
+        timestamp
+            .to_bits()
+            .wrapping_sub(self.origin.to_bits())
+            .div_euclid(self.width)
+    }
+
+    /// The first timestamp in the bucket at `index`.
+    pub fn start(&self, index: i64) -> T {
+        T::from_bits(
+            self.origin
+                .to_bits()
+                .wrapping_add(index.wrapping_mul(self.width)),
+        )
+    }
+}
+
+/// The aggregates of the values in one bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BucketAggregate<T> {
+    pub start: T,
+    pub count: u64,
+    pub sum: u64,
+    pub min: u64,
+    pub max: u64,
+}
+
+impl<T> BucketAggregate<T> {
+    fn new(start: T) -> Self {
+        Self {
+            start,
+            count: 0,
+            sum: 0,
+            min: u64::MAX,
+            max: 0,
+        }
+    }
+
+    fn add(&mut self, value: u64) {
+        self.count += 1;
+        self.sum += value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+}
+
+/// This is a relatively idiomatic Rust implementation of filter then
+/// aggregate by time bucket. The buckets are kept in a `BTreeMap`.
+pub fn filter_aggregate_buckets<T: Timestamp>(
+    timestamps: &[T],
+    values: &[u64],
+    row_ids: &[u32],
+    buckets: TimeBuckets<T>,
+) -> Vec<BucketAggregate<T>> {
+    assert_eq!(timestamps.len(), values.len());
+
+    let mut result = BTreeMap::new();
+    for &id in row_ids.iter() {
+        let index = buckets.index(timestamps[id as usize]);
+        result
+            .entry(index)
+            .or_insert_with(|| BucketAggregate::new(buckets.start(index)))
+            .add(values[id as usize]);
+    }
+    result.into_values().collect()
+}
+
+/// This is an implementation of filter then aggregate by time bucket using
+/// Arrow arrays and kernels: both columns are filtered, then aggregated like
+/// `filter_aggregate_buckets`. Rows where either column is null are skipped.
+pub fn filter_aggregate_buckets_arrow(
+    timestamps: &array::TimestampNanosecondArray,
+    values: &array::UInt64Array,
+    row_ids: &array::BooleanArray,
+    buckets: TimeBuckets<i64>,
+) -> Vec<BucketAggregate<i64>> {
+    let timestamps = kernels::filter::filter(timestamps, row_ids).unwrap();
+    let timestamps = timestamps
+        .as_any()
+        .downcast_ref::<array::TimestampNanosecondArray>()
+        .unwrap();
+    let values = kernels::filter::filter(values, row_ids).unwrap();
+    let values = values
+        .as_any()
+        .downcast_ref::<array::UInt64Array>()
+        .unwrap();
+
+    let mut result = BTreeMap::new();
+    for i in 0..timestamps.len() {
+        if timestamps.is_null(i) || values.is_null(i) {
+            continue;
+        }
+        let index = buckets.index(timestamps.value(i));
+        result
+            .entry(index)
+            .or_insert_with(|| BucketAggregate::new(buckets.start(index)))
+            .add(values.value(i));
+    }
+    result.into_values().collect()
+}
+
+/// This is a relatively idiomatic Rust implementation of computing the bucket
+/// index of each selected row.
+pub fn filter_bucket_indexes<T: Timestamp>(
+    timestamps: &[T],
+    row_ids: &[u32],
+    buckets: TimeBuckets<T>,
+    mut dst: Vec<i64>,
+) -> Vec<i64> {
+    dst.clear();
+    dst.extend(
+        row_ids
+            .iter()
+            .map(|&id| buckets.index(timestamps[id as usize])),
+    );
+    dst
+}
+
+/// This is an implementation of computing the bucket index of each selected
+/// row using SIMD intrinsics. See the module docs.
+pub fn filter_bucket_indexes_simd<T: Timestamp>(
+    timestamps: &[T],
+    row_ids: &[u32],
+    buckets: TimeBuckets<T>,
+    dst: Vec<i64>,
+) -> Result<Vec<i64>, RowIdOutOfBounds> {
+    row_ids::check(row_ids, timestamps.len())?;
+    if timestamps.len() > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_bucket_indexes(timestamps, row_ids, buckets, dst));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_bucket_indexes_simd_unchecked(timestamps, row_ids, buckets, dst) })
+}
+
+/// This is `filter_bucket_indexes_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `timestamps.len()` and at most `i32::MAX`.
+pub unsafe fn filter_bucket_indexes_simd_unchecked<T: Timestamp>(
+    timestamps: &[T],
+    row_ids: &[u32],
+    buckets: TimeBuckets<T>,
+    mut dst: Vec<i64>,
+) -> Vec<i64> {
+    if buckets.width() < SIMD_MIN_WIDTH {
+        return filter_bucket_indexes(timestamps, row_ids, buckets, dst);
+    }
+
+    dst.clear();
+    dst.reserve(row_ids.len());
+
+    let base_ptr = timestamps.as_ptr() as *const i64;
+    let zero = _mm256_setzero_si256();
+    let origin = _mm256_set1_epi64x(buckets.origin.to_bits());
+    let width = _mm256_set1_epi64x(buckets.width);
+    let width_hi = _mm256_srli_epi64(width, 32);
+    let width_minus_one = _mm256_set1_epi64x(buckets.width - 1);
+    let width_pd = _mm256_set1_pd(buckets.width as f64);
+
+    for chunk in row_ids.chunks_exact(4) {
+        let ids = _mm_loadu_si128(chunk.as_ptr() as *const __m128i);
+        let offsets = _mm256_sub_epi64(_mm256_i32gather_epi64(base_ptr, ids, 8), origin);
+
+        let estimate = _mm256_floor_pd(_mm256_div_pd(epi64_to_pd(offsets), width_pd));
+        let mut indexes = pd_to_epi64(estimate);
+
+        // the remainder is exact, since the true remainder is small.
+        let mut rem = _mm256_sub_epi64(offsets, mullo_epi64(indexes, width, width_hi));
+        let under = _mm256_cmpgt_epi64(zero, rem);
+        indexes = _mm256_add_epi64(indexes, under); // under is -1
+        rem = _mm256_add_epi64(rem, _mm256_and_si256(under, width));
+        let over = _mm256_cmpgt_epi64(rem, width_minus_one);
+        indexes = _mm256_sub_epi64(indexes, over);
+
+        _mm256_storeu_si256(dst.as_mut_ptr().add(dst.len()) as *mut __m256i, indexes);
+        dst.set_len(dst.len() + 4);
+    }
+
+    // any remainder - maximum of three values.
+    let rem = row_ids.len() - (row_ids.len() % 4);
+    for &id in row_ids.iter().skip(rem) {
+        dst.push(buckets.index(timestamps[id as usize]));
+    }
+    dst
+}
+
+// Convert `i64` lanes to `f64`. Flipping the sign bit makes them unsigned, and
+// the high and low 32 bits are each placed in the mantissa of a double, which
+// converts them exactly. Adding the halves is the only rounding.
+#[inline]
+unsafe fn epi64_to_pd(x: __m256i) -> __m256d {
+    const TWO_84: f64 = 19_342_813_113_834_066_795_298_816.0;
+    const TWO_63: f64 = 9_223_372_036_854_775_808.0;
+    const TWO_52: f64 = 4_503_599_627_370_496.0;
+
+    let x = _mm256_xor_si256(x, _mm256_set1_epi64x(i64::MIN));
+    let high = _mm256_or_si256(
+        _mm256_srli_epi64(x, 32),
+        _mm256_castpd_si256(_mm256_set1_pd(TWO_84)),
+    );
+    let low = _mm256_blend_epi32(x, _mm256_castpd_si256(_mm256_set1_pd(TWO_52)), 0b1010_1010);
+
+    // (2^84 + high * 2^32) - (2^84 + 2^63) is exact.
+    let high = _mm256_sub_pd(_mm256_castsi256_pd(high), _mm256_set1_pd(TWO_84 + TWO_63));
+    let low = _mm256_sub_pd(_mm256_castsi256_pd(low), _mm256_set1_pd(TWO_52));
+    _mm256_add_pd(high, low)
+}
+
+// Convert whole `f64` lanes to `i64`. Adding 2^52 + 2^51 to a whole number
+// below 2^51 in magnitude leaves it in the low bits of the mantissa.
+#[inline]
+unsafe fn pd_to_epi64(x: __m256d) -> __m256i {
+    let magic = _mm256_set1_pd(6_755_399_441_055_744.0);
+    _mm256_sub_epi64(
+        _mm256_castpd_si256(_mm256_add_pd(x, magic)),
+        _mm256_castpd_si256(magic),
+    )
+}
+
+/// This is an implementation of filter then aggregate by time bucket that
+/// computes the bucket indexes using SIMD intrinsics. See the module docs.
+pub fn filter_aggregate_buckets_simd<T: Timestamp>(
+    timestamps: &[T],
+    values: &[u64],
+    row_ids: &[u32],
+    buckets: TimeBuckets<T>,
+) -> Result<Vec<BucketAggregate<T>>, RowIdOutOfBounds> {
+    assert_eq!(timestamps.len(), values.len());
+    let indexes = filter_bucket_indexes_simd(timestamps, row_ids, buckets, vec![])?;
+
+    let (min, max) = match (indexes.iter().min(), indexes.iter().max()) {
+        (Some(&min), Some(&max)) => (min, max),
+        _ => return Ok(vec![]),
+    };
+
+    // the selected rows span too many buckets for a dense array.
+    let span = max as i128 - min as i128 + 1;
+    if span > row_ids.len() as i128 {
+        let mut result = BTreeMap::new();
+        for (&index, &id) in indexes.iter().zip(row_ids) {
+            result
+                .entry(index)
+                .or_insert_with(|| BucketAggregate::new(buckets.start(index)))
+                .add(values[id as usize]);
+        }
+        return Ok(result.into_values().collect());
+    }
+
+    let mut result = (0..span as i64)
+        .map(|i| BucketAggregate::new(buckets.start(min.wrapping_add(i))))
+        .collect::<Vec<_>>();
+    for (&index, &id) in indexes.iter().zip(row_ids) {
+        result[index.wrapping_sub(min) as usize].add(values[id as usize]);
+    }
+    result.retain(|bucket| bucket.count > 0);
+    Ok(result)
+}
+
+mod test {
+
+    // Aggregate rows by bucket without any bucket index arithmetic.
+    fn exp_buckets(
+        timestamps: &[i64],
+        values: &[u64],
+        row_ids: &[u32],
+        origin: i64,
+        width: i64,
+    ) -> Vec<super::BucketAggregate<i64>> {
+        let mut result: Vec<super::BucketAggregate<i64>> = vec![];
+        let mut ids = row_ids.to_vec();
+        ids.sort_by_key(|&id| timestamps[id as usize]);
+        for id in ids {
+            let (ts, value) = (timestamps[id as usize], values[id as usize]);
+            let start = origin + ((ts - origin) as f64 / width as f64).floor() as i64 * width;
+            match result.last_mut() {
+                Some(bucket) if bucket.start == start => bucket.add(value),
+                _ => {
+                    let mut bucket = super::BucketAggregate::new(start);
+                    bucket.add(value);
+                    result.push(bucket);
+                }
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn time_buckets() {
+        let buckets = super::TimeBuckets::new(100_i64, 10);
+        assert_eq!(buckets.index(100), 0);
+        assert_eq!(buckets.index(109), 0);
+        assert_eq!(buckets.index(110), 1);
+        assert_eq!(buckets.index(99), -1);
+        assert_eq!(buckets.index(90), -1);
+        assert_eq!(buckets.index(89), -2);
+        assert_eq!(buckets.start(-2), 80);
+
+        let buckets = super::TimeBuckets::new(u64::MAX - 5, 10);
+        assert_eq!(buckets.index(u64::MAX), 0);
+        assert_eq!(buckets.index(3), 0);
+        assert_eq!(buckets.index(4), 1);
+        assert_eq!(buckets.start(1), 4);
+    }
+
+    #[test]
+    fn filter_aggregate_buckets() {
+        let timestamps = (0..1000_i64)
+            .map(|i| i * 7919 % 1000 * 1_000_000_000 - 500_000_000_000)
+            .collect::<Vec<_>>();
+        let values = (0..1000_u64).map(|v| v * 31 % 97).collect::<Vec<_>>();
+        let cases = vec![
+            vec![],
+            vec![0_u32, 1, 2],
+            (0..1000).step_by(3).collect(),
+            (0..1000).rev().collect(),
+        ];
+        // a minute, a second (dense), and a nanosecond-aligned minute.
+        let bucket_cases = vec![
+            (0, 60_000_000_000),
+            (0, 1_000_000_000),
+            (-7, 60_000_000_000),
+        ];
+
+        for row_ids in &cases {
+            for &(origin, width) in &bucket_cases {
+                let buckets = super::TimeBuckets::new(origin, width as u64);
+                let exp = exp_buckets(&timestamps, &values, row_ids, origin, width);
+                assert_eq!(
+                    super::filter_aggregate_buckets(&timestamps, &values, row_ids, buckets),
+                    exp
+                );
+                assert_eq!(
+                    super::filter_aggregate_buckets_simd(&timestamps, &values, row_ids, buckets)
+                        .unwrap(),
+                    exp
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn filter_bucket_indexes_simd() {
+        // timestamps at the extremes, and either side of bucket boundaries.
+        let mut timestamps = vec![i64::MIN, i64::MAX, 0, -1, 1];
+        for width in [1_i64 << 13, 60_000_000_000, 1 << 40, i64::MAX] {
+            for k in [-3_i64, -1, 1, 1 << 20, 1 << 30] {
+                let boundary = k.saturating_mul(width);
+                timestamps.extend(&[
+                    boundary.saturating_sub(1),
+                    boundary,
+                    boundary.saturating_add(1),
+                ]);
+            }
+        }
+        let row_ids = (0..timestamps.len() as u32).collect::<Vec<_>>();
+
+        for width in [1_u64 << 13, 9999, 60_000_000_000, 1 << 40, i64::MAX as u64] {
+            for origin in [0_i64, 12345, -1 << 62] {
+                let buckets = super::TimeBuckets::new(origin, width);
+                let ids = timestamps
+                    .iter()
+                    .map(|&ts| (ts as i128 - origin as i128).div_euclid(width as i128) as i64)
+                    .collect::<Vec<_>>();
+                // only compare timestamps within 2^63 of the origin.
+                let in_range = timestamps
+                    .iter()
+                    .map(|&ts| (ts as i128 - origin as i128).abs() < 1 << 63)
+                    .collect::<Vec<_>>();
+
+                let got = super::filter_bucket_indexes_simd(&timestamps, &row_ids, buckets, vec![])
+                    .unwrap();
+                let idiomatic =
+                    super::filter_bucket_indexes(&timestamps, &row_ids, buckets, vec![]);
+                for i in 0..timestamps.len() {
+                    if in_range[i] {
+                        assert_eq!(got[i], ids[i], "{} {} {}", timestamps[i], origin, width);
+                    }
+                    assert_eq!(
+                        got[i], idiomatic[i],
+                        "{} {} {}",
+                        timestamps[i], origin, width
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn filter_aggregate_buckets_arrow() {
+        let timestamps = arrow::array::TimestampNanosecondArray::from(vec![5, 12, -3, 25, 14]);
+        let values = arrow::array::UInt64Array::from(vec![1, 2, 3, 4, 5]);
+        let row_ids = arrow::array::BooleanArray::from(vec![true, true, true, false, true]);
+        let buckets = super::TimeBuckets::new(0, 10);
+
+        let got = super::filter_aggregate_buckets_arrow(&timestamps, &values, &row_ids, buckets);
+        let exp = super::filter_aggregate_buckets(
+            &[5, 12, -3, 25, 14],
+            &[1, 2, 3, 4, 5],
+            &[0, 1, 2, 4],
+            buckets,
+        );
+        assert_eq!(got, exp);
+        assert_eq!(got.len(), 3);
+        assert_eq!((got[2].start, got[2].count, got[2].sum), (10, 2, 7));
+    }
+
+    #[test]
+    fn filter_aggregate_buckets_simd_out_of_bounds() {
+        let buckets = super::TimeBuckets::new(0_u64, 1 << 20);
+        let err = super::filter_aggregate_buckets_simd(
+            &[1, 2, 3, 4],
+            &[1, 2, 3, 4],
+            &[0, 1, 2, 4],
+            buckets,
+        )
+        .unwrap_err();
+        assert_eq!(err.row_id, 4);
+    }
+}
@@ -0,0 +1,300 @@
+//! Filter and materialise functions for variable-width columns (Utf8 or
+//! Binary). These use the same layout as Arrow's `StringArray`: an `offsets`
+//! buffer with `len + 1` entries and a `values` buffer holding the bytes of
+//! every value back to back. Value `i` is `values[offsets[i]..offsets[i + 1]]`.
+//!
+//! Materialising a sub-set of rows means building a new offsets and values pair
+//! for them. The interesting costs are growing the values buffer, and copying
+//! lots of small slices. The faster implementations here do a pre-pass over the
+//! row ids to size the values buffer up front, and copy contiguous runs of row
+//! ids with a single bulk copy (rebasing their offsets in one go).
+use std::arch::x86_64::*;
+
+use arrow::{array, compute::kernels};
+
+use crate::row_ids::{self, RowIdOutOfBounds};
+
+/// This is a relatively idiomatic Rust implementation of filter for a
+/// variable-width column. It serves as a baseline.
+///
+/// The destination buffers are passed in, populated and returned.
+pub fn filter_materialise_var_len(
+    offsets: &[i32],
+    values: &[u8],
+    row_ids: &[u32],
+    mut dst_offsets: Vec<i32>,
+    mut dst_values: Vec<u8>,
+) -> (Vec<i32>, Vec<u8>) {
+    dst_offsets.clear();
+    dst_values.clear();
+    dst_offsets.reserve(row_ids.len() + 1);
+
+    dst_offsets.push(0);
+    for &id in row_ids.iter() {
+        let id = id as usize;
+        dst_values.extend_from_slice(&values[offsets[id] as usize..offsets[id + 1] as usize]);
+        dst_offsets.push(dst_values.len() as i32);
+    }
+
+    assert_eq!(dst_offsets.len(), row_ids.len() + 1);
+    (dst_offsets, dst_values)
+}
+
+/// This is an implementation of filter using Arrow arrays and kernels.
+pub fn filter_materialise_var_len_arrow(
+    values: &array::StringArray,
+    row_ids: &array::BooleanArray,
+) -> array::ArrayRef {
+    kernels::filter::filter(values, row_ids).unwrap()
+}
+
+// Calls `f(start, end)` for each run of contiguous row ids, where `start` is
+// the first row id in the run and `end` is one past the last.
+fn for_each_run(row_ids: &[u32], mut f: impl FnMut(usize, usize)) {
+    let mut i = 0;
+    while i < row_ids.len() {
+        let start = row_ids[i] as usize;
+        let mut end = start + 1;
+        i += 1;
+        while i < row_ids.len() && row_ids[i] as usize == end {
+            end += 1;
+            i += 1;
+        }
+        f(start, end);
+    }
+}
+
+/// This is an implementation of filter for a variable-width column that sizes
+/// the values buffer with a pre-pass over the row ids, and copies each run of
+/// contiguous row ids with a single bulk copy.
+///
+/// Sorted row ids with long runs (e.g., a column that has been sorted by the
+/// columns the predicates were applied to) benefit the most.
+pub fn filter_materialise_var_len_runs(
+    offsets: &[i32],
+    values: &[u8],
+    row_ids: &[u32],
+    mut dst_offsets: Vec<i32>,
+    mut dst_values: Vec<u8>,
+) -> (Vec<i32>, Vec<u8>) {
+    dst_offsets.clear();
+    dst_values.clear();
+
+    let size = row_ids
+        .iter()
+        .map(|&id| (offsets[id as usize + 1] - offsets[id as usize]) as usize)
+        .sum();
+    dst_offsets.reserve(row_ids.len() + 1);
+    dst_values.reserve(size);
+
+    dst_offsets.push(0);
+    for_each_run(row_ids, |start, end| {
+        let delta = dst_values.len() as i32 - offsets[start];
+        dst_values.extend_from_slice(&values[offsets[start] as usize..offsets[end] as usize]);
+        dst_offsets.extend(offsets[start + 1..=end].iter().map(|&o| o + delta));
+    });
+
+    assert_eq!(dst_offsets.len(), row_ids.len() + 1);
+    (dst_offsets, dst_values)
+}
+
+// Sum the lengths of the selected values. Both ends of each value are gathered
+// eight at a time and the lengths are accumulated in 64-bit lanes. Every row id
+// must be less than `offsets.len() - 1` so that the gathers of `id + 1` are in
+// bounds too.
+unsafe fn selected_size_simd(offsets: &[i32], row_ids: &[u32]) -> usize {
+    let base_ptr = offsets.as_ptr();
+    let one = _mm256_set1_epi32(1);
+    let mut size_lanes = _mm256_setzero_si256(); // u64x4
+
+    for chunk in row_ids.chunks_exact(8) {
+        let ids = _mm256_loadu_si256(chunk.as_ptr() as *const __m256i);
+        let starts = _mm256_i32gather_epi32(base_ptr, ids, 4);
+        let ends = _mm256_i32gather_epi32(base_ptr, _mm256_add_epi32(ids, one), 4);
+        let lens = _mm256_sub_epi32(ends, starts);
+
+        size_lanes = _mm256_add_epi64(
+            size_lanes,
+            _mm256_cvtepu32_epi64(_mm256_castsi256_si128(lens)),
+        );
+        size_lanes = _mm256_add_epi64(
+            size_lanes,
+            _mm256_cvtepu32_epi64(_mm256_extracti128_si256(lens, 1)),
+        );
+    }
+
+    let rem = row_ids.len() - (row_ids.len() % 8);
+    let rem_size = row_ids
+        .iter()
+        .skip(rem)
+        .map(|&id| (offsets[id as usize + 1] - offsets[id as usize]) as u64)
+        .sum::<u64>();
+
+    let result: [u64; 4] = std::mem::transmute(size_lanes);
+    (result.iter().sum::<u64>() + rem_size) as usize
+}
+
+/// This is an implementation of filter for a variable-width column using SIMD
+/// intrinsics. It works like `filter_materialise_var_len_runs` except that the
+/// size pre-pass gathers the offsets eight at a time, and the offsets of each
+/// run are rebased eight at a time.
+///
+/// The row ids are checked with `row_ids::check` before any offsets are
+/// gathered, and `RowIdOutOfBounds` is returned if any are out of range.
+pub fn filter_materialise_var_len_simd(
+    offsets: &[i32],
+    values: &[u8],
+    row_ids: &[u32],
+    dst_offsets: Vec<i32>,
+    dst_values: Vec<u8>,
+) -> Result<(Vec<i32>, Vec<u8>), RowIdOutOfBounds> {
+    row_ids::check(row_ids, offsets.len().saturating_sub(1))?;
+    if offsets.len() > row_ids::MAX_GATHER_ROWS {
+        return Ok(filter_materialise_var_len_runs(
+            offsets,
+            values,
+            row_ids,
+            dst_offsets,
+            dst_values,
+        ));
+    }
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe {
+        filter_materialise_var_len_simd_unchecked(offsets, values, row_ids, dst_offsets, dst_values)
+    })
+}
+
+/// This is `filter_materialise_var_len_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `offsets.len() - 1` and less than
+/// `i32::MAX`, since the offsets after each row id are gathered too.
+pub unsafe fn filter_materialise_var_len_simd_unchecked(
+    offsets: &[i32],
+    values: &[u8],
+    row_ids: &[u32],
+    mut dst_offsets: Vec<i32>,
+    mut dst_values: Vec<u8>,
+) -> (Vec<i32>, Vec<u8>) {
+    dst_offsets.clear();
+    dst_values.clear();
+
+    let size = selected_size_simd(offsets, row_ids);
+    dst_offsets.reserve(row_ids.len() + 1);
+    dst_values.reserve(size);
+
+    dst_offsets.push(0);
+    for_each_run(row_ids, |start, end| {
+        let delta = dst_values.len() as i32 - offsets[start];
+        dst_values.extend_from_slice(&values[offsets[start] as usize..offsets[end] as usize]);
+
+        let run_offsets = &offsets[start + 1..=end];
+        let delta_lanes = _mm256_set1_epi32(delta);
+        for chunk in run_offsets.chunks_exact(8) {
+            let rebased = _mm256_add_epi32(
+                _mm256_loadu_si256(chunk.as_ptr() as *const __m256i),
+                delta_lanes,
+            );
+            _mm256_storeu_si256(
+                dst_offsets.as_mut_ptr().add(dst_offsets.len()) as *mut __m256i,
+                rebased,
+            );
+            dst_offsets.set_len(dst_offsets.len() + 8);
+        }
+
+        // rebase any remainder - maximum of seven offsets.
+        let rem = run_offsets.len() - (run_offsets.len() % 8);
+        dst_offsets.extend(run_offsets.iter().skip(rem).map(|&o| o + delta));
+    });
+
+    assert_eq!(dst_offsets.len(), row_ids.len() + 1);
+    (dst_offsets, dst_values)
+}
+
+mod test {
+
+    // Build an offsets and values buffer pair from some strings.
+    fn to_buffers(strs: &[&str]) -> (Vec<i32>, Vec<u8>) {
+        let mut offsets = vec![0];
+        let mut values = vec![];
+        for s in strs {
+            values.extend_from_slice(s.as_bytes());
+            offsets.push(values.len() as i32);
+        }
+        (offsets, values)
+    }
+
+    #[test]
+    fn filter_materialise_var_len() {
+        let strs = (0..100)
+            .map(|i| "abcdefghijklmnopqrstuvwxyz".repeat(i % 7))
+            .map(|s| s[..s.len() / 3].to_owned())
+            .collect::<Vec<_>>();
+        let strs = strs.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+        let (offsets, values) = to_buffers(&strs);
+
+        let cases = vec![
+            vec![],
+            vec![0_u32],
+            vec![0, 1, 2, 3],
+            vec![99, 3, 3, 50],
+            (10..90).collect::<Vec<_>>(),
+            vec![
+                1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 20, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 99,
+            ],
+        ];
+
+        for row_ids in &cases {
+            let exp = to_buffers(
+                &row_ids
+                    .iter()
+                    .map(|&id| strs[id as usize])
+                    .collect::<Vec<_>>(),
+            );
+
+            assert_eq!(
+                super::filter_materialise_var_len(&offsets, &values, row_ids, vec![], vec![]),
+                exp
+            );
+            assert_eq!(
+                super::filter_materialise_var_len_runs(&offsets, &values, row_ids, vec![], vec![]),
+                exp
+            );
+            assert_eq!(
+                super::filter_materialise_var_len_simd(&offsets, &values, row_ids, vec![], vec![]),
+                Ok(exp)
+            );
+        }
+    }
+
+    #[test]
+    fn filter_materialise_var_len_simd_out_of_bounds() {
+        let (offsets, values) = to_buffers(&["a"; 10]);
+        let row_ids = (0..20).collect::<Vec<_>>();
+        let err =
+            super::filter_materialise_var_len_simd(&offsets, &values, &row_ids, vec![], vec![])
+                .unwrap_err();
+        assert_eq!((err.row_id, err.rows), (19, 10));
+
+        let err =
+            super::filter_materialise_var_len_simd(&[], &[], &[0], vec![], vec![]).unwrap_err();
+        assert_eq!((err.row_id, err.rows), (0, 0));
+    }
+
+    #[test]
+    fn filter_materialise_var_len_arrow() {
+        let values = arrow::array::StringArray::from(vec!["a", "bb", "", "dddd", "e"]);
+        let row_ids = arrow::array::BooleanArray::from(vec![false, true, true, true, false]);
+
+        let exp = arrow::array::StringArray::from(vec!["bb", "", "dddd"]);
+        assert_eq!(
+            super::filter_materialise_var_len_arrow(&values, &row_ids)
+                .as_any()
+                .downcast_ref::<arrow::array::StringArray>()
+                .unwrap(),
+            &exp
+        );
+    }
+}
@@ -0,0 +1,211 @@
+//! A frame-of-reference (FOR) encoded column, and filter functions that push
+//! the aggregation down onto the encoded data.
+//!
+//! A FOR column stores the column's minimum value (the `base`) once, and each
+//! row as an offset from the base. The offsets are usually much smaller than
+//! the values so they're bit-packed (see `bit_packed`).
+//!
+//! I care about these because sorted or timestamp columns compress really well
+//! this way, and sums and maxes don't need to decode anything:
+//!
+//! - `sum(values) = sum(offsets) + count * base`
+//! - `max(values) = max(offsets) + base`
+//!
+//! The `_simd` functions use the bit-packed SIMD kernels on the offsets, which
+//! check the row ids and return `RowIdOutOfBounds` if any are out of range.
+use std::arch::x86_64::*;
+
+use crate::{
+    bit_packed::{self, BitPackedColumn},
+    row_ids::RowIdOutOfBounds,
+};
+
+#[derive(Debug, Clone)]
+pub struct FrameOfReferenceColumn {
+    base: u64,
+    offsets: BitPackedColumn,
+}
+
+impl FrameOfReferenceColumn {
+    /// Encode `values` using the minimum value as the base.
+    pub fn new(values: &[u64]) -> Self {
+        let base = values.iter().copied().min().unwrap_or(0);
+        let offsets = values.iter().map(|&v| v - base).collect::<Vec<_>>();
+
+        Self {
+            base,
+            offsets: BitPackedColumn::from_values(&offsets),
+        }
+    }
+
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
+    /// The bit-packed offsets from the base.
+    pub fn offsets(&self) -> &BitPackedColumn {
+        &self.offsets
+    }
+
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Decode the value at row `i`.
+    pub fn get(&self, i: usize) -> u64 {
+        self.base + self.offsets.get(i)
+    }
+
+    /// Decode every value in the column.
+    pub fn decode(&self) -> Vec<u64> {
+        (0..self.len()).map(|i| self.get(i)).collect()
+    }
+}
+
+/// This is a relatively idiomatic Rust implementation of filter on a FOR
+/// column. Each selected value is decoded on its own.
+pub fn filter_materialise_values(
+    col: &FrameOfReferenceColumn,
+    row_ids: &[u32],
+    mut dst: Vec<u64>,
+) -> Vec<u64> {
+    dst.clear();
+    dst.reserve(row_ids.len());
+
+    for &id in row_ids.iter() {
+        dst.push(col.get(id as usize));
+    }
+
+    assert_eq!(dst.len(), row_ids.len());
+    dst
+}
+
+/// This is an implementation of filter on a FOR column using SIMD intrinsics.
+/// The offsets are materialised with `bit_packed::filter_materialise_values_simd`
+/// and then the base is added to them four at a time.
+pub fn filter_materialise_values_simd(
+    col: &FrameOfReferenceColumn,
+    row_ids: &[u32],
+    dst: Vec<u64>,
+) -> Result<Vec<u64>, RowIdOutOfBounds> {
+    let mut dst = bit_packed::filter_materialise_values_simd(&col.offsets, row_ids, dst)?;
+
+    unsafe {
+        let base = _mm256_set1_epi64x(col.base as i64);
+        let mut chunks = dst.chunks_exact_mut(4);
+        for chunk in &mut chunks {
+            let ptr = chunk.as_mut_ptr() as *mut __m256i;
+            _mm256_storeu_si256(ptr, _mm256_add_epi64(_mm256_loadu_si256(ptr), base));
+        }
+
+        // any remainder - maximum of three values.
+        for v in chunks.into_remainder() {
+            *v += col.base;
+        }
+    }
+
+    Ok(dst)
+}
+
+/// This is a relatively idiomatic Rust implementation of filter_sum on a FOR
+/// column. Only the offsets are summed; the base is added `count` times at
+/// the end.
+pub fn filter_sum(col: &FrameOfReferenceColumn, row_ids: &[u32]) -> u64 {
+    bit_packed::filter_sum(&col.offsets, row_ids) + row_ids.len() as u64 * col.base
+}
+
+/// This is an implementation of filter_sum on a FOR column using SIMD
+/// intrinsics to sum the offsets.
+pub fn filter_sum_simd(
+    col: &FrameOfReferenceColumn,
+    row_ids: &[u32],
+) -> Result<u64, RowIdOutOfBounds> {
+    Ok(bit_packed::filter_sum_simd(&col.offsets, row_ids)? + row_ids.len() as u64 * col.base)
+}
+
+/// This is a relatively idiomatic Rust implementation of filter_max on a FOR
+/// column. The max offset is found, and the base added to it.
+pub fn filter_max(col: &FrameOfReferenceColumn, row_ids: &[u32]) -> u64 {
+    bit_packed::filter_max(&col.offsets, row_ids) + col.base
+}
+
+/// This is an implementation of filter_max on a FOR column using SIMD
+/// intrinsics to find the max offset.
+pub fn filter_max_simd(
+    col: &FrameOfReferenceColumn,
+    row_ids: &[u32],
+) -> Result<u64, RowIdOutOfBounds> {
+    Ok(bit_packed::filter_max_simd(&col.offsets, row_ids)? + col.base)
+}
+
+mod test {
+
+    #[test]
+    fn frame_of_reference_column() {
+        let values = (0..1000)
+            .map(|i| 1_600_000_000_000 + i * 37 % 1000)
+            .collect::<Vec<_>>();
+        let col = super::FrameOfReferenceColumn::new(&values);
+
+        assert_eq!(col.base(), 1_600_000_000_000);
+        assert_eq!(col.offsets().width(), 10);
+        assert_eq!(col.decode(), values);
+    }
+
+    #[test]
+    fn filter_frame_of_reference() {
+        let values = (0..1000)
+            .map(|i| u64::MAX - 5000 + (i * 7919) % 3000)
+            .collect::<Vec<_>>();
+        let col = super::FrameOfReferenceColumn::new(&values);
+
+        let cases = vec![
+            vec![0_u32, 1, 2, 3],
+            vec![999],
+            vec![998, 3, 3, 500, 256, 255, 4],
+            (0..1000).step_by(3).collect::<Vec<_>>(),
+        ];
+
+        for row_ids in &cases {
+            let exp = row_ids
+                .iter()
+                .map(|&id| values[id as usize])
+                .collect::<Vec<_>>();
+
+            assert_eq!(super::filter_materialise_values(&col, row_ids, vec![]), exp);
+            assert_eq!(
+                super::filter_materialise_values_simd(&col, row_ids, vec![]).unwrap(),
+                exp
+            );
+            assert_eq!(super::filter_max(&col, row_ids), *exp.iter().max().unwrap());
+            assert_eq!(
+                super::filter_max_simd(&col, row_ids).unwrap(),
+                *exp.iter().max().unwrap()
+            );
+        }
+
+        // sums of values this big overflow, so use smaller ones.
+        let values = (0..1000)
+            .map(|i| (1 << 40) | (i * 13))
+            .collect::<Vec<u64>>();
+        let col = super::FrameOfReferenceColumn::new(&values);
+        for row_ids in &cases {
+            let exp = row_ids.iter().map(|&id| values[id as usize]).sum::<u64>();
+            assert_eq!(super::filter_sum(&col, row_ids), exp);
+            assert_eq!(super::filter_sum_simd(&col, row_ids).unwrap(), exp);
+        }
+    }
+
+    #[test]
+    fn filter_frame_of_reference_out_of_bounds() {
+        let col = super::FrameOfReferenceColumn::new(&[10, 11, 12]);
+        let err = super::filter_sum_simd(&col, &[0, 3]).unwrap_err();
+        assert_eq!((err.row_id, err.rows), (3, 3));
+        let err = super::filter_max_simd(&col, &[9]).unwrap_err();
+        assert_eq!((err.row_id, err.rows), (9, 3));
+    }
+}
@@ -1,5 +1,32 @@
 #![deny(rust_2018_idioms)]
 #![allow(dead_code)]
+pub mod bit_packed;
+pub mod chunked;
+pub mod compare;
+pub mod dataset;
+pub mod delta;
 pub mod filter;
+pub mod filter_aggregates;
+pub mod filter_batch;
+pub mod filter_columns;
+pub mod filter_decimal;
+pub mod filter_dictionary;
+pub mod filter_distinct;
+pub mod filter_float;
 pub mod filter_max;
+pub mod filter_moments;
+pub mod filter_narrow;
+pub mod filter_quantile;
+pub mod filter_selector;
+pub mod filter_signed;
 pub mod filter_sum;
+pub mod filter_time_bucket;
+pub mod filter_var_len;
+pub mod frame_of_reference;
+pub mod narrow_gather;
+pub mod results;
+pub mod row_ids;
+pub mod run_length;
+pub mod runner;
+pub mod simd_u64;
+pub mod wide_row_ids;
@@ -0,0 +1,114 @@
+//! Gathering narrow unsigned integers (`u8`, `u16` and `u32`) eight at a time
+//! into 32-bit lanes, shared by `filter_dictionary` (whose keys are narrow
+//! integers), `filter_narrow` and `filter_columns`.
+//!
+//! AVX2 has no 8-bit or 16-bit gather, so for `u8` and `u16` a 32-bit value
+//! is gathered at the value's byte offset and the unwanted high bytes are
+//! masked off. That reads up to three bytes past the value, so callers compare
+//! each chunk of row ids against `gather_limit` with `any_beyond`, and handle
+//! any chunk that gets too close to the end of the column (or beyond it) with
+//! a scalar path.
+use std::arch::x86_64::*;
+
+/// The unsigned integer types that can be gathered into 32-bit lanes.
+pub trait NarrowValue: Copy + std::fmt::Debug {
+    /// The width of the value in bytes, which is also the scale used when
+    /// gathering values.
+    const WIDTH: usize;
+
+    fn as_usize(self) -> usize;
+
+    // Narrow a gathered (and already masked) 32-bit lane to the value type.
+    fn from_lane(v: u32) -> Self;
+}
+
+impl NarrowValue for u8 {
+    const WIDTH: usize = 1;
+
+    fn as_usize(self) -> usize {
+        self as usize
+    }
+
+    fn from_lane(v: u32) -> Self {
+        v as u8
+    }
+}
+
+impl NarrowValue for u16 {
+    const WIDTH: usize = 2;
+
+    fn as_usize(self) -> usize {
+        self as usize
+    }
+
+    fn from_lane(v: u32) -> Self {
+        v as u16
+    }
+}
+
+impl NarrowValue for u32 {
+    const WIDTH: usize = 4;
+
+    fn as_usize(self) -> usize {
+        self as usize
+    }
+
+    fn from_lane(v: u32) -> Self {
+        v
+    }
+}
+
+// Gather eight values at the row ids in `ids`. Row ids must be low enough that
+// reading four bytes at each value's offset stays within `values`.
+#[inline]
+pub(crate) unsafe fn gather<K: NarrowValue>(values: &[K], ids: __m256i) -> __m256i {
+    let base_ptr = values.as_ptr() as *const i32;
+    match K::WIDTH {
+        1 => _mm256_and_si256(
+            _mm256_i32gather_epi32(base_ptr, ids, 1),
+            _mm256_set1_epi32(0xFF),
+        ),
+        2 => _mm256_and_si256(
+            _mm256_i32gather_epi32(base_ptr, ids, 2),
+            _mm256_set1_epi32(0xFFFF),
+        ),
+        _ => _mm256_i32gather_epi32(base_ptr, ids, 4),
+    }
+}
+
+// The first row id at which gathering four bytes would read past the end of
+// `values`. Any row id at or above this is handled by the scalar path. The
+// gathers' offsets are signed, so it's never above `i32::MAX`.
+pub(crate) fn gather_limit<K: NarrowValue>(values: &[K]) -> u32 {
+    values
+        .len()
+        .saturating_sub(4 / K::WIDTH - 1)
+        .min(i32::MAX as usize) as u32
+}
+
+// Returns true if any of the eight row ids are at or above `limit`.
+#[inline]
+pub(crate) unsafe fn any_beyond(ids: __m256i, limit: __m256i) -> bool {
+    let beyond = _mm256_cmpeq_epi32(_mm256_max_epu32(ids, limit), ids);
+    _mm256_movemask_epi8(beyond) != 0
+}
+
+// Store eight values from 32-bit lanes at `dst_ptr`, packed back down to the
+// value width.
+#[inline]
+pub(crate) unsafe fn store<K: NarrowValue>(lanes: __m256i, dst_ptr: *mut K) {
+    match K::WIDTH {
+        1 => {
+            // 32-bit lanes -> 16-bit -> 8-bit. The packs work within
+            // 128-bit halves so the permute brings the halves together.
+            let packed = _mm256_permute4x64_epi64(_mm256_packus_epi32(lanes, lanes), 0b1000);
+            let packed = _mm256_castsi256_si128(packed);
+            _mm_storel_epi64(dst_ptr as *mut __m128i, _mm_packus_epi16(packed, packed));
+        }
+        2 => {
+            let packed = _mm256_permute4x64_epi64(_mm256_packus_epi32(lanes, lanes), 0b1000);
+            _mm_storeu_si128(dst_ptr as *mut __m128i, _mm256_castsi256_si128(packed));
+        }
+        _ => _mm256_storeu_si256(dst_ptr as *mut __m256i, lanes),
+    }
+}
@@ -0,0 +1,684 @@
+//! Machine-readable benchmark results, and the README's results tables.
+//!
+//! The `arrow-bench` runner can write its measurements to a JSON or CSV file,
+//! along with the environment they were taken in: the CPU model, the
+//! `RUSTFLAGS` the runner was built with, the Arrow revision and the seed used
+//! for the synthetic data. The tables in the README's "Current Results"
+//! section are generated from those files.
+//!
+//! `cargo bench` results can be used too. Criterion saves each benchmark's
+//! samples and estimates under `target/criterion/<group>/<parameter>/`, in a
+//! directory per baseline (`new` for the latest run, `base` for the one before
+//! it, or the name given to `--save-baseline`). `read_criterion` reads one
+//! baseline's benchmarks, and `Results::from_criterion` converts those the
+//! README's tables are made of (the `filter_materialise_*`, `filter_sum_*` and
+//! `filter_max_*` groups) into records. The other benchmarks can't be exported
+//! to a results file, but can still be compared with
+//! `compare::compare_criterion`.
+use std::{fs, fs::File, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::runner::{Impl, Measurement, Op};
+
+/// The Arrow revision the crate is built against. Keep in sync with
+/// `Cargo.toml`.
+pub const ARROW_REV: &str = "5353c285c6dfb3381ac0f1c9e7cd63d7fcb8da4a";
+
+// The headings and order of the README's tables.
+const TABLES: &[(&str, Op)] = &[
+    ("Filter Values", Op::Materialise),
+    ("Filter Max", Op::Max),
+    ("Filter Sum", Op::Sum),
+];
+
+// The criterion benchmark groups the README's tables are made of.
+const CRITERION_GROUPS: &[(&str, Op, Impl)] = &[
+    (
+        "filter_materialise_rust_idiomatic",
+        Op::Materialise,
+        Impl::Idiomatic,
+    ),
+    ("filter_materialise_arrow", Op::Materialise, Impl::Arrow),
+    ("filter_materialise_simd", Op::Materialise, Impl::Simd),
+    ("filter_sum_rust_idiomatic", Op::Sum, Impl::Idiomatic),
+    ("filter_sum_arrow", Op::Sum, Impl::Arrow),
+    ("filter_sum_simd", Op::Sum, Impl::Simd),
+    ("filter_max_rust_idiomatic", Op::Max, Impl::Idiomatic),
+    ("filter_max_arrow", Op::Max, Impl::Arrow),
+    ("filter_max_simd", Op::Max, Impl::Simd),
+];
+
+// The README's name for each implementation, in table row order.
+const IMPLS: &[(&str, Impl)] = &[
+    ("Vanilla (safe) Rust", Impl::Idiomatic),
+    ("Arrow Compute Kernels", Impl::Arrow),
+    ("SIMD intrinsics (unsafe) Rust", Impl::Simd),
+];
+
+/// Where a set of results was measured. `seed` is `None` for results that
+/// didn't use a fixed seed, such as those exported from `cargo bench`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Environment {
+    pub cpu: String,
+    pub rustflags: String,
+    pub arrow_rev: String,
+    pub seed: Option<u64>,
+}
+
+impl Environment {
+    /// The environment of the running binary. `rustflags` is whatever
+    /// `RUSTFLAGS` was set to when the crate was compiled; flags set in a
+    /// `.cargo/config` file aren't visible.
+    pub fn current(seed: u64) -> Self {
+        Self::with_rustflags(option_env!("RUSTFLAGS").unwrap_or(""), Some(seed))
+    }
+
+    /// The environment of benchmarks built separately from the running binary
+    /// with `rustflags`, on this machine.
+    pub fn with_rustflags(rustflags: &str, seed: Option<u64>) -> Self {
+        Self {
+            cpu: cpu_model(),
+            rustflags: rustflags.to_owned(),
+            arrow_rev: ARROW_REV.to_owned(),
+            seed,
+        }
+    }
+}
+
+// The CPU model name, from `/proc/cpuinfo` on Linux or `sysctl` on macOS.
+fn cpu_model() -> String {
+    if let Ok(cpuinfo) = fs::read_to_string("/proc/cpuinfo") {
+        let model = cpuinfo
+            .lines()
+            .find(|line| line.starts_with("model name"))
+            .and_then(|line| line.split_once(':'))
+            .map(|(_, model)| model);
+        if let Some(model) = model {
+            return model.trim().to_owned();
+        }
+    }
+
+    std::process::Command::new("sysctl")
+        .args(["-n", "machdep.cpu.brand_string"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_owned())
+        .unwrap_or_else(|| "unknown".to_owned())
+}
+
+/// One measurement, flattened for export.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    pub op: Op,
+    #[serde(rename = "impl")]
+    pub imp: Impl,
+    pub filter: String,
+    pub rows: usize,
+    pub selected: usize,
+    pub iterations: u64,
+    pub samples: usize,
+    pub ns_per_iter: f64,
+    pub std_dev_ns: f64,
+    pub melem_per_sec: f64,
+    pub gb_per_sec: f64,
+    pub correct: bool,
+}
+
+impl From<&Measurement> for Record {
+    fn from(m: &Measurement) -> Self {
+        Self {
+            op: m.op,
+            imp: m.imp,
+            filter: m.filter.clone(),
+            rows: m.rows,
+            selected: m.selected,
+            iterations: m.iterations,
+            samples: m.samples.len(),
+            ns_per_iter: m.per_iteration().as_secs_f64() * 1e9,
+            std_dev_ns: m.std_dev().as_secs_f64() * 1e9,
+            melem_per_sec: m.melem_per_sec(),
+            gb_per_sec: m.gb_per_sec(),
+            correct: m.correct,
+        }
+    }
+}
+
+/// The records from one run, and the environment they were measured in.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Results {
+    pub environment: Environment,
+    pub records: Vec<Record>,
+}
+
+// A CSV row: the environment is repeated on every row, so each row stands on
+// its own.
+#[derive(Debug, Serialize, Deserialize)]
+struct CsvRow {
+    cpu: String,
+    rustflags: String,
+    arrow_rev: String,
+    seed: Option<u64>,
+    op: Op,
+    #[serde(rename = "impl")]
+    imp: Impl,
+    filter: String,
+    rows: usize,
+    selected: usize,
+    iterations: u64,
+    samples: usize,
+    ns_per_iter: f64,
+    std_dev_ns: f64,
+    melem_per_sec: f64,
+    gb_per_sec: f64,
+    correct: bool,
+}
+
+impl Results {
+    pub fn new(environment: Environment, measurements: &[Measurement]) -> Self {
+        Self {
+            environment,
+            records: measurements.iter().map(Record::from).collect(),
+        }
+    }
+
+    /// Write the results to a `.json` or `.csv` file.
+    pub fn write(&self, path: &Path) {
+        let file = File::create(path).unwrap_or_else(|e| panic!("can't create {:?}: {}", path, e));
+        match extension(path) {
+            Some("json") => serde_json::to_writer_pretty(file, self).unwrap(),
+            Some("csv") => {
+                let mut writer = csv::Writer::from_writer(file);
+                let env = &self.environment;
+                for r in &self.records {
+                    writer
+                        .serialize(CsvRow {
+                            cpu: env.cpu.clone(),
+                            rustflags: env.rustflags.clone(),
+                            arrow_rev: env.arrow_rev.clone(),
+                            seed: env.seed,
+                            op: r.op,
+                            imp: r.imp,
+                            filter: r.filter.clone(),
+                            rows: r.rows,
+                            selected: r.selected,
+                            iterations: r.iterations,
+                            samples: r.samples,
+                            ns_per_iter: r.ns_per_iter,
+                            std_dev_ns: r.std_dev_ns,
+                            melem_per_sec: r.melem_per_sec,
+                            gb_per_sec: r.gb_per_sec,
+                            correct: r.correct,
+                        })
+                        .unwrap();
+                }
+                writer.flush().unwrap();
+            }
+            _ => panic!("unknown results file type {:?}", path),
+        }
+    }
+
+    /// Read results written by `write`.
+    pub fn read(path: &Path) -> Self {
+        let file = File::open(path).unwrap_or_else(|e| panic!("can't open {:?}: {}", path, e));
+        match extension(path) {
+            Some("json") => serde_json::from_reader(file).unwrap(),
+            Some("csv") => {
+                let rows = csv::Reader::from_reader(file)
+                    .deserialize::<CsvRow>()
+                    .collect::<Result<Vec<_>, _>>()
+                    .unwrap();
+
+                let environment = match rows.first() {
+                    Some(row) => Environment {
+                        cpu: row.cpu.clone(),
+                        rustflags: row.rustflags.clone(),
+                        arrow_rev: row.arrow_rev.clone(),
+                        seed: row.seed,
+                    },
+                    None => panic!("{:?} contains no results", path),
+                };
+
+                let records = rows
+                    .into_iter()
+                    .map(|row| Record {
+                        op: row.op,
+                        imp: row.imp,
+                        filter: row.filter,
+                        rows: row.rows,
+                        selected: row.selected,
+                        iterations: row.iterations,
+                        samples: row.samples,
+                        ns_per_iter: row.ns_per_iter,
+                        std_dev_ns: row.std_dev_ns,
+                        melem_per_sec: row.melem_per_sec,
+                        gb_per_sec: row.gb_per_sec,
+                        correct: row.correct,
+                    })
+                    .collect();
+
+                Self {
+                    environment,
+                    records,
+                }
+            }
+            _ => panic!("unknown results file type {:?}", path),
+        }
+    }
+}
+
+impl Results {
+    /// Convert the criterion benchmarks that make up the README's tables into
+    /// records. Criterion doesn't record the number of rows in the column, so
+    /// `rows` is 0; `selected` is the benchmark's throughput in elements.
+    pub fn from_criterion(environment: Environment, benchmarks: &[CriterionBenchmark]) -> Self {
+        let records = benchmarks
+            .iter()
+            .filter_map(|b| {
+                let &(_, op, imp) = CRITERION_GROUPS.iter().find(|(g, _, _)| *g == b.group)?;
+                let selected = b.elements.unwrap_or(0) as usize;
+                let melem_per_sec = selected as f64 / b.mean_ns * 1e3;
+                Some(Record {
+                    op,
+                    imp,
+                    filter: b.parameter.clone(),
+                    rows: 0,
+                    selected,
+                    iterations: b.iterations,
+                    samples: b.samples_ns.len(),
+                    ns_per_iter: b.mean_ns,
+                    std_dev_ns: b.std_dev_ns,
+                    melem_per_sec,
+                    gb_per_sec: melem_per_sec * 8.0 / 1e3,
+                    // the benches assert their results.
+                    correct: true,
+                })
+            })
+            .collect();
+
+        Self {
+            environment,
+            records,
+        }
+    }
+}
+
+/// One benchmark's results as saved by criterion.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CriterionBenchmark {
+    /// The benchmark's id, e.g., `filter_sum_simd/uniform_density_10%`.
+    pub id: String,
+    pub group: String,
+    pub parameter: String,
+
+    // the number of elements an iteration processes, if the benchmark set its
+    // throughput.
+    pub elements: Option<u64>,
+    pub iterations: u64,
+
+    // the mean time per iteration of each sample.
+    pub samples_ns: Vec<f64>,
+
+    // criterion's estimates of the mean time per iteration and its standard
+    // deviation.
+    pub mean_ns: f64,
+    pub std_dev_ns: f64,
+}
+
+// The parts of criterion's `benchmark.json`, `estimates.json` and
+// `sample.json` that are used.
+#[derive(Debug, Deserialize)]
+struct CriterionId {
+    group_id: String,
+    function_id: Option<String>,
+    value_str: Option<String>,
+    full_id: String,
+    throughput: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CriterionEstimate {
+    point_estimate: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct CriterionEstimates {
+    mean: CriterionEstimate,
+    std_dev: CriterionEstimate,
+}
+
+#[derive(Debug, Deserialize)]
+struct CriterionSamples {
+    iters: Vec<f64>,
+    times: Vec<f64>,
+}
+
+/// Read every benchmark saved under `dir` (usually `target/criterion`) for
+/// `baseline`, sorted by id.
+pub fn read_criterion(dir: &Path, baseline: &str) -> Vec<CriterionBenchmark> {
+    let mut benchmarks = vec![];
+    find_criterion(dir, baseline, &mut benchmarks);
+    benchmarks.sort_by(|a, b| a.id.cmp(&b.id));
+    benchmarks
+}
+
+// Add the benchmarks under `dir` to `benchmarks`.
+fn find_criterion(dir: &Path, baseline: &str, benchmarks: &mut Vec<CriterionBenchmark>) {
+    let entries = fs::read_dir(dir).unwrap_or_else(|e| panic!("can't read {:?}: {}", dir, e));
+    for entry in entries {
+        let path = entry.unwrap().path();
+        if !path.is_dir() {
+            continue;
+        }
+        if path.file_name().and_then(|name| name.to_str()) == Some(baseline)
+            && path.join("benchmark.json").exists()
+        {
+            benchmarks.push(read_criterion_benchmark(&path));
+        } else {
+            find_criterion(&path, baseline, benchmarks);
+        }
+    }
+}
+
+fn read_criterion_benchmark(dir: &Path) -> CriterionBenchmark {
+    fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> T {
+        let file = File::open(path).unwrap_or_else(|e| panic!("can't open {:?}: {}", path, e));
+        serde_json::from_reader(file).unwrap_or_else(|e| panic!("can't parse {:?}: {}", path, e))
+    }
+
+    let id: CriterionId = read_json(&dir.join("benchmark.json"));
+    let estimates: CriterionEstimates = read_json(&dir.join("estimates.json"));
+    let samples: CriterionSamples = read_json(&dir.join("sample.json"));
+
+    CriterionBenchmark {
+        id: id.full_id,
+        group: id.group_id,
+        parameter: id.value_str.or(id.function_id).unwrap_or_default(),
+        elements: id
+            .throughput
+            .as_ref()
+            .and_then(|t| t.get("Elements"))
+            .and_then(|n| n.as_u64()),
+        iterations: samples.iters.iter().sum::<f64>() as u64,
+        samples_ns: samples
+            .times
+            .iter()
+            .zip(&samples.iters)
+            .map(|(time, iters)| time / iters)
+            .collect(),
+        mean_ns: estimates.mean.point_estimate,
+        std_dev_ns: estimates.std_dev.point_estimate,
+    }
+}
+
+fn extension(path: &Path) -> Option<&str> {
+    path.extension().and_then(|ext| ext.to_str())
+}
+
+// The README's short name for a filter, e.g., `uniform_density_5%_block_size_5`
+// is `uniform_5%_bs_5`.
+fn short_filter_name(name: &str) -> String {
+    name.replace("uniform_density_", "uniform_")
+        .replace("_block_size_", "_bs_")
+}
+
+// Format a throughput to roughly three significant figures.
+fn format_melem(v: f64) -> String {
+    if v >= 100.0 {
+        format!("{:.0}", v)
+    } else if v >= 10.0 {
+        format!("{:.1}", v)
+    } else {
+        format!("{:.2}", v)
+    }
+}
+
+/// Generate the README's "Current Results" section body: a note on the
+/// environment followed by the "Filter Values", "Filter Max" and "Filter Sum"
+/// tables of Melem/s for each implementation and filter shape.
+///
+/// All of `results` must come from the same environment. If a filter shape is
+/// measured more than once for an implementation, the last measurement wins.
+pub fn markdown_tables(results: &[Results]) -> String {
+    let env = &results.first().expect("no results").environment;
+    for r in results {
+        assert!(
+            r.environment.cpu == env.cpu
+                && r.environment.rustflags == env.rustflags
+                && r.environment.arrow_rev == env.arrow_rev,
+            "results were measured in different environments: {:?} and {:?}",
+            env,
+            r.environment
+        );
+    }
+    let records = results.iter().flat_map(|r| &r.records).collect::<Vec<_>>();
+
+    // only the runner uses a fixed seed.
+    let how = match env.seed {
+        Some(seed) => format!(
+            "using `arrow-bench` with `RUSTFLAGS=\"{}\"` and seed {}",
+            env.rustflags, seed
+        ),
+        None => format!("using `cargo bench` with `RUSTFLAGS=\"{}\"`", env.rustflags),
+    };
+    let mut out = format!(
+        "As of Arrow commit `{}` the results are below.\n\
+         Benchmarks were ran on {} {}.\n\
+         \n\
+         In each table the results are displayed in \"Melem/s\", which means \"millions of \
+         elements per second\" (to go to `GB/s` multiply by `8` ). Bigger is better.\n",
+        env.arrow_rev, env.cpu, how
+    );
+
+    for &(heading, op) in TABLES {
+        let records = records.iter().filter(|r| r.op == op).collect::<Vec<_>>();
+        if records.is_empty() {
+            continue;
+        }
+
+        // the filter shapes, in the order they were measured.
+        let mut filters = Vec::<&str>::new();
+        for r in &records {
+            if !filters.contains(&r.filter.as_str()) {
+                filters.push(&r.filter);
+            }
+        }
+
+        out.push_str(&format!("\n### {}\n\n| Implementation |", heading));
+        for filter in &filters {
+            out.push_str(&format!(" {} |", short_filter_name(filter)));
+        }
+        out.push_str("\n| -------------: |");
+        out.push_str(&" :-------------: |".repeat(filters.len()));
+        out.push('\n');
+
+        for &(label, imp) in IMPLS {
+            out.push_str(&format!("| {} |", label));
+            for filter in &filters {
+                let cell = records
+                    .iter()
+                    .rev()
+                    .find(|r| r.imp == imp && r.filter == *filter)
+                    .map(|r| format_melem(r.melem_per_sec))
+                    .unwrap_or_else(|| "-".to_owned());
+                out.push_str(&format!(" {} |", cell));
+            }
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Replace the body of the README's "Current Results" section with `tables`.
+pub fn update_readme(readme: &str, tables: &str) -> String {
+    let heading = "## Current Results\n";
+    let start = readme
+        .find(heading)
+        .expect("README has no Current Results section")
+        + heading.len();
+    let end = readme[start..]
+        .find("\n## ")
+        .map(|i| start + i + 1)
+        .unwrap_or_else(|| readme.len());
+
+    format!("{}\n{}\n{}", &readme[..start], tables, &readme[end..])
+}
+
+mod test {
+    use std::time::Duration;
+
+    use crate::runner::{Impl, Measurement, Op};
+
+    fn measurement(op: Op, imp: Impl, filter: &str, micros: u64) -> Measurement {
+        Measurement {
+            op,
+            imp,
+            filter: filter.to_owned(),
+            rows: 1000,
+            selected: 500,
+            iterations: 10,
+            total: Duration::from_micros(micros),
+            samples: vec![Duration::from_micros(micros) / 10; 10],
+            correct: true,
+        }
+    }
+
+    fn results() -> super::Results {
+        let measurements = vec![
+            measurement(Op::Sum, Impl::Idiomatic, "uniform_density_10%", 1),
+            measurement(Op::Sum, Impl::Simd, "uniform_density_10%", 2),
+            measurement(Op::Sum, Impl::Simd, "uniform_density_5%_block_size_5", 4),
+            measurement(Op::Materialise, Impl::Arrow, "uniform_density_10%", 1000),
+        ];
+        super::Results::new(super::Environment::current(42), &measurements)
+    }
+
+    #[test]
+    fn round_trip() {
+        let results = results();
+        assert_eq!(results.environment.arrow_rev, super::ARROW_REV);
+        assert!(include_str!("../Cargo.toml").contains(super::ARROW_REV));
+
+        // results without a seed round trip too.
+        let mut unseeded = results.clone();
+        unseeded.environment.seed = None;
+
+        for ext in &["json", "csv"] {
+            let path = std::env::temp_dir().join(format!(
+                "rust_arrow_benches_results_{}.{}",
+                std::process::id(),
+                ext
+            ));
+            for results in &[&results, &unseeded] {
+                results.write(&path);
+                assert_eq!(&super::Results::read(&path), *results);
+            }
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    // Write a benchmark to `dir` the way criterion does.
+    fn write_criterion(dir: &std::path::Path, group: &str, parameter: &str, baseline: &str) {
+        let dir = dir.join(group).join(parameter).join(baseline);
+        std::fs::create_dir_all(&dir).unwrap();
+        let id = format!("{}/{}", group, parameter);
+        std::fs::write(
+            dir.join("benchmark.json"),
+            format!(
+                r#"{{"group_id":"{}","function_id":null,"value_str":"{}","throughput":{{"Elements":500}},"full_id":"{}","directory_name":"{}","title":"{}"}}"#,
+                group, parameter, id, id, id
+            ),
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("estimates.json"),
+            r#"{"mean":{"confidence_interval":{"confidence_level":0.95,"lower_bound":90.0,"upper_bound":110.0},"point_estimate":100.0,"standard_error":5.0},"std_dev":{"confidence_interval":{"confidence_level":0.95,"lower_bound":1.0,"upper_bound":3.0},"point_estimate":2.0,"standard_error":0.5}}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("sample.json"),
+            r#"{"sampling_mode":"Linear","iters":[10.0,20.0,30.0],"times":[1000.0,1900.0,3300.0]}"#,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn read_criterion() {
+        let dir = std::env::temp_dir().join(format!(
+            "rust_arrow_benches_criterion_{}",
+            std::process::id()
+        ));
+        write_criterion(&dir, "filter_sum_simd", "uniform_density_10%", "new");
+        write_criterion(&dir, "filter_sum_simd", "uniform_density_10%", "base");
+        write_criterion(
+            &dir,
+            "filter_narrow_sum_u8_simd",
+            "uniform_density_10%",
+            "new",
+        );
+        write_criterion(&dir, "filter_max_arrow", "uniform_density_50%", "main");
+
+        let benchmarks = super::read_criterion(&dir, "new");
+        let ids = benchmarks.iter().map(|b| b.id.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            ids,
+            vec![
+                "filter_narrow_sum_u8_simd/uniform_density_10%",
+                "filter_sum_simd/uniform_density_10%"
+            ]
+        );
+
+        let b = &benchmarks[1];
+        assert_eq!(b.group, "filter_sum_simd");
+        assert_eq!(b.parameter, "uniform_density_10%");
+        assert_eq!(b.elements, Some(500));
+        assert_eq!(b.iterations, 60);
+        assert_eq!(b.samples_ns, vec![100.0, 95.0, 110.0]);
+        assert_eq!((b.mean_ns, b.std_dev_ns), (100.0, 2.0));
+
+        // only the README's groups become records: 500 elements in 100ns is
+        // 5000 Melem/s.
+        let env = super::Environment::with_rustflags("-C target-cpu=native", None);
+        let results = super::Results::from_criterion(env, &benchmarks);
+        assert_eq!(results.records.len(), 1);
+        let r = &results.records[0];
+        assert_eq!((r.op, r.imp), (Op::Sum, Impl::Simd));
+        assert_eq!((r.selected, r.samples, r.melem_per_sec), (500, 3, 5000.0));
+
+        // there's no seed to report.
+        let tables = super::markdown_tables(&[results]);
+        assert!(tables.contains("using `cargo bench` with `RUSTFLAGS=\"-C target-cpu=native\"`.\n"));
+
+        assert_eq!(super::read_criterion(&dir, "main").len(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn markdown_tables() {
+        let tables = super::markdown_tables(&[results()]);
+        assert!(tables.contains("seed 42"));
+
+        // no max results, so no max table.
+        assert!(!tables.contains("### Filter Max"));
+        assert!(tables.find("### Filter Values").unwrap() < tables.find("### Filter Sum").unwrap());
+
+        // 500 * 10 elements in 1µs is 5000 Melem/s.
+        let sum = &tables[tables.find("### Filter Sum").unwrap()..];
+        assert!(sum.contains("| Implementation | uniform_10% | uniform_5%_bs_5 |\n"));
+        assert!(sum.contains("| Vanilla (safe) Rust | 5000 | - |\n"));
+        assert!(sum.contains("| Arrow Compute Kernels | - | - |\n"));
+        assert!(sum.contains("| SIMD intrinsics (unsafe) Rust | 2500 | 1250 |\n"));
+        assert!(tables.contains("| Arrow Compute Kernels | 5.00 |\n"));
+    }
+
+    #[test]
+    fn update_readme() {
+        let readme = "# Title\n\n## Current Results\n\nold tables\n\n## Summary\n\nText\n";
+        let got = super::update_readme(readme, "new tables\n");
+        assert_eq!(
+            got,
+            "# Title\n\n## Current Results\n\nnew tables\n\n## Summary\n\nText\n"
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! Checking row ids before they are used to gather values from a column.
+//!
+//! The SIMD gather intrinsics don't do any bounds checking: an out-of-range
+//! row id reads whatever memory happens to be at that offset from the start of
+//! the column, which is undefined behaviour. The safe SIMD kernels check their
+//! row ids with `check` first, and fail with `RowIdOutOfBounds` if any of them
+//! is out of range. Each kernel also has an `unsafe fn …_unchecked` variant
+//! that leaves the check to the caller.
+//!
+//! `check` finds the largest row id with SIMD. It reads the row ids
+//! sequentially, eight at a time, which is cheap next to the random reads the
+//! gathers do. A caller that already knows its row ids are sorted can use
+//! `check_sorted` instead, which only looks at the last row id.
+//!
+//! The gathers also treat row ids as signed 32-bit offsets, so a row id above
+//! `i32::MAX` would be read from before the start of the column. Columns with
+//! more than `MAX_GATHER_ROWS` rows are handled by the scalar implementations
+//! in the safe kernels.
+use std::{arch::x86_64::*, fmt};
+
+/// The maximum number of rows in a column the gather intrinsics can address.
+pub const MAX_GATHER_ROWS: usize = i32::MAX as usize + 1;
+
+/// The error returned by the bounds-checked SIMD kernels when a row id is not
+/// a row in the column. `row_id` is the largest row id (the last one for
+/// `check_sorted`) and `rows` is the number of rows in the column. `row_id` is
+/// a `u64` so that the `wide_row_ids` kernels can report their row ids too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RowIdOutOfBounds {
+    pub row_id: u64,
+    pub rows: usize,
+}
+
+impl fmt::Display for RowIdOutOfBounds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "row {} out of bounds ({} rows)", self.row_id, self.rows)
+    }
+}
+
+impl std::error::Error for RowIdOutOfBounds {}
+
+/// Check that every row id is less than `rows`, by finding the largest row
+/// id with SIMD.
+pub fn check(row_ids: &[u32], rows: usize) -> Result<(), RowIdOutOfBounds> {
+    match max_simd(row_ids) {
+        Some(row_id) if row_id as usize >= rows => Err(RowIdOutOfBounds {
+            row_id: row_id as u64,
+            rows,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Check that every row id is less than `rows`, trusting that the row ids are
+/// sorted so that the last one is the largest. Unsorted row ids are only
+/// caught in debug builds.
+pub fn check_sorted(row_ids: &[u32], rows: usize) -> Result<(), RowIdOutOfBounds> {
+    debug_assert!(row_ids.windows(2).all(|w| w[0] <= w[1]));
+    match row_ids.last() {
+        Some(&row_id) if row_id as usize >= rows => Err(RowIdOutOfBounds {
+            row_id: row_id as u64,
+            rows,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Check that every `u64` row id is less than `rows`, by finding the largest
+/// row id with SIMD.
+pub fn check_u64(row_ids: &[u64], rows: usize) -> Result<(), RowIdOutOfBounds> {
+    match max_u64_simd(row_ids) {
+        Some(row_id) if row_id >= rows as u64 => Err(RowIdOutOfBounds { row_id, rows }),
+        _ => Ok(()),
+    }
+}
+
+// The largest row id, found eight at a time.
+fn max_simd(row_ids: &[u32]) -> Option<u32> {
+    unsafe {
+        let mut max_lanes = _mm256_setzero_si256();
+        for chunk in row_ids.chunks_exact(8) {
+            let ids = _mm256_loadu_si256(chunk.as_ptr() as *const __m256i);
+            max_lanes = _mm256_max_epu32(max_lanes, ids);
+        }
+
+        let lanes: [u32; 8] = std::mem::transmute(max_lanes);
+        let rem = row_ids.len() - (row_ids.len() % 8);
+        // the lanes only hold row ids if there was at least one chunk.
+        let lanes = if rem == 0 { &lanes[..0] } else { &lanes[..] };
+        lanes.iter().chain(row_ids.iter().skip(rem)).copied().max()
+    }
+}
+
+// The largest `u64` row id, found four at a time. There is no unsigned 64-bit
+// max, so the high bit is flipped to compare them as signed integers like
+// `filter_max::filter_max_simd`.
+fn max_u64_simd(row_ids: &[u64]) -> Option<u64> {
+    unsafe {
+        let sign_bit = _mm256_set1_epi64x(i64::MIN);
+        let mut max_lanes = sign_bit; // zero, flipped
+        for chunk in row_ids.chunks_exact(4) {
+            let ids = _mm256_xor_si256(
+                _mm256_loadu_si256(chunk.as_ptr() as *const __m256i),
+                sign_bit,
+            );
+            let max_mask = _mm256_cmpgt_epi64(ids, max_lanes);
+            max_lanes = _mm256_blendv_epi8(max_lanes, ids, max_mask);
+        }
+
+        let lanes: [u64; 4] = std::mem::transmute(_mm256_xor_si256(max_lanes, sign_bit));
+        let rem = row_ids.len() - (row_ids.len() % 4);
+        let lanes = if rem == 0 { &lanes[..0] } else { &lanes[..] };
+        lanes.iter().chain(row_ids.iter().skip(rem)).copied().max()
+    }
+}
+
+mod test {
+
+    #[test]
+    fn check() {
+        let row_ids = (0..21).rev().collect::<Vec<u32>>();
+        assert_eq!(super::check(&row_ids, 21), Ok(()));
+        assert_eq!(super::check(&[], 0), Ok(()));
+
+        let cases = vec![
+            (vec![3_u32], 3),
+            (vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 1], 8),
+            (vec![0, 1, 2, 3, 4, 5, 6, 7, 1, 8], 8),
+            (vec![u32::MAX, 0, 0, 0, 0, 0, 0, 0], 100),
+        ];
+        for (row_ids, rows) in cases {
+            let max = *row_ids.iter().max().unwrap() as u64;
+            assert_eq!(
+                super::check(&row_ids, rows),
+                Err(super::RowIdOutOfBounds { row_id: max, rows })
+            );
+        }
+    }
+
+    #[test]
+    fn check_u64() {
+        let row_ids = (0..21).rev().collect::<Vec<u64>>();
+        assert_eq!(super::check_u64(&row_ids, 21), Ok(()));
+        assert_eq!(super::check_u64(&[], 0), Ok(()));
+
+        let cases = vec![
+            (vec![3_u64], 3),
+            (vec![0, 1, 2, 3, 4, 5, 8, 1], 8),
+            (vec![0, 1, 2, 3, 4, 5, 1, 8], 8),
+            (vec![u64::MAX, 0, 0, 0, 1 << 63, 0, 0, 0], 100),
+            (vec![1, 2, 3, 4, u32::MAX as u64 + 1], 1 << 20),
+        ];
+        for (row_ids, rows) in cases {
+            let max = *row_ids.iter().max().unwrap();
+            assert_eq!(
+                super::check_u64(&row_ids, rows),
+                Err(super::RowIdOutOfBounds { row_id: max, rows })
+            );
+        }
+    }
+
+    #[test]
+    fn check_sorted() {
+        assert_eq!(super::check_sorted(&[1, 4, 4, 9], 10), Ok(()));
+        assert_eq!(super::check_sorted(&[], 0), Ok(()));
+
+        let err = super::check_sorted(&[1, 4, 10], 10).unwrap_err();
+        assert_eq!(err.row_id, 10);
+        assert_eq!(err.to_string(), "row 10 out of bounds (10 rows)");
+    }
+}
@@ -0,0 +1,420 @@
+//! A run-length encoded (RLE) column, and filter functions that walk the row
+//! ids and the runs together rather than decoding each row.
+//!
+//! Low-cardinality sorted columns compress into a handful of runs. Each run is
+//! stored as a value and the row one past the end of the run, so the run
+//! holding row `i` can be found with a binary search over the run ends.
+//!
+//! Because each run has a single value, a sum only needs to know how many of
+//! the selected rows fall into each run: `sum += value * count`. When the row
+//! ids are sorted the runs and row ids can be walked together in
+//! `O(runs + row_ids)` without decoding any rows.
+use std::arch::x86_64::*;
+
+use crate::row_ids::{self, RowIdOutOfBounds};
+
+#[derive(Debug, Clone)]
+pub struct RunLengthColumn {
+    // the value of each run.
+    values: Vec<u64>,
+
+    // one past the last row of each run.
+    ends: Vec<u32>,
+}
+
+impl RunLengthColumn {
+    pub fn new(values: &[u64]) -> Self {
+        let mut col = Self {
+            values: vec![],
+            ends: vec![],
+        };
+
+        for (i, &v) in values.iter().enumerate() {
+            match col.values.last() {
+                Some(&last) if last == v => *col.ends.last_mut().unwrap() += 1,
+                _ => {
+                    col.values.push(v);
+                    col.ends.push(i as u32 + 1);
+                }
+            }
+        }
+        col
+    }
+
+    /// The number of runs in the column.
+    pub fn runs(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ends.last().map_or(0, |&end| end as usize)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // The index of the run holding row `i`.
+    fn run(&self, i: usize) -> usize {
+        assert!(
+            i < self.len(),
+            "row {} out of bounds ({} rows)",
+            i,
+            self.len()
+        );
+
+        // a row equal to a run's end belongs to the next run.
+        match self.ends.binary_search(&(i as u32)) {
+            Ok(run) => run + 1,
+            Err(run) => run,
+        }
+    }
+
+    /// Decode the value at row `i`.
+    pub fn get(&self, i: usize) -> u64 {
+        self.values[self.run(i)]
+    }
+
+    /// Decode every value in the column.
+    pub fn decode(&self) -> Vec<u64> {
+        let mut result = Vec::with_capacity(self.len());
+        for (&v, &end) in self.values.iter().zip(self.ends.iter()) {
+            result.resize(end as usize, v);
+        }
+        result
+    }
+}
+
+// Counts the row ids at the start of `row_ids` that are less than `end`. The
+// row ids must be sorted, without duplicates.
+//
+// If `row_ids[0]` is the first of `n` rows left in the run and the `n`th row
+// id is the run's last row, then every row in between must be selected too and
+// they're counted without looking at them.
+fn count_in_run(row_ids: &[u32], end: u32) -> usize {
+    let n = (end - row_ids[0]) as usize;
+    if n <= row_ids.len() && row_ids[n - 1] == end - 1 {
+        return n;
+    }
+
+    row_ids.iter().take_while(|&&id| id < end).count()
+}
+
+// Like `count_in_run` except that partially selected runs are counted by
+// comparing eight row ids to the run's end at a time.
+fn count_in_run_simd(row_ids: &[u32], end: u32) -> usize {
+    let n = (end - row_ids[0]) as usize;
+    if n <= row_ids.len() && row_ids[n - 1] == end - 1 {
+        return n;
+    }
+
+    let mut count = 0;
+    unsafe {
+        // the row ids are unsigned so flip their sign bits to compare them with
+        // a signed compare.
+        let sign = _mm256_set1_epi32(i32::MIN);
+        let end_lanes = _mm256_xor_si256(_mm256_set1_epi32(end as i32), sign);
+
+        for chunk in row_ids.chunks_exact(8) {
+            let ids = _mm256_xor_si256(_mm256_loadu_si256(chunk.as_ptr() as *const __m256i), sign);
+            let in_run =
+                _mm256_movemask_ps(_mm256_castsi256_ps(_mm256_cmpgt_epi32(end_lanes, ids)));
+
+            // the row ids are sorted so the lanes in the run are a prefix of
+            // the chunk.
+            let lanes = in_run.count_ones() as usize;
+            count += lanes;
+            if lanes < 8 {
+                return count;
+            }
+        }
+    }
+
+    // any remainder - maximum of seven row ids.
+    count + row_ids[count..].iter().take_while(|&&id| id < end).count()
+}
+
+// Calls `f(value, count)` for each run that has at least one selected row,
+// where `count` is the number of selected rows in the run. `count_in_run`
+// counts the selected rows at the start of some row ids that are in a run.
+//
+// Walking the runs relies on the row ids being sorted without duplicates, so
+// that's checked first. Any other row ids are handled a row at a time, calling
+// `f(value, 1)` for each one.
+fn for_each_selected_run(
+    col: &RunLengthColumn,
+    row_ids: &[u32],
+    count_in_run: impl Fn(&[u32], u32) -> usize,
+    mut f: impl FnMut(u64, usize),
+) -> Result<(), RowIdOutOfBounds> {
+    row_ids::check(row_ids, col.len())?;
+    if !row_ids.windows(2).all(|w| w[0] < w[1]) {
+        for &id in row_ids.iter() {
+            f(col.get(id as usize), 1);
+        }
+        return Ok(());
+    }
+
+    let mut run = 0;
+    let mut i = 0;
+    while i < row_ids.len() {
+        // skip runs with nothing selected.
+        while col.ends[run] <= row_ids[i] {
+            run += 1;
+        }
+
+        let count = count_in_run(&row_ids[i..], col.ends[run]);
+        f(col.values[run], count);
+        i += count;
+        run += 1;
+    }
+    Ok(())
+}
+
+/// This is a relatively idiomatic Rust implementation of filter on an RLE
+/// column. The run of each selected row is found with a binary search.
+pub fn filter_materialise_values(
+    col: &RunLengthColumn,
+    row_ids: &[u32],
+    mut dst: Vec<u64>,
+) -> Vec<u64> {
+    dst.clear();
+    dst.reserve(row_ids.len());
+
+    for &id in row_ids.iter() {
+        dst.push(col.get(id as usize));
+    }
+
+    assert_eq!(dst.len(), row_ids.len());
+    dst
+}
+
+/// This is a relatively idiomatic Rust implementation of filter_sum on an RLE
+/// column. It serves as a baseline.
+pub fn filter_sum(col: &RunLengthColumn, row_ids: &[u32]) -> u64 {
+    let mut result = 0;
+    for &id in row_ids.iter() {
+        result += col.get(id as usize);
+    }
+    result
+}
+
+/// This is a relatively idiomatic Rust implementation of filter_max on an RLE
+/// column. It serves as a baseline.
+pub fn filter_max(col: &RunLengthColumn, row_ids: &[u32]) -> u64 {
+    row_ids
+        .iter()
+        .map(|&id| col.get(id as usize))
+        .max()
+        .unwrap()
+}
+
+/// This is an implementation of filter on an RLE column that walks the sorted
+/// row ids and the runs together, and writes each run's value once for every
+/// selected row in it.
+///
+/// Row ids that aren't sorted without duplicates are decoded a row at a time,
+/// and `RowIdOutOfBounds` is returned if any are out of range.
+pub fn filter_materialise_values_runs(
+    col: &RunLengthColumn,
+    row_ids: &[u32],
+    mut dst: Vec<u64>,
+) -> Result<Vec<u64>, RowIdOutOfBounds> {
+    dst.clear();
+    dst.reserve(row_ids.len());
+
+    for_each_selected_run(col, row_ids, count_in_run, |v, count| {
+        dst.resize(dst.len() + count, v);
+    })?;
+
+    assert_eq!(dst.len(), row_ids.len());
+    Ok(dst)
+}
+
+/// This is an implementation of filter_sum on an RLE column that walks the
+/// sorted row ids and the runs together, and multiplies each run's value by
+/// the number of selected rows in it.
+///
+/// Row ids that aren't sorted without duplicates are decoded a row at a time,
+/// and `RowIdOutOfBounds` is returned if any are out of range.
+pub fn filter_sum_runs(col: &RunLengthColumn, row_ids: &[u32]) -> Result<u64, RowIdOutOfBounds> {
+    let mut result = 0;
+    for_each_selected_run(col, row_ids, count_in_run, |v, count| {
+        result += v * count as u64;
+    })?;
+    Ok(result)
+}
+
+/// This is an implementation of filter_max on an RLE column that walks the
+/// sorted row ids and the runs together. Only runs with a selected row are
+/// compared.
+///
+/// Row ids that aren't sorted without duplicates are decoded a row at a time,
+/// and `RowIdOutOfBounds` is returned if any are out of range.
+pub fn filter_max_runs(col: &RunLengthColumn, row_ids: &[u32]) -> Result<u64, RowIdOutOfBounds> {
+    let mut result = None;
+    for_each_selected_run(col, row_ids, count_in_run, |v, _| {
+        result = result.max(Some(v));
+    })?;
+    Ok(result.unwrap())
+}
+
+/// This is an implementation of filter on an RLE column like
+/// `filter_materialise_values_runs`, except that the selected rows in each
+/// partially selected run are counted eight at a time using SIMD intrinsics.
+///
+/// Row ids that aren't sorted without duplicates are decoded a row at a time,
+/// and `RowIdOutOfBounds` is returned if any are out of range.
+pub fn filter_materialise_values_simd(
+    col: &RunLengthColumn,
+    row_ids: &[u32],
+    mut dst: Vec<u64>,
+) -> Result<Vec<u64>, RowIdOutOfBounds> {
+    dst.clear();
+    dst.reserve(row_ids.len());
+
+    for_each_selected_run(col, row_ids, count_in_run_simd, |v, count| {
+        dst.resize(dst.len() + count, v);
+    })?;
+
+    assert_eq!(dst.len(), row_ids.len());
+    Ok(dst)
+}
+
+/// This is an implementation of filter_sum on an RLE column like
+/// `filter_sum_runs`, except that the selected rows in each partially selected
+/// run are counted eight at a time using SIMD intrinsics.
+///
+/// Row ids that aren't sorted without duplicates are decoded a row at a time,
+/// and `RowIdOutOfBounds` is returned if any are out of range.
+pub fn filter_sum_simd(col: &RunLengthColumn, row_ids: &[u32]) -> Result<u64, RowIdOutOfBounds> {
+    let mut result = 0;
+    for_each_selected_run(col, row_ids, count_in_run_simd, |v, count| {
+        result += v * count as u64;
+    })?;
+    Ok(result)
+}
+
+/// This is an implementation of filter_max on an RLE column like
+/// `filter_max_runs`, except that the selected rows in each partially
+/// selected run are skipped eight at a time using SIMD intrinsics.
+///
+/// Row ids that aren't sorted without duplicates are decoded a row at a time,
+/// and `RowIdOutOfBounds` is returned if any are out of range.
+pub fn filter_max_simd(col: &RunLengthColumn, row_ids: &[u32]) -> Result<u64, RowIdOutOfBounds> {
+    let mut result = None;
+    for_each_selected_run(col, row_ids, count_in_run_simd, |v, _| {
+        result = result.max(Some(v));
+    })?;
+    Ok(result.unwrap())
+}
+
+mod test {
+
+    // A sorted column with runs of lengths 1 to 24.
+    fn runs() -> Vec<u64> {
+        (0..100_u64)
+            .flat_map(|v| vec![v * 10; (v as usize * 7) % 24 + 1])
+            .collect()
+    }
+
+    #[test]
+    fn run_length_column() {
+        let values = runs();
+        let col = super::RunLengthColumn::new(&values);
+
+        assert_eq!(col.runs(), 100);
+        assert_eq!(col.len(), values.len());
+        assert_eq!(col.decode(), values);
+        for (i, &v) in values.iter().enumerate() {
+            assert_eq!(col.get(i), v);
+        }
+
+        let col = super::RunLengthColumn::new(&[]);
+        assert!(col.is_empty());
+        assert_eq!(col.decode(), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn filter_run_length() {
+        let values = runs();
+        let col = super::RunLengthColumn::new(&values);
+        let n = values.len() as u32;
+
+        let cases = vec![
+            vec![0_u32],
+            vec![0, 1, 2, 3],
+            vec![n - 1],
+            (0..n).collect::<Vec<_>>(),
+            (0..n).step_by(3).collect::<Vec<_>>(),
+            (0..n).step_by(50).collect::<Vec<_>>(),
+            (5..200).chain(300..n - 2).collect::<Vec<_>>(),
+        ];
+
+        for row_ids in &cases {
+            let exp = row_ids
+                .iter()
+                .map(|&id| values[id as usize])
+                .collect::<Vec<_>>();
+            let sum = exp.iter().sum::<u64>();
+            let max = *exp.iter().max().unwrap();
+
+            assert_eq!(super::filter_materialise_values(&col, row_ids, vec![]), exp);
+            assert_eq!(
+                super::filter_materialise_values_runs(&col, row_ids, vec![]).unwrap(),
+                exp
+            );
+            assert_eq!(
+                super::filter_materialise_values_simd(&col, row_ids, vec![]).unwrap(),
+                exp
+            );
+
+            assert_eq!(super::filter_sum(&col, row_ids), sum);
+            assert_eq!(super::filter_sum_runs(&col, row_ids).unwrap(), sum);
+            assert_eq!(super::filter_sum_simd(&col, row_ids).unwrap(), sum);
+
+            assert_eq!(super::filter_max(&col, row_ids), max);
+            assert_eq!(super::filter_max_runs(&col, row_ids).unwrap(), max);
+            assert_eq!(super::filter_max_simd(&col, row_ids).unwrap(), max);
+        }
+    }
+
+    #[test]
+    fn filter_run_length_unsorted() {
+        let values = runs();
+        let col = super::RunLengthColumn::new(&values);
+
+        for row_ids in &[vec![40_u32, 3, 3, 900, 0], vec![7, 7], vec![2, 1, 0]] {
+            let exp = row_ids
+                .iter()
+                .map(|&id| values[id as usize])
+                .collect::<Vec<_>>();
+            let sum = exp.iter().sum::<u64>();
+            let max = *exp.iter().max().unwrap();
+
+            assert_eq!(
+                super::filter_materialise_values_runs(&col, row_ids, vec![]).unwrap(),
+                exp
+            );
+            assert_eq!(
+                super::filter_materialise_values_simd(&col, row_ids, vec![]).unwrap(),
+                exp
+            );
+            assert_eq!(super::filter_sum_runs(&col, row_ids).unwrap(), sum);
+            assert_eq!(super::filter_sum_simd(&col, row_ids).unwrap(), sum);
+            assert_eq!(super::filter_max_runs(&col, row_ids).unwrap(), max);
+            assert_eq!(super::filter_max_simd(&col, row_ids).unwrap(), max);
+        }
+    }
+
+    #[test]
+    fn filter_run_length_out_of_bounds() {
+        let col = super::RunLengthColumn::new(&[1, 1, 2]);
+        let err = super::filter_sum_simd(&col, &[0, 3]).unwrap_err();
+        assert_eq!((err.row_id, err.rows), (3, 3));
+
+        // unsorted row ids are checked too.
+        let err = super::filter_max_runs(&col, &[9, 0]).unwrap_err();
+        assert_eq!((err.row_id, err.rows), (9, 3));
+    }
+}
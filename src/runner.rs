@@ -0,0 +1,438 @@
+//! The pieces of a small benchmark runner, used by the `arrow-bench` binary.
+//!
+//! `cargo bench` runs every combination of implementation and filter shape
+//! through criterion, which is thorough but slow. The runner instead runs a
+//! selection of them with a simple timing loop, and checks each result against
+//! the idiomatic implementation, which is treated as the oracle.
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use arrow::array;
+use rand::{distributions, Rng};
+use serde::{Deserialize, Serialize};
+
+use crate::{filter, filter_max, filter_sum};
+
+/// An operation to benchmark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Op {
+    Materialise,
+    Sum,
+    Max,
+}
+
+impl FromStr for Op {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "materialise" => Ok(Self::Materialise),
+            "sum" => Ok(Self::Sum),
+            "max" => Ok(Self::Max),
+            _ => Err(format!(
+                "unknown op {:?} (expected materialise, sum or max)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Materialise => write!(f, "materialise"),
+            Self::Sum => write!(f, "sum"),
+            Self::Max => write!(f, "max"),
+        }
+    }
+}
+
+/// An implementation of an operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Impl {
+    Idiomatic,
+    Arrow,
+    Simd,
+}
+
+impl FromStr for Impl {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "idiomatic" => Ok(Self::Idiomatic),
+            "arrow" => Ok(Self::Arrow),
+            "simd" => Ok(Self::Simd),
+            _ => Err(format!(
+                "unknown impl {:?} (expected idiomatic, arrow or simd)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Impl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Idiomatic => write!(f, "idiomatic"),
+            Self::Arrow => write!(f, "arrow"),
+            Self::Simd => write!(f, "simd"),
+        }
+    }
+}
+
+// Create a set of row_ids to apply to a column. Provide a prng, the domain that
+// the row_ids can be picked from (`n`) and the probability of a row being
+// selected, represented as `1/prop`.
+pub fn random_filter(rng: &mut impl Rng, n: usize, prop: usize) -> Vec<u32> {
+    let dist = distributions::Uniform::from(0..100);
+    rng.sample_iter(dist)
+        .take(n)
+        .enumerate()
+        .filter_map(|(row_id, x)| {
+            if x < prop {
+                return Some(row_id as u32);
+            }
+            None
+        })
+        .collect::<Vec<_>>()
+}
+
+// Create a set of row_ids to apply to a column using a strategy where "runs"
+// of matching rows are created according to 1/prop probability. Unlike the
+// generator in the benches, runs never overlap so there are no duplicates.
+pub fn random_filter_run(rng: &mut impl Rng, n: usize, prop: usize, run_size: usize) -> Vec<u32> {
+    let dist = distributions::Uniform::from(0..100);
+
+    let mut result = vec![];
+    let mut row_id = 0;
+    while row_id < n {
+        if rng.sample(dist) < prop {
+            result.extend((row_id..(row_id + run_size).min(n)).map(|id| id as u32));
+            row_id += run_size;
+        } else {
+            row_id += 1;
+        }
+    }
+    result
+}
+
+/// A column to benchmark against, in both the plain and Arrow forms.
+pub struct Column {
+    values: Vec<u64>,
+    arrow_values: array::UInt64Array,
+}
+
+impl Column {
+    pub fn new(values: Vec<u64>) -> Self {
+        let arrow_values = array::UInt64Array::from(values.clone());
+        Self {
+            values,
+            arrow_values,
+        }
+    }
+
+    /// A column of `rows` random values in the same range as the benches.
+    pub fn random(rng: &mut impl Rng, rows: usize) -> Self {
+        Self::new(
+            rng.sample_iter(distributions::Uniform::from(0..100000))
+                .take(rows)
+                .collect(),
+        )
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+/// A named filter on a column, in both the row id and Arrow forms.
+pub struct Filter {
+    name: String,
+    row_ids: Vec<u32>,
+    arrow_row_ids: array::BooleanArray,
+}
+
+impl Filter {
+    pub fn new(name: String, rows: usize, row_ids: Vec<u32>) -> Self {
+        let mut filter = vec![false; rows];
+        for &id in &row_ids {
+            filter[id as usize] = true;
+        }
+
+        Self {
+            name,
+            row_ids,
+            arrow_row_ids: array::BooleanArray::from(filter),
+        }
+    }
+
+    /// A random filter selecting `density`% of the rows, in runs of `run_size`
+    /// rows. Its name matches the filter shapes in the benches.
+    pub fn random(rng: &mut impl Rng, rows: usize, density: usize, run_size: usize) -> Self {
+        if run_size <= 1 {
+            let name = format!("uniform_density_{}%", density);
+            return Self::new(name, rows, random_filter(rng, rows, density));
+        }
+
+        let name = format!("uniform_density_{}%_block_size_{}", density, run_size);
+        let row_ids = random_filter_run(rng, rows, density, run_size);
+        Self::new(name, rows, row_ids)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn len(&self) -> usize {
+        self.row_ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.row_ids.is_empty()
+    }
+}
+
+// The result of running a kernel.
+enum Output {
+    Values(Vec<u64>),
+    Array(array::ArrayRef),
+    Scalar(u64),
+}
+
+impl Output {
+    // A cheap value that depends on the output, so it isn't optimised away.
+    fn checksum(&self) -> u64 {
+        match self {
+            Self::Values(values) => values.len() as u64,
+            Self::Array(array) => array.len() as u64,
+            Self::Scalar(v) => *v,
+        }
+    }
+
+    // Whether the output matches the oracle's output.
+    fn matches(&self, oracle: &Self) -> bool {
+        match (self, oracle) {
+            (Self::Values(got), Self::Values(exp)) => got == exp,
+            (Self::Array(got), Self::Values(exp)) => {
+                let got = got.as_any().downcast_ref::<array::UInt64Array>().unwrap();
+                got.len() == exp.len() && exp.iter().enumerate().all(|(i, &v)| got.value(i) == v)
+            }
+            (Self::Scalar(got), Self::Scalar(exp)) => got == exp,
+            _ => false,
+        }
+    }
+}
+
+fn run_kernel(op: Op, imp: Impl, col: &Column, filter: &Filter) -> Output {
+    let (values, row_ids) = (col.values.as_slice(), filter.row_ids.as_slice());
+    let (arrow_values, arrow_row_ids) = (&col.arrow_values, &filter.arrow_row_ids);
+
+    match (op, imp) {
+        (Op::Materialise, Impl::Idiomatic) => {
+            Output::Values(filter::filter_materialise_values(values, row_ids, vec![]))
+        }
+        (Op::Materialise, Impl::Arrow) => Output::Array(filter::filter_materialise_values_arrow(
+            arrow_values,
+            arrow_row_ids,
+        )),
+        (Op::Materialise, Impl::Simd) => {
+            Output::Values(filter::filter_materialise_values_simd(values, row_ids, vec![]).unwrap())
+        }
+        (Op::Sum, Impl::Idiomatic) => Output::Scalar(filter_sum::filter_sum(values, row_ids)),
+        (Op::Sum, Impl::Arrow) => {
+            Output::Scalar(filter_sum::filter_sum_arrow(arrow_values, arrow_row_ids))
+        }
+        (Op::Sum, Impl::Simd) => {
+            Output::Scalar(filter_sum::filter_sum_simd(values, row_ids).unwrap())
+        }
+        (Op::Max, Impl::Idiomatic) => Output::Scalar(filter_max::filter_max(values, row_ids)),
+        (Op::Max, Impl::Arrow) => {
+            Output::Scalar(filter_max::filter_max_arrow(arrow_values, arrow_row_ids))
+        }
+        (Op::Max, Impl::Simd) => {
+            Output::Scalar(filter_max::filter_max_simd(values, row_ids).unwrap())
+        }
+    }
+}
+
+/// The number of samples `measure` takes of each kernel.
+pub const SAMPLES: usize = 20;
+
+/// The timing of one implementation of an operation on one filter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Measurement {
+    pub op: Op,
+    pub imp: Impl,
+    pub filter: String,
+    pub rows: usize,
+    pub selected: usize,
+    pub iterations: u64,
+    pub total: Duration,
+
+    // the mean time per iteration of each sample.
+    pub samples: Vec<Duration>,
+
+    // whether the output matched the idiomatic implementation's.
+    pub correct: bool,
+}
+
+impl Measurement {
+    pub fn per_iteration(&self) -> Duration {
+        self.total.div_f64(self.iterations as f64)
+    }
+
+    /// The sample standard deviation of the time per iteration.
+    pub fn std_dev(&self) -> Duration {
+        let n = self.samples.len();
+        if n < 2 {
+            return Duration::default();
+        }
+
+        let samples = self.samples.iter().map(|s| s.as_secs_f64());
+        let mean = samples.clone().sum::<f64>() / n as f64;
+        let variance = samples.map(|s| (s - mean).powi(2)).sum::<f64>() / (n - 1) as f64;
+        Duration::from_secs_f64(variance.sqrt())
+    }
+
+    /// Millions of selected elements processed per second.
+    pub fn melem_per_sec(&self) -> f64 {
+        (self.selected as f64 * self.iterations as f64) / self.total.as_secs_f64() / 1e6
+    }
+
+    /// Gigabytes of selected `u64` values processed per second.
+    pub fn gb_per_sec(&self) -> f64 {
+        self.melem_per_sec() * 8.0 / 1e3
+    }
+}
+
+/// Run `imp` of `op` repeatedly for at least `min_time`, and check its output
+/// against the idiomatic implementation.
+///
+/// The runs are split into `SAMPLES` samples of the same number of iterations,
+/// so the noise in the timings can be estimated.
+///
+/// There's no max of no rows, so `None` is returned for `Op::Max` on an empty
+/// filter.
+pub fn measure(
+    op: Op,
+    imp: Impl,
+    col: &Column,
+    filter: &Filter,
+    min_time: Duration,
+) -> Option<Measurement> {
+    if op == Op::Max && filter.is_empty() {
+        return None;
+    }
+
+    // the first run warms up, is checked and estimates the time per iteration.
+    let oracle = run_kernel(op, Impl::Idiomatic, col, filter);
+    let start = Instant::now();
+    let output = run_kernel(op, imp, col, filter);
+    let estimate = start.elapsed().as_secs_f64().max(1e-9);
+    let correct = output.matches(&oracle);
+    let mut sink = output.checksum();
+    drop(output);
+
+    let per_sample = min_time.as_secs_f64() / SAMPLES as f64;
+    let sample_iterations = (per_sample / estimate).ceil().max(1.0) as u64;
+
+    let mut samples = Vec::with_capacity(SAMPLES);
+    let mut total = Duration::default();
+    while samples.len() < SAMPLES || total < min_time {
+        let start = Instant::now();
+        for _ in 0..sample_iterations {
+            sink = sink.wrapping_add(run_kernel(op, imp, col, filter).checksum());
+        }
+        let elapsed = start.elapsed();
+        samples.push(elapsed.div_f64(sample_iterations as f64));
+        total += elapsed;
+    }
+
+    // ensure the runs aren't optimised away.
+    unsafe { std::ptr::read_volatile(&sink) };
+
+    Some(Measurement {
+        op,
+        imp,
+        filter: filter.name().to_owned(),
+        rows: col.len(),
+        selected: filter.len(),
+        iterations: sample_iterations * samples.len() as u64,
+        total,
+        samples,
+        correct,
+    })
+}
+
+mod test {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn rng() -> StdRng {
+        StdRng::seed_from_u64(0)
+    }
+
+    #[test]
+    fn random_filters() {
+        let mut rng = rng();
+
+        let filter = super::Filter::random(&mut rng, 1000, 10, 1);
+        assert_eq!(filter.name(), "uniform_density_10%");
+        assert!(filter.len() > 50 && filter.len() < 150);
+
+        let filter = super::Filter::random(&mut rng, 1000, 5, 5);
+        assert_eq!(filter.name(), "uniform_density_5%_block_size_5");
+        assert!(filter.row_ids.windows(2).all(|w| w[0] < w[1]));
+        assert!(filter.row_ids.iter().all(|&id| id < 1000));
+    }
+
+    #[test]
+    fn measure() {
+        let mut rng = rng();
+        let col = super::Column::random(&mut rng, 1000);
+        let filter = super::Filter::random(&mut rng, 1000, 50, 1);
+
+        for &op in &[super::Op::Materialise, super::Op::Sum, super::Op::Max] {
+            for &imp in &[
+                super::Impl::Idiomatic,
+                super::Impl::Arrow,
+                super::Impl::Simd,
+            ] {
+                let m = super::measure(op, imp, &col, &filter, std::time::Duration::from_millis(1))
+                    .unwrap();
+                assert!(m.correct, "{} {} incorrect", op, imp);
+                assert!(m.iterations > 0);
+                assert!(m.samples.len() >= super::SAMPLES);
+                assert_eq!(m.selected, filter.len());
+            }
+        }
+
+        // an empty filter has a sum, but no max.
+        let empty = super::Filter::new("empty".to_owned(), 1000, vec![]);
+        let min_time = std::time::Duration::from_millis(1);
+        let m = super::measure(super::Op::Sum, super::Impl::Simd, &col, &empty, min_time);
+        assert!(m.unwrap().correct);
+        for &imp in &[
+            super::Impl::Idiomatic,
+            super::Impl::Arrow,
+            super::Impl::Simd,
+        ] {
+            assert!(super::measure(super::Op::Max, imp, &col, &empty, min_time).is_none());
+        }
+
+        // outputs that don't match the oracle's are caught.
+        let oracle = super::Output::Values(vec![1, 2, 3]);
+        assert!(super::Output::Values(vec![1, 2, 3]).matches(&oracle));
+        assert!(!super::Output::Values(vec![1, 2]).matches(&oracle));
+        assert!(!super::Output::Scalar(6).matches(&oracle));
+    }
+}
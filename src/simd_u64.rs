@@ -0,0 +1,16 @@
+//! 64-bit integer lane arithmetic that AVX2 doesn't have an instruction for,
+//! shared by `filter_time_bucket` and `filter_distinct`.
+use std::arch::x86_64::*;
+
+// The low 64 bits of `a * b`, from three 32-bit multiplies. `b_hi` is `b`
+// shifted right 32 bits, which callers multiplying by the same `b` many times
+// can compute once.
+#[inline]
+pub(crate) unsafe fn mullo_epi64(a: __m256i, b: __m256i, b_hi: __m256i) -> __m256i {
+    let low = _mm256_mul_epu32(a, b);
+    let cross = _mm256_add_epi64(
+        _mm256_mul_epu32(_mm256_srli_epi64(a, 32), b),
+        _mm256_mul_epu32(a, b_hi),
+    );
+    _mm256_add_epi64(low, _mm256_slli_epi64(cross, 32))
+}
@@ -0,0 +1,438 @@
+//! Filter functions for columns with more than `u32::MAX` rows.
+//!
+//! The rest of the crate caps a column at `u32::MAX` rows so that row ids fit
+//! in a `u32`, and four of them fit in the 128-bit index register of
+//! `_mm256_i32gather_epi64`. There are two ways to address more rows than
+//! that:
+//!
+//! - Wide row ids: `u64` row ids, gathered with `_mm256_i64gather_epi64`. The
+//!   row ids take twice the memory (and memory bandwidth) to read, and each
+//!   gather's indexes take up a full 256-bit register.
+//! - Segmented row ids: the column is split into segments of `SEGMENT_ROWS`
+//!   rows, and each segment holds a `u64` base row and `u32` row ids relative
+//!   to it. Each segment is handed to the existing `u32` kernels (`filter`,
+//!   `filter_sum` and `filter_max`) with the column sliced at its base row.
+//!
+//! The `_simd` and `_segmented` functions check the row ids before gathering
+//! any values, like the `u32` kernels (see `row_ids`).
+use std::arch::x86_64::*;
+
+use crate::{
+    filter, filter_max, filter_sum,
+    row_ids::{self, RowIdOutOfBounds},
+};
+
+/// The number of rows in each segment of a `SegmentedRowIds`. This is as many
+/// rows as `_mm256_i32gather_epi64` can address.
+pub const SEGMENT_ROWS: usize = row_ids::MAX_GATHER_ROWS;
+
+/// Row ids split into segments, each holding the row ids in a range of
+/// `segment_rows` rows relative to the first row of the range.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentedRowIds {
+    segment_rows: usize,
+
+    // the first row of each segment, and the segment's row ids relative to it.
+    segments: Vec<(u64, Vec<u32>)>,
+    len: usize,
+}
+
+impl SegmentedRowIds {
+    pub fn new(row_ids: &[u64]) -> Self {
+        Self::with_segment_rows(row_ids, SEGMENT_ROWS)
+    }
+
+    /// Split `row_ids` into segments of `segment_rows` rows. Sorted row ids
+    /// have one segment per range of rows they select from, and unsorted row
+    /// ids start a new segment whenever they move to another range.
+    pub fn with_segment_rows(row_ids: &[u64], segment_rows: usize) -> Self {
+        assert!(segment_rows > 0 && segment_rows <= SEGMENT_ROWS);
+
+        let mut segments: Vec<(u64, Vec<u32>)> = vec![];
+        for &id in row_ids {
+            let base = id - id % segment_rows as u64;
+            match segments.last_mut() {
+                Some((last_base, ids)) if *last_base == base => ids.push((id - base) as u32),
+                _ => segments.push((base, vec![(id - base) as u32])),
+            }
+        }
+
+        Self {
+            segment_rows,
+            segments,
+            len: row_ids.len(),
+        }
+    }
+
+    pub fn segments(&self) -> &[(u64, Vec<u32>)] {
+        &self.segments
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// This is a relatively idiomatic Rust implementation of filter with `u64`
+/// row ids.
+pub fn filter_materialise_values(values: &[u64], row_ids: &[u64], mut dst: Vec<u64>) -> Vec<u64> {
+    dst.clear();
+    dst.reserve(row_ids.len());
+
+    for &id in row_ids.iter() {
+        dst.push(values[id as usize]);
+    }
+
+    assert_eq!(dst.len(), row_ids.len());
+    dst
+}
+
+/// This is a relatively idiomatic Rust implementation of filter_sum with `u64`
+/// row ids.
+pub fn filter_sum(values: &[u64], row_ids: &[u64]) -> u64 {
+    let mut result = 0;
+    for &id in row_ids.iter() {
+        result += values[id as usize];
+    }
+    result
+}
+
+/// This is a relatively idiomatic Rust implementation of filter_max with `u64`
+/// row ids. It panics if `row_ids` is empty.
+pub fn filter_max(values: &[u64], row_ids: &[u64]) -> u64 {
+    row_ids.iter().map(|&id| values[id as usize]).max().unwrap()
+}
+
+/// This is an implementation of filter with `u64` row ids using SIMD
+/// intrinsics.
+///
+/// The row ids are checked with `row_ids::check_u64` before any values are
+/// gathered, and `RowIdOutOfBounds` is returned if any are out of range.
+pub fn filter_materialise_values_simd(
+    values: &[u64],
+    row_ids: &[u64],
+    dst: Vec<u64>,
+) -> Result<Vec<u64>, RowIdOutOfBounds> {
+    row_ids::check_u64(row_ids, values.len())?;
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_materialise_values_simd_unchecked(values, row_ids, dst) })
+}
+
+/// This is `filter_materialise_values_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `values.len()`.
+pub unsafe fn filter_materialise_values_simd_unchecked(
+    values: &[u64],
+    row_ids: &[u64],
+    mut dst: Vec<u64>,
+) -> Vec<u64> {
+    dst.clear();
+    dst.reserve(row_ids.len());
+
+    let base_ptr = values.as_ptr() as *const i64;
+
+    for chunk in row_ids.chunks_exact(4) {
+        let ids = _mm256_loadu_si256(chunk.as_ptr() as *const __m256i);
+        let mat_values = _mm256_i64gather_epi64(base_ptr, ids, 8);
+
+        _mm256_storeu_si256(dst.as_mut_ptr().add(dst.len()) as *mut __m256i, mat_values);
+        dst.set_len(dst.len() + 4);
+    }
+
+    // materialise any remainder - maximum of three values.
+    let rem = row_ids.len() - (row_ids.len() % 4);
+    for &id in row_ids.iter().skip(rem) {
+        dst.push(values[id as usize]);
+    }
+    assert_eq!(dst.len(), row_ids.len());
+    dst
+}
+
+/// This is an implementation of filter_sum with `u64` row ids using SIMD
+/// intrinsics.
+///
+/// The row ids are checked with `row_ids::check_u64` before any values are
+/// gathered, and `RowIdOutOfBounds` is returned if any are out of range.
+pub fn filter_sum_simd(values: &[u64], row_ids: &[u64]) -> Result<u64, RowIdOutOfBounds> {
+    row_ids::check_u64(row_ids, values.len())?;
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_sum_simd_unchecked(values, row_ids) })
+}
+
+/// This is `filter_sum_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `values.len()`.
+pub unsafe fn filter_sum_simd_unchecked(values: &[u64], row_ids: &[u64]) -> u64 {
+    let base_ptr = values.as_ptr() as *const i64;
+    let mut sum_lanes = _mm256_setzero_si256(); // u64x4
+
+    for chunk in row_ids.chunks_exact(4) {
+        let ids = _mm256_loadu_si256(chunk.as_ptr() as *const __m256i);
+        let row_values = _mm256_i64gather_epi64(base_ptr, ids, 8);
+        sum_lanes = _mm256_add_epi64(sum_lanes, row_values);
+    }
+
+    // sum any remainder - maximum of three values.
+    let rem = row_ids.len() - (row_ids.len() % 4);
+    let rem_sum = row_ids
+        .iter()
+        .skip(rem)
+        .map(|&id| values[id as usize])
+        .sum::<u64>();
+
+    let result: [u64; 4] = std::mem::transmute(sum_lanes);
+    result[0] + result[1] + result[2] + result[3] + rem_sum
+}
+
+/// This is an implementation of filter_max with `u64` row ids using SIMD
+/// intrinsics. Like `filter_max::filter_max_simd` it flips the high bit of
+/// each value to compare them as signed integers.
+///
+/// The row ids are checked with `row_ids::check_u64` before any values are
+/// gathered, and `RowIdOutOfBounds` is returned if any are out of range. It
+/// panics if `row_ids` is empty.
+pub fn filter_max_simd(values: &[u64], row_ids: &[u64]) -> Result<u64, RowIdOutOfBounds> {
+    row_ids::check_u64(row_ids, values.len())?;
+
+    // SAFETY: every row id was checked above.
+    Ok(unsafe { filter_max_simd_unchecked(values, row_ids) })
+}
+
+/// This is `filter_max_simd` without the bounds check.
+///
+/// # Safety
+///
+/// Every row id must be less than `values.len()`.
+pub unsafe fn filter_max_simd_unchecked(values: &[u64], row_ids: &[u64]) -> u64 {
+    if row_ids.len() < 4 {
+        return filter_max(values, row_ids);
+    }
+
+    let base_ptr = values.as_ptr() as *const i64;
+    let sign_bit = _mm256_set1_epi64x(i64::MIN);
+
+    let mut max_lanes = _mm256_xor_si256(
+        _mm256_i64gather_epi64(
+            base_ptr,
+            _mm256_loadu_si256(row_ids.as_ptr() as *const __m256i),
+            8,
+        ),
+        sign_bit,
+    );
+
+    for chunk in row_ids.chunks_exact(4).skip(1) {
+        let ids = _mm256_loadu_si256(chunk.as_ptr() as *const __m256i);
+        let row_values = _mm256_xor_si256(_mm256_i64gather_epi64(base_ptr, ids, 8), sign_bit);
+
+        let max_mask = _mm256_cmpgt_epi64(row_values, max_lanes);
+        max_lanes = _mm256_blendv_epi8(max_lanes, row_values, max_mask);
+    }
+
+    let result: [u64; 4] = std::mem::transmute(_mm256_xor_si256(max_lanes, sign_bit));
+
+    // find the max in any remainder - at most three values.
+    let rem = row_ids.len() - (row_ids.len() % 4);
+    result
+        .iter()
+        .chain(row_ids.iter().skip(rem).map(|&id| &values[id as usize]))
+        .copied()
+        .max()
+        .unwrap()
+}
+
+// Call `f` with each segment's slice of `values` and its row ids. A segment's
+// `RowIdOutOfBounds` is mapped back onto the whole column, so its `row_id` is
+// the largest row id in the first segment with one out of range.
+fn for_each_segment(
+    values: &[u64],
+    row_ids: &SegmentedRowIds,
+    mut f: impl FnMut(&[u64], &[u32]) -> Result<(), RowIdOutOfBounds>,
+) -> Result<(), RowIdOutOfBounds> {
+    for (base, ids) in &row_ids.segments {
+        let start = (*base as usize).min(values.len());
+        let end = (start + row_ids.segment_rows).min(values.len());
+        f(&values[start..end], ids).map_err(|e| RowIdOutOfBounds {
+            row_id: base + e.row_id,
+            rows: values.len(),
+        })?;
+    }
+    Ok(())
+}
+
+/// This is an implementation of filter with segmented row ids, which runs
+/// `filter::filter_materialise_values_simd` on each segment.
+pub fn filter_materialise_values_segmented(
+    values: &[u64],
+    row_ids: &SegmentedRowIds,
+    mut dst: Vec<u64>,
+) -> Result<Vec<u64>, RowIdOutOfBounds> {
+    dst.clear();
+    dst.reserve(row_ids.len());
+
+    let mut segment_dst = vec![];
+    for_each_segment(values, row_ids, |values, ids| {
+        segment_dst =
+            filter::filter_materialise_values_simd(values, ids, std::mem::take(&mut segment_dst))?;
+        dst.extend_from_slice(&segment_dst);
+        Ok(())
+    })?;
+
+    assert_eq!(dst.len(), row_ids.len());
+    Ok(dst)
+}
+
+/// This is an implementation of filter_sum with segmented row ids, which runs
+/// `filter_sum::filter_sum_simd` on each segment.
+pub fn filter_sum_segmented(
+    values: &[u64],
+    row_ids: &SegmentedRowIds,
+) -> Result<u64, RowIdOutOfBounds> {
+    let mut result = 0;
+    for_each_segment(values, row_ids, |values, ids| {
+        result += filter_sum::filter_sum_simd(values, ids)?;
+        Ok(())
+    })?;
+    Ok(result)
+}
+
+/// This is an implementation of filter_max with segmented row ids, which runs
+/// `filter_max::filter_max_simd` on each segment. It panics if `row_ids` is
+/// empty.
+pub fn filter_max_segmented(
+    values: &[u64],
+    row_ids: &SegmentedRowIds,
+) -> Result<u64, RowIdOutOfBounds> {
+    let mut result = None;
+    for_each_segment(values, row_ids, |values, ids| {
+        result = result.max(Some(filter_max::filter_max_simd(values, ids)?));
+        Ok(())
+    })?;
+    Ok(result.unwrap())
+}
+
+mod test {
+
+    #[test]
+    fn segmented_row_ids() {
+        let row_ids =
+            super::SegmentedRowIds::with_segment_rows(&[1, 2, 9, 20, 3, 5_000_000_000], 8);
+        assert_eq!(row_ids.len(), 6);
+        assert_eq!(
+            row_ids.segments(),
+            &[
+                (0, vec![1, 2]),
+                (8, vec![1]),
+                (16, vec![4]),
+                (0, vec![3]),
+                (5_000_000_000, vec![0])
+            ]
+        );
+
+        let row_ids = super::SegmentedRowIds::new(&[1, 2, 9, 20, 3, 5_000_000_000]);
+        assert_eq!(
+            row_ids.segments(),
+            &[
+                (0, vec![1, 2, 9, 20, 3]),
+                (4_294_967_296, vec![705_032_704])
+            ]
+        );
+        assert!(super::SegmentedRowIds::new(&[]).is_empty());
+    }
+
+    #[test]
+    fn filter_simd() {
+        let values = (100..1234).collect::<Vec<u64>>();
+        let cases = vec![
+            vec![0_u64, 1, 2, 3],
+            vec![0, 12],
+            vec![1133, 0, 7, 7, 500, 3, 1],
+            (2..653).collect::<Vec<_>>(),
+        ];
+
+        for row_ids in &cases {
+            let exp = row_ids.iter().map(|&id| id + 100).collect::<Vec<_>>();
+            let sum = exp.iter().sum::<u64>();
+            let max = *exp.iter().max().unwrap();
+
+            assert_eq!(
+                super::filter_materialise_values(&values, row_ids, vec![]),
+                exp
+            );
+            assert_eq!(super::filter_sum(&values, row_ids), sum);
+            assert_eq!(super::filter_max(&values, row_ids), max);
+
+            assert_eq!(
+                super::filter_materialise_values_simd(&values, row_ids, vec![]).unwrap(),
+                exp
+            );
+            assert_eq!(super::filter_sum_simd(&values, row_ids).unwrap(), sum);
+            assert_eq!(super::filter_max_simd(&values, row_ids).unwrap(), max);
+
+            for &segment_rows in &[1, 7, 64, super::SEGMENT_ROWS] {
+                let row_ids = super::SegmentedRowIds::with_segment_rows(row_ids, segment_rows);
+                assert_eq!(
+                    super::filter_materialise_values_segmented(&values, &row_ids, vec![]).unwrap(),
+                    exp
+                );
+                assert_eq!(super::filter_sum_segmented(&values, &row_ids).unwrap(), sum);
+                assert_eq!(super::filter_max_segmented(&values, &row_ids).unwrap(), max);
+            }
+        }
+    }
+
+    #[test]
+    fn filter_max_simd_high_bit() {
+        let values = vec![1, u64::MAX - 1, 1 << 63, 7, 3];
+        assert_eq!(
+            super::filter_max_simd(&values, &[0, 2, 1, 3, 4]).unwrap(),
+            u64::MAX - 1
+        );
+    }
+
+    #[test]
+    fn out_of_bounds() {
+        let values = (100..110).collect::<Vec<u64>>();
+        let row_ids = vec![0, 1, 2, 3, 4, u32::MAX as u64 + 10];
+        let exp = crate::row_ids::RowIdOutOfBounds {
+            row_id: u32::MAX as u64 + 10,
+            rows: 10,
+        };
+
+        assert_eq!(
+            super::filter_materialise_values_simd(&values, &row_ids, vec![]),
+            Err(exp)
+        );
+        assert_eq!(super::filter_sum_simd(&values, &row_ids), Err(exp));
+        assert_eq!(super::filter_max_simd(&values, &row_ids), Err(exp));
+
+        for &segment_rows in &[4, super::SEGMENT_ROWS] {
+            let row_ids = super::SegmentedRowIds::with_segment_rows(&row_ids, segment_rows);
+            assert_eq!(
+                super::filter_materialise_values_segmented(&values, &row_ids, vec![]),
+                Err(exp)
+            );
+            assert_eq!(super::filter_sum_segmented(&values, &row_ids), Err(exp));
+            assert_eq!(super::filter_max_segmented(&values, &row_ids), Err(exp));
+        }
+
+        // the last row of a segment that's only partly in the column.
+        let row_ids = super::SegmentedRowIds::with_segment_rows(&[9, 10], 8);
+        assert_eq!(
+            super::filter_sum_segmented(&values, &row_ids),
+            Err(crate::row_ids::RowIdOutOfBounds {
+                row_id: 10,
+                rows: 10
+            })
+        );
+    }
+}
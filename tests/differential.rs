@@ -0,0 +1,1030 @@
+//! Differential property tests across the implementations of each operation.
+//!
+//! Every implementation of an operation is run on the same randomly generated
+//! column and row ids, and must agree with the idiomatic implementation. Row
+//! ids are generated in every shape the functions accept: empty, unsorted
+//! with duplicates, sorted with duplicates, and sorted without duplicates
+//! (which is all a `BooleanArray` filter can express). Functions documented
+//! as needing sorted row ids are only given sorted row ids.
+//!
+//! When a case fails, proptest shrinks it to a minimal column and set of row
+//! ids before reporting it.
+use std::sync::Arc;
+
+use arrow::{
+    array,
+    datatypes::{DataType, Field, Schema},
+    record_batch::RecordBatch,
+};
+use proptest::{collection, prelude::*};
+
+use rust_arrow_benches::{
+    bit_packed::{self, BitPackedColumn},
+    chunked::{self, ChunkedColumn},
+    delta::{self, DeltaColumn},
+    filter,
+    filter_aggregates::{self, Aggregate},
+    filter_batch,
+    filter_columns::{self, Column, ColumnBuffer},
+    filter_decimal::{self, Decimal128Column},
+    filter_dictionary,
+    filter_distinct::{self, HyperLogLog},
+    filter_float::{self, NanMode},
+    filter_max, filter_moments, filter_narrow,
+    filter_quantile::{self, KllSketch},
+    filter_selector, filter_signed, filter_sum,
+    filter_time_bucket::{self, TimeBuckets},
+    filter_var_len,
+    frame_of_reference::{self, FrameOfReferenceColumn},
+    row_ids,
+    run_length::{self, RunLengthColumn},
+    wide_row_ids::{self, SegmentedRowIds},
+};
+
+// Enough rows for several bit-packed blocks and delta checkpoint intervals.
+const MAX_ROWS: usize = 700;
+
+// Sums of values below this can't overflow a `u64` with up to `2 * MAX_ROWS`
+// row ids.
+const MAX_SUM_VALUE: u64 = 1 << 50;
+
+// A `u64` value, biased towards the edges of the range: small values, and
+// values with the high bit set.
+fn value() -> impl Strategy<Value = u64> {
+    prop_oneof![
+        0..100_u64,
+        any::<u64>(),
+        (u64::MAX - 100)..=u64::MAX,
+        (1_u64 << 63)..(1_u64 << 63) + 100,
+    ]
+}
+
+fn values() -> impl Strategy<Value = Vec<u64>> {
+    collection::vec(value(), 1..MAX_ROWS)
+}
+
+// An `i64` value, biased towards the edges of the range and zero.
+fn signed_value() -> impl Strategy<Value = i64> {
+    prop_oneof![
+        -100..100_i64,
+        any::<i64>(),
+        i64::MIN..i64::MIN + 100,
+        i64::MAX - 100..=i64::MAX,
+    ]
+}
+
+// Unscaled decimal values with up to 38 digits, including values whose sums
+// overflow the precision.
+fn decimal_value() -> impl Strategy<Value = i128> {
+    let max = 10_i128.pow(38) - 1;
+    prop_oneof![
+        -100..100_i128,
+        -max..=max,
+        (i64::MIN as i128)..=(u64::MAX as i128),
+        max - 100..=max,
+        -max..=-max + 100,
+    ]
+}
+
+// Timestamps and bucket origins within 2^62 of zero, so every timestamp is
+// within 2^63 of the origin.
+fn timestamp() -> impl Strategy<Value = i64> {
+    prop_oneof![
+        -1_000_000..1_000_000_i64,
+        -(1_i64 << 62)..1 << 62,
+        1_600_000_000_000_000_000..1_700_000_000_000_000_000_i64,
+    ]
+}
+
+// Bucket widths either side of `filter_time_bucket::SIMD_MIN_WIDTH`.
+fn bucket_width() -> impl Strategy<Value = u64> {
+    prop_oneof![
+        1..100_u64,
+        Just(filter_time_bucket::SIMD_MIN_WIDTH - 1),
+        Just(filter_time_bucket::SIMD_MIN_WIDTH),
+        Just(60_000_000_000),
+        1..=i64::MAX as u64,
+    ]
+}
+
+// Values whose sum over any row ids fits in a `u64`.
+fn sum_values() -> impl Strategy<Value = Vec<u64>> {
+    collection::vec(
+        prop_oneof![
+            0..100_u64,
+            0..MAX_SUM_VALUE,
+            (MAX_SUM_VALUE - 100)..MAX_SUM_VALUE
+        ],
+        1..MAX_ROWS,
+    )
+}
+
+// Values in runs of up to 20 rows.
+fn runs(value: impl Strategy<Value = u64>) -> impl Strategy<Value = Vec<u64>> {
+    collection::vec((value, 1..20_usize), 1..40)
+        .prop_map(|runs| runs.into_iter().flat_map(|(v, n)| vec![v; n]).collect())
+}
+
+// Row ids into a column of `len` rows, in any order and with duplicates,
+// sorted with duplicates, or sorted without duplicates.
+fn row_ids(len: usize) -> impl Strategy<Value = Vec<u32>> {
+    let id = 0..len as u32;
+    prop_oneof![
+        collection::vec(id.clone(), 0..=2 * len),
+        collection::vec(id.clone(), 0..=2 * len).prop_map(|mut ids| {
+            ids.sort_unstable();
+            ids
+        }),
+        collection::btree_set(id, 0..=len).prop_map(|ids| ids.into_iter().collect()),
+    ]
+}
+
+// A column and some row ids into it.
+fn with_row_ids<T: Clone + std::fmt::Debug>(
+    values: impl Strategy<Value = Vec<T>>,
+) -> impl Strategy<Value = (Vec<T>, Vec<u32>)> {
+    values.prop_flat_map(|values| {
+        let len = values.len();
+        (Just(values), row_ids(len))
+    })
+}
+
+fn sorted(row_ids: &[u32]) -> Vec<u32> {
+    let mut row_ids = row_ids.to_vec();
+    row_ids.sort_unstable();
+    row_ids
+}
+
+fn sorted_unique(row_ids: &[u32]) -> Vec<u32> {
+    let mut row_ids = sorted(row_ids);
+    row_ids.dedup();
+    row_ids
+}
+
+// The `BooleanArray` selecting `row_ids` (which must be sorted and unique).
+fn boolean_filter(len: usize, row_ids: &[u32]) -> array::BooleanArray {
+    let mut filter = vec![false; len];
+    for &id in row_ids {
+        filter[id as usize] = true;
+    }
+    array::BooleanArray::from(filter)
+}
+
+fn u64_array(array: &array::ArrayRef) -> Vec<u64> {
+    let array = array.as_any().downcast_ref::<array::UInt64Array>().unwrap();
+    (0..array.len()).map(|i| array.value(i)).collect()
+}
+
+// Whether two float aggregates match: both NaN, or equal to within a relative
+// error bound for sums that were added up in a different order.
+fn float_eq(got: f64, exp: f64, tolerance: f64) -> bool {
+    got == exp || (got.is_nan() && exp.is_nan()) || (got - exp).abs() <= tolerance
+}
+
+// The bound on the error of summing `values` in any order.
+fn sum_tolerance(values: impl Iterator<Item = f64>, epsilon: f64) -> f64 {
+    let (abs_sum, n) = values.fold((0.0, 0), |(sum, n), v| (sum + v.abs(), n + 1));
+    abs_sum * epsilon * (n as f64 + 1.0)
+}
+
+proptest! {
+    #[test]
+    fn materialise((values, row_ids) in with_row_ids(values())) {
+        let exp = filter::filter_materialise_values(&values, &row_ids, vec![]);
+        prop_assert_eq!(
+            filter::filter_materialise_values_simd(&values, &row_ids, vec![]).unwrap(),
+            exp
+        );
+
+        let row_ids = sorted_unique(&row_ids);
+        let exp = filter::filter_materialise_values(&values, &row_ids, vec![]);
+        let got = filter::filter_materialise_values_arrow(
+            &array::UInt64Array::from(values.clone()),
+            &boolean_filter(values.len(), &row_ids),
+        );
+        prop_assert_eq!(u64_array(&got), exp);
+    }
+
+    #[test]
+    fn sum((values, row_ids) in with_row_ids(sum_values())) {
+        let exp = filter_sum::filter_sum(&values, &row_ids);
+        prop_assert_eq!(filter_sum::filter_sum_simd(&values, &row_ids).unwrap(), exp);
+
+        let row_ids = sorted_unique(&row_ids);
+        let exp = filter_sum::filter_sum(&values, &row_ids);
+        let got = filter_sum::filter_sum_arrow(
+            &array::UInt64Array::from(values.clone()),
+            &boolean_filter(values.len(), &row_ids),
+        );
+        prop_assert_eq!(got, exp);
+    }
+
+    #[test]
+    fn max((values, row_ids) in with_row_ids(values())) {
+        if row_ids.is_empty() {
+            return Ok(());
+        }
+
+        let exp = filter_max::filter_max(&values, &row_ids);
+        prop_assert_eq!(filter_max::filter_max_simd(&values, &row_ids).unwrap(), exp);
+
+        let row_ids = sorted_unique(&row_ids);
+        let got = filter_max::filter_max_arrow(
+            &array::UInt64Array::from(values.clone()),
+            &boolean_filter(values.len(), &row_ids),
+        );
+        prop_assert_eq!(got, exp);
+    }
+
+    #[test]
+    fn out_of_bounds(
+        (values, row_ids) in with_row_ids(values()),
+        bad_id in any::<u32>(),
+        at in any::<prop::sample::Index>(),
+    ) {
+        // put a row id that isn't in the column anywhere in the row ids.
+        let bad_id = bad_id.max(values.len() as u32);
+        let mut row_ids = row_ids;
+        row_ids.insert(at.index(row_ids.len() + 1), bad_id);
+
+        let exp = row_ids::RowIdOutOfBounds {
+            row_id: *row_ids.iter().max().unwrap() as u64,
+            rows: values.len(),
+        };
+        prop_assert_eq!(row_ids::check(&row_ids, values.len()), Err(exp));
+        prop_assert_eq!(
+            filter::filter_materialise_values_simd(&values, &row_ids, vec![]),
+            Err(exp)
+        );
+        prop_assert_eq!(filter_sum::filter_sum_simd(&values, &row_ids), Err(exp));
+        prop_assert_eq!(filter_max::filter_max_simd(&values, &row_ids), Err(exp));
+        prop_assert_eq!(filter_selector::filter_arg_max_simd(&values, &row_ids), Err(exp));
+        prop_assert_eq!(filter_selector::filter_arg_min_simd(&values, &row_ids), Err(exp));
+
+        let floats = values.iter().map(|&v| v as f64).collect::<Vec<_>>();
+        prop_assert_eq!(filter_float::filter_sum_f64_simd(&floats, &row_ids), Err(exp));
+        prop_assert_eq!(
+            filter_float::filter_max_f64_simd(&floats, &row_ids, NanMode::Ignore),
+            Err(exp)
+        );
+        let floats = values.iter().map(|&v| v as f32).collect::<Vec<_>>();
+        prop_assert_eq!(filter_float::filter_sum_f32_kahan_simd(&floats, &row_ids), Err(exp));
+        prop_assert_eq!(
+            filter_float::filter_min_f32_simd(&floats, &row_ids, NanMode::Propagate),
+            Err(exp)
+        );
+
+        let keys = vec![0_u16; values.len()];
+        prop_assert_eq!(
+            filter_dictionary::filter_materialise_keys_simd(&keys, &row_ids, vec![]),
+            Err(exp)
+        );
+        prop_assert_eq!(
+            filter_dictionary::filter_materialise_decoded_simd(&keys, &["a"], &row_ids, vec![]),
+            Err(exp)
+        );
+        prop_assert_eq!(
+            filter_dictionary::filter_materialise_decoded_u64_simd(&keys, &[1], &row_ids, vec![]),
+            Err(exp)
+        );
+
+        let narrow = values.iter().map(|&v| v as u8).collect::<Vec<_>>();
+        prop_assert_eq!(
+            filter_narrow::filter_materialise_values_simd(&narrow, &row_ids, vec![]),
+            Err(exp)
+        );
+        prop_assert_eq!(filter_narrow::filter_sum_simd(&narrow, &row_ids), Err(exp));
+        prop_assert_eq!(filter_narrow::filter_max_simd(&narrow, &row_ids), Err(exp));
+
+        let offsets = (0..=values.len() as i32).collect::<Vec<_>>();
+        let bytes = vec![0_u8; values.len()];
+        prop_assert_eq!(
+            filter_var_len::filter_materialise_var_len_simd(&offsets, &bytes, &row_ids, vec![], vec![]),
+            Err(exp)
+        );
+
+        let col = BitPackedColumn::from_values(&values);
+        prop_assert_eq!(
+            bit_packed::filter_materialise_values_simd(&col, &row_ids, vec![]),
+            Err(exp)
+        );
+        prop_assert_eq!(bit_packed::filter_sum_simd(&col, &row_ids), Err(exp));
+        prop_assert_eq!(bit_packed::filter_max_gather_simd(&col, &row_ids), Err(exp));
+        prop_assert_eq!(bit_packed::filter_max_block_simd(&col, &row_ids), Err(exp));
+
+        let col = FrameOfReferenceColumn::new(&values);
+        prop_assert_eq!(
+            frame_of_reference::filter_materialise_values_simd(&col, &row_ids, vec![]),
+            Err(exp)
+        );
+        prop_assert_eq!(frame_of_reference::filter_sum_simd(&col, &row_ids), Err(exp));
+        prop_assert_eq!(frame_of_reference::filter_max_simd(&col, &row_ids), Err(exp));
+        let col = DeltaColumn::new(&values);
+        prop_assert_eq!(delta::filter_materialise_values_simd(&col, &row_ids, vec![]), Err(exp));
+
+        let col = RunLengthColumn::new(&values);
+        prop_assert_eq!(
+            run_length::filter_materialise_values_simd(&col, &row_ids, vec![]),
+            Err(exp)
+        );
+        prop_assert_eq!(run_length::filter_sum_runs(&col, &row_ids), Err(exp));
+        prop_assert_eq!(run_length::filter_max_simd(&col, &row_ids), Err(exp));
+
+        let col = ChunkedColumn::from_values(&values, 7);
+        prop_assert_eq!(
+            chunked::filter_materialise_values_simd(&col, &row_ids, vec![]),
+            Err(exp)
+        );
+        prop_assert_eq!(chunked::filter_sum_split(&col, &row_ids), Err(exp));
+        prop_assert_eq!(chunked::filter_max_simd(&col, &row_ids), Err(exp));
+    }
+
+    #[test]
+    fn signed_i64((values, row_ids) in with_row_ids(collection::vec(signed_value(), 1..MAX_ROWS))) {
+        let exp = filter_signed::filter_sum_i64(&values, &row_ids);
+        let exact = row_ids.iter().map(|&id| values[id as usize] as i128).sum::<i128>();
+        prop_assert_eq!(exp, std::convert::TryFrom::try_from(exact).ok());
+        prop_assert_eq!(filter_signed::filter_sum_i64_simd(&values, &row_ids).unwrap(), exp);
+
+        if row_ids.is_empty() {
+            return Ok(());
+        }
+        let (min, max) = (
+            filter_signed::filter_min_i64(&values, &row_ids),
+            filter_signed::filter_max_i64(&values, &row_ids),
+        );
+        prop_assert_eq!(filter_signed::filter_min_i64_simd(&values, &row_ids).unwrap(), min);
+        prop_assert_eq!(filter_signed::filter_max_i64_simd(&values, &row_ids).unwrap(), max);
+
+        let row_ids = sorted_unique(&row_ids);
+        let arrow_values = array::Int64Array::from(values.clone());
+        let filter = boolean_filter(values.len(), &row_ids);
+        prop_assert_eq!(
+            filter_signed::filter_min_i64_arrow(&arrow_values, &filter),
+            filter_signed::filter_min_i64(&values, &row_ids)
+        );
+        prop_assert_eq!(
+            filter_signed::filter_max_i64_arrow(&arrow_values, &filter),
+            filter_signed::filter_max_i64(&values, &row_ids)
+        );
+    }
+
+    #[test]
+    fn signed_i32((values, row_ids) in with_row_ids(collection::vec(any::<i32>(), 1..MAX_ROWS))) {
+        let exp = filter_signed::filter_sum_i32(&values, &row_ids);
+        prop_assert_eq!(filter_signed::filter_sum_i32_simd(&values, &row_ids).unwrap(), exp);
+
+        if row_ids.is_empty() {
+            return Ok(());
+        }
+        let exp = filter_signed::filter_min_i32(&values, &row_ids);
+        prop_assert_eq!(filter_signed::filter_min_i32_simd(&values, &row_ids).unwrap(), exp);
+        let exp = filter_signed::filter_max_i32(&values, &row_ids);
+        prop_assert_eq!(filter_signed::filter_max_i32_simd(&values, &row_ids).unwrap(), exp);
+    }
+
+    #[test]
+    fn decimal((values, row_ids) in with_row_ids(collection::vec(decimal_value(), 1..MAX_ROWS))) {
+        let col = Decimal128Column::try_new(&values, 38, 4).unwrap();
+        let exp = filter_decimal::filter_sum(&col, &row_ids);
+        prop_assert_eq!(filter_decimal::filter_sum_simd(&col, &row_ids), exp);
+
+        if row_ids.is_empty() {
+            return Ok(());
+        }
+        let exp = filter_decimal::filter_min(&col, &row_ids);
+        prop_assert_eq!(filter_decimal::filter_min_simd(&col, &row_ids).unwrap(), exp);
+        let exp = filter_decimal::filter_max(&col, &row_ids);
+        prop_assert_eq!(filter_decimal::filter_max_simd(&col, &row_ids).unwrap(), exp);
+    }
+
+    #[test]
+    fn time_bucket(
+        (timestamps, row_ids) in with_row_ids(collection::vec(timestamp(), 1..MAX_ROWS)),
+        origin in timestamp(),
+        width in bucket_width(),
+    ) {
+        let values = timestamps.iter().map(|&ts| ts as u64 % MAX_SUM_VALUE).collect::<Vec<_>>();
+        let buckets = TimeBuckets::new(origin, width);
+
+        let exp = filter_time_bucket::filter_bucket_indexes(&timestamps, &row_ids, buckets, vec![]);
+        prop_assert_eq!(
+            filter_time_bucket::filter_bucket_indexes_simd(&timestamps, &row_ids, buckets, vec![])
+                .unwrap(),
+            exp
+        );
+
+        let exp = filter_time_bucket::filter_aggregate_buckets(&timestamps, &values, &row_ids, buckets);
+        prop_assert_eq!(
+            filter_time_bucket::filter_aggregate_buckets_simd(&timestamps, &values, &row_ids, buckets)
+                .unwrap(),
+            exp
+        );
+    }
+
+    #[test]
+    fn distinct((values, row_ids) in with_row_ids(collection::vec(prop_oneof![0..20_u64, any::<u64>()], 1..MAX_ROWS))) {
+        let exp = filter_distinct::filter_count_distinct(&values, &row_ids);
+        prop_assert_eq!(filter_distinct::filter_count_distinct_simd(&values, &row_ids).unwrap(), exp);
+
+        let mut exp = HyperLogLog::new(8);
+        filter_distinct::filter_hyperloglog(&values, &row_ids, &mut exp);
+        let mut hll = HyperLogLog::new(8);
+        filter_distinct::filter_hyperloglog_simd(&values, &row_ids, &mut hll).unwrap();
+        prop_assert_eq!(hll, exp);
+    }
+
+    #[test]
+    fn quantiles(
+        (values, row_ids) in with_row_ids(collection::vec(any::<u64>(), 1..MAX_ROWS)),
+        quantiles in collection::vec(0.0..=1.0_f64, 1..5),
+    ) {
+        let mut sketch = KllSketch::new(8);
+        filter_quantile::filter_kll(&values, &row_ids, &mut sketch);
+        let mut got = KllSketch::new(8);
+        filter_quantile::filter_kll_simd(&values, &row_ids, &mut got).unwrap();
+        prop_assert_eq!(got, sketch);
+
+        if row_ids.is_empty() {
+            return Ok(());
+        }
+        let (exp, _) = filter_quantile::filter_quantiles_sort(&values, &row_ids, &quantiles, vec![]);
+        let (got, _) =
+            filter_quantile::filter_quantiles_select(&values, &row_ids, &quantiles, vec![]).unwrap();
+        prop_assert_eq!(got, exp);
+    }
+
+    #[test]
+    fn moments((values, row_ids) in with_row_ids(collection::vec(prop_oneof![0..=u32::MAX as u64, 0..1_u64 << 56], 1..MAX_ROWS))) {
+        let exp = filter_moments::filter_moments(&values, &row_ids);
+        prop_assert_eq!(filter_moments::filter_moments_simd(&values, &row_ids).unwrap(), exp);
+        prop_assert_eq!(filter_moments::filter_sum_squares(&values, &row_ids), exp.map(|m| m.sum_squares));
+        prop_assert_eq!(filter_moments::filter_sum_squares_simd(&values, &row_ids).unwrap(), exp.map(|m| m.sum_squares));
+    }
+
+    #[test]
+    fn aggregates(
+        (values, row_ids) in with_row_ids(sum_values()),
+        aggregates in collection::vec(
+            prop_oneof![Just(Aggregate::Count), Just(Aggregate::Sum), Just(Aggregate::Min), Just(Aggregate::Max)],
+            0..6,
+        ),
+    ) {
+        let exp = filter_aggregates::filter_aggregates(&values, &row_ids, &aggregates);
+        prop_assert_eq!(
+            filter_aggregates::filter_aggregates_simd(&values, &row_ids, &aggregates).unwrap(),
+            exp.clone()
+        );
+
+        // each aggregate agrees with its single-aggregate kernel.
+        for (aggregate, got) in aggregates.iter().zip(exp) {
+            let single = match aggregate {
+                Aggregate::Count => Some(row_ids.len() as u64),
+                Aggregate::Sum => Some(filter_sum::filter_sum_simd(&values, &row_ids).unwrap()),
+                Aggregate::Min => row_ids.iter().map(|&id| values[id as usize]).min(),
+                Aggregate::Max if row_ids.is_empty() => None,
+                Aggregate::Max => Some(filter_max::filter_max_simd(&values, &row_ids).unwrap()),
+            };
+            prop_assert_eq!(got, single);
+        }
+    }
+
+    #[test]
+    fn columns(
+        (values, row_ids) in with_row_ids(collection::vec(any::<u64>(), 1..MAX_ROWS)),
+        widths in collection::vec(0..4_usize, 0..8),
+    ) {
+        let u8s = values.iter().map(|&v| v as u8).collect::<Vec<_>>();
+        let u16s = values.iter().map(|&v| v as u16).collect::<Vec<_>>();
+        let u32s = values.iter().map(|&v| v as u32).collect::<Vec<_>>();
+        let columns = widths
+            .iter()
+            .map(|width| match width {
+                0 => Column::U8(&u8s),
+                1 => Column::U16(&u16s),
+                2 => Column::U32(&u32s),
+                _ => Column::U64(&values),
+            })
+            .collect::<Vec<_>>();
+
+        // each column agrees with its single-column kernel.
+        let exp = columns
+            .iter()
+            .map(|column| match column {
+                Column::U8(v) => ColumnBuffer::U8(filter_narrow::filter_materialise_values(v, &row_ids, vec![])),
+                Column::U16(v) => ColumnBuffer::U16(filter_narrow::filter_materialise_values(v, &row_ids, vec![])),
+                Column::U32(v) => ColumnBuffer::U32(filter_narrow::filter_materialise_values(v, &row_ids, vec![])),
+                Column::U64(v) => ColumnBuffer::U64(filter::filter_materialise_values(v, &row_ids, vec![])),
+            })
+            .collect::<Vec<_>>();
+        prop_assert_eq!(filter_columns::filter_materialise_columns(&columns, &row_ids, vec![]), exp.clone());
+        prop_assert_eq!(filter_columns::filter_materialise_columns_simd(&columns, &row_ids, vec![]).unwrap(), exp);
+    }
+
+    #[test]
+    fn narrow((values, row_ids) in with_row_ids(collection::vec(any::<u32>(), 1..MAX_ROWS))) {
+        let wide = values.iter().map(|&v| v as u64).collect::<Vec<_>>();
+        let exp_values = filter::filter_materialise_values(&wide, &row_ids, vec![]);
+        let exp_sum = filter_sum::filter_sum(&wide, &row_ids);
+        prop_assert_eq!(
+            filter_narrow::filter_materialise_values_simd(&values, &row_ids, vec![])
+                .unwrap()
+                .into_iter()
+                .map(|v| v as u64)
+                .collect::<Vec<_>>(),
+            exp_values
+        );
+        prop_assert_eq!(filter_narrow::filter_sum_simd(&values, &row_ids).unwrap(), exp_sum);
+
+        let values_u8 = values.iter().map(|&v| v as u8).collect::<Vec<_>>();
+        let values_u16 = values.iter().map(|&v| v as u16).collect::<Vec<_>>();
+        prop_assert_eq!(
+            filter_narrow::filter_sum_simd(&values_u8, &row_ids).unwrap(),
+            filter_narrow::filter_sum(&values_u8, &row_ids)
+        );
+        prop_assert_eq!(
+            filter_narrow::filter_sum_simd(&values_u16, &row_ids).unwrap(),
+            filter_narrow::filter_sum(&values_u16, &row_ids)
+        );
+
+        if row_ids.is_empty() {
+            return Ok(());
+        }
+        prop_assert_eq!(
+            filter_narrow::filter_max_simd(&values, &row_ids).unwrap() as u64,
+            filter_max::filter_max(&wide, &row_ids)
+        );
+        prop_assert_eq!(
+            filter_narrow::filter_max_simd(&values_u8, &row_ids).unwrap(),
+            filter_narrow::filter_max(&values_u8, &row_ids)
+        );
+        prop_assert_eq!(
+            filter_narrow::filter_max_simd(&values_u16, &row_ids).unwrap(),
+            filter_narrow::filter_max(&values_u16, &row_ids)
+        );
+    }
+
+    #[test]
+    fn selectors((values, row_ids) in with_row_ids(values())) {
+        if row_ids.is_empty() {
+            return Ok(());
+        }
+
+        let exp = filter_selector::filter_arg_max(&values, &row_ids);
+        prop_assert_eq!(filter_selector::filter_arg_max_simd(&values, &row_ids).unwrap(), exp);
+        let exp = filter_selector::filter_arg_min(&values, &row_ids);
+        prop_assert_eq!(filter_selector::filter_arg_min_simd(&values, &row_ids).unwrap(), exp);
+        let exp = filter_selector::filter_first(&values, &row_ids);
+        prop_assert_eq!(filter_selector::filter_first_simd(&values, &row_ids), exp);
+        let exp = filter_selector::filter_last(&values, &row_ids);
+        prop_assert_eq!(filter_selector::filter_last_simd(&values, &row_ids), exp);
+
+        let row_ids = sorted_unique(&row_ids);
+        let arrow_values = array::UInt64Array::from(values.clone());
+        let row_id_arr = array::UInt32Array::from((0..values.len() as u32).collect::<Vec<_>>());
+        let filter = boolean_filter(values.len(), &row_ids);
+        prop_assert_eq!(
+            filter_selector::filter_arg_max_arrow(&arrow_values, &row_id_arr, &filter),
+            filter_selector::filter_arg_max(&values, &row_ids)
+        );
+        prop_assert_eq!(
+            filter_selector::filter_arg_min_arrow(&arrow_values, &row_id_arr, &filter),
+            filter_selector::filter_arg_min(&values, &row_ids)
+        );
+        prop_assert_eq!(
+            filter_selector::filter_first_arrow(&arrow_values, &row_id_arr, &filter),
+            filter_selector::filter_first(&values, &row_ids)
+        );
+        prop_assert_eq!(
+            filter_selector::filter_last_arrow(&arrow_values, &row_id_arr, &filter),
+            filter_selector::filter_last(&values, &row_ids)
+        );
+    }
+
+    #[test]
+    fn float_f64(
+        (values, row_ids) in with_row_ids(collection::vec(
+            prop_oneof![
+                10 => -1e6..1e6_f64,
+                1 => Just(f64::NAN),
+                1 => Just(f64::INFINITY),
+                1 => Just(f64::NEG_INFINITY),
+            ],
+            1..MAX_ROWS,
+        ))
+    ) {
+        let selected = row_ids.iter().map(|&id| values[id as usize]).collect::<Vec<_>>();
+        let tolerance = sum_tolerance(selected.iter().copied(), f64::EPSILON);
+
+        let exp = filter_float::filter_sum_f64(&values, &row_ids);
+        for &got in &[
+            filter_float::filter_sum_f64_kahan(&values, &row_ids),
+            filter_float::filter_sum_f64_simd(&values, &row_ids).unwrap(),
+            filter_float::filter_sum_f64_kahan_simd(&values, &row_ids).unwrap(),
+        ] {
+            prop_assert!(float_eq(got, exp, tolerance), "{} != {}", got, exp);
+        }
+
+        for &nan in &[NanMode::Propagate, NanMode::Ignore] {
+            let (max, min) = (
+                filter_float::filter_max_f64(&values, &row_ids, nan),
+                filter_float::filter_min_f64(&values, &row_ids, nan),
+            );
+            let got = filter_float::filter_max_f64_simd(&values, &row_ids, nan).unwrap();
+            prop_assert!(float_eq(got, max, 0.0), "{:?}: {} != {}", nan, got, max);
+            let got = filter_float::filter_min_f64_simd(&values, &row_ids, nan).unwrap();
+            prop_assert!(float_eq(got, min, 0.0), "{:?}: {} != {}", nan, got, min);
+        }
+
+        // Arrow's min and max only agree when there are no NaNs.
+        let row_ids = sorted_unique(&row_ids);
+        if row_ids.is_empty() || row_ids.iter().any(|&id| values[id as usize].is_nan()) {
+            return Ok(());
+        }
+        let arrow_values = array::Float64Array::from(values.clone());
+        let filter = boolean_filter(values.len(), &row_ids);
+        let got = filter_float::filter_sum_f64_arrow(&arrow_values, &filter);
+        let exp = filter_float::filter_sum_f64(&values, &row_ids);
+        prop_assert!(float_eq(got, exp, tolerance), "{} != {}", got, exp);
+        prop_assert_eq!(
+            filter_float::filter_max_f64_arrow(&arrow_values, &filter),
+            filter_float::filter_max_f64(&values, &row_ids, NanMode::Ignore)
+        );
+        prop_assert_eq!(
+            filter_float::filter_min_f64_arrow(&arrow_values, &filter),
+            filter_float::filter_min_f64(&values, &row_ids, NanMode::Ignore)
+        );
+    }
+
+    #[test]
+    fn float_f32(
+        (values, row_ids) in with_row_ids(collection::vec(
+            prop_oneof![
+                10 => -1e3..1e3_f32,
+                1 => Just(f32::NAN),
+                1 => Just(f32::INFINITY),
+                1 => Just(f32::NEG_INFINITY),
+            ],
+            1..MAX_ROWS,
+        ))
+    ) {
+        let selected = row_ids.iter().map(|&id| values[id as usize] as f64);
+        let tolerance = sum_tolerance(selected, f32::EPSILON as f64);
+
+        let exp = filter_float::filter_sum_f32(&values, &row_ids) as f64;
+        for &got in &[
+            filter_float::filter_sum_f32_kahan(&values, &row_ids),
+            filter_float::filter_sum_f32_simd(&values, &row_ids).unwrap(),
+            filter_float::filter_sum_f32_kahan_simd(&values, &row_ids).unwrap(),
+        ] {
+            prop_assert!(float_eq(got as f64, exp, tolerance), "{} != {}", got, exp);
+        }
+
+        for &nan in &[NanMode::Propagate, NanMode::Ignore] {
+            let (max, min) = (
+                filter_float::filter_max_f32(&values, &row_ids, nan),
+                filter_float::filter_min_f32(&values, &row_ids, nan),
+            );
+            let got = filter_float::filter_max_f32_simd(&values, &row_ids, nan).unwrap();
+            prop_assert!(float_eq(got as f64, max as f64, 0.0), "{:?}: {} != {}", nan, got, max);
+            let got = filter_float::filter_min_f32_simd(&values, &row_ids, nan).unwrap();
+            prop_assert!(float_eq(got as f64, min as f64, 0.0), "{:?}: {} != {}", nan, got, min);
+        }
+    }
+
+    #[test]
+    fn dictionary(
+        (keys, row_ids) in with_row_ids(collection::vec(0..300_u32, 1..MAX_ROWS)),
+        dictionary in collection::vec(value(), 300),
+    ) {
+        let keys_u8 = keys.iter().map(|&k| k as u8).collect::<Vec<_>>();
+        let keys_u16 = keys.iter().map(|&k| k as u16).collect::<Vec<_>>();
+
+        prop_assert_eq!(
+            filter_dictionary::filter_materialise_keys_simd(&keys_u8, &row_ids, vec![]).unwrap(),
+            filter_dictionary::filter_materialise_keys(&keys_u8, &row_ids, vec![])
+        );
+        prop_assert_eq!(
+            filter_dictionary::filter_materialise_keys_simd(&keys_u16, &row_ids, vec![]).unwrap(),
+            filter_dictionary::filter_materialise_keys(&keys_u16, &row_ids, vec![])
+        );
+        prop_assert_eq!(
+            filter_dictionary::filter_materialise_keys_simd(&keys, &row_ids, vec![]).unwrap(),
+            filter_dictionary::filter_materialise_keys(&keys, &row_ids, vec![])
+        );
+
+        let exp = filter_dictionary::filter_materialise_decoded(&keys_u8, &dictionary, &row_ids, vec![]);
+        prop_assert_eq!(
+            filter_dictionary::filter_materialise_decoded_simd(&keys_u8, &dictionary, &row_ids, vec![]).unwrap(),
+            exp.clone()
+        );
+        prop_assert_eq!(
+            filter_dictionary::filter_materialise_decoded_u64_simd(&keys_u8, &dictionary, &row_ids, vec![]).unwrap(),
+            exp
+        );
+
+        let exp = filter_dictionary::filter_materialise_decoded(&keys, &dictionary, &row_ids, vec![]);
+        prop_assert_eq!(
+            filter_dictionary::filter_materialise_decoded_simd(&keys, &dictionary, &row_ids, vec![]).unwrap(),
+            exp.clone()
+        );
+        prop_assert_eq!(
+            filter_dictionary::filter_materialise_decoded_u64_simd(&keys, &dictionary, &row_ids, vec![]).unwrap(),
+            exp
+        );
+    }
+
+    #[test]
+    fn var_len((strs, row_ids) in with_row_ids(collection::vec("[a-z]{0,20}", 1..MAX_ROWS))) {
+        let mut offsets = vec![0_i32];
+        let mut values = vec![];
+        for s in &strs {
+            values.extend_from_slice(s.as_bytes());
+            offsets.push(values.len() as i32);
+        }
+
+        let exp = filter_var_len::filter_materialise_var_len(&offsets, &values, &row_ids, vec![], vec![]);
+        prop_assert_eq!(
+            filter_var_len::filter_materialise_var_len_runs(&offsets, &values, &row_ids, vec![], vec![]),
+            exp.clone()
+        );
+        prop_assert_eq!(
+            filter_var_len::filter_materialise_var_len_simd(&offsets, &values, &row_ids, vec![], vec![]),
+            Ok(exp)
+        );
+
+        let row_ids = sorted_unique(&row_ids);
+        let got = filter_var_len::filter_materialise_var_len_arrow(
+            &array::StringArray::from(strs.iter().map(|s| s.as_str()).collect::<Vec<_>>()),
+            &boolean_filter(strs.len(), &row_ids),
+        );
+        let got = got.as_any().downcast_ref::<array::StringArray>().unwrap();
+        let exp = row_ids.iter().map(|&id| strs[id as usize].as_str()).collect::<Vec<_>>();
+        prop_assert_eq!((0..got.len()).map(|i| got.value(i)).collect::<Vec<_>>(), exp);
+    }
+
+    #[test]
+    fn bit_packed(
+        (values, row_ids) in with_row_ids(values()),
+        width in 1..=64_u32,
+    ) {
+        let mask = if width == 64 { u64::MAX } else { (1 << width) - 1 };
+        let values = values.iter().map(|v| v & mask).collect::<Vec<_>>();
+        let col = BitPackedColumn::new(&values, width);
+
+        let exp = filter::filter_materialise_values(&values, &row_ids, vec![]);
+        prop_assert_eq!(bit_packed::filter_materialise_values(&col, &row_ids, vec![]), exp.clone());
+        prop_assert_eq!(
+            bit_packed::filter_materialise_values_gather_simd(&col, &row_ids, vec![]).unwrap(),
+            exp.clone()
+        );
+        prop_assert_eq!(
+            bit_packed::filter_materialise_values_block_simd(&col, &row_ids, vec![]).unwrap(),
+            exp.clone()
+        );
+        prop_assert_eq!(bit_packed::filter_materialise_values_simd(&col, &row_ids, vec![]).unwrap(), exp);
+
+        if row_ids.is_empty() {
+            return Ok(());
+        }
+        let exp = filter_max::filter_max(&values, &row_ids);
+        prop_assert_eq!(bit_packed::filter_max(&col, &row_ids), exp);
+        prop_assert_eq!(bit_packed::filter_max_gather_simd(&col, &row_ids).unwrap(), exp);
+        prop_assert_eq!(bit_packed::filter_max_block_simd(&col, &row_ids).unwrap(), exp);
+        prop_assert_eq!(bit_packed::filter_max_simd(&col, &row_ids).unwrap(), exp);
+    }
+
+    #[test]
+    fn bit_packed_sum((values, row_ids) in with_row_ids(sum_values())) {
+        let col = BitPackedColumn::from_values(&values);
+
+        let exp = filter_sum::filter_sum(&values, &row_ids);
+        prop_assert_eq!(bit_packed::filter_sum(&col, &row_ids), exp);
+        prop_assert_eq!(bit_packed::filter_sum_gather_simd(&col, &row_ids).unwrap(), exp);
+        prop_assert_eq!(bit_packed::filter_sum_block_simd(&col, &row_ids).unwrap(), exp);
+        prop_assert_eq!(bit_packed::filter_sum_simd(&col, &row_ids).unwrap(), exp);
+    }
+
+    #[test]
+    fn frame_of_reference((values, row_ids) in with_row_ids(values())) {
+        let col = FrameOfReferenceColumn::new(&values);
+
+        let exp = filter::filter_materialise_values(&values, &row_ids, vec![]);
+        prop_assert_eq!(
+            frame_of_reference::filter_materialise_values(&col, &row_ids, vec![]),
+            exp.clone()
+        );
+        prop_assert_eq!(
+            frame_of_reference::filter_materialise_values_simd(&col, &row_ids, vec![]).unwrap(),
+            exp
+        );
+
+        if row_ids.is_empty() {
+            return Ok(());
+        }
+        let exp = filter_max::filter_max(&values, &row_ids);
+        prop_assert_eq!(frame_of_reference::filter_max(&col, &row_ids), exp);
+        prop_assert_eq!(frame_of_reference::filter_max_simd(&col, &row_ids).unwrap(), exp);
+    }
+
+    #[test]
+    fn frame_of_reference_sum((values, row_ids) in with_row_ids(sum_values())) {
+        let col = FrameOfReferenceColumn::new(&values);
+
+        let exp = filter_sum::filter_sum(&values, &row_ids);
+        prop_assert_eq!(frame_of_reference::filter_sum(&col, &row_ids), exp);
+        prop_assert_eq!(frame_of_reference::filter_sum_simd(&col, &row_ids).unwrap(), exp);
+    }
+
+    #[test]
+    fn delta((values, row_ids) in with_row_ids(values())) {
+        let col = DeltaColumn::new(&values);
+
+        let exp = filter::filter_materialise_values(&values, &row_ids, vec![]);
+        prop_assert_eq!(delta::filter_materialise_values(&col, &row_ids, vec![]), exp.clone());
+        prop_assert_eq!(
+            delta::filter_materialise_values_prefix_sum(&col, &row_ids, vec![]),
+            exp.clone()
+        );
+        prop_assert_eq!(delta::filter_materialise_values_simd(&col, &row_ids, vec![]).unwrap(), exp);
+    }
+
+    #[test]
+    fn run_length(
+        (values, row_ids) in with_row_ids(runs(prop_oneof![0..4_u64, value()]))
+    ) {
+        let col = RunLengthColumn::new(&values);
+
+        // the run-walking functions fall back to a row at a time for row ids
+        // that aren't sorted without duplicates, so check both.
+        for row_ids in &[row_ids.clone(), sorted_unique(&row_ids)] {
+            let exp = filter::filter_materialise_values(&values, row_ids, vec![]);
+            prop_assert_eq!(run_length::filter_materialise_values(&col, row_ids, vec![]), exp.clone());
+            prop_assert_eq!(
+                run_length::filter_materialise_values_runs(&col, row_ids, vec![]).unwrap(),
+                exp.clone()
+            );
+            prop_assert_eq!(run_length::filter_materialise_values_simd(&col, row_ids, vec![]).unwrap(), exp);
+
+            if row_ids.is_empty() {
+                continue;
+            }
+            let exp = filter_max::filter_max(&values, row_ids);
+            prop_assert_eq!(run_length::filter_max(&col, row_ids), exp);
+            prop_assert_eq!(run_length::filter_max_runs(&col, row_ids).unwrap(), exp);
+            prop_assert_eq!(run_length::filter_max_simd(&col, row_ids).unwrap(), exp);
+        }
+    }
+
+    #[test]
+    fn run_length_sum(
+        (values, row_ids) in with_row_ids(runs(0..MAX_SUM_VALUE))
+    ) {
+        let col = RunLengthColumn::new(&values);
+
+        for row_ids in &[row_ids.clone(), sorted_unique(&row_ids)] {
+            let exp = filter_sum::filter_sum(&values, row_ids);
+            prop_assert_eq!(run_length::filter_sum(&col, row_ids), exp);
+            prop_assert_eq!(run_length::filter_sum_runs(&col, row_ids).unwrap(), exp);
+            prop_assert_eq!(run_length::filter_sum_simd(&col, row_ids).unwrap(), exp);
+        }
+    }
+
+    #[test]
+    fn chunked(
+        (values, row_ids) in with_row_ids(values()),
+        chunk_size in 1..100_usize,
+    ) {
+        let col = ChunkedColumn::from_values(&values, chunk_size);
+
+        // the splitting functions fall back to a row at a time for unsorted
+        // row ids, so check both.
+        for row_ids in &[row_ids.clone(), sorted(&row_ids)] {
+            let exp = filter::filter_materialise_values(&values, row_ids, vec![]);
+            prop_assert_eq!(chunked::filter_materialise_values(&col, row_ids, vec![]), exp.clone());
+            prop_assert_eq!(
+                chunked::filter_materialise_values_split(&col, row_ids, vec![]).unwrap(),
+                exp.clone()
+            );
+            prop_assert_eq!(chunked::filter_materialise_values_simd(&col, row_ids, vec![]).unwrap(), exp);
+
+            if row_ids.is_empty() {
+                continue;
+            }
+            let exp = filter_max::filter_max(&values, row_ids);
+            prop_assert_eq!(chunked::filter_max(&col, row_ids), exp);
+            prop_assert_eq!(chunked::filter_max_split(&col, row_ids).unwrap(), exp);
+            prop_assert_eq!(chunked::filter_max_simd(&col, row_ids).unwrap(), exp);
+        }
+    }
+
+    #[test]
+    fn chunked_sum(
+        (values, row_ids) in with_row_ids(sum_values()),
+        chunk_size in 1..100_usize,
+    ) {
+        let col = ChunkedColumn::from_values(&values, chunk_size);
+
+        let exp = filter_sum::filter_sum(&values, &row_ids);
+        prop_assert_eq!(chunked::filter_sum(&col, &row_ids), exp);
+
+        for row_ids in &[row_ids.clone(), sorted(&row_ids)] {
+            prop_assert_eq!(chunked::filter_sum_split(&col, row_ids).unwrap(), exp);
+            prop_assert_eq!(chunked::filter_sum_simd(&col, row_ids).unwrap(), exp);
+        }
+    }
+
+    #[test]
+    fn wide_row_ids(
+        (values, row_ids) in with_row_ids(values()),
+        segment_rows in 1..100_usize,
+    ) {
+        let wide = row_ids.iter().map(|&id| id as u64).collect::<Vec<_>>();
+        let segmented = SegmentedRowIds::with_segment_rows(&wide, segment_rows);
+
+        let exp = filter::filter_materialise_values(&values, &row_ids, vec![]);
+        prop_assert_eq!(
+            wide_row_ids::filter_materialise_values(&values, &wide, vec![]),
+            exp.clone()
+        );
+        prop_assert_eq!(
+            wide_row_ids::filter_materialise_values_simd(&values, &wide, vec![]).unwrap(),
+            exp.clone()
+        );
+        prop_assert_eq!(
+            wide_row_ids::filter_materialise_values_segmented(&values, &segmented, vec![])
+                .unwrap(),
+            exp
+        );
+
+        if row_ids.is_empty() {
+            return Ok(());
+        }
+        let exp = filter_max::filter_max(&values, &row_ids);
+        prop_assert_eq!(wide_row_ids::filter_max(&values, &wide), exp);
+        prop_assert_eq!(wide_row_ids::filter_max_simd(&values, &wide).unwrap(), exp);
+        prop_assert_eq!(
+            wide_row_ids::filter_max_segmented(&values, &segmented).unwrap(),
+            exp
+        );
+    }
+
+    #[test]
+    fn wide_row_ids_sum(
+        (values, row_ids) in with_row_ids(sum_values()),
+        segment_rows in 1..100_usize,
+    ) {
+        let wide = row_ids.iter().map(|&id| id as u64).collect::<Vec<_>>();
+        let segmented = SegmentedRowIds::with_segment_rows(&wide, segment_rows);
+
+        let exp = filter_sum::filter_sum(&values, &row_ids);
+        prop_assert_eq!(wide_row_ids::filter_sum(&values, &wide), exp);
+        prop_assert_eq!(wide_row_ids::filter_sum_simd(&values, &wide).unwrap(), exp);
+        prop_assert_eq!(
+            wide_row_ids::filter_sum_segmented(&values, &segmented).unwrap(),
+            exp
+        );
+    }
+
+    #[test]
+    fn record_batch(
+        (rows, row_ids) in with_row_ids(collection::vec((value(), any::<i32>(), "[a-z]{0,8}"), 1..MAX_ROWS)),
+    ) {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("u64", DataType::UInt64, false),
+            Field::new("i32", DataType::Int32, false),
+            Field::new("str", DataType::Utf8, false),
+        ]));
+        let columns: Vec<array::ArrayRef> = vec![
+            Arc::new(array::UInt64Array::from(rows.iter().map(|r| r.0).collect::<Vec<_>>())),
+            Arc::new(array::Int32Array::from(rows.iter().map(|r| r.1).collect::<Vec<_>>())),
+            Arc::new(array::StringArray::from(rows.iter().map(|r| r.2.as_str()).collect::<Vec<_>>())),
+        ];
+        let batch = RecordBatch::try_new(schema, columns).unwrap();
+
+        let row_ids = sorted_unique(&row_ids);
+        let projection = [2, 0, 1];
+        let got = filter_batch::filter_record_batch(
+            &batch,
+            &filter_batch::Selection::from_row_ids(row_ids.clone()),
+            &projection,
+        ).unwrap();
+        let exp = filter_batch::filter_record_batch_arrow(
+            &batch,
+            &boolean_filter(rows.len(), &row_ids),
+            &projection,
+        );
+
+        prop_assert_eq!(got.schema(), exp.schema());
+        prop_assert_eq!(got.num_rows(), row_ids.len());
+        prop_assert_eq!(
+            got.column(0).as_any().downcast_ref::<array::StringArray>(),
+            exp.column(0).as_any().downcast_ref::<array::StringArray>()
+        );
+        prop_assert_eq!(u64_array(got.column(1)), u64_array(exp.column(1)));
+        prop_assert_eq!(
+            got.column(2).as_any().downcast_ref::<array::Int32Array>(),
+            exp.column(2).as_any().downcast_ref::<array::Int32Array>()
+        );
+    }
+}